@@ -0,0 +1,145 @@
+//! Instructions per second of the I8080 interpreter core, on pre-decoded workloads
+//! and through the table decoder, as enums and as boxed instructions.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use n88::cpu::CPUStackPointer;
+use n88::instruction::typical::{
+    Arithmetic, Call, DecoderBuilder, Decrement, Increment, Load, PopPair, PushPair, Return,
+};
+use n88::instruction::{DynInstruction, Instruction, InstructionDecoder, Operand};
+use n88::memory::Endian;
+use n88::typical::i8080::I8080ALUControl::{Add, BitXor, Subtract};
+use n88::typical::i8080::I8080ALUFlag::*;
+use n88::typical::i8080::I8080Addressing8Bit::{
+    self, DirectValue, ImmediateRegister, ImmediateValue,
+};
+use n88::typical::i8080::I8080RegisterCode16Bit::{BC, DE, HL};
+use n88::typical::i8080::I8080RegisterCode8Bit::{A, B};
+use n88::typical::i8080::{I8080Flags, I8080Instruction, I8080System};
+
+const ALL: I8080Flags = &[Sign, Zero, AuxiliaryCarry, Parity, Carry];
+const INR: I8080Flags = &[Sign, Zero, AuxiliaryCarry, Parity];
+
+fn run(system: &mut I8080System, program: &[I8080Instruction]) {
+    for instruction in program {
+        instruction.execute(system);
+    }
+}
+
+fn bench_workload(c: &mut Criterion, name: &str, program: Vec<I8080Instruction>) {
+    let mut system: I8080System = I8080System::default();
+    *system.cpu.stack_pointer() = 0xf000;
+    let mut group = c.benchmark_group("i8080");
+    group.throughput(Throughput::Elements(program.len() as u64));
+    group.bench_function(name, |b| b.iter(|| run(black_box(&mut system), &program)));
+    group.finish();
+}
+
+/// MOV A,M / MOV M,A over a 1KB block.
+fn memory_copy(c: &mut Criterion) {
+    let program = (0..0x400u16)
+        .flat_map(|i| {
+            [
+                I8080Instruction::Load(Load::new(ImmediateRegister(A), DirectValue(0x1000 + i))),
+                I8080Instruction::Load(Load::new(DirectValue(0x2000 + i), ImmediateRegister(A))),
+            ]
+        })
+        .collect();
+    bench_workload(c, "memory_copy", program);
+}
+
+/// ADD, SUB and XRA committing every flag, and INR/DCR keeping the carry.
+fn arithmetic(c: &mut Criterion) {
+    let b = ImmediateRegister(B);
+    let a = ImmediateRegister(A);
+    let program = (0..0x100u16)
+        .flat_map(|i| {
+            [
+                I8080Instruction::Arithmetic(Arithmetic::new(Add, ALL, a, ImmediateValue(i as u8))),
+                I8080Instruction::Arithmetic(Arithmetic::new(Subtract, ALL, a, b)),
+                I8080Instruction::Arithmetic(Arithmetic::new(BitXor, ALL, a, b)),
+                I8080Instruction::Increment(Increment::new(INR, b)),
+                I8080Instruction::Decrement(Decrement::new(INR, I8080Addressing8Bit::M)),
+            ]
+        })
+        .collect();
+    bench_workload(c, "arithmetic", program);
+}
+
+/// PUSH/POP of register pairs and CALL/RET.
+fn stack(c: &mut Criterion) {
+    let program = (0..0x100)
+        .flat_map(|_| {
+            [
+                I8080Instruction::Push(PushPair::new(BC)),
+                I8080Instruction::Push(PushPair::new(DE)),
+                I8080Instruction::Call(Call::new(0x0100, Endian::Little)),
+                I8080Instruction::Return(Return::new(Endian::Little)),
+                I8080Instruction::Pop(PopPair::new(HL)),
+                I8080Instruction::Pop(PopPair::new(BC)),
+            ]
+        })
+        .collect();
+    bench_workload(c, "stack", program);
+}
+
+/// MVI B,n; INR B; PUSH B; POP H over and over, decoded as it runs.
+fn decode(c: &mut Criterion) {
+    let program: Vec<u8> = (0..0x100)
+        .flat_map(|i| [0x06, i as u8, 0x04, 0xc5, 0xe1])
+        .collect();
+    let instructions = program.len() as u64 * 4 / 5;
+    let mut group = c.benchmark_group("i8080_decode");
+    group.throughput(Throughput::Elements(instructions));
+
+    let mut decoder = DecoderBuilder::new()
+        .map(0x06, Operand::Imm8, |_, n| {
+            I8080Instruction::Load(Load::new(ImmediateRegister(B), ImmediateValue(n as u8)))
+        })
+        .map(0x04, Operand::None, |_, _| {
+            I8080Instruction::Increment(Increment::new(INR, ImmediateRegister(B)))
+        })
+        .map(0xc5, Operand::None, |_, _| {
+            I8080Instruction::Push(PushPair::new(BC))
+        })
+        .map(0xe1, Operand::None, |_, _| {
+            I8080Instruction::Pop(PopPair::new(HL))
+        })
+        .build();
+    let mut system: I8080System = I8080System::default();
+    group.bench_function("enum", |b| {
+        b.iter(|| {
+            for &byte in &program {
+                let decoded = InstructionDecoder::<I8080System>::decode(&mut decoder, byte);
+                if let Some(instruction) = decoded.complete() {
+                    instruction.execute(&mut system);
+                }
+            }
+        })
+    });
+
+    let mut decoder = DecoderBuilder::<DynInstruction<I8080System>>::new()
+        .map(0x06, Operand::Imm8, |_, n| {
+            Box::new(Load::new(ImmediateRegister(B), ImmediateValue(n as u8)))
+        })
+        .map(0x04, Operand::None, |_, _| {
+            Box::new(Increment::new(INR, ImmediateRegister(B)))
+        })
+        .map(0xc5, Operand::None, |_, _| Box::new(PushPair::new(BC)))
+        .map(0xe1, Operand::None, |_, _| Box::new(PopPair::new(HL)))
+        .build();
+    let mut system: I8080System = I8080System::default();
+    group.bench_function("dyn", |b| {
+        b.iter(|| {
+            for &byte in &program {
+                if let Some(instruction) = decoder.decode(byte).complete() {
+                    instruction.execute(&mut system);
+                }
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, memory_copy, arithmetic, stack, decode);
+criterion_main!(benches);
@@ -1,4 +1,269 @@
+use crate::memory::Memory;
+use crate::register::{Register, RegisterCode, RegisterIncrementable, RegisterSet};
+use crate::system::System;
+
 pub trait Addressing<C> {
     type Size;
     fn value(&self, cpu: &C) -> Self::Size;
+    /// Side effects once the instruction has used the value, e.g. post-increment.
+    fn after(&self, _cpu: &mut C) {}
+}
+
+/// An addressing mode that can also be the destination of a result, e.g. a register or memory.
+pub trait AddressingMut<C>: Addressing<C> {
+    fn write(&self, cpu: &mut C, value: Self::Size);
+}
+
+/// Adds an index to an address, wrapping like the address adder does.
+pub trait WrappingIndex<I> {
+    fn wrapping_index(self, index: I) -> Self;
+}
+
+macro_rules! wrapping_index_impl {
+    ($($a:ty => $($i:ty)*;)*) => {$($(
+        impl WrappingIndex<$i> for $a {
+            fn wrapping_index(self, index: $i) -> Self {
+                self.wrapping_add_signed(index as _)
+            }
+        }
+    )*)*}
+}
+
+macro_rules! wrapping_index_unsigned_impl {
+    ($($a:ty => $($i:ty)*;)*) => {$($(
+        impl WrappingIndex<$i> for $a {
+            fn wrapping_index(self, index: $i) -> Self {
+                self.wrapping_add(index as _)
+            }
+        }
+    )*)*}
+}
+
+wrapping_index_impl! {
+    u8 => i8;
+    u16 => i8 i16;
+    u32 => i8 i16 i32;
+}
+
+wrapping_index_unsigned_impl! {
+    u8 => u8;
+    u16 => u8 u16;
+    u32 => u8 u16 u32;
+}
+
+/// A constant value, e.g. an operand of the instruction.
+#[derive(Debug, Copy, Clone)]
+pub struct Immediate<B> {
+    value: B,
+}
+
+impl<B> Immediate<B> {
+    pub fn new(value: B) -> Self {
+        Self { value }
+    }
+}
+
+impl<C, B: Copy> Addressing<C> for Immediate<B> {
+    type Size = B;
+    fn value(&self, _cpu: &C) -> B {
+        self.value
+    }
+}
+
+/// The value of a register.
+#[derive(Debug, Copy, Clone)]
+pub struct ImmediateRegister<R> {
+    code: R,
+}
+
+impl<R> ImmediateRegister<R> {
+    pub fn new(code: R) -> Self {
+        Self { code }
+    }
+}
+
+impl<C, R, B> Addressing<C> for ImmediateRegister<R>
+where
+    C: RegisterSet<R, Register = B>,
+    R: RegisterCode<Register = B> + Copy,
+{
+    type Size = B;
+    fn value(&self, cpu: &C) -> B {
+        cpu.read_of(self.code)
+    }
+}
+
+impl<C, R, B> AddressingMut<C> for ImmediateRegister<R>
+where
+    C: RegisterSet<R, Register = B>,
+    R: RegisterCode<Register = B> + Copy,
+{
+    fn write(&self, cpu: &mut C, value: B) {
+        cpu.load_of(self.code, value)
+    }
+}
+
+/// The memory addressed by `A`.
+#[derive(Debug, Copy, Clone)]
+pub struct Indirect<A> {
+    address: A,
+}
+
+impl<A> Indirect<A> {
+    pub fn new(address: A) -> Self {
+        Self { address }
+    }
+}
+
+impl<C, M, A> Addressing<System<C, M>> for Indirect<A>
+where
+    M: Memory,
+    A: Addressing<System<C, M>, Size = M::Address>,
+{
+    type Size = M::Data;
+    fn value(&self, system: &System<C, M>) -> M::Data {
+        system.memory.read(self.address.value(system))
+    }
+    fn after(&self, system: &mut System<C, M>) {
+        self.address.after(system)
+    }
+}
+
+impl<C, M, A> AddressingMut<System<C, M>> for Indirect<A>
+where
+    M: Memory,
+    A: Addressing<System<C, M>, Size = M::Address>,
+{
+    fn write(&self, system: &mut System<C, M>, value: M::Data) {
+        let address = self.address.value(system);
+        system.memory.store(address, value)
+    }
+}
+
+/// `base + index`, e.g. Z80 `(IX+d)` as `Indirect<Indexed<ImmediateRegister<_>, Immediate<i8>>>`
+/// or 6502 `abs,X` as `Indirect<Indexed<Immediate<u16>, ImmediateRegister<_>>>`.
+#[derive(Debug, Copy, Clone)]
+pub struct Indexed<A, I> {
+    base: A,
+    index: I,
+}
+
+impl<A, I> Indexed<A, I> {
+    pub fn new(base: A, index: I) -> Self {
+        Self { base, index }
+    }
+}
+
+impl<C, A, I> Addressing<C> for Indexed<A, I>
+where
+    A: Addressing<C>,
+    I: Addressing<C>,
+    A::Size: WrappingIndex<I::Size>,
+{
+    type Size = A::Size;
+    fn value(&self, cpu: &C) -> A::Size {
+        self.base.value(cpu).wrapping_index(self.index.value(cpu))
+    }
+    fn after(&self, cpu: &mut C) {
+        self.base.after(cpu);
+        self.index.after(cpu);
+    }
+}
+
+/// The value of a register, which is incremented afterwards.
+#[derive(Debug, Copy, Clone)]
+pub struct PostIncrement<R> {
+    code: R,
+}
+
+impl<R> PostIncrement<R> {
+    pub fn new(code: R) -> Self {
+        Self { code }
+    }
+}
+
+impl<C, R, B> Addressing<C> for PostIncrement<R>
+where
+    C: RegisterSet<R, Register = B>,
+    R: RegisterCode<Register = B> + Copy,
+    B: Register + RegisterIncrementable,
+{
+    type Size = B;
+    fn value(&self, cpu: &C) -> B {
+        cpu.read_of(self.code)
+    }
+    fn after(&self, cpu: &mut C) {
+        let mut bits = cpu.read_of(self.code);
+        bits.increment();
+        cpu.load_of(self.code, bits);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instruction::typical::Load;
+    use crate::instruction::Instruction;
+    use crate::memory::typical::Memory8Bit64KB;
+    use crate::typical::i8080::I8080RegisterCode16Bit::*;
+    use crate::typical::i8080::I8080RegisterCode8Bit::*;
+    use crate::typical::i8080::{I8080System, I8080};
+    use alloc::vec::Vec;
+
+    fn system() -> I8080System {
+        let bytes: Vec<u8> = (0..=255).collect();
+        I8080System::new(I8080::default(), Memory8Bit64KB::new(&bytes))
+    }
+
+    #[test]
+    fn wrapping_index() {
+        assert_eq!(0x1000u16.wrapping_index(-1i8), 0x0fff);
+        assert_eq!(0xffffu16.wrapping_index(2u8), 0x0001);
+        assert_eq!(0xffu8.wrapping_index(1u8), 0x00);
+        assert_eq!(0x00u8.wrapping_index(-1i8), 0xff);
+    }
+
+    #[test]
+    fn indirect_indexed() {
+        let mut system = system();
+        system.load_of(HL, 0x0040);
+        system.load_of(A, 0x05);
+        let hl = ImmediateRegister::new(HL);
+        assert_eq!(Indirect::new(hl).value(&system), 0x40);
+        let hl_minus_2 = Indexed::new(hl, Immediate::new(-2i8));
+        assert_eq!(hl_minus_2.value(&system), 0x003e);
+        assert_eq!(Indirect::new(hl_minus_2).value(&system), 0x3e);
+        let abs_a = Indexed::new(Immediate::new(0x0010u16), ImmediateRegister::new(A));
+        assert_eq!(Indirect::new(abs_a).value(&system), 0x15);
+    }
+
+    #[test]
+    fn write() {
+        let mut system = system();
+        system.load_of(HL, 0x0030);
+        ImmediateRegister::new(A).write(&mut system, 0x12);
+        assert_eq!(system.read_of(A), 0x12);
+        let hl = Indirect::new(ImmediateRegister::new(HL));
+        hl.write(&mut system, 0x34);
+        assert_eq!(system.memory.read(0x0030), 0x34);
+        // MOV M,A and MOV A,M
+        Load::new(hl, ImmediateRegister::new(A)).execute(&mut system);
+        assert_eq!(system.memory.read(0x0030), 0x12);
+        system.memory.store(0x0030, 0x56);
+        Load::new(A, hl).execute(&mut system);
+        assert_eq!(system.read_of(A), 0x56);
+    }
+
+    #[test]
+    fn post_increment() {
+        let mut system = system();
+        system.load_of(HL, 0x0020);
+        let hl_plus = Indirect::new(PostIncrement::new(HL));
+        Load::new(A, hl_plus).execute(&mut system);
+        assert_eq!(system.read_of(A), 0x20);
+        assert_eq!(system.read_of(HL), 0x0021);
+        Load::new(B, hl_plus).execute(&mut system);
+        assert_eq!(system.read_of(B), 0x21);
+        assert_eq!(system.read_of(HL), 0x0022);
+    }
 }
@@ -1,3 +1,5 @@
+use crate::BitwiseOps;
+
 pub trait FlagSet<F> {
     fn change(&mut self, flag: F, set: bool);
     fn is_set(&self, flag: F) -> bool;
@@ -12,6 +14,30 @@ pub trait FlagSet<F> {
     fn from_slice(flags: &[F]) -> Self
     where
         F: Copy;
+    /// every flag but `flags`, e.g. all but the carry.
+    fn all_except(flags: &[F]) -> Self
+    where
+        F: Copy,
+        Self: Sized,
+    {
+        let mut set = Self::all_on();
+        flags.iter().for_each(|&f| set.reset(f));
+        set
+    }
+    fn with(mut self, flag: F) -> Self
+    where
+        Self: Sized,
+    {
+        self.set(flag);
+        self
+    }
+    fn without(mut self, flag: F) -> Self
+    where
+        Self: Sized,
+    {
+        self.reset(flag);
+        self
+    }
 }
 
 pub trait ALU {
@@ -22,12 +48,127 @@ pub trait ALU {
     fn op(&self, code: Self::Control, a: Self::Data, b: Self::Data) -> (Self::Data, Self::FlagSet);
 }
 
+/// The architectural byte layout of the flags, with bits that read the same whatever is stored,
+/// e.g. the low byte of PSW on I8080.
+pub trait FlagRegister: ALU {
+    type Bits: BitwiseOps;
+    /// bits always read as 1.
+    const FIXED_ONE: Self::Bits;
+    /// bits always read as 0.
+    const FIXED_ZERO: Self::Bits;
+
+    /// forces the fixed bits of a raw register value.
+    fn fixed(bits: Self::Bits) -> Self::Bits {
+        bits & !Self::FIXED_ZERO | Self::FIXED_ONE
+    }
+    fn pack(flags: Self::FlagSet) -> Self::Bits
+    where
+        Self::FlagSet: Into<Self::Bits>,
+    {
+        Self::fixed(flags.into())
+    }
+    fn unpack(bits: Self::Bits) -> Self::FlagSet
+    where
+        Self::FlagSet: From<Self::Bits>,
+    {
+        Self::fixed(bits).into()
+    }
+}
+
+/// ALUs counting one operand up or down, e.g. `INR`/`DCR` of I8080 or `INC`/`DEC` of MOS6502.
+pub trait ALUCounter: ALU {
+    fn increment(&self, a: Self::Data) -> (Self::Data, Self::FlagSet);
+    fn decrement(&self, a: Self::Data) -> (Self::Data, Self::FlagSet);
+}
+
+/// ALUs operating on a single bit, e.g. `BIT`, `SET` and `RES` of Z80.
+pub trait ALUBits: ALU {
+    /// the flags of testing `bit` of `a`, e.g. zero if it is clear.
+    fn bit_test(&self, a: Self::Data, bit: u8) -> Self::FlagSet;
+    fn bit_change(&self, a: Self::Data, bit: u8, set: bool) -> Self::Data;
+}
+
+/// ALUs taking the carry flag in, e.g. `ADC`/`SBB` of I8080 or `ADC`/`SBC` of MOS6502.
+pub trait ALUCarry: ALU {
+    /// the flag carried in.
+    const CARRY: Self::Flag;
+    /// `op` with `carry` added in, or taken away as a borrow by subtractions.
+    fn op_carry(
+        &self,
+        code: Self::Control,
+        a: Self::Data,
+        b: Self::Data,
+        carry: bool,
+    ) -> (Self::Data, Self::FlagSet);
+}
+
+/// carries and two's-complement overflow shared by the ALUs, on unsigned words of any width.
+pub mod helpers {
+    use core::mem::size_of;
+
+    fn bits<T>() -> u32 {
+        size_of::<T>() as u32 * 8
+    }
+
+    /// whether `a + b + carry` carries out of bit `n`, never past bit 64.
+    pub fn carry_from_bit<T: Into<u64>>(n: u32, a: T, b: T, carry: bool) -> bool {
+        let mask = (1u128 << (n.min(64) + 1)) - 1;
+        (a.into() as u128 & mask) + (b.into() as u128 & mask) + carry as u128 > mask
+    }
+
+    /// the carry out of bit 3, e.g. `AC` of I8080 or `H` of Z80.
+    /// Subtractions are `half_carry(a, !b, true)`, as the 8080 computes them.
+    pub fn half_carry<T: Into<u64>>(a: T, b: T, carry: bool) -> bool {
+        carry_from_bit(3, a, b, carry)
+    }
+
+    /// whether `a + b + carry` leaves the signed range of `T`.
+    pub fn overflow_add<T: Into<u64>>(a: T, b: T, carry: bool) -> bool {
+        let (a, b) = (a.into(), b.into());
+        let result = a.wrapping_add(b).wrapping_add(carry as u64);
+        let sign = 1 << (bits::<T>() - 1);
+        (a ^ result) & (b ^ result) & sign != 0
+    }
+
+    /// whether `a - b - borrow` leaves the signed range of `T`.
+    pub fn overflow_sub<T: Into<u64>>(a: T, b: T, borrow: bool) -> bool {
+        let (a, b) = (a.into(), b.into());
+        let result = a.wrapping_sub(b).wrapping_sub(borrow as u64);
+        let sign = 1 << (bits::<T>() - 1);
+        (a ^ b) & (a ^ result) & sign != 0
+    }
+}
+
 pub mod typical {
     use super::*;
-    use crate::BitwiseOps;
-    #[derive(Debug, Default, Eq, PartialEq)]
+    use core::ops::{BitAnd, BitOr, Not};
+
+    /// flags as the bits of `B`; `|`, `&` and `!` are union, intersection and complement,
+    /// so `a & !b` is the flags of `a` not in `b`.
+    #[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
     pub struct FlagSetBits<B: BitwiseOps>(B);
 
+    impl<B: BitwiseOps> BitOr for FlagSetBits<B> {
+        type Output = Self;
+        fn bitor(self, rhs: Self) -> Self {
+            Self(self.0 | rhs.0)
+        }
+    }
+
+    impl<B: BitwiseOps> BitAnd for FlagSetBits<B> {
+        type Output = Self;
+        fn bitand(self, rhs: Self) -> Self {
+            Self(self.0 & rhs.0)
+        }
+    }
+
+    impl<B: BitwiseOps> Not for FlagSetBits<B> {
+        type Output = Self;
+        fn not(self) -> Self {
+            Self(!self.0)
+        }
+    }
+
     impl<B: BitwiseOps, F: Into<B>> FlagSet<F> for FlagSetBits<B> {
         fn change(&mut self, flag: F, set: bool) {
             if set {
@@ -53,7 +194,7 @@ pub mod typical {
         where
             F: Copy,
         {
-            Self(flags.iter().fold(B::ALL_ONE, |b, &f| b | f.into()))
+            Self(flags.iter().fold(B::ALL_ZERO, |b, &f| b | f.into()))
         }
     }
 
@@ -131,6 +272,50 @@ mod test {
         }
     }
 
+    #[test]
+    fn combinators() {
+        use AdderFlag::*;
+        let signed = FlagSetBits::<u8>::from_slice(&[Signed]);
+        let both = FlagSetBits::<u8>::from_slice(&[Overflow, Signed]);
+        assert_eq!(signed | both, 3);
+        assert_eq!(signed & both, 2);
+        assert_eq!(both & !signed, 1);
+        assert_eq!(FlagSetBits::<u8>::all_except(&[Overflow]), 0xfe);
+        assert_eq!(signed.without(Signed).with(Overflow), 1);
+        assert_eq!(both.without(Signed), 1);
+    }
+
+    #[test]
+    fn helpers() {
+        use super::helpers::*;
+        for a in 0..=0xffu8 {
+            for b in 0..=0xffu8 {
+                for c in [false, true] {
+                    let sum = a as i8 as i16 + b as i8 as i16 + c as i16;
+                    assert_eq!(overflow_add(a, b, c), !(-128..=127).contains(&sum));
+                    let diff = a as i8 as i16 - b as i8 as i16 - c as i16;
+                    assert_eq!(overflow_sub(a, b, c), !(-128..=127).contains(&diff));
+                    for n in 0..8 {
+                        let mask = (1u16 << (n + 1)) - 1;
+                        let wide = (a as u16 & mask) + (b as u16 & mask) + c as u16;
+                        assert_eq!(carry_from_bit(n, a, b, c), wide > mask);
+                    }
+                    let wide = a as u16 + b as u16 + c as u16;
+                    assert_eq!(carry_from_bit(7, a, b, c), wide > 0xff);
+                    let nibble = (a & 0x0f) + (b & 0x0f) + c as u8;
+                    assert_eq!(half_carry(a, b, c), nibble > 0x0f);
+                }
+            }
+        }
+        assert!(overflow_add(0x7fffu16, 0x0001, false));
+        assert!(!overflow_add(0x7fffu16, 0xffff, false));
+        assert!(overflow_sub(0x8000u16, 0x0001, false));
+        assert!(carry_from_bit(63, u64::MAX, 0, true));
+        assert!(!carry_from_bit(64, u64::MAX, u64::MAX, true));
+        assert!(!carry_from_bit(u32::MAX, u64::MAX, u64::MAX, true));
+        assert!(overflow_add(i64::MAX as u64, 0, true));
+    }
+
     #[test]
     fn test() {
         let adder = Adder::default();
@@ -0,0 +1,1191 @@
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::string::{String, ToString};
+use alloc::{format, vec::Vec};
+use core::fmt;
+
+pub trait Assembler {
+    type Data;
+    fn assemble(&self, source: &str) -> Result<Vec<Self::Data>, AsmError>;
+}
+
+/// `line` is 1-origin.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum AsmError {
+    UnknownInstruction {
+        line: usize,
+        text: String,
+    },
+    InvalidExpression {
+        line: usize,
+        text: String,
+    },
+    UndefinedSymbol {
+        line: usize,
+        name: String,
+    },
+    DuplicateLabel {
+        line: usize,
+        name: String,
+    },
+    OutOfRange {
+        line: usize,
+        value: i64,
+    },
+    InvalidDirective {
+        line: usize,
+        text: String,
+    },
+    IncludeNotFound {
+        line: usize,
+        name: String,
+    },
+    /// `name` includes itself, directly or through other includes.
+    RecursiveInclude {
+        line: usize,
+        name: String,
+    },
+    UnterminatedMacro {
+        line: usize,
+        name: String,
+    },
+    InvalidRelocation {
+        line: usize,
+        text: String,
+    },
+    /// the code runs past the end of the address space.
+    AddressOverflow {
+        line: usize,
+    },
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AsmError::UnknownInstruction { line, text } => {
+                write!(f, "line {}: unknown instruction `{}`", line, text)
+            }
+            AsmError::InvalidExpression { line, text } => {
+                write!(f, "line {}: invalid expression `{}`", line, text)
+            }
+            AsmError::UndefinedSymbol { line, name } => {
+                write!(f, "line {}: undefined symbol `{}`", line, name)
+            }
+            AsmError::DuplicateLabel { line, name } => {
+                write!(f, "line {}: duplicate label `{}`", line, name)
+            }
+            AsmError::OutOfRange { line, value } => {
+                write!(f, "line {}: value {} out of range", line, value)
+            }
+            AsmError::InvalidDirective { line, text } => {
+                write!(f, "line {}: invalid directive `{}`", line, text)
+            }
+            AsmError::IncludeNotFound { line, name } => {
+                write!(f, "line {}: include `{}` not found", line, name)
+            }
+            AsmError::RecursiveInclude { line, name } => {
+                write!(f, "line {}: `{}` includes itself", line, name)
+            }
+            AsmError::UnterminatedMacro { line, name } => {
+                write!(f, "line {}: macro `{}` has no ENDM", line, name)
+            }
+            AsmError::InvalidRelocation { line, text } => {
+                write!(f, "line {}: `{}` cannot be relocated", line, text)
+            }
+            AsmError::AddressOverflow { line } => {
+                write!(f, "line {}: past the end of the address space", line)
+            }
+        }
+    }
+}
+
+impl core::error::Error for AsmError {}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum LinkError {
+    DuplicatePublic(String),
+    UndefinedExternal(String),
+}
+
+impl fmt::Display for LinkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LinkError::DuplicatePublic(name) => write!(f, "`{}` is defined twice", name),
+            LinkError::UndefinedExternal(name) => write!(f, "`{}` is not defined", name),
+        }
+    }
+}
+
+impl core::error::Error for LinkError {}
+
+pub mod typical {
+    use super::*;
+    use crate::disasm::typical::{I8080Operand, I8080_OPCODES};
+    use crate::memory::Memory;
+
+    /// Assembles 8080 source in Intel syntax, one statement per line:
+    /// `[label:] [mnemonic [operand, ...]] [; comment]`.
+    /// Numbers are decimal, `0FFH`, `0xff`, `101b` or `'c'`; `$` is the current address.
+    ///
+    /// Directives:
+    /// - `ORG addr` continues at `addr`, padding the image with zeros.
+    /// - `name EQU value` defines a symbol; `value` may only refer to symbols above it.
+    /// - `DB 1, 'c', 'string'` / `DW 1234H, label` emit bytes / little-endian words.
+    /// - `DS n` reserves `n` zero bytes.
+    /// - `INCLUDE name` assembles a source registered by [`I8080Assembler::include`],
+    ///   or else, with the `std` feature, the file at that path, in place.
+    /// - `name MACRO param, ...` ... `ENDM` defines a macro, invoked as `name arg, ...`.
+    ///   Parameters are substituted textually.
+    ///
+    /// Errors from included files and macro expansions refer to the line that included them.
+    #[derive(Debug, Default, Clone)]
+    pub struct I8080Assembler {
+        origin: u16,
+        includes: BTreeMap<String, String>,
+    }
+
+    impl I8080Assembler {
+        pub fn new(origin: u16) -> Self {
+            Self {
+                origin,
+                includes: BTreeMap::new(),
+            }
+        }
+
+        /// Registers `source` to be assembled by `INCLUDE name`.
+        pub fn include(mut self, name: &str, source: &str) -> Self {
+            self.includes.insert(name.to_string(), source.to_string());
+            self
+        }
+
+        /// Assembles `source` and stores it into `memory` from the origin.
+        /// Returns the number of bytes written.
+        pub fn assemble_into<M>(&self, source: &str, memory: &mut M) -> Result<usize, AsmError>
+        where
+            M: Memory<Address = u16, Data = u8>,
+        {
+            let bytes = self.assemble(source)?;
+            for (i, &b) in bytes.iter().enumerate() {
+                memory.store(self.origin.wrapping_add(i as u16), b);
+            }
+            Ok(bytes.len())
+        }
+
+        fn read_include(&self, name: &str, line: usize) -> Result<String, AsmError> {
+            let not_found = || AsmError::IncludeNotFound {
+                line,
+                name: name.to_string(),
+            };
+            match self.includes.get(name) {
+                Some(source) => Ok(source.clone()),
+                #[cfg(feature = "std")]
+                None => std::fs::read_to_string(name).map_err(|_| not_found()),
+                #[cfg(not(feature = "std"))]
+                None => Err(not_found()),
+            }
+        }
+
+        /// expands includes and macros into `(line, text)` with comments stripped.
+        fn preprocess(
+            &self,
+            source: &str,
+            line: Option<usize>,
+            macros: &mut BTreeMap<String, Macro>,
+            includes: &mut Vec<String>,
+            depth: usize,
+            out: &mut Vec<(usize, String)>,
+        ) -> Result<(), AsmError> {
+            let mut lines = source.lines().enumerate();
+            while let Some((i, text)) = lines.next() {
+                let line = line.unwrap_or(i + 1);
+                let text = strip_comment(text);
+                let (label, body) = split_label(text);
+                let (mnemonic, operands) = split_operands(body);
+                let invalid = || AsmError::InvalidDirective {
+                    line,
+                    text: text.to_string(),
+                };
+                if depth > MAX_NESTING {
+                    return Err(invalid());
+                }
+                if let Some((name, params)) = named_directive(label, body, "MACRO") {
+                    let params: Vec<String> = match params {
+                        "" => Vec::new(),
+                        params => split_list(params).into_iter().map(str::to_string).collect(),
+                    };
+                    if !params.iter().all(|p| is_identifier(p)) {
+                        return Err(invalid());
+                    }
+                    let mut lines_of_body = Vec::new();
+                    loop {
+                        let Some((_, text)) = lines.next() else {
+                            return Err(AsmError::UnterminatedMacro {
+                                line,
+                                name: name.to_string(),
+                            });
+                        };
+                        if split_label(strip_comment(text))
+                            .1
+                            .eq_ignore_ascii_case("ENDM")
+                        {
+                            break;
+                        }
+                        lines_of_body.push(text.to_string());
+                    }
+                    let body = Macro {
+                        params,
+                        body: lines_of_body,
+                    };
+                    macros.insert(name.to_ascii_uppercase(), body);
+                    continue;
+                }
+                if mnemonic.eq_ignore_ascii_case("INCLUDE") {
+                    let [name] = operands[..] else {
+                        return Err(invalid());
+                    };
+                    let name = name.trim_matches(['"', '\'']);
+                    if includes.iter().any(|included| included == name) {
+                        return Err(AsmError::RecursiveInclude {
+                            line,
+                            name: name.to_string(),
+                        });
+                    }
+                    let source = self.read_include(name, line)?;
+                    if let Some(label) = label {
+                        out.push((line, format!("{}:", label)));
+                    }
+                    includes.push(name.to_string());
+                    self.preprocess(&source, Some(line), macros, includes, depth + 1, out)?;
+                    includes.pop();
+                    continue;
+                }
+                if let Some(m) = macros.get(&mnemonic.to_ascii_uppercase()) {
+                    if m.params.len() != operands.len() {
+                        return Err(invalid());
+                    }
+                    let expanded: Vec<String> = m
+                        .body
+                        .iter()
+                        .map(|text| substitute(text, &m.params, &operands))
+                        .collect();
+                    if let Some(label) = label {
+                        out.push((line, format!("{}:", label)));
+                    }
+                    let expanded = expanded.join("\n");
+                    self.preprocess(&expanded, Some(line), macros, includes, depth + 1, out)?;
+                    continue;
+                }
+                out.push((line, text.to_string()));
+            }
+            Ok(())
+        }
+    }
+
+    /// limit of nested includes and macro expansions.
+    const MAX_NESTING: usize = 16;
+
+    #[derive(Debug)]
+    struct Macro {
+        params: Vec<String>,
+        body: Vec<String>,
+    }
+
+    enum Item<'a> {
+        Instruction {
+            opcode: u8,
+            operand: I8080Operand,
+            expression: Option<&'a str>,
+        },
+        Bytes(Vec<&'a str>),
+        Words(Vec<&'a str>),
+        Space(u16),
+    }
+
+    struct Statement<'a> {
+        line: usize,
+        address: u16,
+        /// from the origin, not wrapping as `address` would.
+        offset: usize,
+        item: Item<'a>,
+    }
+
+    /// A symbol value. Relocatable ones are offsets from the start of an [`Object`].
+    #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+    pub struct Symbol {
+        pub value: u16,
+        pub relocatable: bool,
+    }
+
+    /// Output of [`I8080Assembler::assemble_object`], assembled as if loaded at 0.
+    #[derive(Debug, Default, Clone, Eq, PartialEq)]
+    pub struct Object {
+        pub code: Vec<u8>,
+        /// `PUBLIC` symbols by upper-case name.
+        pub publics: BTreeMap<String, Symbol>,
+        /// offsets of words to add the load address to.
+        pub relocations: Vec<usize>,
+        /// offsets of words to add the address of an `EXTRN` symbol to.
+        pub externals: Vec<(usize, String)>,
+    }
+
+    /// Places `objects` one after another from `origin`, resolving relocations and externals.
+    pub fn link(objects: &[Object], origin: u16) -> Result<Vec<u8>, LinkError> {
+        let mut bases = Vec::new();
+        let mut publics = BTreeMap::new();
+        let mut base = origin;
+        for object in objects {
+            for (name, symbol) in &object.publics {
+                let address = match symbol.relocatable {
+                    true => symbol.value.wrapping_add(base),
+                    false => symbol.value,
+                };
+                if publics.insert(name.clone(), address).is_some() {
+                    return Err(LinkError::DuplicatePublic(name.clone()));
+                }
+            }
+            bases.push(base);
+            base = base.wrapping_add(object.code.len() as u16);
+        }
+        let mut bytes = Vec::new();
+        for (object, base) in objects.iter().zip(bases) {
+            let mut code = object.code.clone();
+            let mut add = |offset: usize, value: u16| {
+                let word = u16::from_le_bytes([code[offset], code[offset + 1]]);
+                code[offset..offset + 2].copy_from_slice(&word.wrapping_add(value).to_le_bytes());
+            };
+            for &offset in &object.relocations {
+                add(offset, base);
+            }
+            for (offset, name) in &object.externals {
+                let address = publics
+                    .get(name)
+                    .ok_or_else(|| LinkError::UndefinedExternal(name.clone()))?;
+                add(*offset, *address);
+            }
+            bytes.extend(code);
+        }
+        Ok(bytes)
+    }
+
+    impl Assembler for I8080Assembler {
+        type Data = u8;
+
+        fn assemble(&self, source: &str) -> Result<Vec<u8>, AsmError> {
+            self.assemble_at(source, self.origin, false)
+                .map(|object| object.code)
+        }
+    }
+
+    impl I8080Assembler {
+        /// Assembles `source` into a relocatable [`Object`] to be [`link`]ed, ignoring the origin.
+        /// `PUBLIC name, ...` exports symbols and `EXTRN name, ...` imports them from other objects.
+        pub fn assemble_object(&self, source: &str) -> Result<Object, AsmError> {
+            self.assemble_at(source, 0, true)
+        }
+
+        fn assemble_at(
+            &self,
+            source: &str,
+            origin: u16,
+            relocatable: bool,
+        ) -> Result<Object, AsmError> {
+            let mut lines = Vec::new();
+            let mut macros = BTreeMap::new();
+            self.preprocess(source, None, &mut macros, &mut Vec::new(), 0, &mut lines)?;
+            let mut symbols = Symbols::default();
+            let mut publics = Vec::new();
+            let mut statements = Vec::new();
+            let mut address = origin;
+            // `address` not wrapped, up to 0x10000 when the code reaches the top
+            let mut location = origin as usize;
+            for (line, text) in &lines {
+                let line = *line;
+                let (label, body) = split_label(text);
+                if let Some((name, expression)) = named_directive(label, body, "EQU") {
+                    let value = symbols.evaluate(expression, address, line)?;
+                    let symbol = Symbol {
+                        value: as_word(value.value, line)?,
+                        relocatable: value.relocation != 0,
+                    };
+                    // an address, or a plain number such as the difference of two
+                    let relocates = matches!(value.relocation, 0 | 1);
+                    if value.external.is_some() || relocatable && !relocates {
+                        return Err(invalid_relocation(line, expression));
+                    }
+                    symbols.define(name, symbol, line)?;
+                    continue;
+                }
+                if let Some(label) = label {
+                    let symbol = Symbol {
+                        value: address,
+                        relocatable: true,
+                    };
+                    symbols.define(label, symbol, line)?;
+                }
+                if body.is_empty() {
+                    continue;
+                }
+                let (mnemonic, operands) = split_operands(body);
+                let argument = skip_word(body);
+                let absolute = |value: Value| match value.external {
+                    Some(_) => Err(invalid_relocation(line, argument)),
+                    None => as_word(value.value, line),
+                };
+                let item = match mnemonic.to_ascii_uppercase().as_str() {
+                    "ORG" => {
+                        let value = absolute(symbols.evaluate(argument, address, line)?)?;
+                        if (value as usize) < location {
+                            return Err(AsmError::OutOfRange {
+                                line,
+                                value: value as i64,
+                            });
+                        }
+                        address = value;
+                        location = value as usize;
+                        continue;
+                    }
+                    "PUBLIC" => {
+                        publics.extend(operands.into_iter().map(|name| (line, name)));
+                        continue;
+                    }
+                    "EXTRN" => {
+                        for name in operands {
+                            symbols.declare_external(name, line)?;
+                        }
+                        continue;
+                    }
+                    "DS" => Item::Space(absolute(symbols.evaluate(argument, address, line)?)?),
+                    "DB" => Item::Bytes(operands),
+                    "DW" => Item::Words(operands),
+                    _ => {
+                        let (opcode, operand, expression) =
+                            match_opcode(body).ok_or_else(|| AsmError::UnknownInstruction {
+                                line,
+                                text: body.to_string(),
+                            })?;
+                        Item::Instruction {
+                            opcode,
+                            operand,
+                            expression,
+                        }
+                    }
+                };
+                let size = match &item {
+                    Item::Instruction { operand, .. } => 1 + operand.size(),
+                    Item::Bytes(items) => items
+                        .iter()
+                        .map(|item| string_literal(item).map_or(1, str::len))
+                        .sum(),
+                    Item::Words(items) => 2 * items.len(),
+                    Item::Space(size) => *size as usize,
+                };
+                if location + size > 0x10000 {
+                    return Err(AsmError::AddressOverflow { line });
+                }
+                statements.push(Statement {
+                    line,
+                    address,
+                    offset: location - origin as usize,
+                    item,
+                });
+                location += size;
+                address = location as u16;
+            }
+            let mut object = Object::default();
+            for (line, name) in publics {
+                let symbol = symbols.get(name).ok_or_else(|| AsmError::UndefinedSymbol {
+                    line,
+                    name: name.to_string(),
+                })?;
+                object.publics.insert(name.to_ascii_uppercase(), symbol);
+            }
+            for st in statements {
+                let bytes = &mut object.code;
+                bytes.resize(st.offset, 0);
+                let line = st.line;
+                let byte = |expression| {
+                    let value = symbols.evaluate(expression, st.address, line)?;
+                    match value.external {
+                        Some(name) if !relocatable => {
+                            return Err(AsmError::UndefinedSymbol { line, name })
+                        }
+                        Some(_) => return Err(invalid_relocation(line, expression)),
+                        None if relocatable && value.relocation != 0 => {
+                            return Err(invalid_relocation(line, expression))
+                        }
+                        None => {}
+                    }
+                    as_byte(value.value, line)
+                };
+                let word = |object: &mut Object, expression| {
+                    let value = symbols.evaluate(expression, st.address, line)?;
+                    let offset = object.code.len();
+                    match value.external {
+                        Some(name) if !relocatable => {
+                            return Err(AsmError::UndefinedSymbol { line, name })
+                        }
+                        Some(name) => object.externals.push((offset, name)),
+                        None => {}
+                    }
+                    match value.relocation {
+                        _ if !relocatable => {}
+                        0 => {}
+                        1 => object.relocations.push(offset),
+                        _ => return Err(invalid_relocation(line, expression)),
+                    }
+                    let word = as_word(value.value, line)?;
+                    object.code.extend(word.to_le_bytes());
+                    Ok(())
+                };
+                match st.item {
+                    Item::Instruction {
+                        opcode,
+                        operand,
+                        expression,
+                    } => {
+                        bytes.push(opcode);
+                        let Some(expression) = expression else {
+                            continue;
+                        };
+                        match operand {
+                            I8080Operand::Byte => bytes.push(byte(expression)?),
+                            I8080Operand::Word => word(&mut object, expression)?,
+                            I8080Operand::Implied => unreachable!(),
+                        }
+                    }
+                    Item::Bytes(items) => {
+                        for item in items {
+                            match string_literal(item) {
+                                Some(s) => bytes.extend(s.bytes()),
+                                None => bytes.push(byte(item)?),
+                            }
+                        }
+                    }
+                    Item::Words(items) => {
+                        for item in items {
+                            word(&mut object, item)?;
+                        }
+                    }
+                    Item::Space(size) => bytes.resize(bytes.len() + size as usize, 0),
+                }
+            }
+            Ok(object)
+        }
+    }
+
+    fn invalid_relocation(line: usize, text: &str) -> AsmError {
+        AsmError::InvalidRelocation {
+            line,
+            text: text.to_string(),
+        }
+    }
+
+    /// value of an expression. `relocation` counts how many times the load address is added,
+    /// and `external` is an `EXTRN` symbol whose address is to be added.
+    #[derive(Debug, Default)]
+    struct Value {
+        value: i64,
+        relocation: i64,
+        external: Option<String>,
+    }
+
+    #[derive(Debug, Default)]
+    struct Symbols {
+        symbols: BTreeMap<String, Symbol>,
+        externals: BTreeSet<String>,
+    }
+
+    impl Symbols {
+        fn get(&self, name: &str) -> Option<Symbol> {
+            self.symbols.get(&name.to_ascii_uppercase()).copied()
+        }
+
+        fn define(&mut self, name: &str, symbol: Symbol, line: usize) -> Result<(), AsmError> {
+            let key = name.to_ascii_uppercase();
+            if self.externals.contains(&key) || self.symbols.insert(key, symbol).is_some() {
+                return Err(AsmError::DuplicateLabel {
+                    line,
+                    name: name.to_string(),
+                });
+            }
+            Ok(())
+        }
+
+        fn declare_external(&mut self, name: &str, line: usize) -> Result<(), AsmError> {
+            let key = name.to_ascii_uppercase();
+            if !is_identifier(name) || self.symbols.contains_key(&key) {
+                return Err(AsmError::DuplicateLabel {
+                    line,
+                    name: name.to_string(),
+                });
+            }
+            self.externals.insert(key);
+            Ok(())
+        }
+
+        /// evaluates `[+|-] term [(+|-) term]...`.
+        fn evaluate(&self, expression: &str, address: u16, line: usize) -> Result<Value, AsmError> {
+            let invalid = || AsmError::InvalidExpression {
+                line,
+                text: expression.to_string(),
+            };
+            let mut value = Value::default();
+            let mut sign = 1;
+            let mut rest = expression.trim();
+            if let Some(r) = rest.strip_prefix('-') {
+                sign = -1;
+                rest = r.trim_start();
+            }
+            loop {
+                let (term, tail) = rest.split_at(term_end(rest));
+                let term = self
+                    .evaluate_term(term.trim(), address, line)?
+                    .ok_or_else(invalid)?;
+                value.value += sign * term.value;
+                value.relocation += sign * term.relocation;
+                if let Some(name) = term.external {
+                    if sign < 0 || value.external.is_some() {
+                        return Err(invalid_relocation(line, expression));
+                    }
+                    value.external = Some(name);
+                }
+                let mut tail = tail.trim_start().chars();
+                sign = match tail.next() {
+                    None => return Ok(value),
+                    Some('+') => 1,
+                    Some('-') => -1,
+                    Some(_) => return Err(invalid()),
+                };
+                rest = tail.as_str().trim_start();
+            }
+        }
+
+        /// `Ok(None)` if `term` is not a valid term.
+        fn evaluate_term(
+            &self,
+            term: &str,
+            address: u16,
+            line: usize,
+        ) -> Result<Option<Value>, AsmError> {
+            let absolute = |value| Value {
+                value,
+                ..Value::default()
+            };
+            if term == "$" {
+                return Ok(Some(Value {
+                    value: address as i64,
+                    relocation: 1,
+                    external: None,
+                }));
+            }
+            if let Some(c) = term.strip_prefix('\'').and_then(|t| t.strip_suffix('\'')) {
+                let mut chars = c.chars();
+                return Ok(match (chars.next(), chars.next()) {
+                    (Some(c), None) if c.is_ascii() => Some(absolute(c as i64)),
+                    _ => None,
+                });
+            }
+            if term.starts_with(|c: char| c.is_ascii_digit()) {
+                return Ok(parse_number(term).map(absolute));
+            }
+            if !is_identifier(term) {
+                return Ok(None);
+            }
+            let key = term.to_ascii_uppercase();
+            if self.externals.contains(&key) {
+                return Ok(Some(Value {
+                    external: Some(key),
+                    ..Value::default()
+                }));
+            }
+            match self.symbols.get(&key) {
+                Some(symbol) => Ok(Some(Value {
+                    value: symbol.value as i64,
+                    relocation: symbol.relocatable as i64,
+                    external: None,
+                })),
+                None => Err(AsmError::UndefinedSymbol {
+                    line,
+                    name: term.to_string(),
+                }),
+            }
+        }
+    }
+
+    fn as_byte(value: i64, line: usize) -> Result<u8, AsmError> {
+        if (-0x80..=0xff).contains(&value) {
+            Ok(value as u8)
+        } else {
+            Err(AsmError::OutOfRange { line, value })
+        }
+    }
+
+    fn as_word(value: i64, line: usize) -> Result<u16, AsmError> {
+        if (-0x8000..=0xffff).contains(&value) {
+            Ok(value as u16)
+        } else {
+            Err(AsmError::OutOfRange { line, value })
+        }
+    }
+
+    /// the text after the first word.
+    fn skip_word(text: &str) -> &str {
+        text.split_once(char::is_whitespace)
+            .map_or("", |(_, rest)| rest.trim_start())
+    }
+
+    /// `(name, rest)` of `name DIRECTIVE rest` or `name: DIRECTIVE rest`.
+    fn named_directive<'a>(
+        label: Option<&'a str>,
+        body: &'a str,
+        directive: &str,
+    ) -> Option<(&'a str, &'a str)> {
+        let (name, body) = match label {
+            Some(label) => (label, body),
+            None => body.split_once(char::is_whitespace)?,
+        };
+        let body = body.trim_start();
+        let matched = split_operands(body).0.eq_ignore_ascii_case(directive) && is_identifier(name);
+        matched.then(|| (name, skip_word(body)))
+    }
+
+    /// contents of a quoted string of two or more characters.
+    fn string_literal(item: &str) -> Option<&str> {
+        let s = item.strip_prefix('\'')?.strip_suffix('\'')?;
+        (s.len() > 1).then_some(s)
+    }
+
+    /// replaces identifiers in `text` that are one of `params` with the corresponding `args`.
+    fn substitute(text: &str, params: &[String], args: &[&str]) -> String {
+        let mut res = String::new();
+        let mut quoted = false;
+        let mut rest = text;
+        while let Some(c) = rest.chars().next() {
+            if c == '\'' {
+                quoted = !quoted;
+            }
+            if quoted || !(c.is_ascii_alphabetic() || c == '_') {
+                res.push(c);
+                rest = &rest[c.len_utf8()..];
+                continue;
+            }
+            let end = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            let (word, tail) = rest.split_at(end);
+            match params.iter().position(|p| p.eq_ignore_ascii_case(word)) {
+                Some(i) => res.push_str(args[i]),
+                None => res.push_str(word),
+            }
+            rest = tail;
+        }
+        res
+    }
+
+    fn strip_comment(text: &str) -> &str {
+        let mut quoted = false;
+        for (i, c) in text.char_indices() {
+            match c {
+                '\'' => quoted = !quoted,
+                ';' if !quoted => return text[..i].trim(),
+                _ => {}
+            }
+        }
+        text.trim()
+    }
+
+    fn split_label(text: &str) -> (Option<&str>, &str) {
+        match text.split_once(':') {
+            Some((label, body)) if is_identifier(label) => (Some(label), body.trim()),
+            _ => (None, text),
+        }
+    }
+
+    fn is_identifier(text: &str) -> bool {
+        let mut chars = text.chars();
+        matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+            && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+    }
+
+    /// splits `MNEMONIC op1, op2` into the mnemonic and trimmed operands.
+    fn split_operands(text: &str) -> (&str, Vec<&str>) {
+        match text.split_once(char::is_whitespace) {
+            Some((mnemonic, rest)) => (mnemonic, split_list(rest)),
+            None => (text, Vec::new()),
+        }
+    }
+
+    /// splits at commas outside quotes.
+    fn split_list(text: &str) -> Vec<&str> {
+        let mut res = Vec::new();
+        let mut quoted = false;
+        let mut start = 0;
+        for (i, c) in text.char_indices() {
+            match c {
+                '\'' => quoted = !quoted,
+                ',' if !quoted => {
+                    res.push(text[start..i].trim());
+                    start = i + 1;
+                }
+                _ => {}
+            }
+        }
+        res.push(text[start..].trim());
+        res
+    }
+
+    /// finds the opcode whose fixed operands match, leaving the expression operand if any.
+    fn match_opcode(body: &str) -> Option<(u8, I8080Operand, Option<&str>)> {
+        let (mnemonic, operands) = split_operands(body);
+        I8080_OPCODES.iter().enumerate().find_map(|(opcode, op)| {
+            let op = (*op)?;
+            let (template, fixed) = split_operands(op.mnemonic.trim_end());
+            let fixed: Vec<&str> = fixed.into_iter().filter(|s| !s.is_empty()).collect();
+            let arity = fixed.len() + op.operand.size().min(1);
+            let matched = template.eq_ignore_ascii_case(mnemonic)
+                && operands.len() == arity
+                && fixed
+                    .iter()
+                    .zip(&operands)
+                    .all(|(a, b)| a.eq_ignore_ascii_case(b));
+            matched.then(|| (opcode as u8, op.operand, operands.get(fixed.len()).copied()))
+        })
+    }
+
+    /// a character literal may contain `+` or `-`.
+    fn term_end(text: &str) -> usize {
+        if let Some(literal) = text.strip_prefix('\'') {
+            return literal.find('\'').map_or(text.len(), |i| i + 2);
+        }
+        text.find(['+', '-']).unwrap_or(text.len())
+    }
+
+    pub(crate) fn parse_number(text: &str) -> Option<i64> {
+        let lower = text.to_ascii_lowercase();
+        if let Some(hex) = lower.strip_prefix("0x") {
+            i64::from_str_radix(hex, 16).ok()
+        } else if let Some(hex) = lower.strip_suffix('h') {
+            i64::from_str_radix(hex, 16).ok()
+        } else if let Some(bin) = lower.strip_suffix('b') {
+            i64::from_str_radix(bin, 2).ok()
+        } else {
+            lower.parse().ok()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::typical::*;
+    use super::*;
+    use crate::disasm::typical::I8080Disassembler;
+    use crate::disasm::Disassembler;
+    use crate::memory::typical::Memory8Bit64KB;
+    use crate::memory::Memory;
+    use alloc::vec;
+
+    #[test]
+    fn assemble() {
+        let source = "
+            ; scan a string for a newline
+                    LXI H, 8000H
+            loop:   MOV A,M     ; next char
+                    INX H
+                    CPI 0AH
+                    JNZ loop
+                    MVI b, 'a'
+                    HLT
+        ";
+        let bytes = I8080Assembler::default().assemble(source).unwrap();
+        assert_eq!(
+            bytes,
+            vec![0x21, 0x00, 0x80, 0x7e, 0x23, 0xfe, 0x0a, 0xc2, 0x03, 0x00, 0x06, 0x61, 0x76]
+        );
+    }
+
+    #[test]
+    fn expressions() {
+        let asm = I8080Assembler::new(0x100);
+        assert_eq!(
+            asm.assemble("start: JMP start + 3\nLXI SP, $ - 1\nMVI A, -1\nADI 0x10\nORI 101b"),
+            Ok(vec![
+                0xc3, 0x03, 0x01, 0x31, 0x02, 0x01, 0x3e, 0xff, 0xc6, 0x10, 0xf6, 0x05
+            ])
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let asm = I8080Assembler::default();
+        assert_eq!(
+            asm.assemble("NOP\nMOV A,Q"),
+            Err(AsmError::UnknownInstruction {
+                line: 2,
+                text: "MOV A,Q".to_string()
+            })
+        );
+        assert_eq!(
+            asm.assemble("JMP nowhere"),
+            Err(AsmError::UndefinedSymbol {
+                line: 1,
+                name: "nowhere".to_string()
+            })
+        );
+        assert_eq!(
+            asm.assemble("a: NOP\na: NOP"),
+            Err(AsmError::DuplicateLabel {
+                line: 2,
+                name: "a".to_string()
+            })
+        );
+        assert_eq!(
+            asm.assemble("MVI A, 256"),
+            Err(AsmError::OutOfRange {
+                line: 1,
+                value: 256
+            })
+        );
+        assert_eq!(
+            asm.assemble("MVI A, 1 +"),
+            Err(AsmError::InvalidExpression {
+                line: 1,
+                text: "1 +".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let bytes: Vec<u8> = (0..=255u8)
+            .flat_map(|opcode| [opcode, 0x34, 0x12])
+            .collect();
+        let listing = I8080Disassembler.disassemble_all(&bytes);
+        let source: Vec<String> = listing
+            .into_iter()
+            .filter(|(_, text)| !text.starts_with("DB"))
+            .map(|(_, text)| text)
+            .collect();
+        let asm = I8080Assembler::default();
+        for line in source {
+            let bytes = asm.assemble(&line).unwrap();
+            assert_eq!(I8080Disassembler.disassemble(&bytes).unwrap().0, line);
+        }
+    }
+
+    #[test]
+    fn assemble_into() {
+        let mut memory = Memory8Bit64KB::default();
+        let len = I8080Assembler::new(0x100)
+            .assemble_into("MVI A, 5\nHLT", &mut memory)
+            .unwrap();
+        assert_eq!(len, 3);
+        assert_eq!(memory.read(0x100), 0x3e);
+        assert_eq!(memory.read(0x101), 0x05);
+        assert_eq!(memory.read(0x102), 0x76);
+    }
+
+    #[test]
+    fn directives() {
+        let source = "
+            CR      EQU 0DH
+            count:  EQU 2 + 1
+                    ORG 0103H
+            start:  MVI C, count
+                    JMP data
+                    DS 2
+            data:   DB 'hi', CR, 'a', 0
+                    DW start, data + 1
+        ";
+        let bytes = I8080Assembler::new(0x100).assemble(source).unwrap();
+        assert_eq!(
+            bytes,
+            vec![
+                0, 0, 0, // ORG padding
+                0x0e, 0x03, 0xc3, 0x0a, 0x01, 0, 0, b'h', b'i', 0x0d, b'a', 0, 0x03, 0x01, 0x0b,
+                0x01,
+            ]
+        );
+        assert_eq!(
+            I8080Assembler::new(0x100).assemble("ORG 0FFH"),
+            Err(AsmError::OutOfRange {
+                line: 1,
+                value: 0xff
+            })
+        );
+        // up to the top, but not past it
+        let top = I8080Assembler::default().assemble("ORG 0FFFEH\nDW 1\nDS 0");
+        assert_eq!(top.map(|bytes| bytes.len()), Ok(0x10000));
+        assert_eq!(
+            I8080Assembler::default().assemble("ORG 0FFFEH\nDS 4"),
+            Err(AsmError::AddressOverflow { line: 2 })
+        );
+        assert_eq!(
+            I8080Assembler::default().assemble("ORG 0FFFFH\nNOP\nORG 0"),
+            Err(AsmError::OutOfRange { line: 3, value: 0 })
+        );
+        assert_eq!(
+            I8080Assembler::default().assemble("x EQU y\ny EQU 1"),
+            Err(AsmError::UndefinedSymbol {
+                line: 1,
+                name: "y".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn include() {
+        let asm = I8080Assembler::default()
+            .include("putc.asm", "putc: OUT 1\nRET")
+            .include("lib.asm", "INCLUDE putc.asm\nexit: HLT");
+        let bytes = asm
+            .assemble("MVI A, 'x'\nCALL putc\nJMP exit\nINCLUDE \"lib.asm\"")
+            .unwrap();
+        assert_eq!(
+            bytes,
+            vec![0x3e, b'x', 0xcd, 0x08, 0x00, 0xc3, 0x0b, 0x00, 0xd3, 0x01, 0xc9, 0x76]
+        );
+        assert_eq!(
+            asm.assemble("NOP\nINCLUDE missing.asm"),
+            Err(AsmError::IncludeNotFound {
+                line: 2,
+                name: "missing.asm".to_string()
+            })
+        );
+        let recursive = I8080Assembler::default()
+            .include("nop", "NOP")
+            .include("self", "INCLUDE self")
+            .include("a", "INCLUDE b")
+            .include("b", "NOP\nINCLUDE a");
+        assert_eq!(
+            recursive.assemble("INCLUDE self"),
+            Err(AsmError::RecursiveInclude {
+                line: 1,
+                name: "self".to_string()
+            })
+        );
+        assert!(matches!(
+            recursive.assemble("NOP\nINCLUDE a"),
+            Err(AsmError::RecursiveInclude { line: 2, .. })
+        ));
+        // the same file twice is not recursion
+        assert_eq!(
+            recursive.assemble("INCLUDE nop\nINCLUDE nop"),
+            Ok(vec![0, 0])
+        );
+    }
+
+    #[test]
+    fn macros() {
+        let source = "
+            print   MACRO port, char
+                    MVI A, char  ; port is substituted, 'port' is not
+                    OUT port
+                    ENDM
+            halt    MACRO
+                    HLT
+                    ENDM
+            start:  print 2, 'p'
+                    print 3, 'port'
+                    halt
+        ";
+        let bytes = I8080Assembler::default().assemble(source);
+        assert!(matches!(
+            bytes,
+            Err(AsmError::InvalidExpression { line: 10, .. })
+        ));
+        let source = source.replace("'port'", "'q'");
+        let bytes = I8080Assembler::default().assemble(&source).unwrap();
+        assert_eq!(
+            bytes,
+            vec![0x3e, b'p', 0xd3, 0x02, 0x3e, b'q', 0xd3, 0x03, 0x76]
+        );
+        assert_eq!(
+            I8080Assembler::default().assemble("m MACRO\nNOP"),
+            Err(AsmError::UnterminatedMacro {
+                line: 1,
+                name: "m".to_string()
+            })
+        );
+        assert!(matches!(
+            I8080Assembler::default().assemble("m MACRO a\nENDM\nm 1, 2"),
+            Err(AsmError::InvalidDirective { line: 3, .. })
+        ));
+    }
+
+    #[test]
+    fn link() {
+        let runtime = "
+                    PUBLIC  putc, cr
+            cr      EQU     0DH
+            putc:   OUT     1
+                    RET
+        ";
+        let main = "
+                    EXTRN   putc
+                    PUBLIC  main
+            main:   MVI     A, 'A'
+                    CALL    putc
+                    JMP     main
+                    DW      putc + 1, msg
+            msg:    DB      'ok'
+        ";
+        let assembler = I8080Assembler::default();
+        let runtime = assembler.assemble_object(runtime).unwrap();
+        let main = assembler.assemble_object(main).unwrap();
+        assert_eq!(
+            runtime.publics["PUTC"],
+            Symbol {
+                value: 0,
+                relocatable: true
+            }
+        );
+        assert_eq!(
+            runtime.publics["CR"],
+            Symbol {
+                value: 0x0d,
+                relocatable: false
+            }
+        );
+        assert_eq!(main.relocations, vec![6, 10]);
+        assert_eq!(
+            main.externals,
+            vec![(3, "PUTC".to_string()), (8, "PUTC".to_string())]
+        );
+        let bytes = typical::link(&[main.clone(), runtime.clone()], 0x0100).unwrap();
+        assert_eq!(
+            bytes,
+            vec![
+                0x3e, b'A', 0xcd, 0x0e, 0x01, 0xc3, 0x00, 0x01, 0x0f, 0x01, 0x0c, 0x01, b'o', b'k',
+                0xd3, 0x01, 0xc9
+            ]
+        );
+        assert_eq!(
+            typical::link(core::slice::from_ref(&main), 0),
+            Err(LinkError::UndefinedExternal("PUTC".to_string()))
+        );
+        assert!(matches!(
+            typical::link(&[runtime.clone(), runtime], 0),
+            Err(LinkError::DuplicatePublic(_))
+        ));
+        assert_eq!(
+            assembler.assemble_object("here: MVI A, here"),
+            Err(AsmError::InvalidRelocation {
+                line: 1,
+                text: "here".to_string()
+            })
+        );
+        assert_eq!(
+            assembler.assemble_object("here: NOP\nback EQU 0 - here"),
+            Err(AsmError::InvalidRelocation {
+                line: 2,
+                text: "0 - here".to_string()
+            })
+        );
+        assert_eq!(
+            assembler.assemble("EXTRN putc\nCALL putc"),
+            Err(AsmError::UndefinedSymbol {
+                line: 2,
+                name: "PUTC".to_string()
+            })
+        );
+    }
+}
@@ -0,0 +1,1118 @@
+use crate::memory::{Memory, MemoryError};
+use alloc::collections::VecDeque;
+use alloc::rc::Rc;
+use alloc::{boxed::Box, format, string::String, string::ToString, vec::Vec};
+use core::cell::RefCell;
+use core::fmt;
+use core::ops::{RangeInclusive, Sub};
+
+/// A peripheral mapped onto a [`Bus`]. Offsets are relative to the start of its range.
+///
+/// Unlike [`Memory`], reading may change the device, e.g. clearing a status flag.
+pub trait Device {
+    type Address;
+    type Data;
+    fn read(&mut self, offset: Self::Address) -> Self::Data;
+    fn write(&mut self, offset: Self::Address, data: Self::Data);
+    /// advances the device by `cycles` CPU cycles.
+    fn tick(&mut self, _cycles: u64) {}
+    /// whether the device is asserting its interrupt line.
+    fn interrupt(&self) -> bool {
+        false
+    }
+    /// like `write`, but fails where the write would be lost.
+    fn try_write(
+        &mut self,
+        offset: Self::Address,
+        data: Self::Data,
+    ) -> Result<(), MemoryError<Self::Address>> {
+        self.write(offset, data);
+        Ok(())
+    }
+    /// puts the device in its state after the reset line is asserted.
+    fn reset(&mut self) {}
+    /// what the device puts on the data bus when its interrupt is acknowledged,
+    /// e.g. an RST opcode for I8080.
+    fn acknowledge(&mut self) -> Option<Self::Data> {
+        None
+    }
+    /// a name for machine descriptions, the type name by default.
+    fn name(&self) -> String {
+        short_type_name::<Self>()
+    }
+    /// what the device is, for [`Bus::memory_map`].
+    fn kind(&self) -> RegionKind {
+        RegionKind::Io
+    }
+    /// the bank currently selected, for devices switching banks.
+    fn bank(&self) -> Option<usize> {
+        None
+    }
+    /// whether writes take effect, e.g. false for ROM.
+    fn writable(&self) -> bool {
+        true
+    }
+    /// cycles between a write at `offset` and its effect, e.g. until the next scanline
+    /// for a palette. The [`Bus`] holds such writes back until due.
+    fn write_latency(&self, _offset: Self::Address) -> u64 {
+        0
+    }
+    /// the layout [`Device::save_state`] writes, raised whenever fields are appended to it.
+    fn state_version(&self) -> u16 {
+        0
+    }
+    /// appends what the device holds beyond its registers, e.g. counters and phases,
+    /// little endian. A new version only appends fields to the previous one.
+    fn save_state(&self, _state: &mut Vec<u8>) {}
+    /// restores a state saved at `version`. Fields a newer version appended are ignored,
+    /// and those an older version lacks keep their values.
+    fn load_state(&mut self, _version: u16, _state: &[u8]) -> Result<(), StateError> {
+        Ok(())
+    }
+}
+
+/// What a device saved of itself, as [`Bus::save_state`] collects it.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct DeviceState {
+    pub name: String,
+    pub version: u16,
+    pub bytes: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum StateError {
+    /// a state shorter than its version has it.
+    Truncated,
+    /// states saved from a bus with a different number of devices.
+    Count { expected: usize, found: usize },
+    /// a state saved from another device than the one mapped at `index`.
+    Device {
+        index: usize,
+        expected: String,
+        found: String,
+    },
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StateError::Truncated => write!(f, "truncated device state"),
+            StateError::Count { expected, found } => {
+                write!(f, "{} device states for {} devices", found, expected)
+            }
+            StateError::Device {
+                index,
+                expected,
+                found,
+            } => write!(
+                f,
+                "device {}: state of {} loaded into {}",
+                index, found, expected
+            ),
+        }
+    }
+}
+
+impl core::error::Error for StateError {}
+
+/// Reads the fields of a device state in order, for [`Device::load_state`].
+#[derive(Debug)]
+pub struct StateReader<'a>(&'a [u8]);
+
+impl<'a> StateReader<'a> {
+    pub fn new(state: &'a [u8]) -> Self {
+        Self(state)
+    }
+
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], StateError> {
+        let (field, rest) = self.0.split_first_chunk().ok_or(StateError::Truncated)?;
+        self.0 = rest;
+        Ok(*field)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, StateError> {
+        self.take().map(u8::from_le_bytes)
+    }
+
+    pub fn u16(&mut self) -> Result<u16, StateError> {
+        self.take().map(u16::from_le_bytes)
+    }
+
+    pub fn u32(&mut self) -> Result<u32, StateError> {
+        self.take().map(u32::from_le_bytes)
+    }
+
+    pub fn u64(&mut self) -> Result<u64, StateError> {
+        self.take().map(u64::from_le_bytes)
+    }
+
+    pub fn bool(&mut self) -> Result<bool, StateError> {
+        self.u8().map(|byte| byte != 0)
+    }
+}
+
+/// What answers in a region of a [`Bus`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum RegionKind {
+    Ram,
+    Rom,
+    /// registers of a peripheral.
+    Io,
+}
+
+/// A range of addresses answered by one device, as [`Bus::memory_map`] finds it.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct MapEntry {
+    pub start: u64,
+    pub end: u64,
+    pub device: String,
+    pub kind: RegionKind,
+    pub bank: Option<usize>,
+    pub writable: bool,
+}
+
+/// `core::any::type_name` without the module path and generic arguments.
+pub(crate) fn short_type_name<T: ?Sized>() -> String {
+    let name = core::any::type_name::<T>();
+    let name = name.split('<').next().unwrap_or(name);
+    name.rsplit("::").next().unwrap_or(name).to_string()
+}
+
+/// A device shared with the rest of the machine, e.g. VRAM also read by the display.
+impl<T: Device> Device for Rc<RefCell<T>> {
+    type Address = T::Address;
+    type Data = T::Data;
+    fn read(&mut self, offset: T::Address) -> T::Data {
+        self.borrow_mut().read(offset)
+    }
+    fn write(&mut self, offset: T::Address, data: T::Data) {
+        self.borrow_mut().write(offset, data)
+    }
+    fn tick(&mut self, cycles: u64) {
+        self.borrow_mut().tick(cycles)
+    }
+    fn interrupt(&self) -> bool {
+        self.borrow().interrupt()
+    }
+    fn try_write(
+        &mut self,
+        offset: T::Address,
+        data: T::Data,
+    ) -> Result<(), MemoryError<T::Address>> {
+        self.borrow_mut().try_write(offset, data)
+    }
+    fn reset(&mut self) {
+        self.borrow_mut().reset()
+    }
+    fn acknowledge(&mut self) -> Option<T::Data> {
+        self.borrow_mut().acknowledge()
+    }
+    fn name(&self) -> String {
+        self.borrow().name()
+    }
+    fn kind(&self) -> RegionKind {
+        self.borrow().kind()
+    }
+    fn bank(&self) -> Option<usize> {
+        self.borrow().bank()
+    }
+    fn writable(&self) -> bool {
+        self.borrow().writable()
+    }
+    fn write_latency(&self, offset: T::Address) -> u64 {
+        self.borrow().write_latency(offset)
+    }
+    fn state_version(&self) -> u16 {
+        self.borrow().state_version()
+    }
+    fn save_state(&self, state: &mut Vec<u8>) {
+        self.borrow().save_state(state)
+    }
+    fn load_state(&mut self, version: u16, state: &[u8]) -> Result<(), StateError> {
+        self.borrow_mut().load_state(version, state)
+    }
+}
+
+type BoxedDevice<A, D> = RefCell<Box<dyn Device<Address = A, Data = D>>>;
+
+/// Memory built of devices mapped onto address ranges.
+///
+/// A later mapping takes precedence where ranges overlap, so ROM can be mapped over RAM.
+/// Unmapped addresses read as the open bus value and ignore writes.
+pub struct Bus<A, D> {
+    regions: Vec<(RangeInclusive<A>, BoxedDevice<A, D>)>,
+    open_bus: D,
+    /// cycles ticked since power on.
+    now: u64,
+    /// writes held back by [`Device::write_latency`], by when they are due.
+    scheduled: VecDeque<Scheduled<A, D>>,
+}
+
+#[derive(Debug)]
+struct Scheduled<A, D> {
+    due: u64,
+    region: usize,
+    offset: A,
+    data: D,
+}
+
+impl<A, D: Default> Default for Bus<A, D> {
+    fn default() -> Self {
+        Self::new(D::default())
+    }
+}
+
+impl<A, D> Bus<A, D> {
+    pub fn new(open_bus: D) -> Self {
+        Self {
+            regions: Vec::new(),
+            open_bus,
+            now: 0,
+            scheduled: VecDeque::new(),
+        }
+    }
+
+    pub fn map<T>(&mut self, range: RangeInclusive<A>, device: T) -> &mut Self
+    where
+        T: Device<Address = A, Data = D> + 'static,
+    {
+        self.regions.push((range, RefCell::new(Box::new(device))));
+        self
+    }
+
+    pub fn map_memory<M>(&mut self, range: RangeInclusive<A>, memory: M) -> &mut Self
+    where
+        M: Memory<Address = A, Data = D> + 'static,
+    {
+        self.map(range, typical::MemoryDevice::new(memory))
+    }
+
+    pub fn map_rom<M>(&mut self, range: RangeInclusive<A>, memory: M) -> &mut Self
+    where
+        M: Memory<Address = A, Data = D> + 'static,
+    {
+        self.map(range, typical::Rom::new(memory))
+    }
+
+    pub fn ranges(&self) -> impl Iterator<Item = &RangeInclusive<A>> {
+        self.regions.iter().map(|(range, _)| range)
+    }
+
+    /// the ranges in mapping order, with the names of their devices.
+    pub fn devices(&self) -> impl Iterator<Item = (&RangeInclusive<A>, String)> {
+        self.regions
+            .iter()
+            .map(|(range, device)| (range, device.borrow().name()))
+    }
+
+    /// the map as the CPU currently sees it, in address order: a later mapping hides
+    /// what it overlaps, and unmapped gaps are left out.
+    pub fn memory_map(&self) -> Vec<MapEntry>
+    where
+        A: Copy + Into<u64>,
+    {
+        let bounds = |range: &RangeInclusive<A>| {
+            (
+                (*range.start()).into() as u128,
+                (*range.end()).into() as u128 + 1,
+            )
+        };
+        let mut edges: Vec<u128> = self
+            .ranges()
+            .flat_map(|range| {
+                let (start, end) = bounds(range);
+                [start, end]
+            })
+            .collect();
+        edges.sort_unstable();
+        edges.dedup();
+        let mut map: Vec<(u128, u128, usize)> = Vec::new();
+        for pair in edges.windows(2) {
+            let (start, end) = (pair[0], pair[1]);
+            let top = self.regions.iter().rposition(|(range, _)| {
+                let (s, e) = bounds(range);
+                s <= start && end <= e
+            });
+            match (top, map.last_mut()) {
+                (Some(i), Some(last)) if last.2 == i && last.1 == start => last.1 = end,
+                (Some(i), _) => map.push((start, end, i)),
+                (None, _) => {}
+            }
+        }
+        map.into_iter()
+            .map(|(start, end, i)| {
+                let device = self.regions[i].1.borrow();
+                MapEntry {
+                    start: start as u64,
+                    end: (end - 1) as u64,
+                    device: device.name(),
+                    kind: device.kind(),
+                    bank: device.bank(),
+                    writable: device.writable(),
+                }
+            })
+            .collect()
+    }
+
+    /// advances every device by `cycles` CPU cycles, making the writes held back take
+    /// effect at the cycles they are due.
+    pub fn tick(&mut self, cycles: u64) {
+        let end = self.now + cycles;
+        while self.scheduled.front().is_some_and(|write| write.due <= end) {
+            let write = self.scheduled.pop_front().unwrap();
+            self.tick_devices(write.due - self.now);
+            self.now = write.due;
+            self.regions[write.region]
+                .1
+                .get_mut()
+                .write(write.offset, write.data);
+        }
+        self.tick_devices(end - self.now);
+        self.now = end;
+    }
+
+    fn tick_devices(&mut self, cycles: u64) {
+        if cycles == 0 {
+            return;
+        }
+        for (_, device) in &mut self.regions {
+            device.get_mut().tick(cycles)
+        }
+    }
+
+    /// writes held back by [`Device::write_latency`] and not yet due.
+    pub fn scheduled(&self) -> usize {
+        self.scheduled.len()
+    }
+
+    /// resets every device, dropping the writes held back.
+    pub fn reset(&mut self) {
+        self.scheduled.clear();
+        for (_, device) in &mut self.regions {
+            device.get_mut().reset()
+        }
+    }
+
+    /// the states of the devices, in mapping order, without the writes still held back.
+    pub fn save_state(&self) -> Vec<DeviceState> {
+        self.regions
+            .iter()
+            .map(|(_, device)| {
+                let device = device.borrow();
+                let mut bytes = Vec::new();
+                device.save_state(&mut bytes);
+                DeviceState {
+                    name: device.name(),
+                    version: device.state_version(),
+                    bytes,
+                }
+            })
+            .collect()
+    }
+
+    /// restores the states [`Bus::save_state`] saved from a bus mapped the same way,
+    /// loading none of them unless every device matches.
+    pub fn load_state(&mut self, states: &[DeviceState]) -> Result<(), StateError> {
+        if states.len() != self.regions.len() {
+            return Err(StateError::Count {
+                expected: self.regions.len(),
+                found: states.len(),
+            });
+        }
+        for (index, ((_, device), state)) in self.regions.iter().zip(states).enumerate() {
+            let name = device.borrow().name();
+            if name != state.name {
+                return Err(StateError::Device {
+                    index,
+                    expected: name,
+                    found: state.name.clone(),
+                });
+            }
+        }
+        for ((_, device), state) in self.regions.iter_mut().zip(states) {
+            device.get_mut().load_state(state.version, &state.bytes)?
+        }
+        Ok(())
+    }
+
+    /// whether any device is asserting its interrupt line.
+    pub fn interrupt(&self) -> bool {
+        self.regions
+            .iter()
+            .any(|(_, device)| device.borrow().interrupt())
+    }
+
+    /// acknowledges the interrupt of the first device asserting its line,
+    /// returning what it puts on the data bus.
+    pub fn acknowledge(&mut self) -> Option<D> {
+        self.regions
+            .iter_mut()
+            .map(|(_, device)| device.get_mut())
+            .find(|device| device.interrupt())
+            .and_then(|device| device.acknowledge())
+    }
+}
+
+impl<A, D> Bus<A, D>
+where
+    A: Copy + PartialOrd + Sub<Output = A>,
+{
+    fn find(&self, address: A) -> Option<(A, &BoxedDevice<A, D>)> {
+        self.regions
+            .iter()
+            .rev()
+            .find(|(range, _)| range.contains(&address))
+            .map(|(range, device)| (address - *range.start(), device))
+    }
+
+    /// holds the write back if the device has a latency at `offset`, returning whether it did.
+    fn schedule(&mut self, address: A, data: D) -> bool {
+        let Some(region) = self
+            .regions
+            .iter()
+            .rposition(|(range, _)| range.contains(&address))
+        else {
+            return false;
+        };
+        let (range, device) = &self.regions[region];
+        let offset = address - *range.start();
+        let latency = device.borrow().write_latency(offset);
+        if latency == 0 {
+            return false;
+        }
+        let due = self.now + latency;
+        // after those due at the same cycle, so writes keep their order
+        let at = self.scheduled.partition_point(|write| write.due <= due);
+        self.scheduled.insert(
+            at,
+            Scheduled {
+                due,
+                region,
+                offset,
+                data,
+            },
+        );
+        true
+    }
+}
+
+impl<A, D> Memory for Bus<A, D>
+where
+    A: Copy + PartialOrd + Sub<Output = A>,
+    D: Copy,
+{
+    type Address = A;
+    type Data = D;
+    fn read(&self, address: A) -> D {
+        match self.find(address) {
+            Some((offset, device)) => device.borrow_mut().read(offset),
+            None => self.open_bus,
+        }
+    }
+    fn store(&mut self, address: A, data: D) {
+        if self.schedule(address, data) {
+            return;
+        }
+        if let Some((offset, device)) = self.find(address) {
+            device.borrow_mut().write(offset, data)
+        }
+    }
+    fn try_read(&self, address: A) -> Result<D, MemoryError<A>> {
+        match self.find(address) {
+            Some((offset, device)) => Ok(device.borrow_mut().read(offset)),
+            None => Err(MemoryError::Unmapped(address)),
+        }
+    }
+    fn try_store(&mut self, address: A, data: D) -> Result<(), MemoryError<A>> {
+        if self.schedule(address, data) {
+            return Ok(());
+        }
+        match self.find(address) {
+            Some((offset, device)) => device
+                .borrow_mut()
+                .try_write(offset, data)
+                .map_err(|e| e.at(address)),
+            None => Err(MemoryError::Unmapped(address)),
+        }
+    }
+}
+
+impl<A: fmt::Debug, D: fmt::Debug> fmt::Debug for Bus<A, D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Bus")
+            .field("ranges", &self.ranges().collect::<Vec<_>>())
+            .field("open_bus", &self.open_bus)
+            .finish()
+    }
+}
+
+pub mod typical {
+    use super::*;
+    use crate::debug::Access;
+    use crate::signal::Line;
+    use alloc::collections::BTreeMap;
+
+    /// RAM, VRAM or anything else that simply holds its data.
+    #[derive(Debug, Default)]
+    pub struct MemoryDevice<M> {
+        pub memory: M,
+    }
+
+    impl<M> MemoryDevice<M> {
+        pub fn new(memory: M) -> Self {
+            Self { memory }
+        }
+    }
+
+    impl<M: Memory> Device for MemoryDevice<M> {
+        type Address = M::Address;
+        type Data = M::Data;
+        fn read(&mut self, offset: M::Address) -> M::Data {
+            self.memory.read(offset)
+        }
+        fn write(&mut self, offset: M::Address, data: M::Data) {
+            self.memory.store(offset, data)
+        }
+        fn name(&self) -> String {
+            "RAM".to_string()
+        }
+        fn kind(&self) -> RegionKind {
+            RegionKind::Ram
+        }
+    }
+
+    /// Memory that ignores writes.
+    #[derive(Debug, Default)]
+    pub struct Rom<M> {
+        pub memory: M,
+    }
+
+    impl<M> Rom<M> {
+        pub fn new(memory: M) -> Self {
+            Self { memory }
+        }
+    }
+
+    impl<M: Memory> Device for Rom<M> {
+        type Address = M::Address;
+        type Data = M::Data;
+        fn read(&mut self, offset: M::Address) -> M::Data {
+            self.memory.read(offset)
+        }
+        fn write(&mut self, _offset: M::Address, _data: M::Data) {}
+        fn try_write(
+            &mut self,
+            offset: M::Address,
+            _data: M::Data,
+        ) -> Result<(), MemoryError<M::Address>> {
+            Err(MemoryError::ReadOnly(offset))
+        }
+        fn name(&self) -> String {
+            "ROM".to_string()
+        }
+        fn kind(&self) -> RegionKind {
+            RegionKind::Rom
+        }
+        fn writable(&self) -> bool {
+            false
+        }
+    }
+
+    /// Banks of memory sharing a range, the one answering chosen by the `select` line,
+    /// e.g. driven by a port register. An out of range selection reads as the last bank.
+    ///
+    /// A switch changes the memory under a [`crate::cache::DecodeCache`] without a write
+    /// it could see; give it the line with [`crate::cache::DecodeCache::clear_on`].
+    #[derive(Debug)]
+    pub struct BankedMemory<M> {
+        banks: Vec<M>,
+        select: Line<usize>,
+        writable: bool,
+    }
+
+    impl<M> BankedMemory<M> {
+        pub fn new(banks: Vec<M>, select: Line<usize>) -> Self {
+            assert!(!banks.is_empty());
+            Self {
+                banks,
+                select,
+                writable: true,
+            }
+        }
+
+        /// banks of ROM, ignoring writes.
+        pub fn rom(banks: Vec<M>, select: Line<usize>) -> Self {
+            Self {
+                writable: false,
+                ..Self::new(banks, select)
+            }
+        }
+
+        pub fn banks(&self) -> &[M] {
+            &self.banks
+        }
+
+        fn selected(&self) -> usize {
+            self.select.get().min(self.banks.len() - 1)
+        }
+    }
+
+    impl<M: Memory> Device for BankedMemory<M> {
+        type Address = M::Address;
+        type Data = M::Data;
+        fn read(&mut self, offset: M::Address) -> M::Data {
+            self.banks[self.selected()].read(offset)
+        }
+        fn write(&mut self, offset: M::Address, data: M::Data) {
+            if self.writable {
+                let bank = self.selected();
+                self.banks[bank].store(offset, data)
+            }
+        }
+        fn try_write(
+            &mut self,
+            offset: M::Address,
+            data: M::Data,
+        ) -> Result<(), MemoryError<M::Address>> {
+            if !self.writable {
+                return Err(MemoryError::ReadOnly(offset));
+            }
+            self.write(offset, data);
+            Ok(())
+        }
+        fn name(&self) -> String {
+            match self.writable {
+                true => "RAM".to_string(),
+                false => "ROM".to_string(),
+            }
+        }
+        fn kind(&self) -> RegionKind {
+            match self.writable {
+                true => RegionKind::Ram,
+                false => RegionKind::Rom,
+            }
+        }
+        fn bank(&self) -> Option<usize> {
+            Some(self.selected())
+        }
+        fn writable(&self) -> bool {
+            self.writable
+        }
+    }
+
+    #[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+    pub struct StubAccesses {
+        pub reads: u64,
+        pub writes: u64,
+    }
+
+    /// The first access to an offset of a [`StubDevice`].
+    #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+    pub struct StubNotice<A> {
+        /// cycles the device had been ticked by then.
+        pub time: u64,
+        pub offset: A,
+        pub access: Access,
+    }
+
+    /// Stands in for an unimplemented device, reading as `value` and ignoring writes.
+    ///
+    /// The first access to each offset is noted, unless quiet, for the frontend to take with
+    /// [`StubDevice::take_notices`]. Notes are rate limited, by default to
+    /// [`StubDevice::RATE_LIMIT`], and those over the limit are only counted, so that a
+    /// program probing every port does not flood the frontend. Every access is counted
+    /// for [`StubDevice::report`], to show what a machine still lacks.
+    #[derive(Debug, Clone)]
+    pub struct StubDevice<A, D> {
+        name: String,
+        value: D,
+        quiet: bool,
+        accesses: BTreeMap<A, StubAccesses>,
+        notices: Vec<StubNotice<A>>,
+        /// notices let through per window of cycles.
+        limit: (usize, u64),
+        time: u64,
+        window: (u64, usize),
+        suppressed: u64,
+    }
+
+    impl<A: Ord + Copy + fmt::LowerHex, D: Copy> StubDevice<A, D> {
+        /// notices, per cycles: 8 per second at 4 MHz.
+        pub const RATE_LIMIT: (usize, u64) = (8, 4_000_000);
+
+        pub fn new(name: &str, value: D) -> Self {
+            Self {
+                name: name.to_string(),
+                value,
+                quiet: false,
+                accesses: BTreeMap::new(),
+                notices: Vec::new(),
+                limit: Self::RATE_LIMIT,
+                time: 0,
+                window: (0, 0),
+                suppressed: 0,
+            }
+        }
+
+        pub fn quiet(mut self, quiet: bool) -> Self {
+            self.quiet = quiet;
+            self
+        }
+
+        /// lets through at most `notices` notices in any window of `cycles` cycles.
+        pub fn rate_limit(mut self, notices: usize, cycles: u64) -> Self {
+            self.limit = (notices, cycles);
+            self
+        }
+
+        pub fn accesses(&self) -> &BTreeMap<A, StubAccesses> {
+            &self.accesses
+        }
+
+        /// the notices since the last call, oldest first.
+        pub fn take_notices(&mut self) -> Vec<StubNotice<A>> {
+            core::mem::take(&mut self.notices)
+        }
+
+        /// how many notices the rate limit has dropped.
+        pub fn suppressed(&self) -> u64 {
+            self.suppressed
+        }
+
+        /// one line per accessed offset.
+        pub fn report(&self) -> String {
+            self.accesses
+                .iter()
+                .map(|(offset, a)| {
+                    format!(
+                        "{} +{:#x}: {} reads, {} writes\n",
+                        self.name, offset, a.reads, a.writes
+                    )
+                })
+                .collect()
+        }
+
+        fn access(&mut self, offset: A, access: Access) -> &mut StubAccesses {
+            if !self.quiet && !self.accesses.contains_key(&offset) {
+                self.notice(offset, access);
+            }
+            self.accesses.entry(offset).or_default()
+        }
+
+        fn notice(&mut self, offset: A, access: Access) {
+            let (notices, cycles) = self.limit;
+            if self.time - self.window.0 >= cycles {
+                self.window = (self.time, 0);
+            }
+            match self.window.1 < notices {
+                true => {
+                    self.window.1 += 1;
+                    self.notices.push(StubNotice {
+                        time: self.time,
+                        offset,
+                        access,
+                    });
+                }
+                false => self.suppressed += 1,
+            }
+        }
+    }
+
+    impl<A: Ord + Copy + fmt::LowerHex, D: Copy> Device for StubDevice<A, D> {
+        type Address = A;
+        type Data = D;
+        fn read(&mut self, offset: A) -> D {
+            self.access(offset, Access::Read).reads += 1;
+            self.value
+        }
+        fn write(&mut self, offset: A, _data: D) {
+            self.access(offset, Access::Write).writes += 1;
+        }
+        fn tick(&mut self, cycles: u64) {
+            self.time = self.time.saturating_add(cycles);
+        }
+        fn name(&self) -> String {
+            self.name.clone()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::typical::Memory8Bit64KB;
+    use alloc::vec;
+
+    /// counts reads, like a status port cleared on read.
+    #[derive(Debug, Default)]
+    struct Counter {
+        reads: u8,
+        last: u8,
+    }
+
+    impl Device for Counter {
+        type Address = u16;
+        type Data = u8;
+        fn read(&mut self, _offset: u16) -> u8 {
+            self.reads += 1;
+            self.reads
+        }
+        fn write(&mut self, offset: u16, data: u8) {
+            self.last = data.wrapping_add(offset as u8);
+        }
+    }
+
+    #[test]
+    fn dispatch() {
+        let counter = Rc::new(RefCell::new(Counter::default()));
+        let mut bus = Bus::new(0xff);
+        bus.map_memory(0x0000..=0xffff, Memory8Bit64KB::default())
+            .map_rom(0x0000..=0x00ff, Memory8Bit64KB::new(&[0xc3, 0x00, 0x01]))
+            .map(0xe000..=0xe001, counter.clone());
+        assert_eq!(bus.read(0x0000), 0xc3);
+        bus.store(0x0000, 0x00);
+        assert_eq!(bus.read(0x0000), 0xc3);
+        bus.store(0x0100, 0x12);
+        assert_eq!(bus.read(0x0100), 0x12);
+        assert_eq!(bus.read(0xe000), 1);
+        assert_eq!(bus.read(0xe001), 2);
+        bus.store(0xe001, 0x10);
+        assert_eq!(counter.borrow().last, 0x11);
+        assert_eq!(bus.ranges().count(), 3);
+    }
+
+    #[test]
+    fn memory_map() {
+        use crate::signal::Line;
+        use typical::BankedMemory;
+
+        let select = Line::new(0);
+        let mut bus = Bus::new(0xff);
+        bus.map_memory(0x0000..=0xffff, Memory8Bit64KB::default())
+            .map(
+                0x0000..=0x7fff,
+                BankedMemory::rom(vec![Memory8Bit64KB::new(&[0x00]); 2], select.clone()),
+            )
+            .map(0xe000..=0xe001, Counter::default());
+        let entry = |start, end, device: &str, kind, bank, writable| MapEntry {
+            start,
+            end,
+            device: device.to_string(),
+            kind,
+            bank,
+            writable,
+        };
+        let ram = |start, end| entry(start, end, "RAM", RegionKind::Ram, None, true);
+        select.set(1);
+        assert_eq!(
+            bus.memory_map(),
+            [
+                entry(0x0000, 0x7fff, "ROM", RegionKind::Rom, Some(1), false),
+                ram(0x8000, 0xdfff),
+                entry(0xe000, 0xe001, "Counter", RegionKind::Io, None, true),
+                ram(0xe002, 0xffff),
+            ]
+        );
+    }
+
+    /// requests an interrupt with RST 7 until acknowledged.
+    #[derive(Debug, Default)]
+    struct Requester(bool);
+
+    impl Device for Requester {
+        type Address = u16;
+        type Data = u8;
+        fn read(&mut self, _offset: u16) -> u8 {
+            0xff
+        }
+        fn write(&mut self, _offset: u16, _data: u8) {
+            self.0 = true;
+        }
+        fn interrupt(&self) -> bool {
+            self.0
+        }
+        fn acknowledge(&mut self) -> Option<u8> {
+            self.0 = false;
+            Some(0xff)
+        }
+    }
+
+    #[test]
+    fn acknowledge() {
+        let mut bus = Bus::new(0xff);
+        bus.map_memory(0x0000..=0xffff, Memory8Bit64KB::default())
+            .map(0xe000..=0xe000, Requester::default());
+        assert_eq!(bus.acknowledge(), None);
+        bus.store(0xe000, 0);
+        assert!(bus.interrupt());
+        assert_eq!(bus.acknowledge(), Some(0xff));
+        assert!(!bus.interrupt());
+    }
+
+    #[test]
+    fn open_bus() {
+        let mut bus: Bus<u16, u8> = Bus::new(0xff);
+        bus.map_memory(0x8000..=0xffff, Memory8Bit64KB::default());
+        bus.store(0x1000, 0x12);
+        assert_eq!(bus.read(0x1000), 0xff);
+        bus.store(0x8000, 0x12);
+        assert_eq!(bus.read(0x8000), 0x12);
+    }
+
+    #[test]
+    fn fallible() {
+        let mut bus: Bus<u16, u8> = Bus::new(0xff);
+        bus.map_memory(0x8000..=0xffff, Memory8Bit64KB::default())
+            .map_rom(0xf000..=0xffff, Memory8Bit64KB::default());
+        assert_eq!(bus.try_read(0x1000), Err(MemoryError::Unmapped(0x1000)));
+        assert_eq!(bus.try_store(0x1000, 0), Err(MemoryError::Unmapped(0x1000)));
+        assert_eq!(bus.try_store(0xf001, 0), Err(MemoryError::ReadOnly(0xf001)));
+        assert_eq!(bus.try_store(0x8001, 0x12), Ok(()));
+        assert_eq!(bus.try_read(0x8001), Ok(0x12));
+    }
+
+    #[test]
+    fn stub() {
+        use typical::StubDevice;
+        let stub = Rc::new(RefCell::new(StubDevice::new("crtc", 0xff).quiet(true)));
+        let mut bus = Bus::default();
+        bus.map(0x0050..=0x0051, stub.clone());
+        assert_eq!(bus.read(0x0051), 0xff);
+        bus.read(0x0051);
+        bus.store(0x0050, 0x00);
+        let stub = stub.borrow();
+        assert_eq!(stub.accesses()[&1].reads, 2);
+        assert_eq!(stub.accesses()[&0].writes, 1);
+        assert_eq!(
+            stub.report(),
+            "crtc +0x0: 0 reads, 1 writes\ncrtc +0x1: 2 reads, 0 writes\n"
+        );
+    }
+
+    #[test]
+    fn stub_notices() {
+        use crate::debug::Access;
+        use typical::{StubDevice, StubNotice};
+
+        let mut stub = StubDevice::<u16, u8>::new("opn", 0xff).rate_limit(2, 100);
+        for offset in 0..4 {
+            stub.read(offset);
+        }
+        stub.write(0, 0x00);
+        stub.tick(100);
+        stub.write(4, 0x00);
+        let notice = |time, offset, access| StubNotice {
+            time,
+            offset,
+            access,
+        };
+        assert_eq!(
+            stub.take_notices(),
+            [
+                notice(0, 0, Access::Read),
+                notice(0, 1, Access::Read),
+                notice(100, 4, Access::Write),
+            ]
+        );
+        assert_eq!(stub.suppressed(), 2);
+        assert!(stub.take_notices().is_empty());
+    }
+
+    #[test]
+    fn write_latency() {
+        /// a palette taking a write 10 cycles later, and counting the cycles it was
+        /// ticked with the value written.
+        #[derive(Debug, Default)]
+        struct Palette {
+            color: u8,
+            shown: u64,
+        }
+        impl Device for Palette {
+            type Address = u16;
+            type Data = u8;
+            fn read(&mut self, offset: u16) -> u8 {
+                match offset {
+                    0 => self.color,
+                    _ => self.shown as u8,
+                }
+            }
+            fn write(&mut self, _offset: u16, data: u8) {
+                self.color = data;
+                self.shown = 0;
+            }
+            fn tick(&mut self, cycles: u64) {
+                self.shown += cycles;
+            }
+            fn write_latency(&self, offset: u16) -> u64 {
+                match offset {
+                    0 => 10,
+                    _ => 0,
+                }
+            }
+        }
+        let mut bus = Bus::new(0xff);
+        bus.map(0x00..=0x01, Palette::default());
+        bus.store(0x00, 0x01);
+        bus.tick(4);
+        bus.store(0x00, 0x02);
+        assert_eq!(bus.scheduled(), 2);
+        bus.tick(5);
+        assert_eq!(bus.read(0x00), 0x00);
+        // each write lands at its cycle within a tick
+        bus.tick(4);
+        assert_eq!((bus.read(0x00), bus.read(0x01)), (0x01, 3));
+        bus.tick(2);
+        assert_eq!((bus.read(0x00), bus.read(0x01)), (0x02, 1));
+        assert_eq!(bus.scheduled(), 0);
+        bus.store(0x00, 0x03);
+        bus.reset();
+        bus.tick(100);
+        assert_eq!(bus.read(0x00), 0x02);
+    }
+
+    #[test]
+    fn state() {
+        use crate::typical::timer::Timer;
+        let mut bus = Bus::new(0xff);
+        bus.map(0x00..=0x03, Timer::new())
+            .map(0x10..=0x10, Counter::default());
+        bus.store(0x00, 0x34);
+        bus.store(0x02, Timer::ENABLE);
+        let states = bus.save_state();
+        assert_eq!(
+            states[0],
+            DeviceState {
+                name: "Timer".into(),
+                version: 0,
+                bytes: vec![0x34, 0x00, 0x34, 0x00, 0x00, 0x00, 0x01, 0x00],
+            }
+        );
+        assert_eq!(states[1].bytes, []);
+        bus.store(0x00, 0x00);
+        bus.store(0x02, 0x00);
+        bus.load_state(&states).unwrap();
+        assert_eq!((bus.read(0x00), bus.read(0x02)), (0x34, 0x01));
+
+        let mut swapped = Bus::new(0xff);
+        swapped
+            .map(0x10..=0x10, Counter::default())
+            .map(0x00..=0x03, Timer::new());
+        assert_eq!(
+            swapped.load_state(&states),
+            Err(StateError::Device {
+                index: 0,
+                expected: "Counter".into(),
+                found: "Timer".into()
+            })
+        );
+        let mut timer = Timer::new();
+        assert_eq!(
+            timer.load_state(0, &states[0].bytes[..7]),
+            Err(StateError::Truncated)
+        );
+        // fields appended by a newer version are ignored
+        let mut newer = states[0].bytes.clone();
+        newer.push(0x55);
+        assert_eq!(timer.load_state(1, &newer), Ok(()));
+    }
+}
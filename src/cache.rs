@@ -0,0 +1,228 @@
+use crate::cpu::CPU;
+use crate::instruction::{DecodeResult, InstructionDecoder};
+use crate::memory::Memory;
+use crate::observer::ExecutionObserver;
+use crate::register::RegisterIncrementable;
+use crate::signal::Line;
+use alloc::collections::BTreeMap;
+use alloc::rc::Rc;
+use alloc::vec::Vec;
+use core::cell::Cell;
+
+/// Decoded instructions by the address of their first word, so that loops are decoded once.
+///
+/// Entries are dropped when a word they were decoded from is written, keeping
+/// self-modifying code correct. [`crate::machine::Machine::step_cached`] runs from it and
+/// attaches it as the observer of the CPU's writes; call [`DecodeCache::invalidate`] for
+/// writes by other bus masters, and [`DecodeCache::clear_on`] the lines switching banks.
+#[derive(Debug)]
+pub struct DecodeCache<I> {
+    entries: BTreeMap<u64, Cached<I>>,
+    /// the longest instruction cached, bounding the entries a write may hit.
+    longest: u64,
+    /// the last instruction wrapping around the address space, not cached.
+    wrapped: Option<Cached<I>>,
+    hits: u64,
+    misses: u64,
+    /// set by the lines of [`DecodeCache::clear_on`] on a change.
+    stale: Rc<Cell<bool>>,
+}
+
+#[derive(Debug)]
+struct Cached<I> {
+    instruction: I,
+    len: u64,
+}
+
+impl<I> Default for DecodeCache<I> {
+    fn default() -> Self {
+        Self {
+            entries: BTreeMap::new(),
+            longest: 0,
+            wrapped: None,
+            hits: 0,
+            misses: 0,
+            stale: Rc::new(Cell::new(false)),
+        }
+    }
+}
+
+impl<I> DecodeCache<I> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// the instruction at `pc` with its length in words, decoding it on a miss.
+    /// Fails with the offending word if it does not decode.
+    pub fn fetch<C, M, D>(
+        &mut self,
+        memory: &M,
+        pc: M::Address,
+        decoder: &mut D,
+    ) -> Result<(&I, u64), M::Data>
+    where
+        M: Memory,
+        M::Address: Copy + Into<u64> + RegisterIncrementable,
+        D: InstructionDecoder<C, Instruction = I, InstructionSize = M::Data>,
+    {
+        if self.stale.replace(false) {
+            self.clear();
+        }
+        let start = pc.into();
+        if self.entries.contains_key(&start) {
+            self.hits += 1;
+        } else {
+            self.misses += 1;
+            decoder.reset();
+            let mut address = pc;
+            let mut len = 0;
+            let mut wrapped = false;
+            let instruction = loop {
+                let data = memory.read(address);
+                wrapped |= address.overflowing_increment();
+                len += 1;
+                match decoder.decode(data) {
+                    DecodeResult::Incomplete => continue,
+                    DecodeResult::Complete(instruction) => break instruction,
+                    DecodeResult::Invalid(data) => return Err(data),
+                }
+            };
+            let cached = Cached { instruction, len };
+            // one wrapping around the address space would escape invalidation; keep it aside.
+            if wrapped {
+                let cached = self.wrapped.insert(cached);
+                return Ok((&cached.instruction, cached.len));
+            }
+            self.longest = self.longest.max(len);
+            self.entries.insert(start, cached);
+        }
+        let cached = &self.entries[&start];
+        Ok((&cached.instruction, cached.len))
+    }
+
+    /// drops the instructions decoded from `address`.
+    pub fn invalidate<A: Into<u64>>(&mut self, address: A) {
+        let address = address.into();
+        let first = address.saturating_sub(self.longest.saturating_sub(1));
+        let stale: Vec<u64> = self
+            .entries
+            .range(first..=address)
+            .filter(|(&start, cached)| start + cached.len > address)
+            .map(|(&start, _)| start)
+            .collect();
+        for start in stale {
+            self.entries.remove(&start);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// clears the cache before the next fetch whenever a dispatch finds `line` changed,
+    /// e.g. the select line of a [`crate::bus::typical::BankedMemory`] the code runs from.
+    pub fn clear_on<T: Clone + 'static>(&self, line: &Line<T>) {
+        let stale = self.stale.clone();
+        line.subscribe(move |_| stale.set(true));
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    pub fn misses(&self) -> u64 {
+        self.misses
+    }
+}
+
+impl<C, I> ExecutionObserver<C> for DecodeCache<I>
+where
+    C: CPU,
+    C::Address: Into<u64>,
+{
+    fn on_memory_write(&mut self, address: C::Address, _data: C::Data) {
+        self.invalidate(address);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::tests::CPU8;
+    use crate::instruction::typical::{DecoderBuilder, Nop};
+    use crate::instruction::{DynInstruction, Operand};
+    use crate::memory::typical::Memory8Bit64KB;
+    use alloc::boxed::Box;
+    use alloc::rc::Rc;
+    use core::cell::Cell;
+
+    #[test]
+    fn cache() {
+        let decoded = Rc::new(Cell::new(0));
+        let counter = decoded.clone();
+        let mut decoder = DecoderBuilder::<DynInstruction<()>>::new()
+            .map_all(0x00..=0xff, Operand::Imm16, move |_, _| {
+                counter.set(counter.get() + 1);
+                Box::new(Nop)
+            })
+            .build();
+        let mut memory = Memory8Bit64KB::new(&[0x01, 0x34, 0x12, 0x01, 0x00, 0x00]);
+        let mut cache = DecodeCache::new();
+        for _ in 0..3 {
+            let (_, len) = cache.fetch(&memory, 0x0000, &mut decoder).unwrap();
+            assert_eq!(len, 3);
+            cache.fetch(&memory, 0x0003, &mut decoder).unwrap();
+        }
+        assert_eq!((decoded.get(), cache.hits(), cache.misses()), (2, 4, 2));
+        // patching the operand of the first decodes it again, leaving the second
+        memory.store(0x0002, 0x56);
+        ExecutionObserver::<CPU8>::on_memory_write(&mut cache, 0x0002, 0x56);
+        assert_eq!(cache.len(), 1);
+        cache.fetch(&memory, 0x0000, &mut decoder).unwrap();
+        cache.fetch(&memory, 0x0003, &mut decoder).unwrap();
+        assert_eq!(decoded.get(), 3);
+        // the end of the address space is never cached
+        cache.fetch(&memory, 0xffff, &mut decoder).unwrap();
+        cache.fetch(&memory, 0xffff, &mut decoder).unwrap();
+        assert_eq!((decoded.get(), cache.len()), (5, 2));
+    }
+
+    #[test]
+    fn bank_switch() {
+        use crate::bus::typical::BankedMemory;
+        use crate::bus::Bus;
+        use alloc::vec;
+
+        let decoded = Rc::new(Cell::new(0));
+        let counter = decoded.clone();
+        let mut decoder = DecoderBuilder::<DynInstruction<()>>::new()
+            .map_all(0x00..=0xff, Operand::None, move |_, _| {
+                counter.set(counter.get() + 1);
+                Box::new(Nop)
+            })
+            .build();
+        let select = Line::new(0);
+        let banks = vec![Memory8Bit64KB::new(&[0x00]), Memory8Bit64KB::new(&[0x76])];
+        let mut bus = Bus::default();
+        bus.map(0x0000..=0xffff, BankedMemory::new(banks, select.clone()));
+        let mut cache = DecodeCache::new();
+        cache.clear_on(&select);
+        cache.fetch(&bus, 0x0000, &mut decoder).unwrap();
+        cache.fetch(&bus, 0x0000, &mut decoder).unwrap();
+        // a switch is seen once dispatched, between instructions
+        select.set(1);
+        cache.fetch(&bus, 0x0000, &mut decoder).unwrap();
+        assert_eq!(decoded.get(), 1);
+        select.dispatch();
+        cache.fetch(&bus, 0x0000, &mut decoder).unwrap();
+        assert_eq!((decoded.get(), cache.hits(), cache.misses()), (2, 2, 2));
+    }
+}
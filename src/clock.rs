@@ -0,0 +1,169 @@
+use core::time::Duration;
+
+/// A CPU clock, converting between cycles and emulated time.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Clock {
+    hz: u64,
+}
+
+impl Clock {
+    /// PC-8801 in 4 MHz mode.
+    pub const PC8801_4MHZ: Self = Self { hz: 3_993_600 };
+    /// PC-8801 mkII SR and later in 8 MHz mode.
+    pub const PC8801_8MHZ: Self = Self { hz: 7_987_200 };
+
+    /// a clock of `hz`, `None` for 0 Hz, which never runs a cycle.
+    pub const fn new(hz: u64) -> Option<Self> {
+        match hz {
+            0 => None,
+            hz => Some(Self { hz }),
+        }
+    }
+
+    pub fn hz(&self) -> u64 {
+        self.hz
+    }
+
+    /// cycles in a frame of `frames_per_second`, rounded down.
+    pub fn cycles_per_frame(&self, frames_per_second: f64) -> u64 {
+        (self.hz as f64 / frames_per_second) as u64
+    }
+
+    /// cycles elapsed in `duration`, rounded down.
+    pub fn cycles_in(&self, duration: Duration) -> u64 {
+        (duration.as_nanos() * self.hz as u128 / 1_000_000_000) as u64
+    }
+
+    /// emulated time taken by `cycles`.
+    pub fn duration_of(&self, cycles: u64) -> Duration {
+        Duration::from_nanos((cycles as u128 * 1_000_000_000 / self.hz as u128) as u64)
+    }
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Self::PC8801_4MHZ
+    }
+}
+
+/// How fast emulated time runs against real time.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Speed {
+    ratio: Option<f64>,
+}
+
+impl Speed {
+    pub const REAL_TIME: Self = Self { ratio: Some(1.0) };
+    /// as fast as the host runs, never waiting.
+    pub const UNLIMITED: Self = Self { ratio: None };
+
+    /// `ratio` emulated seconds per real second, above 1 to fast-forward and below
+    /// to slow down; `None` unless positive and finite.
+    pub fn new(ratio: f64) -> Option<Self> {
+        (ratio > 0.0 && ratio.is_finite()).then_some(Self { ratio: Some(ratio) })
+    }
+
+    /// the ratio, `None` if unlimited.
+    pub fn ratio(&self) -> Option<f64> {
+        self.ratio
+    }
+
+    /// the real time `cycles` of `clock` take at this speed, `None` if unlimited.
+    pub fn real_time(&self, clock: Clock, cycles: u64) -> Option<Duration> {
+        self.ratio
+            .map(|ratio| clock.duration_of(cycles).div_f64(ratio))
+    }
+}
+
+impl Default for Speed {
+    fn default() -> Self {
+        Self::REAL_TIME
+    }
+}
+
+/// Waits for real time to catch up with a machine, as the frontend paces it: sleeping,
+/// waiting for the vertical sync, or for room in the audio output.
+pub trait Pacer {
+    /// waits until `due` of real time has passed since pacing started.
+    fn wait(&mut self, due: Duration);
+}
+
+/// Paces by sleeping the thread. When real time runs ahead by more than
+/// [`Sleep::MAX_LAG`], as after a slow frame on the host, it stops catching up
+/// instead of racing through the frames missed.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone)]
+pub struct Sleep {
+    start: std::time::Instant,
+}
+
+#[cfg(feature = "std")]
+impl Sleep {
+    pub const MAX_LAG: Duration = Duration::from_millis(100);
+
+    /// pacing from now.
+    pub fn new() -> Self {
+        Self {
+            start: std::time::Instant::now(),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Default for Sleep {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "std")]
+impl Pacer for Sleep {
+    fn wait(&mut self, due: Duration) {
+        let now = self.start.elapsed();
+        match due.checked_sub(now) {
+            Some(ahead) => std::thread::sleep(ahead),
+            None if now - due > Self::MAX_LAG => self.start += now - due,
+            None => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conversions() {
+        let clock = Clock::PC8801_8MHZ;
+        assert_eq!(clock.cycles_in(Duration::from_secs(1)), 7_987_200);
+        assert_eq!(clock.cycles_in(Duration::from_millis(1)), 7_987);
+        assert_eq!(clock.duration_of(7_987_200), Duration::from_secs(1));
+        assert_eq!(Clock::PC8801_4MHZ.cycles_per_frame(60.0), 66_560);
+        assert_eq!(clock.cycles_per_frame(60.0), 2 * 66_560);
+        assert_eq!(
+            Clock::new(2_000_000).unwrap().duration_of(1),
+            Duration::from_nanos(500)
+        );
+        assert_eq!(Clock::new(0), None);
+    }
+
+    #[test]
+    fn speed() {
+        let clock = Clock::new(1_000_000).unwrap();
+        assert_eq!(
+            Speed::REAL_TIME.real_time(clock, 1_000),
+            Some(Duration::from_millis(1))
+        );
+        let fast = Speed::new(2.0).unwrap();
+        assert_eq!(
+            fast.real_time(clock, 1_000),
+            Some(Duration::from_micros(500))
+        );
+        let slow = Speed::new(0.25).unwrap();
+        assert_eq!(slow.real_time(clock, 1_000), Some(Duration::from_millis(4)));
+        assert_eq!(Speed::UNLIMITED.real_time(clock, 1_000), None);
+        assert_eq!(Speed::new(0.0), None);
+        assert_eq!(Speed::new(f64::INFINITY), None);
+        assert_eq!(Speed::new(f64::NAN), None);
+    }
+}
@@ -0,0 +1,160 @@
+use crate::cpu::CPU;
+use crate::observer::ExecutionObserver;
+use alloc::collections::BTreeSet;
+use alloc::{string::String, vec::Vec};
+use core::fmt::Write;
+use core::ops::RangeInclusive;
+
+/// The addresses fetched as code at least once, opcodes and operands alike,
+/// to check how much of a test ROM ran or find the routines a program uses.
+///
+/// Run the machine with it, by [`Machine::run_observed`] or [`Machine::step_observed`].
+///
+/// [`Machine::run_observed`]: crate::machine::Machine::run_observed
+/// [`Machine::step_observed`]: crate::machine::Machine::step_observed
+#[derive(Debug, Clone)]
+pub struct Coverage {
+    executed: BTreeSet<u64>,
+    /// addresses of the first words of instructions.
+    entries: BTreeSet<u64>,
+    opcode_next: bool,
+}
+
+impl Default for Coverage {
+    fn default() -> Self {
+        Self {
+            executed: BTreeSet::new(),
+            entries: BTreeSet::new(),
+            opcode_next: true,
+        }
+    }
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_executed<A: Into<u64>>(&self, address: A) -> bool {
+        self.executed.contains(&address.into())
+    }
+
+    /// executed addresses, in order.
+    pub fn addresses(&self) -> impl Iterator<Item = u64> + '_ {
+        self.executed.iter().copied()
+    }
+
+    /// the addresses instructions started at, in order.
+    pub fn instructions(&self) -> impl Iterator<Item = u64> + '_ {
+        self.entries.iter().copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.executed.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.executed.is_empty()
+    }
+
+    /// runs of consecutive executed addresses.
+    pub fn ranges(&self) -> Vec<RangeInclusive<u64>> {
+        let mut ranges: Vec<RangeInclusive<u64>> = Vec::new();
+        for &address in &self.executed {
+            match ranges.last_mut() {
+                Some(last) if last.end().checked_add(1) == Some(address) => {
+                    *last = *last.start()..=address
+                }
+                _ => ranges.push(address..=address),
+            }
+        }
+        ranges
+    }
+
+    /// the share of `range` executed, from 0 to 1.
+    pub fn ratio(&self, range: RangeInclusive<u64>) -> f64 {
+        // in floating point, as the whole of 0..=u64::MAX is one more than u64 holds
+        let size = range.end().saturating_sub(*range.start()) as f64 + 1.0;
+        self.executed.range(range).count() as f64 / size
+    }
+
+    /// the ranges as lines of `start-end` in hex, to diff between runs or load elsewhere.
+    pub fn export(&self) -> String {
+        let mut out = String::new();
+        for range in self.ranges() {
+            let _ = writeln!(out, "{:04x}-{:04x}", range.start(), range.end());
+        }
+        out
+    }
+
+    /// adds the coverage of another run.
+    pub fn merge(&mut self, other: &Coverage) {
+        self.executed.extend(&other.executed);
+        self.entries.extend(&other.entries);
+    }
+
+    pub fn clear(&mut self) {
+        self.executed.clear();
+        self.entries.clear();
+        self.opcode_next = true;
+    }
+}
+
+impl<C: CPU> ExecutionObserver<C> for Coverage
+where
+    C::Address: Into<u64>,
+{
+    fn on_fetch(&mut self, address: C::Address, _data: C::Data) {
+        let address = address.into();
+        self.executed.insert(address);
+        if self.opcode_next {
+            self.entries.insert(address);
+            self.opcode_next = false;
+        }
+    }
+    fn on_execute(&mut self, _cpu: &C) {
+        self.opcode_next = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::Bus;
+    use crate::clock::Clock;
+    use crate::machine::Machine;
+    use crate::memory::typical::Memory8Bit64KB;
+    use crate::typical::i8080::I8080;
+
+    #[test]
+    fn coverage() {
+        let mut memory = Memory8Bit64KB::new(&[
+            0x3e, 0x10, // MVI A,10
+            0x47, // MOV B,A
+            0xc3, 0x10, 0x00, // JMP 0010
+            0x76, // HLT, skipped
+        ]);
+        memory.as_mut_slice()[0x0010] = 0x76;
+        let mut bus = Bus::default();
+        bus.map_memory(0x0000..=0xffff, memory);
+        let mut machine = Machine::new(I8080::default(), bus, Clock::PC8801_4MHZ);
+        let mut coverage = Coverage::new();
+        machine.run_observed(1000, &mut coverage);
+        assert!(coverage.is_executed(0x0005u16) && !coverage.is_executed(0x0006u16));
+        assert_eq!(coverage.ranges(), [0x0000..=0x0005, 0x0010..=0x0010]);
+        assert_eq!(coverage.instructions().collect::<Vec<_>>(), [0, 2, 3, 0x10]);
+        assert_eq!(coverage.ratio(0x0000..=0x0007), 0.75);
+        assert_eq!(coverage.export(), "0000-0005\n0010-0010\n");
+        let mut total = Coverage::new();
+        total.merge(&coverage);
+        assert_eq!(total.len(), 7);
+    }
+
+    #[test]
+    fn top_of_address_space() {
+        let mut coverage = Coverage::new();
+        coverage.executed.extend([0, u64::MAX - 1, u64::MAX]);
+        assert_eq!(coverage.ranges(), [0..=0, u64::MAX - 1..=u64::MAX]);
+        assert!(coverage.ratio(0..=u64::MAX) > 0.0);
+    }
+}
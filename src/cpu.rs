@@ -1,15 +1,27 @@
+use crate::addressing::{Addressing, AddressingMut};
 use crate::alu::{FlagSet, ALU};
-use crate::memory::Memory;
-use crate::register::typical::Register16In8Loader;
+use crate::memory::{Memory, MemoryError};
+use crate::observer::ExecutionObserver;
+use crate::register::typical::{MaskedRegisterLoader, Register16In8Loader};
 use crate::register::{
     Register, RegisterCode, RegisterDecrementable, RegisterIncrementable, RegisterLoader,
     RegisterReader,
 };
+use crate::BitwiseOps;
+use alloc::{string::String, string::ToString};
 
+#[derive(Debug, Clone, Eq, PartialEq)]
 pub enum CPURunningState {
     Running,
     Halted,
-    Error,
+    /// stopped on a fault, with a description of what went wrong.
+    Error(String),
+}
+
+impl<A: core::fmt::LowerHex> From<MemoryError<A>> for CPURunningState {
+    fn from(error: MemoryError<A>) -> Self {
+        CPURunningState::Error(error.to_string())
+    }
 }
 
 pub trait CPU: Sized {
@@ -35,11 +47,124 @@ where
         let address = self.address();
         self.load_data(memory.read(address))
     }
+    fn store_memory_observed<O>(self, memory: &mut M, observer: &mut O) -> Self
+    where
+        O: ExecutionObserver<Self>,
+    {
+        observer.on_memory_write(self.address(), self.data());
+        self.store_memory(memory)
+    }
+    fn fetch_memory_observed<O>(self, memory: &M, observer: &mut O) -> Self
+    where
+        O: ExecutionObserver<Self>,
+    {
+        let temp = self.fetch_memory(memory);
+        observer.on_memory_read(temp.address(), temp.data());
+        temp
+    }
 }
 
 /// todo: ALUの素晴らしい設計を後で考える
 pub trait CPUAlu: CPU {
     type ALU: ALU;
+    fn alu(&self) -> &Self::ALU;
+    /// `acc <- acc op rhs`, returning the flags for the caller to commit.
+    fn alu_acc_op(
+        &mut self,
+        control: <Self::ALU as ALU>::Control,
+        rhs: Self::Data,
+    ) -> <Self::ALU as ALU>::FlagSet
+    where
+        Self: CPUAccumulator,
+        Self::ALU: ALU<Data = Self::Data>,
+    {
+        let (result, flags) = self.alu().op(control, self.acc(), rhs);
+        self.acc_write(result);
+        flags
+    }
+    /// `dst <- dst op src`, returning the flags for the caller to commit.
+    fn alu_op<D, S>(
+        &mut self,
+        control: <Self::ALU as ALU>::Control,
+        dst: &D,
+        src: &S,
+    ) -> <Self::ALU as ALU>::FlagSet
+    where
+        D: AddressingMut<Self, Size = Self::Data>,
+        S: Addressing<Self, Size = Self::Data>,
+        Self::ALU: ALU<Data = Self::Data>,
+    {
+        let rhs = src.value(self);
+        src.after(self);
+        let (result, flags) = self.alu().op(control, dst.value(self), rhs);
+        dst.write(self, result);
+        dst.after(self);
+        flags
+    }
+}
+
+/// Runs whole instructions, for [`crate::machine::Machine`].
+pub trait CPUStep<M>: CPU {
+    /// executes one instruction, returning the cycles it took.
+    fn step(&mut self, memory: &mut M) -> u64 {
+        self.step_observed(memory, &mut ())
+    }
+    /// like `step`, reporting the words fetched, the instruction before it executes and
+    /// its memory accesses to `observer`.
+    fn step_observed<O>(&mut self, memory: &mut M, observer: &mut O) -> u64
+    where
+        O: ExecutionObserver<Self>;
+    /// offers a pending interrupt between instructions, returning the cycles taken
+    /// to accept it, or 0 if it is not accepted.
+    fn interrupt(&mut self, memory: &mut M) -> u64 {
+        self.interrupt_observed(memory, &mut ())
+    }
+    /// like `interrupt`, reporting what accepting it executes and accesses to `observer`.
+    fn interrupt_observed<O>(&mut self, _memory: &mut M, _observer: &mut O) -> u64
+    where
+        O: ExecutionObserver<Self>,
+    {
+        0
+    }
+}
+
+/// Runs instructions decoded by the run loop, so that
+/// [`crate::machine::Machine::step_cached`] can keep them in a [`crate::cache::DecodeCache`].
+pub trait CPUExecute<M>: CPUStep<M> + CPUProgramCounter {
+    type Instruction;
+    /// executes `instruction`, the `len` words at the program counter, returning the cycles
+    /// it took. Its stores are reported to `observer`.
+    fn execute<O>(
+        &mut self,
+        memory: &mut M,
+        instruction: &Self::Instruction,
+        len: u64,
+        observer: &mut O,
+    ) -> u64
+    where
+        O: ExecutionObserver<Self>;
+}
+
+/// The halt state and the interrupt enable flip-flop, as HLT, EI and DI see them.
+///
+/// A halted CPU idles until it accepts an interrupt, so it halts for good while
+/// interrupts are disabled.
+pub trait CPUHalt: CPU {
+    fn halted(&self) -> bool;
+    fn set_halted(&mut self, halted: bool);
+    fn interrupt_enabled(&self) -> bool;
+    fn set_interrupt_enabled(&mut self, enabled: bool);
+    fn running_state(&self) -> CPURunningState {
+        match self.halted() {
+            true => CPURunningState::Halted,
+            false => CPURunningState::Running,
+        }
+    }
+}
+
+/// The reset line. Registers the hardware leaves alone keep their values.
+pub trait CPUReset<M>: CPU {
+    fn reset(&mut self, memory: &mut M);
 }
 
 pub trait CPURegisters<C: RegisterCode<Register = Self::Register>>: CPU {
@@ -62,23 +187,38 @@ pub trait CPURegisters<C: RegisterCode<Register = Self::Register>>: CPU {
     }
 }
 
+/// The accumulator is accessed by value, as it may share storage with other registers
+/// such as PSW of I8080.
 pub trait CPUAccumulator: CPU {
-    fn acc(&mut self) -> &mut Self::Data;
-    fn acc_load(self) -> Self;
-    fn acc_read(self) -> Self;
+    fn acc(&self) -> Self::Data;
+    fn acc_write(&mut self, data: Self::Data);
+    /// loads the data bus into the accumulator.
+    fn acc_load(mut self) -> Self {
+        let data = self.data();
+        self.acc_write(data);
+        self
+    }
+    /// puts the accumulator on the data bus.
+    fn acc_read(self) -> Self {
+        let acc = self.acc();
+        self.load_data(acc)
+    }
 }
 
-/// todo: ALUができたらやる
+/// The flag register. Implementors provide whole-register access with `flag_read`
+/// and `flag_load`; masked loads preserve the flags outside the mask.
 pub trait CPUFlagRegister: CPUAlu {
-    type FlagRegisterSize: Into<<Self::ALU as ALU>::FlagSet>;
-    fn flag_load(&mut self, bits: Self::FlagRegisterSize) {
-        self.flag_load_masked(<Self::ALU as ALU>::FlagSet::all_on(), bits);
-    }
+    type FlagRegisterSize: Into<<Self::ALU as ALU>::FlagSet>
+        + From<<Self::ALU as ALU>::FlagSet>
+        + BitwiseOps;
+    fn flag_load(&mut self, bits: Self::FlagRegisterSize);
     fn flag_load_masked(
         &mut self,
         flag_mask: <Self::ALU as ALU>::FlagSet,
         bits: Self::FlagRegisterSize,
-    );
+    ) {
+        MaskedRegisterLoader::new(FlagRegisterLoader(self), flag_mask.into()).load(bits)
+    }
     fn flag_load_mask_slice(
         &mut self,
         flag_masks: &[<Self::ALU as ALU>::Flag],
@@ -95,6 +235,22 @@ pub trait CPUFlagRegister: CPUAlu {
     }
 }
 
+/// the flag register of `C` as a loader.
+struct FlagRegisterLoader<'a, C>(&'a mut C);
+
+impl<C: CPUFlagRegister> RegisterReader for FlagRegisterLoader<'_, C> {
+    type Size = C::FlagRegisterSize;
+    fn read(&self) -> Self::Size {
+        self.0.flag_read()
+    }
+}
+
+impl<C: CPUFlagRegister> RegisterLoader for FlagRegisterLoader<'_, C> {
+    fn load(&mut self, bits: Self::Size) {
+        self.0.flag_load(bits)
+    }
+}
+
 pub trait CPUProgramCounter: CPU {
     fn program_counter(&mut self) -> &mut Self::Address;
     fn program_counter_read(mut self) -> Self {
@@ -107,7 +263,7 @@ pub trait CPUProgramCounter: CPU {
         Self: CPU<Address = u16, Data = u8>,
     {
         let data = self.data();
-        Register16In8Loader::new(self.program_counter(), true).load(data);
+        Register16In8Loader::high(self.program_counter()).load(data);
         self
     }
     /// fixme: u16 and u8 hardcode.
@@ -116,7 +272,7 @@ pub trait CPUProgramCounter: CPU {
         Self: CPU<Address = u16, Data = u8>,
     {
         let data = self.data();
-        Register16In8Loader::new(self.program_counter(), false).load(data);
+        Register16In8Loader::low(self.program_counter()).load(data);
         self
     }
     fn program_fetch<M>(self, memory: &M) -> Self
@@ -129,6 +285,43 @@ pub trait CPUProgramCounter: CPU {
         temp.program_counter().increment();
         temp
     }
+    /// fetches a little-endian 16-bit immediate onto the address bus.
+    fn program_fetch_word<M>(self, memory: &M) -> Self
+    where
+        Self: CPU<Address = u16, Data = u8> + CPUMemory<M>,
+        M: Memory<Data = u8, Address = u16>,
+    {
+        let temp = self.program_fetch(memory);
+        let low = temp.data();
+        let temp = temp.program_fetch(memory);
+        let high = temp.data();
+        temp.load_address(u16::from_le_bytes([low, high]))
+    }
+    /// fetches a signed 8-bit displacement and puts the target it points to, relative to
+    /// the following instruction, onto the address bus.
+    fn program_fetch_signed<M>(self, memory: &M) -> Self
+    where
+        Self: CPU<Address = u16, Data = u8> + CPUMemory<M>,
+        M: Memory<Data = u8, Address = u16>,
+    {
+        let mut temp = self.program_fetch(memory);
+        let displacement = temp.data() as i8;
+        let target = temp
+            .program_counter()
+            .wrapping_add_signed(displacement as i16);
+        temp.load_address(target)
+    }
+    fn program_fetch_observed<M, O>(self, memory: &M, observer: &mut O) -> Self
+    where
+        Self: CPUMemory<M>,
+        M: Memory<Data = Self::Data, Address = Self::Address>,
+        Self::Address: RegisterIncrementable,
+        O: ExecutionObserver<Self>,
+    {
+        let temp = self.program_fetch(memory);
+        observer.on_fetch(temp.address(), temp.data());
+        temp
+    }
 }
 
 pub trait CPUStackPointer: CPU {
@@ -143,7 +336,7 @@ pub trait CPUStackPointer: CPU {
         Self: CPU<Address = u16, Data = u8>,
     {
         let data = self.data();
-        Register16In8Loader::new(self.stack_pointer(), true).load(data);
+        Register16In8Loader::high(self.stack_pointer()).load(data);
         self
     }
     /// fixme: u16 and u8 hardcode.
@@ -152,7 +345,7 @@ pub trait CPUStackPointer: CPU {
         Self: CPU<Address = u16, Data = u8>,
     {
         let data = self.data();
-        Register16In8Loader::new(self.stack_pointer(), false).load(data);
+        Register16In8Loader::low(self.stack_pointer()).load(data);
         self
     }
 
@@ -162,7 +355,7 @@ pub trait CPUStackPointer: CPU {
         M: Memory<Data = Self::Data, Address = Self::Address>,
         Self::Address: RegisterDecrementable,
     {
-        self.stack_pointer().decrement();
+        self.stack_pointer_decrement();
         self.stack_pointer_read().store_memory(memory)
     }
     fn pop<M>(self, memory: &M) -> Self
@@ -172,14 +365,53 @@ pub trait CPUStackPointer: CPU {
         Self::Address: RegisterIncrementable,
     {
         let mut temp = self.stack_pointer_read().fetch_memory(memory);
-        temp.stack_pointer().increment();
+        temp.stack_pointer_increment();
         temp
     }
+    /// pushes `pair` high byte first, leaving the low byte on top, as PUSH of I8080.
+    fn push_pair<M>(self, memory: &mut M, pair: u16) -> Self
+    where
+        Self: CPU<Address = u16, Data = u8> + CPUMemory<M>,
+        M: Memory<Data = u8, Address = u16>,
+    {
+        let [low, high] = pair.to_le_bytes();
+        self.load_data(high)
+            .push(memory)
+            .load_data(low)
+            .push(memory)
+    }
+    /// pops a pair pushed by [`CPUStackPointer::push_pair`] onto the address bus.
+    fn pop_pair<M>(self, memory: &M) -> Self
+    where
+        Self: CPU<Address = u16, Data = u8> + CPUMemory<M>,
+        M: Memory<Data = u8, Address = u16>,
+    {
+        let temp = self.pop(memory);
+        let low = temp.data();
+        let temp = temp.pop(memory);
+        let high = temp.data();
+        temp.load_address(u16::from_le_bytes([low, high]))
+    }
+    /// moves the stack pointer one slot down. Override for a stack confined to a page.
+    fn stack_pointer_decrement(&mut self)
+    where
+        Self::Address: RegisterDecrementable,
+    {
+        self.stack_pointer().decrement()
+    }
+    /// moves the stack pointer one slot up. Override for a stack confined to a page.
+    fn stack_pointer_increment(&mut self)
+    where
+        Self::Address: RegisterIncrementable,
+    {
+        self.stack_pointer().increment()
+    }
 }
 
 pub trait CPUJump: CPU + CPUProgramCounter {
     fn jump(mut self, address: Self::Address) -> Self {
-        self.load_address(address)
+        *self.program_counter() = address;
+        self
     }
     /// fixme: u16 and u8 hardcode.
     fn jump_high(mut self) -> Self
@@ -187,7 +419,7 @@ pub trait CPUJump: CPU + CPUProgramCounter {
         Self: CPU<Address = u16, Data = u8>,
     {
         let data = self.data();
-        Register16In8Loader::new(self.program_counter(), true).load(data);
+        Register16In8Loader::high(self.program_counter()).load(data);
         self
     }
     /// fixme: u16 and u8 hardcode.
@@ -196,29 +428,36 @@ pub trait CPUJump: CPU + CPUProgramCounter {
         Self: CPU<Address = u16, Data = u8>,
     {
         let data = self.data();
-        Register16In8Loader::new(self.program_counter(), false).load(data);
+        Register16In8Loader::low(self.program_counter()).load(data);
         self
     }
-    /// todo: ALUが実装できたら
-    fn jump_on(&mut self, index: Self::Address, flag: <Self::ALU as ALU>::Flag)
+    fn jump_on(&mut self, address: Self::Address, flag: <Self::ALU as ALU>::Flag)
     where
         Self: CPUFlagRegister,
-        <Self::ALU as ALU>::FlagSet: From<Self::FlagRegisterSize>;
+        <Self::ALU as ALU>::FlagSet: From<Self::FlagRegisterSize>,
+    {
+        if self.flag_on(flag) {
+            *self.program_counter() = address;
+        }
+    }
 }
 
 #[cfg(test)]
-mod tests {
-    use crate::cpu::{CPUMemory, CPUProgramCounter, CPUStackPointer, CPU};
+pub(crate) mod tests {
+    use crate::cpu::{CPUHalt, CPUMemory, CPUProgramCounter, CPUStackPointer, CPU};
     use crate::memory::typical::Memory8Bit64KB;
     use crate::memory::Memory;
+    use crate::observer::ExecutionObserver;
+    use alloc::{vec, vec::Vec};
 
     #[derive(Debug, Default, Copy, Clone)]
-    struct CPU8 {
+    pub(crate) struct CPU8 {
         data: u8,
-        af: u16,
         sp: u16,
         pc: u16,
         address: u16,
+        halted: bool,
+        interrupt_enabled: bool,
     }
 
     impl CPU for CPU8 {
@@ -266,6 +505,21 @@ mod tests {
         }
     }
 
+    impl CPUHalt for CPU8 {
+        fn halted(&self) -> bool {
+            self.halted
+        }
+        fn set_halted(&mut self, halted: bool) {
+            self.halted = halted
+        }
+        fn interrupt_enabled(&self) -> bool {
+            self.interrupt_enabled
+        }
+        fn set_interrupt_enabled(&mut self, enabled: bool) {
+            self.interrupt_enabled = enabled
+        }
+    }
+
     #[test]
     fn pc() {
         let mut memory = Memory8Bit64KB::default();
@@ -273,12 +527,25 @@ mod tests {
             memory.store(i, i as u8);
         }
         let cpu = (0..=3).fold(CPU8::default(), |cpu, _| cpu.program_fetch(&memory));
-        println!("{:?}", cpu);
         assert_eq!(cpu.data(), 3);
         let cpu = (0..=123).fold(CPU8::default(), |cpu, _| cpu.program_fetch(&memory));
         assert_eq!(cpu.data(), 123);
     }
 
+    #[test]
+    fn fetch_word() {
+        let memory = Memory8Bit64KB::new(&[0xc3, 0x34, 0x12, 0xfc, 0x7f]);
+        let cpu = CPU8::default()
+            .program_fetch(&memory)
+            .program_fetch_word(&memory);
+        assert_eq!(cpu.address(), 0x1234);
+        assert_eq!(cpu.pc, 3);
+        let cpu = cpu.program_fetch_signed(&memory);
+        assert_eq!(cpu.address(), 0x0000);
+        let cpu = cpu.program_fetch_signed(&memory);
+        assert_eq!((cpu.address(), cpu.pc), (0x0084, 5));
+    }
+
     #[test]
     fn sp() {
         let mut cpu = CPU8::default();
@@ -295,15 +562,56 @@ mod tests {
             .push(&mut memory)
             .load_data(5)
             .push(&mut memory);
-        let cpu = cpu.pop(&mut memory);
+        let cpu = cpu.pop(&memory);
         assert_eq!(cpu.data(), 5);
-        let cpu = cpu.pop(&mut memory);
+        let cpu = cpu.pop(&memory);
         assert_eq!(cpu.data(), 1);
-        let cpu = cpu.pop(&mut memory);
+        let cpu = cpu.pop(&memory);
         assert_eq!(cpu.data(), 4);
-        let cpu = cpu.pop(&mut memory);
+        let cpu = cpu.pop(&memory);
         assert_eq!(cpu.data(), 1);
-        let cpu = cpu.pop(&mut memory);
+        let cpu = cpu.pop(&memory);
         assert_eq!(cpu.data(), 3);
     }
+
+    #[derive(Debug, Default)]
+    struct AccessLog {
+        fetches: Vec<(u16, u8)>,
+        reads: Vec<(u16, u8)>,
+        writes: Vec<(u16, u8)>,
+    }
+
+    impl ExecutionObserver<CPU8> for AccessLog {
+        fn on_fetch(&mut self, address: u16, data: u8) {
+            self.fetches.push((address, data));
+        }
+        fn on_memory_read(&mut self, address: u16, data: u8) {
+            self.reads.push((address, data));
+        }
+        fn on_memory_write(&mut self, address: u16, data: u8) {
+            self.writes.push((address, data));
+        }
+    }
+
+    #[test]
+    fn observer() {
+        let mut memory = Memory8Bit64KB::default();
+        memory.store(0, 0x12);
+        memory.store(1, 0x34);
+        let mut log = AccessLog::default();
+        let cpu = CPU8::default()
+            .program_fetch_observed(&memory, &mut log)
+            .program_fetch_observed(&memory, &mut log)
+            .load_address(0x100)
+            .store_memory_observed(&mut memory, &mut log)
+            .load_address(0)
+            .fetch_memory_observed(&memory, &mut log);
+        assert_eq!(cpu.data(), 0x12);
+        assert_eq!(log.fetches, vec![(0, 0x12), (1, 0x34)]);
+        assert_eq!(log.writes, vec![(0x100, 0x34)]);
+        assert_eq!(log.reads, vec![(0, 0x12)]);
+        assert_eq!(memory.read(0x100), 0x34);
+        let cpu = cpu.program_fetch_observed(&memory, &mut ());
+        assert_eq!(cpu.data(), 0x00);
+    }
 }
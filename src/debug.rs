@@ -0,0 +1,507 @@
+use crate::cpu::{CPUHalt, CPU};
+use crate::memory::Memory;
+use crate::observer::ExecutionObserver;
+use alloc::collections::btree_map::Entry;
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::{boxed::Box, vec::Vec};
+use core::fmt;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+/// Why the run loop handed control back to the frontend.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum StopReason<A> {
+    Breakpoint(A),
+    Watchpoint(A, Access),
+    Step,
+    Halted,
+}
+
+/// A breakpoint condition over the CPU and memory, e.g. `|cpu, _| cpu.a == 0xff`.
+pub type Condition<C, M> = Box<dyn Fn(&C, &M) -> bool>;
+
+struct Breakpoint<C, M> {
+    condition: Option<Condition<C, M>>,
+    /// removed once hit, e.g. to run to a line.
+    temporary: bool,
+    hits: u64,
+}
+
+impl<C, M> fmt::Debug for Breakpoint<C, M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Breakpoint")
+            .field("conditional", &self.condition.is_some())
+            .field("temporary", &self.temporary)
+            .field("hits", &self.hits)
+            .finish()
+    }
+}
+
+/// PC breakpoints, memory watchpoints and single-stepping.
+///
+/// Step the machine with it, by [`Machine::step_observed`], so that it sees memory accesses,
+/// and ask [`Debugger::before_instruction`] before each step whether to stop.
+/// Conditional breakpoints see the CPU `C` and memory `M`, given to
+/// [`Debugger::before_instruction_in`] instead.
+///
+/// [`Machine::step_observed`]: crate::machine::Machine::step_observed
+#[derive(Debug)]
+pub struct Debugger<A, C = (), M = ()> {
+    breakpoints: BTreeMap<A, Breakpoint<C, M>>,
+    read_watchpoints: BTreeSet<A>,
+    write_watchpoints: BTreeSet<A>,
+    stepping: bool,
+    pending: Option<StopReason<A>>,
+    /// the breakpoint just reported, not to be hit again by the next check, which resumes
+    /// on it. Taken by that check, so a loop back to it stops again.
+    resumed_from: Option<A>,
+}
+
+impl<A, C, M> Default for Debugger<A, C, M> {
+    fn default() -> Self {
+        Self {
+            breakpoints: BTreeMap::new(),
+            read_watchpoints: BTreeSet::new(),
+            write_watchpoints: BTreeSet::new(),
+            stepping: false,
+            pending: None,
+            resumed_from: None,
+        }
+    }
+}
+
+impl<A: Ord + Copy> Debugger<A> {
+    /// Called by the run loop before executing the instruction at `pc`.
+    /// A watchpoint hit by the previous instruction is reported first.
+    pub fn before_instruction(&mut self, pc: A) -> Option<StopReason<A>> {
+        self.before_instruction_in(pc, &(), &())
+    }
+}
+
+impl<A: Ord + Copy, C, M> Debugger<A, C, M> {
+    pub fn add_breakpoint(&mut self, address: A) {
+        self.insert_breakpoint(address, None, false);
+    }
+    /// stops at `address` only when `condition` holds, checked as the instruction is reached.
+    pub fn add_conditional_breakpoint<F>(&mut self, address: A, condition: F)
+    where
+        F: Fn(&C, &M) -> bool + 'static,
+    {
+        self.insert_breakpoint(address, Some(Box::new(condition)), false);
+    }
+    /// a breakpoint removed once hit.
+    pub fn add_temporary_breakpoint(&mut self, address: A) {
+        self.insert_breakpoint(address, None, true);
+    }
+    fn insert_breakpoint(
+        &mut self,
+        address: A,
+        condition: Option<Condition<C, M>>,
+        temporary: bool,
+    ) {
+        let breakpoint = Breakpoint {
+            condition,
+            temporary,
+            hits: 0,
+        };
+        self.breakpoints.insert(address, breakpoint);
+    }
+    pub fn remove_breakpoint(&mut self, address: A) -> bool {
+        self.breakpoints.remove(&address).is_some()
+    }
+    pub fn breakpoints(&self) -> impl Iterator<Item = A> + '_ {
+        self.breakpoints.keys().copied()
+    }
+    /// how many times the breakpoint at `address` has stopped the run.
+    pub fn hits(&self, address: A) -> Option<u64> {
+        self.breakpoints.get(&address).map(|b| b.hits)
+    }
+    pub fn add_watchpoint(&mut self, address: A, access: Access) {
+        self.watchpoints_mut(access).insert(address);
+    }
+    pub fn remove_watchpoint(&mut self, address: A, access: Access) -> bool {
+        self.watchpoints_mut(access).remove(&address)
+    }
+    pub fn watchpoints(&self, access: Access) -> impl Iterator<Item = A> + '_ {
+        match access {
+            Access::Read => self.read_watchpoints.iter().copied(),
+            Access::Write => self.write_watchpoints.iter().copied(),
+        }
+    }
+    fn watchpoints_mut(&mut self, access: Access) -> &mut BTreeSet<A> {
+        match access {
+            Access::Read => &mut self.read_watchpoints,
+            Access::Write => &mut self.write_watchpoints,
+        }
+    }
+    /// Stops before the next instruction after the current one.
+    pub fn step(&mut self) {
+        self.stepping = true;
+    }
+    /// [`Debugger::before_instruction`] checking the conditions on `cpu` and `memory`.
+    pub fn before_instruction_in(&mut self, pc: A, cpu: &C, memory: &M) -> Option<StopReason<A>> {
+        let resuming = self.resumed_from.take() == Some(pc);
+        if let Some(reason) = self.pending.take() {
+            self.stepping = false;
+            return Some(reason);
+        }
+        if let Some(breakpoint) = self.breakpoints.get_mut(&pc) {
+            let holds = match &breakpoint.condition {
+                Some(condition) => condition(cpu, memory),
+                None => true,
+            };
+            if holds && !resuming {
+                breakpoint.hits += 1;
+                if breakpoint.temporary {
+                    self.breakpoints.remove(&pc);
+                }
+                self.resumed_from = Some(pc);
+                self.stepping = false;
+                return Some(StopReason::Breakpoint(pc));
+            }
+        }
+        if self.stepping {
+            self.stepping = false;
+            // the first boundary is the instruction being stepped itself.
+            self.pending = Some(StopReason::Step);
+        }
+        None
+    }
+    /// [`Debugger::before_instruction_in`], but stopping with [`StopReason::Halted`] while
+    /// the CPU is halted with interrupts disabled, as nothing can wake it up.
+    pub fn before_instruction_or_halt(
+        &mut self,
+        pc: A,
+        cpu: &C,
+        memory: &M,
+    ) -> Option<StopReason<A>>
+    where
+        C: CPUHalt,
+    {
+        if self.pending.is_none() && cpu.halted() && !cpu.interrupt_enabled() {
+            self.stepping = false;
+            return Some(StopReason::Halted);
+        }
+        self.before_instruction_in(pc, cpu, memory)
+    }
+    fn watch(&mut self, address: A, access: Access) {
+        if self.pending.is_none() && self.watchpoints_mut(access).contains(&address) {
+            self.pending = Some(StopReason::Watchpoint(address, access));
+        }
+    }
+}
+
+impl<X, C, M> ExecutionObserver<X> for Debugger<X::Address, C, M>
+where
+    X: CPU,
+    X::Address: Ord + Copy,
+{
+    fn on_memory_read(&mut self, address: X::Address, _data: X::Data) {
+        self.watch(address, Access::Read);
+    }
+    fn on_memory_write(&mut self, address: X::Address, _data: X::Data) {
+        self.watch(address, Access::Write);
+    }
+}
+
+/// Breakpoints patched into memory as a trap opcode, such as RST 7 on I8080,
+/// so that running costs nothing until one is fetched.
+///
+/// Step the machine with it, by [`Machine::step_observed`], to catch the fetch of a trap.
+/// The trap has run by the time the step returns, so put back the CPU kept from before
+/// the step on a hit; an RST only pushed below the stack pointer. Then
+/// [`TrapBreakpoints::step_over`] restores the original instruction for one instruction
+/// and [`TrapBreakpoints::after_instruction`] patches it back.
+///
+/// [`Machine::step_observed`]: crate::machine::Machine::step_observed
+#[derive(Debug)]
+pub struct TrapBreakpoints<A, D> {
+    trap: D,
+    originals: BTreeMap<A, D>,
+    hit: Option<A>,
+    stepping_over: Option<A>,
+}
+
+impl<A: Ord + Copy, D: Copy + PartialEq> TrapBreakpoints<A, D> {
+    pub fn new(trap: D) -> Self {
+        Self {
+            trap,
+            originals: BTreeMap::new(),
+            hit: None,
+            stepping_over: None,
+        }
+    }
+
+    pub fn insert<M>(&mut self, memory: &mut M, address: A)
+    where
+        M: Memory<Address = A, Data = D>,
+    {
+        if let Entry::Vacant(entry) = self.originals.entry(address) {
+            entry.insert(memory.read(address));
+            memory.store(address, self.trap);
+        }
+    }
+
+    /// restores the original instruction, returning whether there was a breakpoint.
+    pub fn remove<M>(&mut self, memory: &mut M, address: A) -> bool
+    where
+        M: Memory<Address = A, Data = D>,
+    {
+        match self.originals.remove(&address) {
+            Some(original) => {
+                if self.stepping_over != Some(address) {
+                    memory.store(address, original);
+                }
+                self.stepping_over = self.stepping_over.filter(|&a| a != address);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// removes every breakpoint, e.g. before saving a memory image.
+    pub fn clear<M>(&mut self, memory: &mut M)
+    where
+        M: Memory<Address = A, Data = D>,
+    {
+        let addresses: Vec<_> = self.originals.keys().copied().collect();
+        for address in addresses {
+            self.remove(memory, address);
+        }
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = A> + '_ {
+        self.originals.keys().copied()
+    }
+
+    /// memory as the program sees it, without traps, for disassemblers and the like.
+    pub fn peek<M>(&self, memory: &M, address: A) -> D
+    where
+        M: Memory<Address = A, Data = D>,
+    {
+        match self.originals.get(&address) {
+            Some(&original) if self.stepping_over != Some(address) => original,
+            _ => memory.read(address),
+        }
+    }
+
+    /// the breakpoint whose trap was fetched since the last call.
+    pub fn take_hit(&mut self) -> Option<StopReason<A>> {
+        self.hit.take().map(StopReason::Breakpoint)
+    }
+
+    /// puts back the original instruction at `pc` to resume from the breakpoint there.
+    pub fn step_over<M>(&mut self, memory: &mut M, pc: A)
+    where
+        M: Memory<Address = A, Data = D>,
+    {
+        if let Some(&original) = self.originals.get(&pc) {
+            memory.store(pc, original);
+            self.stepping_over = Some(pc);
+        }
+    }
+
+    /// Called by the run loop after each instruction, re-arming a breakpoint stepped over.
+    pub fn after_instruction<M>(&mut self, memory: &mut M)
+    where
+        M: Memory<Address = A, Data = D>,
+    {
+        if let Some(address) = self.stepping_over.take() {
+            memory.store(address, self.trap);
+        }
+    }
+}
+
+impl<C> ExecutionObserver<C> for TrapBreakpoints<C::Address, C::Data>
+where
+    C: CPU,
+    C::Address: Ord,
+    C::Data: PartialEq,
+{
+    fn on_fetch(&mut self, address: C::Address, data: C::Data) {
+        if data == self.trap && self.originals.contains_key(&address) && self.hit.is_none() {
+            self.hit = Some(address);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::Bus;
+    use crate::clock::Clock;
+    use crate::cpu::tests::CPU8;
+    use crate::cpu::{CPUAccumulator, CPUProgramCounter};
+    use crate::machine::Machine;
+    use crate::memory::typical::Memory8Bit64KB;
+    use crate::typical::i8080::I8080;
+    use alloc::vec;
+
+    fn machine(program: &[u8]) -> Machine<I8080, u16, u8> {
+        let mut bus = Bus::default();
+        bus.map_memory(0x0000..=0xffff, Memory8Bit64KB::new(program));
+        Machine::new(I8080::default(), bus, Clock::PC8801_4MHZ)
+    }
+
+    #[test]
+    fn breakpoint() {
+        let mut debugger = Debugger::default();
+        debugger.add_breakpoint(0x10);
+        assert_eq!(debugger.before_instruction(0x0f), None);
+        assert_eq!(
+            debugger.before_instruction(0x10),
+            Some(StopReason::Breakpoint(0x10))
+        );
+        // resuming on the breakpoint executes it.
+        assert_eq!(debugger.before_instruction(0x10), None);
+        assert_eq!(debugger.before_instruction(0x11), None);
+        assert_eq!(
+            debugger.before_instruction(0x10),
+            Some(StopReason::Breakpoint(0x10))
+        );
+        assert!(debugger.remove_breakpoint(0x10));
+        assert_eq!(debugger.before_instruction(0x11), None);
+        assert_eq!(debugger.before_instruction(0x10), None);
+    }
+
+    #[test]
+    fn self_loop() {
+        let mut debugger = Debugger::default();
+        debugger.add_breakpoint(0x10);
+        // JMP $, hit again every time round
+        for _ in 0..2 {
+            assert_eq!(
+                debugger.before_instruction(0x10),
+                Some(StopReason::Breakpoint(0x10))
+            );
+            assert_eq!(debugger.before_instruction(0x10), None);
+        }
+        assert_eq!(debugger.hits(0x10), Some(2));
+    }
+
+    #[test]
+    fn halted() {
+        let mut debugger = Debugger::<u16, CPU8>::default();
+        let mut cpu = CPU8::default();
+        assert_eq!(debugger.before_instruction_or_halt(0, &cpu, &()), None);
+        cpu.set_halted(true);
+        assert_eq!(
+            debugger.before_instruction_or_halt(1, &cpu, &()),
+            Some(StopReason::Halted)
+        );
+        // an interrupt can still wake it up
+        cpu.set_interrupt_enabled(true);
+        assert_eq!(debugger.before_instruction_or_halt(1, &cpu, &()), None);
+    }
+
+    #[test]
+    fn conditional() {
+        use crate::memory::typical::Memory8Bit64KB;
+
+        let mut debugger = Debugger::<u16, u8, Memory8Bit64KB>::default();
+        debugger
+            .add_conditional_breakpoint(0x1234, |&a, memory| a == 0xff && memory.read(0x8000) == 0);
+        debugger.add_temporary_breakpoint(0x2000);
+        let memory = Memory8Bit64KB::default();
+        assert_eq!(debugger.before_instruction_in(0x1234, &0x00, &memory), None);
+        assert_eq!(
+            debugger.before_instruction_in(0x1234, &0xff, &memory),
+            Some(StopReason::Breakpoint(0x1234))
+        );
+        assert_eq!(debugger.before_instruction_in(0x1234, &0xff, &memory), None);
+        assert_eq!(debugger.before_instruction_in(0x1235, &0xff, &memory), None);
+        assert_eq!(
+            debugger.before_instruction_in(0x1234, &0xff, &memory),
+            Some(StopReason::Breakpoint(0x1234))
+        );
+        assert_eq!(debugger.hits(0x1234), Some(2));
+        assert_eq!(
+            debugger.before_instruction_in(0x2000, &0x00, &memory),
+            Some(StopReason::Breakpoint(0x2000))
+        );
+        assert_eq!(debugger.hits(0x2000), None);
+        assert_eq!(debugger.before_instruction_in(0x2001, &0x00, &memory), None);
+        assert_eq!(debugger.before_instruction_in(0x2000, &0x00, &memory), None);
+        assert_eq!(debugger.breakpoints().collect::<Vec<_>>(), vec![0x1234]);
+    }
+
+    #[test]
+    fn watchpoint() {
+        let mut machine = machine(&[
+            0x3e, 0x01, // MVI A,1
+            0x32, 0x00, 0x80, // STA 8000
+            0x3a, 0x01, 0x80, // LDA 8001
+            0x76, // HLT
+        ]);
+        let mut debugger = Debugger::<u16, I8080, Bus<u16, u8>>::default();
+        debugger.add_watchpoint(0x8000, Access::Write);
+        debugger.add_watchpoint(0x8001, Access::Read);
+        debugger.add_watchpoint(0x8002, Access::Read);
+        assert!(debugger.remove_watchpoint(0x8002, Access::Read));
+        assert_eq!(
+            debugger.watchpoints(Access::Read).collect::<Vec<_>>(),
+            vec![0x8001]
+        );
+        debugger.add_breakpoint(0x0005);
+        let mut run = || loop {
+            let pc = *machine.cpu.program_counter();
+            if let Some(reason) =
+                debugger.before_instruction_or_halt(pc, &machine.cpu, &machine.bus)
+            {
+                return (pc, reason);
+            }
+            machine.step_observed(&mut debugger);
+        };
+        // the watchpoint hit by STA is reported before the breakpoint after it
+        assert_eq!(
+            run(),
+            (0x0005, StopReason::Watchpoint(0x8000, Access::Write))
+        );
+        assert_eq!(run(), (0x0005, StopReason::Breakpoint(0x0005)));
+        assert_eq!(
+            run(),
+            (0x0008, StopReason::Watchpoint(0x8001, Access::Read))
+        );
+        assert_eq!(run(), (0x0009, StopReason::Halted));
+    }
+
+    #[test]
+    fn step() {
+        let mut debugger = Debugger::default();
+        debugger.step();
+        assert_eq!(debugger.before_instruction(0), None);
+        assert_eq!(debugger.before_instruction(1), Some(StopReason::Step));
+        assert_eq!(debugger.before_instruction(2), None);
+        assert_eq!(debugger.before_instruction(3), None);
+    }
+
+    #[test]
+    fn trap() {
+        // NOP; INR A; HLT
+        let mut machine = machine(&[0x00, 0x3c, 0x76]);
+        let mut traps = TrapBreakpoints::new(0xff);
+        traps.insert(&mut machine.bus, 0x0001);
+        assert_eq!(machine.bus.read(0x0001), 0xff);
+        assert_eq!(traps.peek(&machine.bus, 0x0001), 0x3c);
+        machine.step_observed(&mut traps);
+        assert_eq!(traps.take_hit(), None);
+        let before = machine.cpu;
+        machine.step_observed(&mut traps);
+        assert_eq!(traps.take_hit(), Some(StopReason::Breakpoint(0x0001)));
+        // RST 7 ran; undo it and resume on the breakpoint
+        assert_eq!(*machine.cpu.program_counter(), 0x0038);
+        machine.cpu = before;
+        traps.step_over(&mut machine.bus, 0x0001);
+        machine.step_observed(&mut traps);
+        assert_eq!((machine.cpu.acc(), traps.take_hit()), (0x01, None));
+        traps.after_instruction(&mut machine.bus);
+        assert_eq!(machine.bus.read(0x0001), 0xff);
+        assert!(traps.remove(&mut machine.bus, 0x0001));
+        assert_eq!(machine.bus.read(0x0001), 0x3c);
+        assert_eq!(traps.breakpoints().count(), 0);
+    }
+}
@@ -0,0 +1,478 @@
+use crate::memory::Memory;
+use alloc::{format, string::String, string::ToString, vec::Vec};
+
+pub trait Disassembler {
+    type Data;
+    /// Disassembles the instruction at the head of `words` into its text and length.
+    /// Returns `None` if `words` ends before the instruction does.
+    fn disassemble(&self, words: &[Self::Data]) -> Option<(String, usize)>;
+    fn disassemble_all(&self, words: &[Self::Data]) -> Vec<(usize, String)> {
+        let mut res = Vec::new();
+        let mut offset = 0;
+        while let Some((text, len)) = self.disassemble(&words[offset..]) {
+            res.push((offset, text));
+            offset += len;
+        }
+        res
+    }
+}
+
+pub mod typical {
+    use super::*;
+    use crate::symbols::SymbolTable;
+    use crate::typical::i8080::canonical_opcode;
+
+    #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+    pub enum I8080Operand {
+        Implied,
+        Byte,
+        Word,
+    }
+
+    impl I8080Operand {
+        /// bytes following the opcode.
+        pub fn size(self) -> usize {
+            match self {
+                I8080Operand::Implied => 0,
+                I8080Operand::Byte => 1,
+                I8080Operand::Word => 2,
+            }
+        }
+    }
+
+    /// `mnemonic` is the full text for implied operands,
+    /// otherwise the operand is appended to it.
+    #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+    pub struct I8080Opcode {
+        pub mnemonic: &'static str,
+        pub operand: I8080Operand,
+    }
+
+    const fn op(mnemonic: &'static str, operand: I8080Operand) -> Option<I8080Opcode> {
+        Some(I8080Opcode { mnemonic, operand })
+    }
+
+    use I8080Operand::*;
+
+    /// indexed by opcode. `None` for undocumented opcodes, see [`canonical_opcode`].
+    pub const I8080_OPCODES: [Option<I8080Opcode>; 256] = [
+        // 0x00
+        op("NOP", Implied),
+        op("LXI B,", Word),
+        op("STAX B", Implied),
+        op("INX B", Implied),
+        op("INR B", Implied),
+        op("DCR B", Implied),
+        op("MVI B,", Byte),
+        op("RLC", Implied),
+        None,
+        op("DAD B", Implied),
+        op("LDAX B", Implied),
+        op("DCX B", Implied),
+        op("INR C", Implied),
+        op("DCR C", Implied),
+        op("MVI C,", Byte),
+        op("RRC", Implied),
+        // 0x10
+        None,
+        op("LXI D,", Word),
+        op("STAX D", Implied),
+        op("INX D", Implied),
+        op("INR D", Implied),
+        op("DCR D", Implied),
+        op("MVI D,", Byte),
+        op("RAL", Implied),
+        None,
+        op("DAD D", Implied),
+        op("LDAX D", Implied),
+        op("DCX D", Implied),
+        op("INR E", Implied),
+        op("DCR E", Implied),
+        op("MVI E,", Byte),
+        op("RAR", Implied),
+        // 0x20
+        None,
+        op("LXI H,", Word),
+        op("SHLD ", Word),
+        op("INX H", Implied),
+        op("INR H", Implied),
+        op("DCR H", Implied),
+        op("MVI H,", Byte),
+        op("DAA", Implied),
+        None,
+        op("DAD H", Implied),
+        op("LHLD ", Word),
+        op("DCX H", Implied),
+        op("INR L", Implied),
+        op("DCR L", Implied),
+        op("MVI L,", Byte),
+        op("CMA", Implied),
+        // 0x30
+        None,
+        op("LXI SP,", Word),
+        op("STA ", Word),
+        op("INX SP", Implied),
+        op("INR M", Implied),
+        op("DCR M", Implied),
+        op("MVI M,", Byte),
+        op("STC", Implied),
+        None,
+        op("DAD SP", Implied),
+        op("LDA ", Word),
+        op("DCX SP", Implied),
+        op("INR A", Implied),
+        op("DCR A", Implied),
+        op("MVI A,", Byte),
+        op("CMC", Implied),
+        // 0x40
+        op("MOV B,B", Implied),
+        op("MOV B,C", Implied),
+        op("MOV B,D", Implied),
+        op("MOV B,E", Implied),
+        op("MOV B,H", Implied),
+        op("MOV B,L", Implied),
+        op("MOV B,M", Implied),
+        op("MOV B,A", Implied),
+        op("MOV C,B", Implied),
+        op("MOV C,C", Implied),
+        op("MOV C,D", Implied),
+        op("MOV C,E", Implied),
+        op("MOV C,H", Implied),
+        op("MOV C,L", Implied),
+        op("MOV C,M", Implied),
+        op("MOV C,A", Implied),
+        // 0x50
+        op("MOV D,B", Implied),
+        op("MOV D,C", Implied),
+        op("MOV D,D", Implied),
+        op("MOV D,E", Implied),
+        op("MOV D,H", Implied),
+        op("MOV D,L", Implied),
+        op("MOV D,M", Implied),
+        op("MOV D,A", Implied),
+        op("MOV E,B", Implied),
+        op("MOV E,C", Implied),
+        op("MOV E,D", Implied),
+        op("MOV E,E", Implied),
+        op("MOV E,H", Implied),
+        op("MOV E,L", Implied),
+        op("MOV E,M", Implied),
+        op("MOV E,A", Implied),
+        // 0x60
+        op("MOV H,B", Implied),
+        op("MOV H,C", Implied),
+        op("MOV H,D", Implied),
+        op("MOV H,E", Implied),
+        op("MOV H,H", Implied),
+        op("MOV H,L", Implied),
+        op("MOV H,M", Implied),
+        op("MOV H,A", Implied),
+        op("MOV L,B", Implied),
+        op("MOV L,C", Implied),
+        op("MOV L,D", Implied),
+        op("MOV L,E", Implied),
+        op("MOV L,H", Implied),
+        op("MOV L,L", Implied),
+        op("MOV L,M", Implied),
+        op("MOV L,A", Implied),
+        // 0x70
+        op("MOV M,B", Implied),
+        op("MOV M,C", Implied),
+        op("MOV M,D", Implied),
+        op("MOV M,E", Implied),
+        op("MOV M,H", Implied),
+        op("MOV M,L", Implied),
+        op("HLT", Implied),
+        op("MOV M,A", Implied),
+        op("MOV A,B", Implied),
+        op("MOV A,C", Implied),
+        op("MOV A,D", Implied),
+        op("MOV A,E", Implied),
+        op("MOV A,H", Implied),
+        op("MOV A,L", Implied),
+        op("MOV A,M", Implied),
+        op("MOV A,A", Implied),
+        // 0x80
+        op("ADD B", Implied),
+        op("ADD C", Implied),
+        op("ADD D", Implied),
+        op("ADD E", Implied),
+        op("ADD H", Implied),
+        op("ADD L", Implied),
+        op("ADD M", Implied),
+        op("ADD A", Implied),
+        op("ADC B", Implied),
+        op("ADC C", Implied),
+        op("ADC D", Implied),
+        op("ADC E", Implied),
+        op("ADC H", Implied),
+        op("ADC L", Implied),
+        op("ADC M", Implied),
+        op("ADC A", Implied),
+        // 0x90
+        op("SUB B", Implied),
+        op("SUB C", Implied),
+        op("SUB D", Implied),
+        op("SUB E", Implied),
+        op("SUB H", Implied),
+        op("SUB L", Implied),
+        op("SUB M", Implied),
+        op("SUB A", Implied),
+        op("SBB B", Implied),
+        op("SBB C", Implied),
+        op("SBB D", Implied),
+        op("SBB E", Implied),
+        op("SBB H", Implied),
+        op("SBB L", Implied),
+        op("SBB M", Implied),
+        op("SBB A", Implied),
+        // 0xA0
+        op("ANA B", Implied),
+        op("ANA C", Implied),
+        op("ANA D", Implied),
+        op("ANA E", Implied),
+        op("ANA H", Implied),
+        op("ANA L", Implied),
+        op("ANA M", Implied),
+        op("ANA A", Implied),
+        op("XRA B", Implied),
+        op("XRA C", Implied),
+        op("XRA D", Implied),
+        op("XRA E", Implied),
+        op("XRA H", Implied),
+        op("XRA L", Implied),
+        op("XRA M", Implied),
+        op("XRA A", Implied),
+        // 0xB0
+        op("ORA B", Implied),
+        op("ORA C", Implied),
+        op("ORA D", Implied),
+        op("ORA E", Implied),
+        op("ORA H", Implied),
+        op("ORA L", Implied),
+        op("ORA M", Implied),
+        op("ORA A", Implied),
+        op("CMP B", Implied),
+        op("CMP C", Implied),
+        op("CMP D", Implied),
+        op("CMP E", Implied),
+        op("CMP H", Implied),
+        op("CMP L", Implied),
+        op("CMP M", Implied),
+        op("CMP A", Implied),
+        // 0xC0
+        op("RNZ", Implied),
+        op("POP B", Implied),
+        op("JNZ ", Word),
+        op("JMP ", Word),
+        op("CNZ ", Word),
+        op("PUSH B", Implied),
+        op("ADI ", Byte),
+        op("RST 0", Implied),
+        op("RZ", Implied),
+        op("RET", Implied),
+        op("JZ ", Word),
+        None,
+        op("CZ ", Word),
+        op("CALL ", Word),
+        op("ACI ", Byte),
+        op("RST 1", Implied),
+        // 0xD0
+        op("RNC", Implied),
+        op("POP D", Implied),
+        op("JNC ", Word),
+        op("OUT ", Byte),
+        op("CNC ", Word),
+        op("PUSH D", Implied),
+        op("SUI ", Byte),
+        op("RST 2", Implied),
+        op("RC", Implied),
+        None,
+        op("JC ", Word),
+        op("IN ", Byte),
+        op("CC ", Word),
+        None,
+        op("SBI ", Byte),
+        op("RST 3", Implied),
+        // 0xE0
+        op("RPO", Implied),
+        op("POP H", Implied),
+        op("JPO ", Word),
+        op("XTHL", Implied),
+        op("CPO ", Word),
+        op("PUSH H", Implied),
+        op("ANI ", Byte),
+        op("RST 4", Implied),
+        op("RPE", Implied),
+        op("PCHL", Implied),
+        op("JPE ", Word),
+        op("XCHG", Implied),
+        op("CPE ", Word),
+        None,
+        op("XRI ", Byte),
+        op("RST 5", Implied),
+        // 0xF0
+        op("RP", Implied),
+        op("POP PSW", Implied),
+        op("JP ", Word),
+        op("DI", Implied),
+        op("CP ", Word),
+        op("PUSH PSW", Implied),
+        op("ORI ", Byte),
+        op("RST 6", Implied),
+        op("RM", Implied),
+        op("SPHL", Implied),
+        op("JM ", Word),
+        op("EI", Implied),
+        op("CM ", Word),
+        None,
+        op("CPI ", Byte),
+        op("RST 7", Implied),
+    ];
+
+    /// Intel style hex literal, e.g. `3EH`, `0FFH`.
+    pub(crate) fn hex_literal(value: u16, digits: usize) -> String {
+        let hex = format!("{:0digits$X}H", value, digits = digits);
+        if hex.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            format!("0{}", hex)
+        } else {
+            hex
+        }
+    }
+
+    #[derive(Debug, Default, Copy, Clone)]
+    pub struct I8080Disassembler;
+
+    impl Disassembler for I8080Disassembler {
+        type Data = u8;
+
+        fn disassemble(&self, bytes: &[u8]) -> Option<(String, usize)> {
+            self.disassemble_with(bytes, &SymbolTable::new())
+        }
+    }
+
+    impl I8080Disassembler {
+        /// like [`Disassembler::disassemble`], showing 16-bit operands that have a label by it.
+        pub fn disassemble_with(
+            &self,
+            bytes: &[u8],
+            symbols: &SymbolTable,
+        ) -> Option<(String, usize)> {
+            let (&opcode, rest) = bytes.split_first()?;
+            let Some(op) = I8080_OPCODES[canonical_opcode(opcode) as usize] else {
+                return Some((format!("DB {}", hex_literal(opcode as u16, 2)), 1));
+            };
+            let operand = rest.get(..op.operand.size())?;
+            let text = match op.operand {
+                Implied => op.mnemonic.to_string(),
+                Byte => format!("{}{}", op.mnemonic, hex_literal(operand[0] as u16, 2)),
+                Word => {
+                    let word = u16::from_le_bytes([operand[0], operand[1]]);
+                    match symbols.label(word) {
+                        Some(label) => format!("{}{}", op.mnemonic, label),
+                        None => format!("{}{}", op.mnemonic, hex_literal(word, 4)),
+                    }
+                }
+            };
+            Some((text, 1 + op.operand.size()))
+        }
+
+        /// Disassembles `count` instructions from `start`, wrapping around the address space.
+        pub fn disassemble_memory<M>(
+            &self,
+            memory: &M,
+            start: u16,
+            count: usize,
+        ) -> Vec<(u16, String)>
+        where
+            M: Memory<Address = u16, Data = u8>,
+        {
+            let mut address = start;
+            (0..count)
+                .map(|_| {
+                    let bytes = [0, 1, 2].map(|i| memory.read(address.wrapping_add(i)));
+                    let (text, len) = self.disassemble(&bytes).unwrap();
+                    let res = (address, text);
+                    address = address.wrapping_add(len as u16);
+                    res
+                })
+                .collect()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::typical::*;
+    use super::*;
+    use crate::memory::typical::Memory8Bit64KB;
+    use alloc::vec;
+
+    #[test]
+    fn disassemble() {
+        let dis = I8080Disassembler;
+        assert_eq!(dis.disassemble(&[0x00]), Some(("NOP".to_string(), 1)));
+        assert_eq!(dis.disassemble(&[0x78]), Some(("MOV A,B".to_string(), 1)));
+        assert_eq!(
+            dis.disassemble(&[0x3e, 0xff]),
+            Some(("MVI A,0FFH".to_string(), 2))
+        );
+        assert_eq!(
+            dis.disassemble(&[0xc3, 0x34, 0x12]),
+            Some(("JMP 1234H".to_string(), 3))
+        );
+        assert_eq!(dis.disassemble(&[0x08]), Some(("NOP".to_string(), 1)));
+        assert_eq!(
+            dis.disassemble(&[0xdd, 0x34, 0x12]),
+            Some(("CALL 1234H".to_string(), 3))
+        );
+        assert_eq!(dis.disassemble(&[0xcd, 0x00]), None);
+        assert_eq!(dis.disassemble(&[]), None);
+    }
+
+    #[test]
+    fn symbols() {
+        use crate::symbols::SymbolTable;
+
+        let symbols = SymbolTable::parse("3f2a print_char\n").unwrap();
+        let dis = I8080Disassembler;
+        assert_eq!(
+            dis.disassemble_with(&[0xcd, 0x2a, 0x3f], &symbols),
+            Some(("CALL print_char".to_string(), 3))
+        );
+        assert_eq!(
+            dis.disassemble_with(&[0xcd, 0x2b, 0x3f], &symbols),
+            Some(("CALL 3F2BH".to_string(), 3))
+        );
+    }
+
+    #[test]
+    fn disassemble_all() {
+        let program = [0x21, 0x00, 0x80, 0x7e, 0xfe, 0x0a, 0xc2, 0x03, 0x00, 0x76];
+        let listing = I8080Disassembler.disassemble_all(&program);
+        assert_eq!(
+            listing,
+            vec![
+                (0, "LXI H,8000H".to_string()),
+                (3, "MOV A,M".to_string()),
+                (4, "CPI 0AH".to_string()),
+                (6, "JNZ 0003H".to_string()),
+                (9, "HLT".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn disassemble_memory() {
+        let memory = Memory8Bit64KB::new(&[0x01, 0x34, 0x12, 0xc5, 0xc9]);
+        let listing = I8080Disassembler.disassemble_memory(&memory, 0, 3);
+        assert_eq!(
+            listing,
+            vec![
+                (0, "LXI B,1234H".to_string()),
+                (3, "PUSH B".to_string()),
+                (4, "RET".to_string()),
+            ]
+        );
+    }
+}
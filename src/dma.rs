@@ -0,0 +1,299 @@
+use crate::bus::Device;
+use crate::memory::Memory;
+use crate::register::RegisterIncrementable;
+
+/// A block transfer in progress, copying `length` units from `source` to `destination`.
+///
+/// Each unit moved steals `cycles_per_unit` cycles from the CPU.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Transfer<A> {
+    pub source: A,
+    pub destination: A,
+    pub length: usize,
+    pub cycles_per_unit: u64,
+}
+
+impl<A: Copy + RegisterIncrementable> Transfer<A> {
+    pub fn new(source: A, destination: A, length: usize) -> Self {
+        Self {
+            source,
+            destination,
+            length,
+            cycles_per_unit: 4,
+        }
+    }
+
+    pub fn cycles_per_unit(mut self, cycles: u64) -> Self {
+        self.cycles_per_unit = cycles;
+        self
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.length == 0
+    }
+
+    /// units that fit in `budget` cycles.
+    fn units(&self, budget: u64) -> usize {
+        match self.cycles_per_unit {
+            0 => self.length,
+            cycles => self.length.min((budget / cycles) as usize),
+        }
+    }
+
+    /// Copies between memories as far as `budget` cycles allow, returning the cycles stolen.
+    pub fn run<S, D, T>(&mut self, src: &S, dst: &mut D, budget: u64) -> u64
+    where
+        S: Memory<Address = A, Data = T>,
+        D: Memory<Address = A, Data = T>,
+    {
+        let units = self.units(budget);
+        for _ in 0..units {
+            dst.store(self.destination, src.read(self.source));
+            self.source.increment();
+            self.destination.increment();
+        }
+        self.length -= units;
+        units as u64 * self.cycles_per_unit
+    }
+
+    /// Like [`Transfer::run`], but reads a device at the fixed offset `source`, e.g. its data port.
+    pub fn run_from_device<S, D, T>(&mut self, src: &mut S, dst: &mut D, budget: u64) -> u64
+    where
+        S: Device<Address = A, Data = T>,
+        D: Memory<Address = A, Data = T>,
+    {
+        let units = self.units(budget);
+        for _ in 0..units {
+            dst.store(self.destination, src.read(self.source));
+            self.destination.increment();
+        }
+        self.length -= units;
+        units as u64 * self.cycles_per_unit
+    }
+}
+
+pub mod typical {
+    use super::*;
+
+    /// Direction of a µPD8257 channel, from the top 2 bits of its count register.
+    #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+    pub enum UPD8257Mode {
+        /// count only.
+        Verify,
+        /// device to memory.
+        Write,
+        /// memory to device.
+        Read,
+    }
+
+    #[derive(Debug, Default, Copy, Clone)]
+    struct Channel {
+        address: u16,
+        count: u16,
+    }
+
+    /// µPD8257 (i8257) four-channel DMA controller, as used by the PC-8801 CRTC.
+    ///
+    /// Registers, by offset: channel n address at 2n and terminal count at 2n+1,
+    /// both written low byte first; mode set (write) and status (read) at 8.
+    #[derive(Debug, Default, Clone)]
+    pub struct UPD8257 {
+        channels: [Channel; 4],
+        mode: u8,
+        status: u8,
+        high_byte: bool,
+    }
+
+    impl UPD8257 {
+        pub const TC_STOP: u8 = 0x40;
+        pub const CYCLES_PER_BYTE: u64 = 4;
+
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        /// whether `channel` is enabled, never for a channel past the 4th.
+        pub fn enabled(&self, channel: usize) -> bool {
+            channel < self.channels.len() && self.mode & (1 << channel) != 0
+        }
+
+        /// the mode of `channel`, `None` past the 4th.
+        pub fn mode(&self, channel: usize) -> Option<UPD8257Mode> {
+            let mode = match self.channels.get(channel)?.count >> 14 {
+                1 => UPD8257Mode::Write,
+                2 => UPD8257Mode::Read,
+                _ => UPD8257Mode::Verify,
+            };
+            Some(mode)
+        }
+
+        /// the address `channel` transfers next, `None` past the 4th.
+        pub fn address(&self, channel: usize) -> Option<u16> {
+            self.channels.get(channel).map(|ch| ch.address)
+        }
+
+        /// Services `channel` between `memory` and `device` for up to `budget` cycles,
+        /// returning the cycles stolen from the CPU, or `None` if there is no such channel.
+        pub fn run_channel<M, D>(
+            &mut self,
+            channel: usize,
+            memory: &mut M,
+            device: &mut D,
+            budget: u64,
+        ) -> Option<u64>
+        where
+            M: Memory<Address = u16, Data = u8>,
+            D: Device<Address = u16, Data = u8>,
+        {
+            let mode = self.mode(channel)?;
+            let mut cycles = 0;
+            while self.enabled(channel) && cycles + Self::CYCLES_PER_BYTE <= budget {
+                let ch = &mut self.channels[channel];
+                match mode {
+                    UPD8257Mode::Write => memory.store(ch.address, device.read(0)),
+                    UPD8257Mode::Read => device.write(0, memory.read(ch.address)),
+                    UPD8257Mode::Verify => {}
+                }
+                ch.address = ch.address.wrapping_add(1);
+                cycles += Self::CYCLES_PER_BYTE;
+                let count = ch.count & 0x3fff;
+                ch.count = (ch.count & 0xc000) | count.wrapping_sub(1) & 0x3fff;
+                if count == 0 {
+                    self.status |= 1 << channel;
+                    if self.mode & Self::TC_STOP != 0 {
+                        self.mode &= !(1 << channel);
+                    }
+                }
+            }
+            Some(cycles)
+        }
+    }
+
+    impl Device for UPD8257 {
+        type Address = u16;
+        type Data = u8;
+
+        fn read(&mut self, offset: u16) -> u8 {
+            if offset == 8 {
+                return core::mem::take(&mut self.status);
+            }
+            let Some(channel) = self.channels.get((offset / 2) as usize) else {
+                return 0xff;
+            };
+            let word = match offset % 2 {
+                0 => channel.address,
+                _ => channel.count,
+            };
+            self.high_byte = !self.high_byte;
+            match self.high_byte {
+                true => word as u8,
+                false => (word >> 8) as u8,
+            }
+        }
+
+        fn write(&mut self, offset: u16, data: u8) {
+            if offset == 8 {
+                self.mode = data;
+                self.high_byte = false;
+                return;
+            }
+            let Some(channel) = self.channels.get_mut((offset / 2) as usize) else {
+                return;
+            };
+            let word = match offset % 2 {
+                0 => &mut channel.address,
+                _ => &mut channel.count,
+            };
+            *word = match self.high_byte {
+                false => (*word & 0xff00) | data as u16,
+                true => (*word & 0x00ff) | (data as u16) << 8,
+            };
+            self.high_byte = !self.high_byte;
+        }
+
+        fn reset(&mut self) {
+            *self = Self::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::typical::*;
+    use super::*;
+    use crate::memory::typical::Memory8Bit64KB;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn transfer() {
+        let src = Memory8Bit64KB::new(&[1, 2, 3, 4, 5]);
+        let mut dst = Memory8Bit64KB::default();
+        let mut transfer = Transfer::new(0x0000u16, 0x8000u16, 5).cycles_per_unit(2);
+        assert_eq!(transfer.run(&src, &mut dst, 7), 6);
+        assert_eq!(transfer.length, 2);
+        assert_eq!(transfer.run(&src, &mut dst, 100), 4);
+        assert!(transfer.is_done());
+        assert_eq!(
+            (0x8000..0x8006).map(|i| dst.read(i)).collect::<Vec<_>>(),
+            [1, 2, 3, 4, 5, 0]
+        );
+    }
+
+    /// a device whose data port counts up.
+    #[derive(Debug, Default)]
+    struct Port(u8);
+
+    impl Device for Port {
+        type Address = u16;
+        type Data = u8;
+        fn read(&mut self, _offset: u16) -> u8 {
+            self.0 += 1;
+            self.0
+        }
+        fn write(&mut self, _offset: u16, data: u8) {
+            self.0 = data;
+        }
+    }
+
+    #[test]
+    fn from_device() {
+        let mut memory = Memory8Bit64KB::default();
+        let mut transfer = Transfer::new(0u16, 0x0010u16, 3);
+        assert_eq!(transfer.run_from_device(&mut Port(0), &mut memory, 100), 12);
+        assert_eq!([memory.read(0x10), memory.read(0x12)], [1, 3]);
+    }
+
+    #[test]
+    fn upd8257() {
+        let mut dma = UPD8257::new();
+        let mut memory = Memory8Bit64KB::new(&[0, 0, 0, 0x11, 0x22, 0x33]);
+        let mut port = Port(0);
+        // channel 2: read 3 bytes from 0x0003, stopping at terminal count
+        for (offset, data) in [(4, 0x03), (4, 0x00), (5, 0x02), (5, 0x80)] {
+            dma.write(offset, data);
+        }
+        dma.write(8, UPD8257::TC_STOP | 0x04);
+        assert_eq!(dma.mode(2), Some(UPD8257Mode::Read));
+        assert_eq!(dma.run_channel(2, &mut memory, &mut port, 9), Some(8));
+        assert_eq!(port.0, 0x22);
+        assert_eq!(dma.run_channel(2, &mut memory, &mut port, 100), Some(4));
+        assert_eq!(port.0, 0x33);
+        assert!(!dma.enabled(2));
+        assert_eq!(dma.read(8), 0x04);
+        assert_eq!(dma.read(8), 0x00);
+        assert_eq!([dma.read(4), dma.read(4)], [0x06, 0x00]);
+        // channel 0: write from the device into memory
+        for (offset, data) in [(0, 0x00), (0, 0x01), (1, 0x01), (1, 0x40)] {
+            dma.write(offset, data);
+        }
+        dma.write(8, 0x01);
+        assert_eq!(dma.run_channel(0, &mut memory, &mut Port(0), 8), Some(8));
+        assert_eq!([memory.read(0x0100), memory.read(0x0101)], [1, 2]);
+        assert_eq!(dma.address(0), Some(0x0102));
+        // there are only 4 channels
+        dma.write(8, 0xff);
+        assert_eq!(dma.run_channel(4, &mut memory, &mut Port(0), 8), None);
+        assert!(!dma.enabled(8));
+        assert_eq!(dma.address(4), None);
+    }
+}
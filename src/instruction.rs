@@ -1,32 +1,153 @@
-use crate::cpu::CPUMemory;
+use crate::cpu::CPURunningState;
+use alloc::{boxed::Box, format, vec, vec::Vec};
+use core::fmt;
 
 pub trait Instruction<C> {
-    fn execute(self, cpu: C) -> C;
+    fn execute(&self, cpu: &mut C);
 }
 
+/// An instruction of any type, for decoders open to user extensions.
+/// Decoders of a fixed instruction set may decode into an enum instead, allocating nothing.
+pub type DynInstruction<C> = Box<dyn Instruction<C>>;
+
+impl<C, I: Instruction<C> + ?Sized> Instruction<C> for Box<I> {
+    fn execute(&self, cpu: &mut C) {
+        (**self).execute(cpu)
+    }
+}
+
+/// What a decoder made of the words fed so far.
+pub enum DecodeResult<I, D> {
+    /// more words are needed.
+    Incomplete,
+    Complete(I),
+    /// the word cannot start or continue an instruction; the decoder is reset.
+    Invalid(D),
+}
+
+impl<I, D> DecodeResult<I, D> {
+    pub fn complete(self) -> Option<I> {
+        match self {
+            DecodeResult::Complete(instruction) => Some(instruction),
+            _ => None,
+        }
+    }
+    pub fn is_incomplete(&self) -> bool {
+        matches!(self, DecodeResult::Incomplete)
+    }
+}
+
+impl<I, D: fmt::LowerHex> DecodeResult<I, D> {
+    /// what stops the CPU on an invalid word, e.g. `illegal opcode fd`.
+    pub fn error(&self) -> Option<CPURunningState> {
+        match self {
+            DecodeResult::Invalid(data) => Some(CPURunningState::Error(format!(
+                "illegal opcode {:02x}",
+                data
+            ))),
+            _ => None,
+        }
+    }
+}
+
+impl<I, D: fmt::Debug> fmt::Debug for DecodeResult<I, D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeResult::Incomplete => write!(f, "Incomplete"),
+            DecodeResult::Complete(_) => write!(f, "Complete(..)"),
+            DecodeResult::Invalid(data) => f.debug_tuple("Invalid").field(data).finish(),
+        }
+    }
+}
+
+/// A resumable decoder, fed one word at a time.
 pub trait InstructionDecoder<C> {
+    type Instruction: Instruction<C>;
     type InstructionSize;
-    fn decode(&mut self, data: Self::InstructionSize) -> Option<Box<dyn Instruction<C>>>;
+    fn decode(
+        &mut self,
+        data: Self::InstructionSize,
+    ) -> DecodeResult<Self::Instruction, Self::InstructionSize>;
+    /// whether an instruction is partially decoded.
+    fn needs_more(&self) -> bool;
+    /// drops a partially decoded instruction, e.g. on reset or a jump mid-stream.
+    fn reset(&mut self);
+}
+
+/// What a decoder does with an opcode it does not know.
+/// Strict emulation stops, while software relying on undocumented behaviour may need a hook.
+pub enum IllegalOpcode<I, D> {
+    /// executes nothing.
+    Nop,
+    /// reports it [`DecodeResult::Invalid`], stopping with [`DecodeResult::error`].
+    Error,
+    /// decodes it with the given function.
+    Hook(Box<dyn Fn(D) -> I>),
+}
+
+// derived, it would require `I: Default`, which boxed instructions are not.
+#[allow(clippy::derivable_impls)]
+impl<I, D> Default for IllegalOpcode<I, D> {
+    fn default() -> Self {
+        IllegalOpcode::Nop
+    }
+}
+
+impl<I: From<typical::Nop>, D> IllegalOpcode<I, D> {
+    /// the instruction `opcode` executes as, for a decoder that does not know it,
+    /// or `None` for the decoder to report it invalid.
+    pub fn decode(&self, opcode: D) -> Option<I> {
+        match self {
+            IllegalOpcode::Nop => Some(typical::Nop.into()),
+            IllegalOpcode::Error => None,
+            IllegalOpcode::Hook(hook) => Some(hook(opcode)),
+        }
+    }
+}
+
+/// Operands following an opcode, as a [`typical::DecoderBuilder`] reads them.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Operand {
+    None,
+    Imm8,
+    /// little-endian.
+    Imm16,
+}
+
+impl Operand {
+    pub fn bytes(self) -> usize {
+        match self {
+            Operand::None => 0,
+            Operand::Imm8 => 1,
+            Operand::Imm16 => 2,
+        }
+    }
 }
 
 pub mod typical {
     use super::*;
-    use crate::addressing::Addressing;
-    use crate::alu::ALU;
+    use crate::addressing::{Addressing, AddressingMut};
+    use crate::alu::{ALUBits, ALUCarry, ALUCounter, ALU};
     use crate::cpu::*;
-    use crate::memory::Memory;
+    use crate::memory::{Endian, Memory, MemoryWord};
     use crate::register::*;
+    use crate::system::System;
+    use alloc::collections::BTreeMap;
+    use alloc::rc::Rc;
+    use core::cell::RefCell;
 
+    #[derive(Clone)]
     pub struct Jump<A> {
         address: A,
     }
 
     impl<C, A> Instruction<C> for Jump<A>
     where
-        C: CPUJump<Address = A>,
+        C: CPUJump<Address = A> + Copy,
+        A: Copy,
     {
-        fn execute(self, cpu: C) -> C {
-            cpu.jump(self.address)
+        fn execute(&self, cpu: &mut C) {
+            *cpu = cpu.jump(self.address)
         }
     }
 
@@ -36,17 +157,478 @@ pub mod typical {
         }
     }
 
+    /// fetches a signed displacement and jumps relative to the following instruction,
+    /// as JR of Z80.
+    #[derive(Clone)]
+    pub struct JumpRelative;
+
+    impl<C, M> Instruction<System<C, M>> for JumpRelative
+    where
+        C: CPUJump<Address = u16, Data = u8> + CPUMemory<M> + Copy,
+        M: Memory<Address = u16, Data = u8>,
+    {
+        fn execute(&self, system: &mut System<C, M>) {
+            let cpu = system.cpu.program_fetch_signed(&system.memory);
+            system.cpu = cpu.jump(cpu.address())
+        }
+    }
+
+    /// [`JumpRelative`] taken only if `flag` is `set`, as JR NZ of Z80 or BNE of 6502.
+    /// The displacement is fetched either way.
+    #[derive(Clone)]
+    pub struct JumpRelativeIf<F> {
+        flag: F,
+        set: bool,
+    }
+
+    impl<F> JumpRelativeIf<F> {
+        pub fn new(flag: F, set: bool) -> Self {
+            Self { flag, set }
+        }
+    }
+
+    impl<C, M, F> Instruction<System<C, M>> for JumpRelativeIf<F>
+    where
+        C: CPUJump<Address = u16, Data = u8> + CPUFlagRegister + CPUMemory<M> + Copy,
+        C::ALU: ALU<Flag = F>,
+        M: Memory<Address = u16, Data = u8>,
+        F: Copy,
+    {
+        fn execute(&self, system: &mut System<C, M>) {
+            let cpu = system.cpu.program_fetch_signed(&system.memory);
+            system.cpu = match cpu.flag_on(self.flag) == self.set {
+                true => cpu.jump(cpu.address()),
+                false => cpu,
+            }
+        }
+    }
+
+    /// builds an instruction from its opcode and operand, zero-extended.
+    type Constructor<I> = Rc<dyn Fn(u8, u16) -> I>;
+
+    /// Registers opcodes with their operands and constructors, instead of a 256-arm match.
+    /// The constructors build [`DynInstruction`]s, or an enum of the instruction set
+    /// so that decoding allocates nothing.
+    ///
+    /// ```
+    /// use n88::instruction::typical::{DecoderBuilder, Nop};
+    /// use n88::instruction::{DynInstruction, InstructionDecoder, Operand};
+    ///
+    /// let mut decoder = DecoderBuilder::<DynInstruction<()>>::new()
+    ///     .map(0x00, Operand::None, |_, _| Box::new(Nop))
+    ///     .map_all([0x01, 0x11, 0x21], Operand::Imm16, |_, _| Box::new(Nop))
+    ///     .build();
+    /// assert!(decoder.decode(0x21).is_incomplete());
+    /// assert!(decoder.decode(0x34).is_incomplete());
+    /// assert!(decoder.decode(0x12).complete().is_some());
+    /// ```
+    pub struct DecoderBuilder<I> {
+        table: Vec<Option<(Operand, Constructor<I>)>>,
+        illegal: IllegalOpcode<I, u8>,
+    }
+
+    impl<I> Default for DecoderBuilder<I> {
+        fn default() -> Self {
+            Self {
+                table: vec![None; 0x100],
+                illegal: IllegalOpcode::Nop,
+            }
+        }
+    }
+
+    impl<I> DecoderBuilder<I> {
+        pub fn new() -> Self {
+            Self::default()
+        }
+        pub fn map<F>(self, opcode: u8, operand: Operand, constructor: F) -> Self
+        where
+            F: Fn(u8, u16) -> I + 'static,
+        {
+            self.map_all([opcode], operand, constructor)
+        }
+        /// maps a family of opcodes, e.g. `MOV r, r`, telling them apart by the opcode.
+        pub fn map_all<O, F>(mut self, opcodes: O, operand: Operand, constructor: F) -> Self
+        where
+            O: IntoIterator<Item = u8>,
+            F: Fn(u8, u16) -> I + 'static,
+        {
+            let constructor: Constructor<I> = Rc::new(constructor);
+            for opcode in opcodes {
+                self.table[opcode as usize] = Some((operand, constructor.clone()));
+            }
+            self
+        }
+        /// what to do with opcodes not mapped, [`IllegalOpcode::Nop`] by default.
+        pub fn illegal(mut self, illegal: IllegalOpcode<I, u8>) -> Self {
+            self.illegal = illegal;
+            self
+        }
+        pub fn build(self) -> TableDecoder<I> {
+            TableDecoder {
+                table: self.table,
+                illegal: self.illegal,
+                opcode: None,
+                operand: Vec::with_capacity(2),
+            }
+        }
+    }
+
+    /// the decoder built by [`DecoderBuilder`].
+    pub struct TableDecoder<I> {
+        table: Vec<Option<(Operand, Constructor<I>)>>,
+        illegal: IllegalOpcode<I, u8>,
+        opcode: Option<u8>,
+        operand: Vec<u8>,
+    }
+
+    impl<C, I: Instruction<C> + From<Nop>> InstructionDecoder<C> for TableDecoder<I> {
+        type Instruction = I;
+        type InstructionSize = u8;
+
+        fn decode(&mut self, data: u8) -> DecodeResult<I, u8> {
+            let opcode = match self.opcode {
+                Some(opcode) => {
+                    self.operand.push(data);
+                    opcode
+                }
+                None => data,
+            };
+            let (operand, constructor) = match &self.table[opcode as usize] {
+                Some(entry) => entry,
+                None => {
+                    return match self.illegal.decode(opcode) {
+                        Some(instruction) => DecodeResult::Complete(instruction),
+                        None => DecodeResult::Invalid(opcode),
+                    }
+                }
+            };
+            if self.operand.len() < operand.bytes() {
+                self.opcode = Some(opcode);
+                return DecodeResult::Incomplete;
+            }
+            let value = self
+                .operand
+                .iter()
+                .rev()
+                .fold(0u16, |value, &byte| value << 8 | byte as u16);
+            let instruction = constructor(opcode, value);
+            self.reset();
+            DecodeResult::Complete(instruction)
+        }
+
+        fn needs_more(&self) -> bool {
+            self.opcode.is_some()
+        }
+
+        fn reset(&mut self) {
+            self.opcode = None;
+            self.operand.clear();
+        }
+    }
+
+    /// Tries `first`, then `then` for the words `first` finds invalid, so that a base
+    /// decoder can be extended with traps or escape opcodes without rewriting its table.
+    /// `first` should report what it does not know as invalid, e.g. with [`IllegalOpcode::Error`].
+    /// Chains of more decoders nest, `then` being a `ChainedDecoder` itself.
+    ///
+    /// Words `first` took before giving up are replayed into `then`; the instruction is
+    /// invalid if `then` decodes it before all are replayed.
+    pub struct ChainedDecoder<A, B, D> {
+        first: A,
+        then: B,
+        words: Vec<D>,
+        fallen_back: bool,
+    }
+
+    impl<A, B, D> ChainedDecoder<A, B, D> {
+        pub fn new(first: A, then: B) -> Self {
+            Self {
+                first,
+                then,
+                words: Vec::new(),
+                fallen_back: false,
+            }
+        }
+    }
+
+    impl<C, A, B, I, D> InstructionDecoder<C> for ChainedDecoder<A, B, D>
+    where
+        A: InstructionDecoder<C, Instruction = I, InstructionSize = D>,
+        B: InstructionDecoder<C, Instruction = I, InstructionSize = D>,
+        I: Instruction<C>,
+        D: Copy,
+    {
+        type Instruction = I;
+        type InstructionSize = D;
+
+        fn decode(&mut self, data: D) -> DecodeResult<I, D> {
+            if !self.fallen_back {
+                match self.first.decode(data) {
+                    DecodeResult::Incomplete => {
+                        self.words.push(data);
+                        return DecodeResult::Incomplete;
+                    }
+                    DecodeResult::Complete(instruction) => {
+                        self.words.clear();
+                        return DecodeResult::Complete(instruction);
+                    }
+                    DecodeResult::Invalid(_) => {}
+                }
+                self.fallen_back = true;
+                self.then.reset();
+                for word in core::mem::take(&mut self.words) {
+                    if !self.then.decode(word).is_incomplete() {
+                        self.reset();
+                        return DecodeResult::Invalid(data);
+                    }
+                }
+            }
+            let result = self.then.decode(data);
+            if !result.is_incomplete() {
+                self.reset();
+            }
+            result
+        }
+
+        fn needs_more(&self) -> bool {
+            match self.fallen_back {
+                true => self.then.needs_more(),
+                false => self.first.needs_more(),
+            }
+        }
+
+        fn reset(&mut self) {
+            self.first.reset();
+            self.then.reset();
+            self.words.clear();
+            self.fallen_back = false;
+        }
+    }
+
+    /// handlers of [`HostCall`]s by service number.
+    type Services<S> = Rc<RefCell<BTreeMap<u8, Rc<dyn Fn(&mut S)>>>>;
+
+    /// Host functions the guest calls through a trap opcode followed by a service number,
+    /// e.g. to print from a test ROM, or to run a ROM routine natively.
+    ///
+    /// The decoder of [`HostCalls::decoder`] takes the trap opcode only; chain it before the
+    /// decoder of the instruction set with a [`ChainedDecoder`]. Services may be registered
+    /// after the decoder is built, and unregistered ones do nothing.
+    pub struct HostCalls<S> {
+        services: Services<S>,
+    }
+
+    impl<S> Default for HostCalls<S> {
+        fn default() -> Self {
+            Self {
+                services: Rc::new(RefCell::new(BTreeMap::new())),
+            }
+        }
+    }
+
+    impl<S: 'static> HostCalls<S> {
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        /// calls `handler` with the system, its CPU and memory, for `service`.
+        /// The guest goes on after the trap once it returns.
+        pub fn register<F: Fn(&mut S) + 'static>(&self, service: u8, handler: F) -> &Self {
+            self.services.borrow_mut().insert(service, Rc::new(handler));
+            self
+        }
+
+        pub fn unregister(&self, service: u8) {
+            self.services.borrow_mut().remove(&service);
+        }
+
+        /// decodes `opcode` and the service number after it, e.g. an opcode the CPU leaves unused.
+        pub fn decoder(&self, opcode: u8) -> TableDecoder<DynInstruction<S>> {
+            let services = self.services.clone();
+            DecoderBuilder::new()
+                .map(
+                    opcode,
+                    Operand::Imm8,
+                    move |_, service| -> DynInstruction<S> {
+                        Box::new(HostCall {
+                            service: service as u8,
+                            services: services.clone(),
+                        })
+                    },
+                )
+                .illegal(IllegalOpcode::Error)
+                .build()
+        }
+    }
+
+    /// calls the handler of a service of [`HostCalls`].
+    pub struct HostCall<S> {
+        service: u8,
+        services: Services<S>,
+    }
+
+    impl<S> Instruction<S> for HostCall<S> {
+        fn execute(&self, system: &mut S) {
+            // cloned out, so that the handler may register services
+            let handler = self.services.borrow().get(&self.service).cloned();
+            if let Some(handler) = handler {
+                handler(system)
+            }
+        }
+    }
+
+    /// does nothing.
+    #[derive(Clone)]
+    pub struct Nop;
+
+    impl<C> Instruction<C> for Nop {
+        fn execute(&self, _cpu: &mut C) {}
+    }
+
+    impl<C> From<Nop> for DynInstruction<C> {
+        fn from(nop: Nop) -> Self {
+            Box::new(nop)
+        }
+    }
+
+    /// pushes the program counter as a word and jumps, as CALL of I8080.
+    #[derive(Clone)]
+    pub struct Call {
+        address: u16,
+        endian: Endian,
+    }
+
+    impl Call {
+        pub fn new(address: u16, endian: Endian) -> Self {
+            Self { address, endian }
+        }
+    }
+
+    impl<C, M> Instruction<System<C, M>> for Call
+    where
+        C: CPUJump<Address = u16, Data = u8> + CPUStackPointer + Copy,
+        M: Memory<Address = u16, Data = u8>,
+    {
+        fn execute(&self, system: &mut System<C, M>) {
+            let cpu = &mut system.cpu;
+            let pc = *cpu.program_counter();
+            cpu.stack_pointer_decrement();
+            cpu.stack_pointer_decrement();
+            let sp = *cpu.stack_pointer();
+            system.memory.store_word(sp, pc, self.endian);
+            system.cpu = system.cpu.jump(self.address)
+        }
+    }
+
+    /// pushes the program counter and jumps to a fixed vector, as RST of I8080.
+    #[derive(Clone)]
+    pub struct Restart {
+        vector: u16,
+    }
+
+    impl Restart {
+        pub fn new(vector: u16) -> Self {
+            Self { vector }
+        }
+
+        /// RST `n` of I8080 and Z80, jumping to `n * 8`.
+        pub fn rst(n: u8) -> Self {
+            Self::new((n & 7) as u16 * 8)
+        }
+
+        /// the RST of an I8080 opcode, such as an interrupt controller puts on the data bus
+        /// during interrupt acknowledge.
+        pub fn from_opcode(opcode: u8) -> Option<Self> {
+            (opcode & 0xc7 == 0xc7).then(|| Self::rst(opcode >> 3))
+        }
+
+        pub fn vector(&self) -> u16 {
+            self.vector
+        }
+    }
+
+    impl<C, M> Instruction<System<C, M>> for Restart
+    where
+        C: CPUJump<Address = u16, Data = u8> + CPUStackPointer + Copy,
+        M: Memory<Address = u16, Data = u8>,
+    {
+        fn execute(&self, system: &mut System<C, M>) {
+            Call::new(self.vector, Endian::Little).execute(system)
+        }
+    }
+
+    /// pops a word into the program counter, as RET of I8080.
+    #[derive(Clone)]
+    pub struct Return {
+        endian: Endian,
+    }
+
+    impl Return {
+        pub fn new(endian: Endian) -> Self {
+            Self { endian }
+        }
+    }
+
+    impl<C, M> Instruction<System<C, M>> for Return
+    where
+        C: CPUJump<Address = u16, Data = u8> + CPUStackPointer + Copy,
+        M: Memory<Address = u16, Data = u8>,
+    {
+        fn execute(&self, system: &mut System<C, M>) {
+            let cpu = &mut system.cpu;
+            let pc = system.memory.read_word(*cpu.stack_pointer(), self.endian);
+            cpu.stack_pointer_increment();
+            cpu.stack_pointer_increment();
+            system.cpu = system.cpu.jump(pc)
+        }
+    }
+
+    /// stops the CPU until it accepts an interrupt, as HLT of I8080.
+    #[derive(Clone)]
+    pub struct Halt;
+
+    impl<C: CPUHalt> Instruction<C> for Halt {
+        fn execute(&self, cpu: &mut C) {
+            cpu.set_halted(true)
+        }
+    }
+
+    impl<C: CPUHalt, M> Instruction<System<C, M>> for Halt {
+        fn execute(&self, system: &mut System<C, M>) {
+            system.cpu.set_halted(true)
+        }
+    }
+
+    /// sets the interrupt enable flip-flop, as EI and DI of I8080.
+    #[derive(Clone)]
+    pub struct InterruptEnable(pub bool);
+
+    impl<C: CPUHalt> Instruction<C> for InterruptEnable {
+        fn execute(&self, cpu: &mut C) {
+            cpu.set_interrupt_enabled(self.0)
+        }
+    }
+
+    impl<C: CPUHalt, M> Instruction<System<C, M>> for InterruptEnable {
+        fn execute(&self, system: &mut System<C, M>) {
+            system.cpu.set_interrupt_enabled(self.0)
+        }
+    }
+
+    #[derive(Clone)]
     pub struct Push<B> {
         data: B,
     }
 
-    impl<CPU, B> Instruction<CPU> for Push<B>
+    impl<C, M, B> Instruction<System<C, M>> for Push<B>
     where
-        CPU: CPUStackPointer,
-        B: Copy,
+        C: CPUStackPointer<Data = B> + CPUMemory<M> + Copy,
+        C::Address: RegisterDecrementable,
+        M: Memory<Data = B, Address = C::Address>,
+        B: Copy + Register,
     {
-        fn execute(self, cpu: &mut CPU) {
-            cpu.push(self.data)
+        fn execute(&self, system: &mut System<C, M>) {
+            system.cpu = system.cpu.load_data(self.data).push(&mut system.memory)
         }
     }
 
@@ -56,26 +638,99 @@ pub mod typical {
         }
     }
 
-    pub struct Pop<C> {
-        dst: C,
+    #[derive(Clone)]
+    pub struct Pop<R> {
+        dst: R,
+    }
+
+    impl<R> Pop<R> {
+        pub fn new(dst: R) -> Self {
+            Self { dst }
+        }
     }
 
-    impl<CPU, C, B> Instruction<CPU> for Pop<C>
+    impl<C, M, R, B> Instruction<System<C, M>> for Pop<R>
     where
-        CPU: CPUStackPointer + RegisterSet<C, Register = B>,
-        C: Copy + RegisterCode<Register = B>,
+        C: CPUStackPointer<Data = B> + CPUMemory<M> + RegisterSet<R, Register = B> + Copy,
+        C::Address: RegisterIncrementable,
+        M: Memory<Data = B, Address = C::Address>,
+        R: RegisterCode<Register = B> + Copy,
+        B: Copy + Register,
     {
-        fn execute(&self, cpu: &mut CPU) {
-            let src = cpu.pop();
-            cpu.load_of(self.dst, src)
+        fn execute(&self, system: &mut System<C, M>) {
+            system.cpu = system.cpu.pop(&system.memory);
+            let data = system.cpu.data();
+            system.cpu.load_of(self.dst, data)
+        }
+    }
+
+    /// pushes a 16-bit register pair, as PUSH B of I8080.
+    #[derive(Clone)]
+    pub struct PushPair<R> {
+        src: R,
+    }
+
+    impl<R> PushPair<R> {
+        pub fn new(src: R) -> Self {
+            Self { src }
+        }
+    }
+
+    impl<C, M, R> Instruction<System<C, M>> for PushPair<R>
+    where
+        C: CPUStackPointer<Address = u16, Data = u8>
+            + CPUMemory<M>
+            + RegisterSet<R, Register = u16>
+            + Copy,
+        M: Memory<Address = u16, Data = u8>,
+        R: RegisterCode<Register = u16> + Copy,
+    {
+        fn execute(&self, system: &mut System<C, M>) {
+            let pair = system.cpu.read_of(self.src);
+            system.cpu = system.cpu.push_pair(&mut system.memory, pair)
+        }
+    }
+
+    /// pops a 16-bit register pair, as POP PSW of I8080.
+    #[derive(Clone)]
+    pub struct PopPair<R> {
+        dst: R,
+    }
+
+    impl<R> PopPair<R> {
+        pub fn new(dst: R) -> Self {
+            Self { dst }
         }
     }
 
+    impl<C, M, R> Instruction<System<C, M>> for PopPair<R>
+    where
+        C: CPUStackPointer<Address = u16, Data = u8>
+            + CPUMemory<M>
+            + RegisterSet<R, Register = u16>
+            + Copy,
+        M: Memory<Address = u16, Data = u8>,
+        R: RegisterCode<Register = u16> + Copy,
+    {
+        fn execute(&self, system: &mut System<C, M>) {
+            system.cpu = system.cpu.pop_pair(&system.memory);
+            let pair = system.cpu.address();
+            system.cpu.load_of(self.dst, pair)
+        }
+    }
+
+    #[derive(Clone)]
     pub struct Condition<F, I> {
         cond: F,
         then: I,
     }
 
+    impl<F, I> Condition<F, I> {
+        pub fn new(cond: F, then: I) -> Self {
+            Self { cond, then }
+        }
+    }
+
     impl<CPU, F, I> Instruction<CPU> for Condition<F, I>
     where
         F: Fn(&CPU) -> bool,
@@ -88,29 +743,34 @@ pub mod typical {
         }
     }
 
-    pub struct Load<C, A> {
-        dst: C,
-        src: A,
+    /// writes the value of `src` into `dst`.
+    #[derive(Clone)]
+    pub struct Load<D, S> {
+        dst: D,
+        src: S,
     }
 
-    impl<C, A> Load<C, A> {
-        pub fn new(dst: C, src: A) -> Self {
+    impl<D, S> Load<D, S> {
+        pub fn new(dst: D, src: S) -> Self {
             Self { dst, src }
         }
     }
 
-    impl<CPU, C, B, A> Instruction<CPU> for Load<C, A>
+    impl<CPU, D, S, B> Instruction<CPU> for Load<D, S>
     where
-        CPU: RegisterSet<C, Register = B>,
-        C: RegisterCode<Register = B> + Copy,
-        A: Addressing<CPU, Size = B> + Copy,
+        D: AddressingMut<CPU, Size = B>,
+        S: Addressing<CPU, Size = B>,
     {
         fn execute(&self, cpu: &mut CPU) {
             let bits = self.src.value(cpu);
-            cpu.load_of(self.dst, bits);
+            self.src.after(cpu);
+            self.dst.write(cpu, bits);
+            self.dst.after(cpu);
         }
     }
 
+    /// stores `src` into the memory addressed by `dst`.
+    #[derive(Clone)]
     pub struct Store<D, S> {
         dst: D,
         src: S,
@@ -122,23 +782,240 @@ pub mod typical {
         }
     }
 
-    impl<C, D, S> Instruction<C> for Store<D, S> {
+    impl<C, M, D, S> Instruction<System<C, M>> for Store<D, S>
+    where
+        M: Memory,
+        D: Addressing<System<C, M>, Size = M::Address>,
+        S: Addressing<System<C, M>, Size = M::Data>,
+    {
+        fn execute(&self, system: &mut System<C, M>) {
+            let dst = self.dst.value(system);
+            let src = self.src.value(system);
+            self.dst.after(system);
+            self.src.after(system);
+            system.memory.store(dst, src);
+        }
+    }
+
+    /// swaps two registers of the same size, e.g. `XCHG` of I8080.
+    #[derive(Clone)]
+    pub struct Exchange<R, S> {
+        a: R,
+        b: S,
+    }
+
+    impl<R, S> Exchange<R, S> {
+        pub fn new(a: R, b: S) -> Self {
+            Self { a, b }
+        }
+    }
+
+    impl<C, R, S, B> Instruction<C> for Exchange<R, S>
+    where
+        C: RegisterSet<R, Register = B> + RegisterSet<S, Register = B>,
+        R: RegisterCode<Register = B> + Copy,
+        S: RegisterCode<Register = B> + Copy,
+    {
+        fn execute(&self, cpu: &mut C) {
+            let a = cpu.read_of(self.a);
+            let b = cpu.read_of(self.b);
+            cpu.load_of(self.a, b);
+            cpu.load_of(self.b, a);
+        }
+    }
+
+    /// swaps a register with the word on top of the stack, e.g. `XTHL` of I8080.
+    #[derive(Clone)]
+    pub struct ExchangeStack<R> {
+        register: R,
+        endian: Endian,
+    }
+
+    impl<R> ExchangeStack<R> {
+        pub fn new(register: R, endian: Endian) -> Self {
+            Self { register, endian }
+        }
+    }
+
+    impl<C, M, R> Instruction<System<C, M>> for ExchangeStack<R>
+    where
+        C: CPUStackPointer<Address = u16> + RegisterSet<R, Register = u16>,
+        M: Memory<Address = u16, Data = u8>,
+        R: RegisterCode<Register = u16> + Copy,
+    {
+        fn execute(&self, system: &mut System<C, M>) {
+            let sp = *system.cpu.stack_pointer();
+            let top = system.memory.read_word(sp, self.endian);
+            let register = system.cpu.read_of(self.register);
+            system.memory.store_word(sp, register, self.endian);
+            system.cpu.load_of(self.register, top);
+        }
+    }
+
+    /// swaps a register bank with its alternate, e.g. Z80 `EXX`.
+    #[derive(Clone)]
+    pub struct ExchangeBank<K> {
+        bank: K,
+    }
+
+    impl<K> ExchangeBank<K> {
+        pub fn new(bank: K) -> Self {
+            Self { bank }
+        }
+    }
+
+    impl<C: RegisterBanks<K>, K: Copy> Instruction<C> for ExchangeBank<K> {
         fn execute(&self, cpu: &mut C) {
-            let dst = self.dst.value(cpu);
-            let src = self.src.value(cpu);
-            cpu.store_memory(dst, src);
+            cpu.exchange_bank(self.bank)
+        }
+    }
+
+    /// how a pointer register moves after each element of a block instruction.
+    #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+    pub enum BlockStep {
+        Increment,
+        Decrement,
+    }
+
+    fn block_step<T, R, A>(target: &mut T, code: R, step: BlockStep)
+    where
+        T: RegisterSet<R, Register = A>,
+        R: RegisterCode<Register = A> + Copy,
+        A: RegisterIncrementable + RegisterDecrementable,
+    {
+        let mut bits = target.read_of(code);
+        match step {
+            BlockStep::Increment => bits.increment(),
+            BlockStep::Decrement => bits.decrement(),
+        }
+        target.load_of(code, bits)
+    }
+
+    /// decrements `counter` and tells whether it is still nonzero.
+    fn block_count<T, Q, A>(target: &mut T, counter: Q) -> bool
+    where
+        T: RegisterSet<Q, Register = A>,
+        Q: RegisterCode<Register = A> + Copy,
+        A: RegisterDecrementable + Default + Eq + Copy,
+    {
+        let mut count = target.read_of(counter);
+        count.decrement();
+        target.load_of(counter, count);
+        count != A::default()
+    }
+
+    /// copies the memory at `src` to the memory at `dst`, steps both pointers and
+    /// decrements `counter`. Repeating runs until `counter` reaches zero,
+    /// e.g. Z80 `LDIR` is `BlockTransfer::new(HL, DE, BC, BlockStep::Increment, true)`.
+    #[derive(Clone)]
+    pub struct BlockTransfer<R, Q> {
+        src: R,
+        dst: R,
+        counter: Q,
+        step: BlockStep,
+        repeat: bool,
+    }
+
+    impl<R, Q> BlockTransfer<R, Q> {
+        pub fn new(src: R, dst: R, counter: Q, step: BlockStep, repeat: bool) -> Self {
+            Self {
+                src,
+                dst,
+                counter,
+                step,
+                repeat,
+            }
+        }
+    }
+
+    impl<C, M, R, Q, A> Instruction<System<C, M>> for BlockTransfer<R, Q>
+    where
+        C: RegisterSet<R, Register = A> + RegisterSet<Q, Register = A>,
+        M: Memory<Address = A>,
+        R: RegisterCode<Register = A> + Copy,
+        Q: RegisterCode<Register = A> + Copy,
+        A: RegisterIncrementable + RegisterDecrementable + Default + Eq + Copy,
+    {
+        fn execute(&self, system: &mut System<C, M>) {
+            loop {
+                let data = system.memory.read(system.read_of(self.src));
+                let dst = system.read_of(self.dst);
+                system.memory.store(dst, data);
+                block_step(system, self.src, self.step);
+                block_step(system, self.dst, self.step);
+                if !block_count(system, self.counter) || !self.repeat {
+                    break;
+                }
+            }
+        }
+    }
+
+    /// compares `acc` with the memory at `src`, steps the pointer and decrements `counter`.
+    /// Repeating runs until they are equal or `counter` reaches zero, e.g. Z80 `CPIR`.
+    /// `flags` receives whether they were equal and whether `counter` is nonzero.
+    #[derive(Clone)]
+    pub struct BlockCompare<K, R, Q, F> {
+        acc: K,
+        src: R,
+        counter: Q,
+        step: BlockStep,
+        repeat: bool,
+        flags: F,
+    }
+
+    impl<K, R, Q, F> BlockCompare<K, R, Q, F> {
+        pub fn new(acc: K, src: R, counter: Q, step: BlockStep, repeat: bool, flags: F) -> Self {
+            Self {
+                acc,
+                src,
+                counter,
+                step,
+                repeat,
+                flags,
+            }
         }
     }
 
+    impl<C, M, K, R, Q, F, A, B> Instruction<System<C, M>> for BlockCompare<K, R, Q, F>
+    where
+        C: RegisterSet<K, Register = B>
+            + RegisterSet<R, Register = A>
+            + RegisterSet<Q, Register = A>,
+        M: Memory<Address = A, Data = B>,
+        K: RegisterCode<Register = B> + Copy,
+        R: RegisterCode<Register = A> + Copy,
+        Q: RegisterCode<Register = A> + Copy,
+        A: RegisterIncrementable + RegisterDecrementable + Default + Eq + Copy,
+        B: Eq,
+        F: Fn(&mut System<C, M>, bool, bool),
+    {
+        fn execute(&self, system: &mut System<C, M>) {
+            loop {
+                let data = system.memory.read(system.read_of(self.src));
+                let equal = system.read_of(self.acc) == data;
+                block_step(system, self.src, self.step);
+                let remaining = block_count(system, self.counter);
+                (self.flags)(system, equal, remaining);
+                if equal || !remaining || !self.repeat {
+                    break;
+                }
+            }
+        }
+    }
+
+    /// `dst <- dst op rhs` on the ALU, committing only `flags` to the flag register,
+    /// e.g. `INR B` is `Arithmetic::new(Increase, [Sign, Zero, AuxiliaryCarry, Parity], B, _)`.
+    /// `flags` is any list of flags, an array or a `Vec`.
+    #[derive(Clone)]
     pub struct Arithmetic<C, F, D, L> {
         control: C,
-        flags: Vec<F>,
+        flags: F,
         dst: D,
         rhs: L,
     }
 
     impl<C, F, D, L> Arithmetic<C, F, D, L> {
-        pub fn new(control: C, flags: Vec<F>, dst: D, rhs: L) -> Self {
+        pub fn new(control: C, flags: F, dst: D, rhs: L) -> Self {
             Self {
                 control,
                 flags,
@@ -148,32 +1025,349 @@ pub mod typical {
         }
     }
 
-    // todo: aluの（ｒｙ
-    // impl<CPU, A, C, F, D, L, B, G> Instruction<CPU> for Arithmetic<C, F, D, L>
-    // where
-    //     CPU: CPUAccumulator
-    //         + CPUFlagRegister<ALU = A, FlagRegisterSize = G>
-    //         + RegisterSet<D, Register = B>,
-    //     A: ALU<Data = B, Control = C, Flag = F>,
-    //     C: Copy,
-    //     F: Copy,
-    //     D: RegisterCode<Register = B> + Copy,
-    //     L: Addressing<CPU, Size = B>,
-    //     G: From<A::FlagSet>,
-    // {
-    //     fn execute(&self, cpu: &mut CPU) {
-    //         let rhs = self.rhs.value(cpu);
-    //         let (res, flags) = cpu.alu_acc_op(self.control, rhs);
-    //         cpu.flag_load_mask_slice(&self.flags, flags.into());
-    //         cpu.load_of(self.dst, res);
-    //     }
-    // }
+    impl<CPU, A, C, F, D, L, B> Instruction<CPU> for Arithmetic<C, F, D, L>
+    where
+        CPU: CPUFlagRegister<ALU = A, Data = B>,
+        A: ALU<Data = B, Control = C>,
+        C: Copy,
+        F: AsRef<[A::Flag]>,
+        A::Flag: Copy,
+        D: AddressingMut<CPU, Size = B>,
+        L: Addressing<CPU, Size = B>,
+    {
+        fn execute(&self, cpu: &mut CPU) {
+            let flags = cpu.alu_op(self.control, &self.dst, &self.rhs);
+            cpu.flag_load_mask_slice(self.flags.as_ref(), flags.into());
+        }
+    }
+
+    /// operands may be in memory, e.g. `INR M`.
+    impl<CPU, M, A, C, F, D, L, B> Instruction<System<CPU, M>> for Arithmetic<C, F, D, L>
+    where
+        CPU: CPUFlagRegister<ALU = A>,
+        A: ALU<Data = B, Control = C>,
+        C: Copy,
+        F: AsRef<[A::Flag]>,
+        A::Flag: Copy,
+        D: AddressingMut<System<CPU, M>, Size = B>,
+        L: Addressing<System<CPU, M>, Size = B>,
+    {
+        fn execute(&self, system: &mut System<CPU, M>) {
+            let rhs = self.rhs.value(system);
+            self.rhs.after(system);
+            let lhs = self.dst.value(system);
+            let (result, flags) = system.cpu.alu().op(self.control, lhs, rhs);
+            self.dst.write(system, result);
+            self.dst.after(system);
+            system
+                .cpu
+                .flag_load_mask_slice(self.flags.as_ref(), flags.into());
+        }
+    }
+
+    /// the flags of `lhs op rhs` on the ALU, committing only `flags` and dropping the result,
+    /// e.g. `CMP` of I8080 as a subtraction.
+    #[derive(Clone)]
+    pub struct Compare<C, F, L, R> {
+        control: C,
+        flags: F,
+        lhs: L,
+        rhs: R,
+    }
+
+    impl<C, F, L, R> Compare<C, F, L, R> {
+        pub fn new(control: C, flags: F, lhs: L, rhs: R) -> Self {
+            Self {
+                control,
+                flags,
+                lhs,
+                rhs,
+            }
+        }
+    }
+
+    impl<CPU, A, C, F, L, R, B> Instruction<CPU> for Compare<C, F, L, R>
+    where
+        CPU: CPUFlagRegister<ALU = A, Data = B>,
+        A: ALU<Data = B, Control = C>,
+        C: Copy,
+        F: AsRef<[A::Flag]>,
+        A::Flag: Copy,
+        L: Addressing<CPU, Size = B>,
+        R: Addressing<CPU, Size = B>,
+    {
+        fn execute(&self, cpu: &mut CPU) {
+            let rhs = self.rhs.value(cpu);
+            self.rhs.after(cpu);
+            let lhs = self.lhs.value(cpu);
+            self.lhs.after(cpu);
+            let (_, flags) = cpu.alu().op(self.control, lhs, rhs);
+            cpu.flag_load_mask_slice(self.flags.as_ref(), flags.into());
+        }
+    }
+
+    /// operands may be in memory, e.g. `CMP M`.
+    impl<CPU, M, A, C, F, L, R, B> Instruction<System<CPU, M>> for Compare<C, F, L, R>
+    where
+        CPU: CPUFlagRegister<ALU = A>,
+        A: ALU<Data = B, Control = C>,
+        C: Copy,
+        F: AsRef<[A::Flag]>,
+        A::Flag: Copy,
+        L: Addressing<System<CPU, M>, Size = B>,
+        R: Addressing<System<CPU, M>, Size = B>,
+    {
+        fn execute(&self, system: &mut System<CPU, M>) {
+            let rhs = self.rhs.value(system);
+            self.rhs.after(system);
+            let lhs = self.lhs.value(system);
+            self.lhs.after(system);
+            let (_, flags) = system.cpu.alu().op(self.control, lhs, rhs);
+            system
+                .cpu
+                .flag_load_mask_slice(self.flags.as_ref(), flags.into());
+        }
+    }
+
+    /// [`Arithmetic`] taking the carry flag in, e.g. `ADC` and `SBB` of I8080.
+    #[derive(Clone)]
+    pub struct ArithmeticWithCarry<C, F, D, L> {
+        control: C,
+        flags: F,
+        dst: D,
+        rhs: L,
+    }
+
+    impl<C, F, D, L> ArithmeticWithCarry<C, F, D, L> {
+        pub fn new(control: C, flags: F, dst: D, rhs: L) -> Self {
+            Self {
+                control,
+                flags,
+                dst,
+                rhs,
+            }
+        }
+    }
+
+    impl<CPU, A, C, F, D, L, B> Instruction<CPU> for ArithmeticWithCarry<C, F, D, L>
+    where
+        CPU: CPUFlagRegister<ALU = A, Data = B>,
+        A: ALUCarry<Data = B, Control = C>,
+        C: Copy,
+        F: AsRef<[A::Flag]>,
+        A::Flag: Copy,
+        D: AddressingMut<CPU, Size = B>,
+        L: Addressing<CPU, Size = B>,
+    {
+        fn execute(&self, cpu: &mut CPU) {
+            let carry = cpu.flag_on(A::CARRY);
+            let rhs = self.rhs.value(cpu);
+            self.rhs.after(cpu);
+            let lhs = self.dst.value(cpu);
+            let (result, flags) = cpu.alu().op_carry(self.control, lhs, rhs, carry);
+            self.dst.write(cpu, result);
+            self.dst.after(cpu);
+            cpu.flag_load_mask_slice(self.flags.as_ref(), flags.into());
+        }
+    }
+
+    /// operands may be in memory, e.g. `ADC M`.
+    impl<CPU, M, A, C, F, D, L, B> Instruction<System<CPU, M>> for ArithmeticWithCarry<C, F, D, L>
+    where
+        CPU: CPUFlagRegister<ALU = A>,
+        A: ALUCarry<Data = B, Control = C>,
+        C: Copy,
+        F: AsRef<[A::Flag]>,
+        A::Flag: Copy,
+        D: AddressingMut<System<CPU, M>, Size = B>,
+        L: Addressing<System<CPU, M>, Size = B>,
+    {
+        fn execute(&self, system: &mut System<CPU, M>) {
+            let carry = system.cpu.flag_on(A::CARRY);
+            let rhs = self.rhs.value(system);
+            self.rhs.after(system);
+            let lhs = self.dst.value(system);
+            let (result, flags) = system.cpu.alu().op_carry(self.control, lhs, rhs, carry);
+            self.dst.write(system, result);
+            self.dst.after(system);
+            system
+                .cpu
+                .flag_load_mask_slice(self.flags.as_ref(), flags.into());
+        }
+    }
+
+    macro_rules! counter_instruction {
+        ($name:ident, $op:ident, $doc:literal) => {
+            #[doc = $doc]
+            #[derive(Clone)]
+            pub struct $name<F, D> {
+                flags: F,
+                dst: D,
+            }
+
+            impl<F, D> $name<F, D> {
+                pub fn new(flags: F, dst: D) -> Self {
+                    Self { flags, dst }
+                }
+            }
+
+            impl<CPU, A, F, D, B> Instruction<CPU> for $name<F, D>
+            where
+                CPU: CPUFlagRegister<ALU = A, Data = B>,
+                A: ALUCounter<Data = B>,
+                F: AsRef<[A::Flag]>,
+                A::Flag: Copy,
+                D: AddressingMut<CPU, Size = B>,
+            {
+                fn execute(&self, cpu: &mut CPU) {
+                    let (result, flags) = cpu.alu().$op(self.dst.value(cpu));
+                    self.dst.write(cpu, result);
+                    self.dst.after(cpu);
+                    cpu.flag_load_mask_slice(self.flags.as_ref(), flags.into());
+                }
+            }
+
+            impl<CPU, M, A, F, D, B> Instruction<System<CPU, M>> for $name<F, D>
+            where
+                CPU: CPUFlagRegister<ALU = A>,
+                A: ALUCounter<Data = B>,
+                F: AsRef<[A::Flag]>,
+                A::Flag: Copy,
+                D: AddressingMut<System<CPU, M>, Size = B>,
+            {
+                fn execute(&self, system: &mut System<CPU, M>) {
+                    let (result, flags) = system.cpu.alu().$op(self.dst.value(system));
+                    self.dst.write(system, result);
+                    self.dst.after(system);
+                    system
+                        .cpu
+                        .flag_load_mask_slice(self.flags.as_ref(), flags.into());
+                }
+            }
+        };
+    }
+
+    counter_instruction!(
+        Increment,
+        increment,
+        "`dst <- dst + 1` on the ALU, committing only `flags`, e.g. `INR` of I8080 keeping the carry."
+    );
+    counter_instruction!(
+        Decrement,
+        decrement,
+        "`dst <- dst - 1` on the ALU, committing only `flags`, e.g. `DCR` of I8080 keeping the carry."
+    );
+
+    /// tests `bit` of `src` on the ALU, committing only `flags`, e.g. Z80 `BIT 7,H`.
+    #[derive(Clone)]
+    pub struct BitTest<F, S> {
+        bit: u8,
+        flags: F,
+        src: S,
+    }
+
+    impl<F, S> BitTest<F, S> {
+        pub fn new(bit: u8, flags: F, src: S) -> Self {
+            Self { bit, flags, src }
+        }
+    }
+
+    impl<CPU, A, F, S, B> Instruction<CPU> for BitTest<F, S>
+    where
+        CPU: CPUFlagRegister<ALU = A, Data = B>,
+        A: ALUBits<Data = B>,
+        F: AsRef<[A::Flag]>,
+        A::Flag: Copy,
+        S: Addressing<CPU, Size = B>,
+    {
+        fn execute(&self, cpu: &mut CPU) {
+            let flags = cpu.alu().bit_test(self.src.value(cpu), self.bit);
+            self.src.after(cpu);
+            cpu.flag_load_mask_slice(self.flags.as_ref(), flags.into());
+        }
+    }
+
+    /// operands may be in memory, e.g. Z80 `BIT 0,(HL)`.
+    impl<CPU, M, A, F, S, B> Instruction<System<CPU, M>> for BitTest<F, S>
+    where
+        CPU: CPUFlagRegister<ALU = A>,
+        A: ALUBits<Data = B>,
+        F: AsRef<[A::Flag]>,
+        A::Flag: Copy,
+        S: Addressing<System<CPU, M>, Size = B>,
+    {
+        fn execute(&self, system: &mut System<CPU, M>) {
+            let flags = system.cpu.alu().bit_test(self.src.value(system), self.bit);
+            self.src.after(system);
+            system
+                .cpu
+                .flag_load_mask_slice(self.flags.as_ref(), flags.into());
+        }
+    }
+
+    macro_rules! bit_change_instruction {
+        ($name:ident, $set:literal, $doc:literal) => {
+            #[doc = $doc]
+            #[derive(Clone)]
+            pub struct $name<D> {
+                bit: u8,
+                dst: D,
+            }
+
+            impl<D> $name<D> {
+                pub fn new(bit: u8, dst: D) -> Self {
+                    Self { bit, dst }
+                }
+            }
+
+            impl<CPU, A, D, B> Instruction<CPU> for $name<D>
+            where
+                CPU: CPUFlagRegister<ALU = A, Data = B>,
+                A: ALUBits<Data = B>,
+                D: AddressingMut<CPU, Size = B>,
+            {
+                fn execute(&self, cpu: &mut CPU) {
+                    let result = cpu.alu().bit_change(self.dst.value(cpu), self.bit, $set);
+                    self.dst.write(cpu, result);
+                    self.dst.after(cpu);
+                }
+            }
+
+            impl<CPU, M, A, D, B> Instruction<System<CPU, M>> for $name<D>
+            where
+                CPU: CPUFlagRegister<ALU = A>,
+                A: ALUBits<Data = B>,
+                D: AddressingMut<System<CPU, M>, Size = B>,
+            {
+                fn execute(&self, system: &mut System<CPU, M>) {
+                    let value = self.dst.value(system);
+                    let result = system.cpu.alu().bit_change(value, self.bit, $set);
+                    self.dst.write(system, result);
+                    self.dst.after(system);
+                }
+            }
+        };
+    }
+
+    bit_change_instruction!(
+        BitSet,
+        true,
+        "sets `bit` of `dst` on the ALU, leaving the flags, e.g. Z80 `SET 3,A`."
+    );
+    bit_change_instruction!(
+        BitReset,
+        false,
+        "clears `bit` of `dst` on the ALU, leaving the flags, e.g. Z80 `RES 3,A`."
+    );
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::cpu::CPURunningState;
     use crate::instruction::tests::Instructions::{Add, LoadA, LoadB};
-    use crate::instruction::{Instruction, InstructionDecoder};
+    use crate::instruction::DecodeResult::{self, Complete, Incomplete, Invalid};
+    use crate::instruction::{DynInstruction, IllegalOpcode, Instruction, InstructionDecoder};
+    use alloc::{boxed::Box, string::ToString, vec::Vec};
 
     #[derive(Debug, Default)]
     struct CPU8 {
@@ -200,31 +1394,47 @@ mod tests {
         }
     }
 
-    #[derive(Debug, Default)]
+    #[derive(Default)]
     struct CPU8Decoder {
         len: usize,
         buf: [u8; 2],
+        illegal: IllegalOpcode<DynInstruction<CPU8>, u8>,
     }
 
     impl InstructionDecoder<CPU8> for CPU8Decoder {
+        type Instruction = DynInstruction<CPU8>;
         type InstructionSize = u8;
 
-        fn decode(&mut self, data: Self::InstructionSize) -> Option<Box<dyn Instruction<CPU8>>> {
+        fn decode(&mut self, data: u8) -> DecodeResult<DynInstruction<CPU8>, u8> {
+            if self.len == 0 && data > 2 {
+                return match self.illegal.decode(data) {
+                    Some(instruction) => Complete(instruction),
+                    None => Invalid(data),
+                };
+            }
             self.buf[self.len] = data;
             self.len += 1;
             if self.len == 1 && self.buf[0] == 2 {
                 self.len = 0;
-                Some(Box::new(Add))
+                Complete(Box::new(Add))
             } else if self.len == 2 {
                 self.len = 0;
                 match self.buf[0] {
-                    0 => Some(Box::new(LoadA(self.buf[1]))),
-                    _ => Some(Box::new(LoadB(self.buf[1]))),
+                    0 => Complete(Box::new(LoadA(self.buf[1]))),
+                    _ => Complete(Box::new(LoadB(self.buf[1]))),
                 }
             } else {
-                None
+                Incomplete
             }
         }
+
+        fn needs_more(&self) -> bool {
+            self.len != 0
+        }
+
+        fn reset(&mut self) {
+            self.len = 0;
+        }
     }
 
     #[test]
@@ -248,14 +1458,238 @@ mod tests {
         use Instructions::*;
         let mut cpu = CPU8::default();
         let mut decoder = CPU8Decoder::default();
-        decoder.decode(0);
-        decoder.decode(31).unwrap().execute(&mut cpu);
+        assert!(decoder.decode(0).is_incomplete());
+        assert!(decoder.needs_more());
+        decoder.decode(31).complete().unwrap().execute(&mut cpu);
         Add.execute(&mut cpu);
         assert_eq!(cpu.a, 31);
         decoder.decode(1);
-        decoder.decode(41).unwrap().execute(&mut cpu);
+        decoder.decode(41).complete().unwrap().execute(&mut cpu);
         assert_eq!(cpu.b, 41);
-        decoder.decode(2).unwrap().execute(&mut cpu);
+        decoder.decode(2).complete().unwrap().execute(&mut cpu);
         assert_eq!(cpu.a, 72);
+        // reset mid-stream, so that 2 starts an instruction rather than being an operand.
+        decoder.decode(1);
+        decoder.reset();
+        assert!(!decoder.needs_more());
+        decoder.decode(2).complete().unwrap().execute(&mut cpu);
+        assert_eq!((cpu.a, cpu.b), (113, 41));
+    }
+
+    #[test]
+    fn illegal() {
+        let mut cpu = CPU8::default();
+        let mut decoder = CPU8Decoder::default();
+        decoder.decode(0xff).complete().unwrap().execute(&mut cpu);
+        assert_eq!((cpu.a, cpu.b), (0, 0));
+        decoder.illegal = IllegalOpcode::Hook(Box::new(|op| Box::new(LoadB(op))));
+        decoder.decode(0xfe).complete().unwrap().execute(&mut cpu);
+        assert_eq!(cpu.b, 0xfe);
+        decoder.illegal = IllegalOpcode::Error;
+        let invalid = decoder.decode(0xfd);
+        assert!(matches!(invalid, Invalid(0xfd)));
+        assert_eq!(
+            invalid.error(),
+            Some(CPURunningState::Error("illegal opcode fd".to_string()))
+        );
+        // legal opcodes decode as ever
+        assert!(decoder.decode(2).complete().is_some());
+    }
+
+    #[test]
+    fn builder() {
+        use super::typical::DecoderBuilder;
+        use super::Operand;
+
+        let mut decoder = DecoderBuilder::<DynInstruction<CPU8>>::new()
+            .map(0, Operand::Imm8, |_, a| Box::new(LoadA(a as u8)))
+            .map(1, Operand::Imm8, |_, b| Box::new(LoadB(b as u8)))
+            .map(2, Operand::None, |_, _| Box::new(Add))
+            .map_all([3, 4], Operand::Imm16, |op, ab| {
+                Box::new(Instructions::Etc(Box::new(move |cpu: &mut CPU8| {
+                    cpu.a = (ab >> 8) as u8;
+                    cpu.b = ab as u8 + op;
+                })))
+            })
+            .illegal(IllegalOpcode::Error)
+            .build();
+        let mut cpu = CPU8::default();
+        let mut run = |bytes: &[u8]| {
+            for &byte in bytes {
+                if let Some(instruction) = decoder.decode(byte).complete() {
+                    instruction.execute(&mut cpu);
+                }
+            }
+            decoder.needs_more()
+        };
+        assert!(!run(&[0, 30, 1, 12, 2]));
+        assert!(run(&[4, 0x10]));
+        assert!(!run(&[0x20]));
+        assert_eq!((cpu.a, cpu.b), (0x20, 0x14));
+        let invalid = decoder.decode(0xff);
+        assert!(matches!(invalid, Invalid(0xff)));
+        assert_eq!(
+            invalid.error(),
+            Some(CPURunningState::Error("illegal opcode ff".to_string()))
+        );
+        assert!(!decoder.needs_more());
+    }
+
+    #[test]
+    fn chained() {
+        use super::typical::{ChainedDecoder, DecoderBuilder};
+        use super::Operand;
+
+        let base = DecoderBuilder::<DynInstruction<CPU8>>::new()
+            .map(0, Operand::Imm8, |_, a| Box::new(LoadA(a as u8)))
+            .map(2, Operand::None, |_, _| Box::new(Add))
+            .illegal(IllegalOpcode::Error)
+            .build();
+        // an escape opcode taking over 0x02, and a new one
+        let escapes = DecoderBuilder::<DynInstruction<CPU8>>::new()
+            .map(2, Operand::Imm8, |_, b| Box::new(LoadB(b as u8)))
+            .map(0xed, Operand::None, |_, _| Box::new(LoadB(0xed)))
+            .illegal(IllegalOpcode::Error)
+            .build();
+        let mut decoder = ChainedDecoder::new(escapes, base);
+        let mut cpu = CPU8::default();
+        for byte in [0, 0x10, 2] {
+            if let Some(instruction) = decoder.decode(byte).complete() {
+                instruction.execute(&mut cpu);
+            }
+        }
+        assert!(decoder.needs_more());
+        decoder.decode(0x03).complete().unwrap().execute(&mut cpu);
+        assert_eq!((cpu.a, cpu.b), (0x10, 0x03));
+        decoder.decode(0xed).complete().unwrap().execute(&mut cpu);
+        assert_eq!(cpu.b, 0xed);
+        assert!(matches!(decoder.decode(0xff), Invalid(0xff)));
+        assert!(!decoder.needs_more());
+    }
+
+    #[test]
+    fn host_call() {
+        use super::typical::{ChainedDecoder, DecoderBuilder, HostCalls, Nop};
+        use super::Operand;
+        use crate::memory::typical::Memory8Bit64KB;
+        use crate::memory::Memory;
+        use crate::system::System;
+        use alloc::rc::Rc;
+        use core::cell::RefCell;
+
+        type S = System<CPU8, Memory8Bit64KB>;
+        let printed = Rc::new(RefCell::new(Vec::new()));
+        let calls = HostCalls::<S>::new();
+        let out = printed.clone();
+        // prints the byte at the address in A, and answers in B
+        calls.register(1, move |system: &mut S| {
+            out.borrow_mut()
+                .push(system.memory.read(system.cpu.a as u16));
+            system.cpu.b = 0xff;
+        });
+        let base = DecoderBuilder::<DynInstruction<S>>::new()
+            .map(0, Operand::None, |_, _| Box::new(Nop))
+            .illegal(IllegalOpcode::Error)
+            .build();
+        let mut decoder = ChainedDecoder::new(calls.decoder(0xed), base);
+        let mut system = System::new(CPU8::default(), Memory8Bit64KB::new(b"\0hi"));
+        system.cpu.a = 2;
+        let mut run = |words: &[u8], system: &mut S| {
+            for &word in words {
+                if let Some(instruction) = decoder.decode(word).complete() {
+                    instruction.execute(system);
+                }
+            }
+        };
+        run(&[0xed, 1, 0x00], &mut system);
+        assert_eq!(
+            (printed.borrow().as_slice(), system.cpu.b),
+            (&b"i"[..], 0xff)
+        );
+        // registered after the decoder was built, and an unregistered service
+        calls.register(2, |system: &mut S| system.cpu.a = 1);
+        run(&[0xed, 2, 0xed, 3, 0xed, 1], &mut system);
+        assert_eq!(printed.borrow().as_slice(), b"ih");
+    }
+
+    #[test]
+    fn block() {
+        use super::typical::*;
+        use crate::memory::typical::Memory8Bit64KB;
+        use crate::memory::Memory;
+        use crate::register::RegisterSet;
+        use crate::typical::i8080::I8080RegisterCode16Bit::*;
+        use crate::typical::i8080::I8080RegisterCode8Bit::*;
+        use crate::typical::i8080::{I8080System, I8080};
+        use core::cell::Cell;
+
+        let mut system = I8080System::new(I8080::default(), Memory8Bit64KB::new(b"hello"));
+        system.load_of(HL, 0x0000);
+        system.load_of(DE, 0x0100);
+        system.load_of(BC, 5);
+        BlockTransfer::new(HL, DE, BC, BlockStep::Increment, true).execute(&mut system);
+        assert_eq!(
+            (0x0100..0x0105)
+                .map(|i| system.memory.read(i))
+                .collect::<Vec<_>>(),
+            b"hello"
+        );
+        assert_eq!(
+            (system.read_of(HL), system.read_of(DE), system.read_of(BC)),
+            (5, 0x0105, 0)
+        );
+        // LDD moves once, backwards
+        system.load_of(HL, 0x0004);
+        system.load_of(BC, 5);
+        BlockTransfer::new(HL, DE, BC, BlockStep::Decrement, false).execute(&mut system);
+        assert_eq!(system.memory.read(0x0105), b'o');
+        assert_eq!(
+            (system.read_of(HL), system.read_of(DE), system.read_of(BC)),
+            (3, 0x0104, 4)
+        );
+
+        let found = Cell::new((false, false));
+        let flags = |_: &mut I8080System, equal, remaining| found.set((equal, remaining));
+        system.load_of(A, b'l');
+        system.load_of(HL, 0x0000);
+        system.load_of(BC, 5);
+        BlockCompare::new(A, HL, BC, BlockStep::Increment, true, flags).execute(&mut system);
+        assert_eq!(found.get(), (true, true));
+        assert_eq!((system.read_of(HL), system.read_of(BC)), (3, 2));
+        system.load_of(A, b'z');
+        BlockCompare::new(A, HL, BC, BlockStep::Increment, true, flags).execute(&mut system);
+        assert_eq!(found.get(), (false, false));
+        assert_eq!(system.read_of(HL), 5);
+    }
+
+    #[test]
+    fn call() {
+        use super::typical::*;
+        use crate::cpu::{CPUProgramCounter, CPUStackPointer};
+        use crate::memory::typical::Memory8Bit64KB;
+        use crate::memory::{Endian, Memory};
+        use crate::typical::i8080::{I8080System, I8080};
+
+        let mut system = I8080System::new(I8080::default(), Memory8Bit64KB::default());
+        *system.cpu.program_counter() = 0x1234;
+        *system.cpu.stack_pointer() = 0x0000;
+        Call::new(0x8000, Endian::Little).execute(&mut system);
+        assert_eq!(*system.cpu.program_counter(), 0x8000);
+        assert_eq!(*system.cpu.stack_pointer(), 0xfffe);
+        assert_eq!(
+            (system.memory.read(0xfffe), system.memory.read(0xffff)),
+            (0x34, 0x12)
+        );
+        Return::new(Endian::Little).execute(&mut system);
+        assert_eq!(*system.cpu.program_counter(), 0x1234);
+        assert_eq!(*system.cpu.stack_pointer(), 0x0000);
+
+        // RST 7 injected on interrupt acknowledge
+        let rst = Restart::from_opcode(0xff).unwrap();
+        assert_eq!(rst.vector(), 0x0038);
+        assert!(Restart::from_opcode(0xc3).is_none());
+        rst.execute(&mut system);
+        assert_eq!(*system.cpu.program_counter(), 0x0038);
+        assert_eq!(system.memory.read(0xfffe), 0x34);
     }
 }
@@ -1,4 +1,8 @@
-use std::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, Not};
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+use core::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, Not};
 
 pub trait BitwiseOps:
     BitAnd<Output = Self>
@@ -27,14 +31,55 @@ bitwise_ops_impl!(u8 u16 u32 u64 usize);
 
 pub mod register;
 
-// pub mod instruction;
+pub mod instruction;
+
+pub mod cache;
 
 pub mod alu;
 
 pub mod memory;
 
+pub mod bus;
+
+pub mod loader;
+
+pub mod noise;
+
+pub mod dma;
+
+pub mod clock;
+
 pub mod cpu;
 
 pub mod addressing;
 
+pub mod system;
+
+pub mod machine;
+
+pub mod registry;
+
+#[cfg(feature = "std")]
+pub mod runner;
+
+pub mod observer;
+
+pub mod signal;
+
+pub mod trace;
+
+pub mod profile;
+
+pub mod coverage;
+
+pub mod rewind;
+
+pub mod disasm;
+
+pub mod symbols;
+
+pub mod asm;
+
+pub mod debug;
+
 pub mod typical;
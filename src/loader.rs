@@ -0,0 +1,291 @@
+use crate::memory::{Memory, MemoryError};
+use alloc::{string::String, string::ToString, vec::Vec};
+use core::fmt;
+#[cfg(feature = "std")]
+use std::io::{self, Read};
+
+/// `line` is 1-origin.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum HexError {
+    InvalidRecord { line: usize, text: String },
+    Checksum { line: usize },
+    UnsupportedRecord { line: usize, kind: u8 },
+    OutOfRange { line: usize, address: u64 },
+}
+
+impl fmt::Display for HexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HexError::InvalidRecord { line, text } => {
+                write!(f, "line {}: invalid record `{}`", line, text)
+            }
+            HexError::Checksum { line } => write!(f, "line {}: checksum mismatch", line),
+            HexError::UnsupportedRecord { line, kind } => {
+                write!(f, "line {}: unsupported record type {:02x}", line, kind)
+            }
+            HexError::OutOfRange { line, address } => {
+                write!(f, "line {}: address {:#x} out of range", line, address)
+            }
+        }
+    }
+}
+
+impl core::error::Error for HexError {}
+
+/// Loads Intel HEX `text` into `memory`, returning the entry point if the file has one.
+///
+/// Segment and linear addressing records are honoured as far as `M::Address` reaches,
+/// and data past the end of `memory` is [`HexError::OutOfRange`].
+pub fn load_hex<M>(memory: &mut M, text: &str) -> Result<Option<M::Address>, HexError>
+where
+    M: Memory<Data = u8>,
+    M::Address: TryFrom<u32>,
+{
+    let mut base = 0u32;
+    let mut entry = None;
+    for (i, source) in text.lines().enumerate() {
+        let line = i + 1;
+        let source = source.trim();
+        if source.is_empty() {
+            continue;
+        }
+        let invalid = || HexError::InvalidRecord {
+            line,
+            text: source.to_string(),
+        };
+        let bytes = source
+            .strip_prefix(':')
+            .filter(|digits| digits.len() % 2 == 0 && digits.is_ascii())
+            .and_then(|digits| {
+                (0..digits.len())
+                    .step_by(2)
+                    .map(|j| u8::from_str_radix(&digits[j..j + 2], 16).ok())
+                    .collect::<Option<Vec<u8>>>()
+            })
+            .ok_or_else(invalid)?;
+        if bytes.len() < 5 || bytes.len() != bytes[0] as usize + 5 {
+            return Err(invalid());
+        }
+        if bytes.iter().fold(0u8, |sum, &b| sum.wrapping_add(b)) != 0 {
+            return Err(HexError::Checksum { line });
+        }
+        let offset = u16::from_be_bytes([bytes[1], bytes[2]]) as u32;
+        let data = &bytes[4..bytes.len() - 1];
+        let word = |n: usize| -> Result<u32, HexError> {
+            match data.len() == n {
+                true => Ok(data.iter().fold(0, |w, &b| w << 8 | b as u32)),
+                false => Err(invalid()),
+            }
+        };
+        let out_of_range = |address: u64| HexError::OutOfRange { line, address };
+        let address = |a: u32| M::Address::try_from(a).map_err(|_| out_of_range(a.into()));
+        match bytes[3] {
+            0x00 => {
+                for (j, &b) in data.iter().enumerate() {
+                    let offset = offset + j as u32;
+                    let a = base
+                        .checked_add(offset)
+                        .ok_or_else(|| out_of_range(u64::from(base) + u64::from(offset)))?;
+                    memory
+                        .try_store(address(a)?, b)
+                        .map_err(|_| out_of_range(a.into()))?;
+                }
+            }
+            0x01 => break,
+            0x02 => base = word(2)? << 4,
+            0x03 => {
+                let cs_ip = word(4)?;
+                entry = Some(address((cs_ip >> 16 << 4) + (cs_ip & 0xffff))?);
+            }
+            0x04 => base = word(2)? << 16,
+            0x05 => entry = Some(address(word(4)?)?),
+            kind => return Err(HexError::UnsupportedRecord { line, kind }),
+        }
+    }
+    Ok(entry)
+}
+
+#[derive(Debug)]
+pub enum BinError {
+    /// the image does not fit between `offset` and the end of the memory.
+    TooLarge { offset: u64, length: usize },
+    /// the image overlaps memory that cannot be written, such as a ROM page.
+    ReadOnly { address: u64 },
+    #[cfg(feature = "std")]
+    Io(io::Error),
+}
+
+impl fmt::Display for BinError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BinError::TooLarge { offset, length } => {
+                write!(f, "{} bytes at {:#x} do not fit in memory", length, offset)
+            }
+            BinError::ReadOnly { address } => write!(f, "address {:#x} is read-only", address),
+            #[cfg(feature = "std")]
+            BinError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl core::error::Error for BinError {}
+
+#[cfg(feature = "std")]
+impl From<io::Error> for BinError {
+    fn from(e: io::Error) -> Self {
+        BinError::Io(e)
+    }
+}
+
+/// Copies a raw image such as a ROM dump or a CP/M COM file (at 0x100) to `offset`,
+/// returning the bytes written. Nothing is written unless the whole image fits:
+/// if a store fails, the bytes stored before it are put back as they were.
+pub fn load_bin<M>(memory: &mut M, offset: M::Address, bytes: &[u8]) -> Result<usize, BinError>
+where
+    M: Memory<Data = u8>,
+    M::Address: Copy + Into<u64> + TryFrom<u64>,
+{
+    let start: u64 = offset.into();
+    let too_large = || BinError::TooLarge {
+        offset: start,
+        length: bytes.len(),
+    };
+    let addresses = (start..start + bytes.len() as u64)
+        .map(M::Address::try_from)
+        .collect::<Result<Vec<_>, _>>();
+    let Ok(addresses) = addresses else {
+        return Err(too_large());
+    };
+    let mut previous = Vec::with_capacity(bytes.len());
+    for (&address, &b) in addresses.iter().zip(bytes) {
+        // write-only memory is left as loaded
+        let old = memory.try_read(address).ok();
+        if let Err(e) = memory.try_store(address, b) {
+            let stored = addresses[..previous.len()].iter().zip(previous);
+            for (&address, old) in stored.rev() {
+                if let Some(old) = old {
+                    memory.store(address, old);
+                }
+            }
+            return Err(match e {
+                MemoryError::ReadOnly(_) | MemoryError::NoAccess(_) => BinError::ReadOnly {
+                    address: address.into(),
+                },
+                _ => too_large(),
+            });
+        }
+        previous.push(old);
+    }
+    Ok(bytes.len())
+}
+
+/// Like [`load_bin`], reading the image to its end.
+#[cfg(feature = "std")]
+pub fn load_bin_from<M, R>(
+    memory: &mut M,
+    offset: M::Address,
+    mut reader: R,
+) -> Result<usize, BinError>
+where
+    M: Memory<Data = u8>,
+    M::Address: Copy + Into<u64> + TryFrom<u64>,
+    R: Read,
+{
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    load_bin(memory, offset, &bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::typical::{BoxedFlatMemory, Memory8Bit64KB};
+
+    #[test]
+    fn hex() {
+        let mut memory = Memory8Bit64KB::default();
+        let text = ":0301000021341295\n\
+                    :02010300C3FF38\n\
+                    :0400000300000100F8\n\
+                    :00000001FF\n\
+                    :01000000FF00\n";
+        assert_eq!(load_hex(&mut memory, text), Ok(Some(0x0100)));
+        assert_eq!(
+            (0x0100..0x0105).map(|a| memory.read(a)).collect::<Vec<_>>(),
+            [0x21, 0x34, 0x12, 0xc3, 0xff]
+        );
+        // nothing after the end of file record
+        assert_eq!(memory.read(0x0000), 0x00);
+
+        assert_eq!(
+            load_hex(&mut memory, ":020000040001F9\n:01000000AA55\n"),
+            Err(HexError::OutOfRange {
+                line: 2,
+                address: 0x10000
+            })
+        );
+        let mut big = BoxedFlatMemory::<0x20000>::default();
+        assert_eq!(
+            load_hex(&mut big, ":020000040001F9\n:01000000AA55\n"),
+            Ok(None)
+        );
+        assert_eq!(big.read(0x10000), 0xaa);
+        // at the top of linear addressing, past the end of the memory
+        assert_eq!(
+            load_hex(&mut big, ":02000004FFFFFC\n:02FFFF00AABB9B\n"),
+            Err(HexError::OutOfRange {
+                line: 2,
+                address: 0xffff_ffff
+            })
+        );
+
+        assert_eq!(
+            load_hex(&mut memory, ":0100000012EE\n"),
+            Err(HexError::Checksum { line: 1 })
+        );
+        assert_eq!(
+            load_hex(&mut memory, "\n0100000012ED\n"),
+            Err(HexError::InvalidRecord {
+                line: 2,
+                text: "0100000012ED".to_string()
+            })
+        );
+        assert_eq!(
+            load_hex(&mut memory, ":00000006FA\n"),
+            Err(HexError::UnsupportedRecord { line: 1, kind: 6 })
+        );
+    }
+
+    #[test]
+    fn bin() {
+        use crate::memory::typical::{FlatMemory, PageAccess, PagedMemory};
+
+        let mut memory = Memory8Bit64KB::default();
+        let com = [0xc3, 0x00, 0x00];
+        assert_eq!(load_bin(&mut memory, 0x0100, &com).unwrap(), 3);
+        assert_eq!(memory.read(0x0102), 0x00);
+        assert_eq!(memory.read(0x0100), 0xc3);
+        #[cfg(feature = "std")]
+        assert_eq!(load_bin_from(&mut memory, 0xfffe, &[1, 2][..]).unwrap(), 2);
+        assert!(matches!(
+            load_bin(&mut memory, 0xffff, &[1, 2]),
+            Err(BinError::TooLarge {
+                offset: 0xffff,
+                length: 2
+            })
+        ));
+        let mut rom = FlatMemory::<0x8000>::default();
+        let error = load_bin(&mut rom, 0x0001, &[0xff; 0x8000]).unwrap_err();
+        assert_eq!(error.to_string(), "32768 bytes at 0x1 do not fit in memory");
+        assert_eq!(rom.read(0x0001), 0x00);
+        assert_eq!(load_bin(&mut rom, 0x0000, &[0xff; 0x8000]).unwrap(), 0x8000);
+
+        // a ROM page in the middle
+        let mut paged = PagedMemory::new(Memory8Bit64KB::default(), 8, 0xff);
+        paged.protect(0x0200, 0x02ff, PageAccess::ReadOnly);
+        paged.inner_mut().store(0x01ff, 0x12);
+        let error = load_bin(&mut paged, 0x0100, &[0xff; 0x200]).unwrap_err();
+        assert!(matches!(error, BinError::ReadOnly { address: 0x0200 }));
+        assert_eq!((paged.read(0x0100), paged.read(0x01ff)), (0x00, 0x12));
+    }
+}
@@ -0,0 +1,1015 @@
+use crate::bus::{short_type_name, Bus, DeviceState, StateError};
+use crate::cache::DecodeCache;
+use crate::clock::{Clock, Pacer, Speed};
+use crate::cpu::{CPUExecute, CPUHalt, CPUProgramCounter, CPUReset, CPURunningState, CPUStep, CPU};
+use crate::debug::{Debugger, StopReason};
+use crate::instruction::InstructionDecoder;
+use crate::observer::ExecutionObserver;
+use crate::register::RegisterIncrementable;
+use crate::signal::{Line, Signals};
+use alloc::collections::VecDeque;
+use alloc::rc::Rc;
+use alloc::{boxed::Box, format, string::String, vec::Vec};
+use core::cell::{Cell, RefCell};
+use core::fmt::{self, Write};
+use core::ops::Sub;
+use core::time::Duration;
+
+/// A CPU, the bus with its devices, and the clock driving them.
+///
+/// Devices advance in lockstep with the CPU, by the cycles of each instruction, and
+/// their interrupts are offered to the CPU between instructions, after the changes
+/// of the lines in `signals` are dispatched. A write to a device with a
+/// [`write_latency`](crate::bus::Device::write_latency) takes effect at the cycle it is due,
+/// even within an instruction.
+pub struct Machine<C, A, D> {
+    pub cpu: C,
+    pub bus: Bus<A, D>,
+    pub signals: Signals,
+    clock: Clock,
+    /// a clock switched to by a line [`Machine::follow_clock`]s, not yet taken.
+    switched: Rc<Cell<Option<Clock>>>,
+    cycles: u64,
+    /// the cycles when the clock last changed, and the emulated time until then.
+    clock_since: (u64, Duration),
+    /// cycles the last [`Machine::run_for_cycles`] ran beyond its budget, owed by the next.
+    overshoot: u64,
+    paused: bool,
+    speed: Speed,
+    /// events not yet delivered, shared with the lines [`Machine::watch`]es.
+    events: Rc<RefCell<VecDeque<MachineEvent<A>>>>,
+    listeners: Vec<Listener<A>>,
+    /// real time the frames paced so far take at their speeds.
+    paced: Duration,
+    trap: Option<Trap<C, A, D>>,
+}
+
+/// Called before each instruction with the CPU and the bus, to do what the program
+/// expects of what is not emulated, e.g. [`crate::typical::cpm::Bdos::trap`] serving
+/// the calls to an operating system.
+pub type Trap<C, A, D> = Box<dyn FnMut(&mut C, &mut Bus<A, D>)>;
+
+/// What [`Machine::save_state`] saved, for a [`Rewind`](crate::rewind::Rewind) or a
+/// save file. Memory is saved only by the devices holding it that save their contents.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct MachineState<C> {
+    pub cpu: C,
+    pub cycles: u64,
+    pub devices: Vec<DeviceState>,
+}
+
+/// What a [`Machine`] tells the frontends [subscribed](Machine::subscribe) to it.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum MachineEvent<A> {
+    /// the reset line was asserted.
+    Reset,
+    /// a run under a debugger stopped, by [`Machine::run_debugged`].
+    Stopped(StopReason<A>),
+    /// [`Machine::run_for_cycles`] ran a budget, e.g. for an audio buffer now ready.
+    BudgetRun(Consumed),
+    /// a line [`Machine::watch`]ed changed, e.g. disk activity, a tape motor or an FDD LED.
+    Activity { name: String, on: bool },
+}
+
+/// Called with each event a [`Machine`] emits.
+pub type Listener<A> = Box<dyn FnMut(&MachineEvent<A>)>;
+
+/// What a budgeted run consumed.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct Consumed {
+    pub instructions: u64,
+    pub cycles: u64,
+    /// cycles beyond the budget, as the last instruction cannot be cut short.
+    pub overshoot: u64,
+}
+
+impl<C, A, D> Machine<C, A, D>
+where
+    C: CPUStep<Bus<A, D>>,
+{
+    pub fn new(cpu: C, bus: Bus<A, D>, clock: Clock) -> Self {
+        Self {
+            cpu,
+            bus,
+            signals: Signals::new(),
+            clock,
+            switched: Rc::default(),
+            cycles: 0,
+            clock_since: (0, Duration::ZERO),
+            overshoot: 0,
+            paused: false,
+            speed: Speed::REAL_TIME,
+            events: Rc::default(),
+            listeners: Vec::new(),
+            paced: Duration::ZERO,
+            trap: None,
+        }
+    }
+
+    /// calls `trap` before each instruction, the one at the program counter it leaves
+    /// running next, in place of any set before.
+    pub fn set_trap<F: FnMut(&mut C, &mut Bus<A, D>) + 'static>(&mut self, trap: F) {
+        self.trap = Some(Box::new(trap));
+    }
+
+    pub fn clear_trap(&mut self) {
+        self.trap = None;
+    }
+
+    pub fn clock(&self) -> Clock {
+        self.clock
+    }
+
+    /// runs at `clock` from now on, e.g. switched to 8 MHz by the program.
+    pub fn set_clock(&mut self, clock: Clock) {
+        self.clock_since = (self.cycles, self.elapsed());
+        self.clock = clock;
+    }
+
+    /// Switches to the clock `line` carries whenever it changes, as driven by a
+    /// [`SpeedSwitch`](crate::typical::speed_switch::SpeedSwitch) port. The switch takes
+    /// effect after the instruction writing it. The line is added to the signals.
+    pub fn follow_clock(&mut self, line: Line<Clock>) {
+        let switched = Rc::clone(&self.switched);
+        line.subscribe(move |&clock| switched.set(Some(clock)));
+        self.set_clock(line.get());
+        self.signals.add(line);
+    }
+
+    /// Stops the budgeted runs, [`Machine::run_for`] and those built on it, which run
+    /// nothing until [`Machine::resume`]: the CPU, the devices and the cycle count stand
+    /// still together, and an audio frontend fills the budgets it asks for with silence.
+    /// [`Machine::step`] and [`Machine::run_for_instructions`] still run, for a debugger.
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    pub fn paused(&self) -> bool {
+        self.paused
+    }
+
+    /// the speed [`Machine::run_frame_paced`] keeps, real time by default.
+    pub fn set_speed(&mut self, speed: Speed) {
+        self.speed = speed;
+    }
+
+    pub fn speed(&self) -> Speed {
+        self.speed
+    }
+
+    /// cycles run since power on.
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// emulated time since power on, at the clocks run at.
+    pub fn elapsed(&self) -> Duration {
+        let (cycles, elapsed) = self.clock_since;
+        elapsed + self.clock.duration_of(self.cycles - cycles)
+    }
+
+    /// the CPU, the time and the states of the devices.
+    pub fn save_state(&self) -> MachineState<C>
+    where
+        C: Clone,
+    {
+        MachineState {
+            cpu: self.cpu.clone(),
+            cycles: self.cycles,
+            devices: self.bus.save_state(),
+        }
+    }
+
+    /// restores what [`Machine::save_state`] saved, leaving the machine as it was
+    /// if the devices do not match.
+    pub fn load_state(&mut self, state: &MachineState<C>) -> Result<(), StateError>
+    where
+        C: Clone,
+    {
+        self.bus.load_state(&state.devices)?;
+        self.cpu = state.cpu.clone();
+        self.cycles = state.cycles;
+        self.clock_since = (0, Duration::ZERO);
+        self.overshoot = 0;
+        Ok(())
+    }
+
+    /// asserts the reset line of the devices and the CPU. Memory keeps its contents.
+    pub fn reset(&mut self)
+    where
+        C: CPUReset<Bus<A, D>>,
+    {
+        self.bus.reset();
+        self.cpu.reset(&mut self.bus);
+        self.emit(MachineEvent::Reset);
+    }
+
+    /// calls `listener` with every event from now on, after those subscribed before.
+    pub fn subscribe<F: FnMut(&MachineEvent<A>) + 'static>(&mut self, listener: F) {
+        self.listeners.push(Box::new(listener));
+    }
+
+    /// Emits [`MachineEvent::Activity`] named `name` whenever `line` changes, such as
+    /// the access lamp of a drive. The line is added to the [`Machine::signals`].
+    ///
+    /// [`Machine::signals`]: Machine#structfield.signals
+    pub fn watch(&mut self, name: &str, line: Line<bool>)
+    where
+        A: 'static,
+    {
+        let events = Rc::clone(&self.events);
+        let name = String::from(name);
+        line.subscribe(move |&on| {
+            events.borrow_mut().push_back(MachineEvent::Activity {
+                name: name.clone(),
+                on,
+            })
+        });
+        self.signals.add(line);
+    }
+
+    /// delivers `event` and those pending to the listeners, in order.
+    pub fn emit(&mut self, event: MachineEvent<A>) {
+        self.events.borrow_mut().push_back(event);
+        self.deliver();
+    }
+
+    fn deliver(&mut self) {
+        loop {
+            // not borrowed while a listener runs
+            let Some(event) = self.events.borrow_mut().pop_front() else {
+                break;
+            };
+            for listener in &mut self.listeners {
+                listener(&event);
+            }
+        }
+    }
+
+    /// runs one instruction, and the interrupt it may let in, returning the cycles taken.
+    pub fn step(&mut self) -> u64 {
+        self.before_step();
+        let cycles = self.cpu.step(&mut self.bus);
+        self.after_step(cycles, &mut ())
+    }
+
+    /// Runs like [`Machine::step`], reporting the CPU's fetches, instructions and memory
+    /// accesses to `observer`, such as a [`crate::trace::TraceBuffer`] or a
+    /// [`crate::debug::Debugger`].
+    pub fn step_observed<O: ExecutionObserver<C>>(&mut self, observer: &mut O) -> u64 {
+        self.before_step();
+        let cycles = self.cpu.step_observed(&mut self.bus, observer);
+        self.after_step(cycles, observer)
+    }
+
+    /// Runs like [`Machine::step`], but takes the instruction at the program counter from
+    /// `cache`, decoding it with `decoder` on a miss, so that loops are decoded once.
+    /// The CPU's stores invalidate the cache; writes by other bus masters must be
+    /// given to [`DecodeCache::invalidate`], and bank switches to [`DecodeCache::clear_on`].
+    /// Fails with the word that does not decode.
+    pub fn step_cached<S, X>(
+        &mut self,
+        cache: &mut DecodeCache<C::Instruction>,
+        decoder: &mut X,
+    ) -> Result<u64, D>
+    where
+        C: CPUExecute<Bus<A, D>, Address = A, Data = D> + CPUHalt,
+        C::Instruction: Clone,
+        A: Copy + PartialOrd + Sub<Output = A> + Into<u64> + RegisterIncrementable,
+        D: Copy,
+        X: InstructionDecoder<S, Instruction = C::Instruction, InstructionSize = D>,
+    {
+        self.before_step();
+        // a halted CPU fetches nothing
+        if self.cpu.halted() {
+            let cycles = self.cpu.step(&mut self.bus);
+            return Ok(self.after_step(cycles, cache));
+        }
+        let pc = *self.cpu.program_counter();
+        let (instruction, len) = cache
+            .fetch(&self.bus, pc, decoder)
+            .map(|(instruction, len)| (instruction.clone(), len))?;
+        let cycles = self.cpu.execute(&mut self.bus, &instruction, len, cache);
+        Ok(self.after_step(cycles, cache))
+    }
+
+    fn before_step(&mut self) {
+        if let Some(trap) = &mut self.trap {
+            trap(&mut self.cpu, &mut self.bus);
+        }
+    }
+
+    /// ticks the devices by the `cycles` of an instruction and lets in an interrupt,
+    /// reporting what it executes to `observer`.
+    fn after_step<O: ExecutionObserver<C>>(&mut self, mut cycles: u64, observer: &mut O) -> u64 {
+        self.bus.tick(cycles);
+        if self.signals.dispatch() {
+            self.deliver();
+        }
+        if self.bus.interrupt() {
+            let accepted = self.cpu.interrupt_observed(&mut self.bus, observer);
+            self.bus.tick(accepted);
+            cycles += accepted;
+        }
+        self.cycles += cycles;
+        if let Some(clock) = self.switched.take() {
+            self.set_clock(clock);
+        }
+        cycles
+    }
+
+    /// runs whole instructions until at least `cycles` have passed, returning the
+    /// cycles actually run, which may overshoot by part of an instruction.
+    /// Stops early if a step takes no cycles, as the CPU is not going anywhere.
+    pub fn run_for(&mut self, cycles: u64) -> u64 {
+        let mut run = 0;
+        if self.paused {
+            return run;
+        }
+        while run < cycles {
+            match self.step() {
+                0 => break,
+                step => run += step,
+            }
+        }
+        run
+    }
+
+    /// Runs like [`Machine::run_for`], but stops early when the CPU halts with interrupts
+    /// disabled, as nothing can wake it up, with its [`CPUHalt::running_state`]:
+    /// [`CPURunningState::Halted`], or [`CPURunningState::Error`] if it faulted.
+    pub fn run(&mut self, cycles: u64) -> CPURunningState
+    where
+        C: CPUHalt,
+    {
+        self.run_observed(cycles, &mut ())
+    }
+
+    /// Runs like [`Machine::run`], stepping with [`Machine::step_observed`].
+    pub fn run_observed<O>(&mut self, cycles: u64, observer: &mut O) -> CPURunningState
+    where
+        C: CPUHalt,
+        O: ExecutionObserver<C>,
+    {
+        let mut run = 0;
+        if self.paused {
+            return self.cpu.running_state();
+        }
+        while run < cycles {
+            if self.cpu.halted() && !self.cpu.interrupt_enabled() {
+                return self.cpu.running_state();
+            }
+            match self.step_observed(observer) {
+                0 => break,
+                step => run += step,
+            }
+        }
+        self.cpu.running_state()
+    }
+
+    /// Runs whole instructions for a budget of `cycles`, such as a video frame or an
+    /// audio buffer. Cycles run over one budget are taken from the next, so that a
+    /// sequence of budgets runs exactly their sum. Stops short if a step takes no cycles.
+    /// Emits [`MachineEvent::BudgetRun`].
+    pub fn run_for_cycles(&mut self, cycles: u64) -> Consumed {
+        let consumed = self.run_budget(cycles);
+        self.emit(MachineEvent::BudgetRun(consumed));
+        consumed
+    }
+
+    fn run_budget(&mut self, cycles: u64) -> Consumed {
+        let mut consumed = Consumed::default();
+        if self.paused {
+            consumed.overshoot = self.overshoot;
+            return consumed;
+        }
+        let budget = match cycles.checked_sub(self.overshoot) {
+            Some(budget) => budget,
+            None => {
+                self.overshoot -= cycles;
+                consumed.overshoot = self.overshoot;
+                return consumed;
+            }
+        };
+        while consumed.cycles < budget {
+            match self.step() {
+                0 => break,
+                step => consumed.cycles += step,
+            }
+            consumed.instructions += 1;
+        }
+        self.overshoot = consumed.cycles.saturating_sub(budget);
+        consumed.overshoot = self.overshoot;
+        consumed
+    }
+
+    /// Runs like [`Machine::run_observed`] under `debugger`, asking it before each
+    /// instruction whether to stop, for at most `cycles`. Why it stopped, if it did, is
+    /// also emitted as [`MachineEvent::Stopped`].
+    pub fn run_debugged(
+        &mut self,
+        cycles: u64,
+        debugger: &mut Debugger<A, C, Bus<A, D>>,
+    ) -> Option<StopReason<A>>
+    where
+        C: CPU<Address = A> + CPUHalt + CPUProgramCounter,
+        A: Ord + Copy,
+    {
+        let mut run = 0;
+        if self.paused {
+            return None;
+        }
+        while run < cycles {
+            let pc = *self.cpu.program_counter();
+            if let Some(reason) = debugger.before_instruction_or_halt(pc, &self.cpu, &self.bus) {
+                self.emit(MachineEvent::Stopped(reason));
+                return Some(reason);
+            }
+            match self.step_observed(debugger) {
+                0 => break,
+                step => run += step,
+            }
+        }
+        None
+    }
+
+    /// runs exactly `instructions` instructions.
+    pub fn run_for_instructions(&mut self, instructions: u64) -> Consumed {
+        let mut consumed = Consumed::default();
+        for _ in 0..instructions {
+            consumed.cycles += self.step();
+        }
+        consumed.instructions = instructions;
+        consumed
+    }
+
+    /// runs a video frame of `frames_per_second`.
+    pub fn run_frame(&mut self, frames_per_second: f64) -> u64 {
+        self.run_for(self.clock.cycles_per_frame(frames_per_second))
+    }
+
+    /// Runs a frame like [`Machine::run_frame`], then waits with `pacer` until real time
+    /// has caught up with the frames paced so far at their [`Speed`]s. A paused frame
+    /// takes as long as a running one, and an unlimited one does not wait.
+    pub fn run_frame_paced<P: Pacer>(&mut self, frames_per_second: f64, pacer: &mut P) -> u64 {
+        let frame = self.clock.cycles_per_frame(frames_per_second);
+        let run = self.run_for(frame);
+        if let Some(real) = self.speed.real_time(self.clock, frame) {
+            self.paced += real;
+            pacer.wait(self.paced);
+        }
+        run
+    }
+
+    /// runs a frame like [`Machine::run_frame`] even if paused, and stays paused,
+    /// to step a paused game frame by frame.
+    pub fn advance_frame(&mut self, frames_per_second: f64) -> u64 {
+        let paused = core::mem::replace(&mut self.paused, false);
+        let run = self.run_frame(frames_per_second);
+        self.paused = paused;
+        run
+    }
+
+    /// how the machine is wired, for checking and documentation.
+    pub fn describe(&self) -> Description
+    where
+        A: Copy + Into<u64>,
+    {
+        Description {
+            cpu: short_type_name::<C>(),
+            clock_hz: self.clock.hz(),
+            regions: self
+                .bus
+                .devices()
+                .map(|(range, device)| Region {
+                    start: (*range.start()).into(),
+                    end: (*range.end()).into(),
+                    device,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// A device mapped onto the bus.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Region {
+    pub start: u64,
+    pub end: u64,
+    pub device: String,
+}
+
+/// The components of a [`Machine`] and their connections.
+///
+/// The CPU reaches every region through the bus, which ticks the devices at the CPU
+/// clock and ORs their interrupt lines into the one INT line of the CPU.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Description {
+    pub cpu: String,
+    pub clock_hz: u64,
+    /// in mapping order; later regions take precedence where they overlap.
+    pub regions: Vec<Region>,
+}
+
+impl Description {
+    pub fn to_json(&self) -> String {
+        let mut json = String::new();
+        let _ = write!(
+            json,
+            "{{\"cpu\":{},\"clock_hz\":{},\"interrupt\":\"INT\",\"regions\":[",
+            json_string(&self.cpu),
+            self.clock_hz
+        );
+        for (i, region) in self.regions.iter().enumerate() {
+            let _ = write!(
+                json,
+                "{}{{\"start\":{},\"end\":{},\"device\":{}}}",
+                if i == 0 { "" } else { "," },
+                region.start,
+                region.end,
+                json_string(&region.device)
+            );
+        }
+        json.push_str("]}");
+        json
+    }
+
+    /// a graphviz graph of CPU, bus and devices.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph machine {\n");
+        let _ = writeln!(
+            dot,
+            "  cpu [shape=box, label={}];",
+            json_string(&format!("{}\n{} Hz", self.cpu, self.clock_hz))
+        );
+        dot.push_str("  bus [shape=ellipse, label=\"bus\"];\n");
+        dot.push_str("  cpu -> bus [dir=both];\n");
+        dot.push_str("  bus -> cpu [style=dashed, label=\"INT\"];\n");
+        for (i, region) in self.regions.iter().enumerate() {
+            let _ = writeln!(dot, "  d{} [label={}];", i, json_string(&region.device));
+            let _ = writeln!(
+                dot,
+                "  bus -> d{} [dir=both, label=\"{:#06x}-{:#06x}\"];",
+                i, region.start, region.end
+            );
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+impl<C: fmt::Debug, A: fmt::Debug, D: fmt::Debug> fmt::Debug for Machine<C, A, D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Machine")
+            .field("cpu", &self.cpu)
+            .field("bus", &self.bus)
+            .field("signals", &self.signals)
+            .field("clock", &self.clock)
+            .field("clock_since", &self.clock_since)
+            .field("cycles", &self.cycles)
+            .field("overshoot", &self.overshoot)
+            .field("paused", &self.paused)
+            .field("speed", &self.speed)
+            .field("events", &self.events.borrow())
+            .field("listeners", &self.listeners.len())
+            .field("paced", &self.paced)
+            .field("trap", &self.trap.is_some())
+            .finish()
+    }
+}
+
+/// `s` quoted and escaped, valid in both JSON and DOT.
+fn json_string(s: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::tests::CPU8;
+    use crate::cpu::{CPUMemory, CPUProgramCounter, CPU};
+    use crate::instruction::typical::{Halt, Nop};
+    use crate::instruction::Instruction;
+    use crate::memory::typical::Memory8Bit64KB;
+    use crate::memory::Memory;
+    use crate::typical::timer::Timer;
+
+    impl CPUMemory<Bus<u16, u8>> for CPU8 {}
+
+    /// every instruction is a 4-cycle NOP but 0x76, HLT, and 0xed, which takes no cycles;
+    /// interrupts jump to 0x0038 if enabled or halted.
+    impl CPUStep<Bus<u16, u8>> for CPU8 {
+        fn step_observed<O>(&mut self, memory: &mut Bus<u16, u8>, observer: &mut O) -> u64
+        where
+            O: ExecutionObserver<Self>,
+        {
+            if !self.halted() {
+                *self = self.program_fetch_observed(memory, observer);
+                observer.on_execute(self);
+                match self.data() {
+                    0x76 => Halt.execute(self),
+                    0xed => return 0,
+                    _ => {}
+                }
+            }
+            4
+        }
+        fn interrupt_observed<O>(&mut self, memory: &mut Bus<u16, u8>, _observer: &mut O) -> u64
+        where
+            O: ExecutionObserver<Self>,
+        {
+            if self.halted() && !self.interrupt_enabled() {
+                return 0;
+            }
+            memory.read(0xe003);
+            self.set_halted(false);
+            *self.program_counter() = 0x0038;
+            11
+        }
+    }
+
+    #[test]
+    fn lockstep() {
+        let mut bus = Bus::default();
+        bus.map_memory(0x0000..=0xffff, Memory8Bit64KB::default())
+            .map(0xe000..=0xe003, Timer::new());
+        bus.store(0xe000, 10);
+        bus.store(
+            0xe002,
+            Timer::ENABLE | Timer::INTERRUPT_ENABLE | Timer::ONE_SHOT,
+        );
+        let mut machine = Machine::new(CPU8::default(), bus, Clock::new(1_000_000).unwrap());
+        assert_eq!(machine.step(), 4);
+        assert_eq!(machine.step(), 4);
+        assert_eq!(machine.cpu.address(), 0x0001);
+        // the timer expires during the third instruction
+        assert_eq!(machine.step(), 4 + 11);
+        assert_eq!(*machine.cpu.program_counter(), 0x0038);
+        assert_eq!(machine.run_for(10), 12);
+        assert_eq!(machine.cycles(), 35);
+        assert_eq!(machine.elapsed(), Duration::from_micros(35));
+        assert_eq!(machine.run_frame(100_000.0), 12);
+    }
+
+    #[test]
+    fn paced() {
+        #[derive(Default)]
+        struct Waits(Vec<Duration>);
+        impl Pacer for Waits {
+            fn wait(&mut self, due: Duration) {
+                self.0.push(due)
+            }
+        }
+        let mut machine = Machine::new(
+            CPU8::default(),
+            Bus::new(0x00),
+            Clock::new(1_000_000).unwrap(),
+        );
+        let mut waits = Waits::default();
+        assert_eq!(machine.run_frame_paced(1000.0, &mut waits), 1000);
+        machine.set_speed(Speed::new(2.0).unwrap());
+        machine.run_frame_paced(1000.0, &mut waits);
+        machine.pause();
+        machine.run_frame_paced(1000.0, &mut waits);
+        machine.resume();
+        machine.set_speed(Speed::UNLIMITED);
+        machine.run_frame_paced(1000.0, &mut waits);
+        assert_eq!(machine.cycles(), 3000);
+        assert_eq!(
+            waits.0,
+            [
+                Duration::from_millis(1),
+                Duration::from_micros(1500),
+                Duration::from_millis(2)
+            ]
+        );
+    }
+
+    #[test]
+    fn events() {
+        let mut bus = Bus::default();
+        bus.map_memory(0x0000..=0xffff, Memory8Bit64KB::default());
+        let mut machine = Machine::new(CPU8::default(), bus, Clock::new(1_000_000).unwrap());
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let log = Rc::clone(&events);
+        machine.subscribe(move |event| log.borrow_mut().push(event.clone()));
+        let lamp = Line::new(false);
+        machine.watch("drive 1", lamp.clone());
+        lamp.raise();
+        machine.step();
+        // a change undone before the next instruction is not seen
+        lamp.lower();
+        lamp.raise();
+        machine.step();
+        let consumed = machine.run_for_cycles(8);
+        machine.reset();
+        let mut debugger = Debugger::default();
+        debugger.add_breakpoint(0x0002);
+        assert_eq!(
+            machine.run_debugged(100, &mut debugger),
+            Some(StopReason::Breakpoint(0x0002))
+        );
+        assert_eq!(
+            *events.borrow(),
+            [
+                MachineEvent::Activity {
+                    name: "drive 1".into(),
+                    on: true
+                },
+                MachineEvent::BudgetRun(consumed),
+                MachineEvent::Reset,
+                MachineEvent::Stopped(StopReason::Breakpoint(0x0002)),
+            ]
+        );
+    }
+
+    #[test]
+    fn pause() {
+        let mut bus = Bus::default();
+        bus.map_memory(0x0000..=0xffff, Memory8Bit64KB::default())
+            .map(0xe000..=0xe003, Timer::new());
+        bus.store(0xe000, 10);
+        bus.store(
+            0xe002,
+            Timer::ENABLE | Timer::INTERRUPT_ENABLE | Timer::ONE_SHOT,
+        );
+        let mut machine = Machine::new(CPU8::default(), bus, Clock::new(1_000_000).unwrap());
+        machine.pause();
+        assert!(machine.paused());
+        assert_eq!(machine.run_for(100), 0);
+        assert_eq!(machine.run_for_cycles(100), Consumed::default());
+        assert_eq!(machine.run(100), CPURunningState::Running);
+        assert_eq!(machine.run_frame(100_000.0), 0);
+        assert_eq!(machine.cycles(), 0);
+        // the timer froze with the CPU, and expires in the first frame stepped
+        assert_eq!(machine.advance_frame(100_000.0), 4 + 4 + 4 + 11);
+        assert!(machine.paused());
+        assert_eq!(machine.run_for(100), 0);
+        assert_eq!(machine.step(), 4);
+        machine.resume();
+        assert_eq!(machine.run_for(4), 4);
+        assert_eq!(machine.cycles(), 31);
+    }
+
+    #[test]
+    fn state() {
+        let mut bus = Bus::default();
+        bus.map_memory(0x0000..=0xffff, Memory8Bit64KB::default())
+            .map(0xe000..=0xe003, Timer::new());
+        bus.store(0xe000, 10);
+        bus.store(0xe002, Timer::ENABLE | Timer::INTERRUPT_ENABLE);
+        let mut machine = Machine::new(CPU8::default(), bus, Clock::new(1_000_000).unwrap());
+        machine.step();
+        let state = machine.save_state();
+        assert_eq!(machine.step(), 4);
+        assert_eq!(machine.step(), 4 + 11);
+        // the timer counts down again from where it was saved
+        machine.load_state(&state).unwrap();
+        assert_eq!(machine.cycles(), 4);
+        assert_eq!(machine.step(), 4);
+        assert_eq!(machine.step(), 4 + 11);
+        let mut other = Machine::new(CPU8::default(), Bus::default(), machine.clock());
+        assert_eq!(
+            other.load_state(&state),
+            Err(StateError::Count {
+                expected: 0,
+                found: 2
+            })
+        );
+    }
+
+    #[test]
+    fn signals() {
+        use crate::typical::pic::Pic;
+        use alloc::rc::Rc;
+        use core::cell::Cell;
+
+        let mut bus = Bus::default();
+        bus.map_memory(0x0000..=0xffff, Memory8Bit64KB::default());
+        let mut machine = Machine::new(CPU8::default(), bus, Clock::default());
+        let vrtc = machine.signals.line(false);
+        let mut pic = Pic::new();
+        let line = vrtc.clone();
+        pic.connect(1, move || line.is_raised());
+        machine.bus.map(0xe000..=0xe002, pic);
+        let frames = Rc::new(Cell::new(0));
+        let counter = frames.clone();
+        vrtc.subscribe(move |&raised| counter.set(counter.get() + raised as u32));
+        vrtc.raise();
+        assert_eq!(frames.get(), 0);
+        machine.cpu.set_interrupt_enabled(true);
+        assert_eq!(machine.step(), 4 + 11);
+        assert_eq!(frames.get(), 1);
+        assert_eq!(machine.bus.read(0xe001), 0b10);
+    }
+
+    #[test]
+    fn budget() {
+        let mut bus = Bus::default();
+        bus.map_memory(0x0000..=0xffff, Memory8Bit64KB::default());
+        let mut machine = Machine::new(CPU8::default(), bus, Clock::default());
+        let consumed = machine.run_for_instructions(3);
+        assert_eq!((consumed.instructions, consumed.cycles), (3, 12));
+        // 10 cycles take three 4-cycle instructions, 2 cycles into the next budget
+        let consumed = machine.run_for_cycles(10);
+        assert_eq!(
+            consumed,
+            Consumed {
+                instructions: 3,
+                cycles: 12,
+                overshoot: 2
+            }
+        );
+        assert_eq!(machine.run_for_cycles(1).overshoot, 1);
+        assert_eq!(machine.run_for_cycles(1).instructions, 0);
+        assert_eq!(machine.run_for_cycles(8).cycles, 8);
+        assert_eq!(machine.cycles(), 12 + 10 + 1 + 1 + 8);
+    }
+
+    #[test]
+    fn no_progress() {
+        let mut bus = Bus::default();
+        bus.map_memory(
+            0x0000..=0xffff,
+            Memory8Bit64KB::new(&[0x00, 0xed, 0x00, 0xed]),
+        );
+        let mut machine = Machine::new(CPU8::default(), bus, Clock::default());
+        assert_eq!(machine.run_for(100), 4);
+        let consumed = machine.run_for_cycles(100);
+        assert_eq!((consumed.cycles, consumed.overshoot), (4, 0));
+        assert_eq!(machine.run(100), CPURunningState::Running);
+    }
+
+    #[test]
+    fn halt() {
+        let mut bus = Bus::default();
+        bus.map_memory(0x0000..=0xffff, Memory8Bit64KB::new(&[0x00, 0x76]))
+            .map(0xe000..=0xe003, Timer::new());
+        bus.store(0xe000, 100);
+        bus.store(
+            0xe002,
+            Timer::ENABLE | Timer::INTERRUPT_ENABLE | Timer::ONE_SHOT,
+        );
+        let mut machine = Machine::new(CPU8::default(), bus, Clock::default());
+        machine.cpu.set_interrupt_enabled(true);
+        assert_eq!(machine.run(50), CPURunningState::Halted);
+        assert_eq!(*machine.cpu.program_counter(), 0x0002);
+        // the timer wakes it up
+        assert_eq!(machine.run(48), CPURunningState::Running);
+        assert_eq!(*machine.cpu.program_counter(), 0x0038);
+        machine.cpu.set_interrupt_enabled(false);
+        *machine.cpu.program_counter() = 0x0001;
+        assert_eq!(machine.run(1000), CPURunningState::Halted);
+        assert_eq!(machine.cycles(), 52 + 48 + 11 + 4);
+    }
+
+    impl CPUReset<Bus<u16, u8>> for CPU8 {
+        fn reset(&mut self, _memory: &mut Bus<u16, u8>) {
+            *self.program_counter() = 0;
+            self.set_halted(false);
+        }
+    }
+
+    #[test]
+    fn reset() {
+        let mut bus = Bus::default();
+        bus.map_memory(0x0000..=0xffff, Memory8Bit64KB::new(&[0x76]))
+            .map(0xe000..=0xe003, Timer::new());
+        bus.store(0xe002, Timer::ENABLE);
+        let mut machine = Machine::new(CPU8::default(), bus, Clock::default());
+        assert_eq!(machine.run(100), CPURunningState::Halted);
+        machine.reset();
+        assert_eq!(machine.cpu.running_state(), CPURunningState::Running);
+        assert_eq!(machine.bus.read(0xe002), 0);
+        assert_eq!(machine.bus.read(0x0000), 0x76);
+    }
+
+    /// 0x32 stores HLT at its 16-bit operand, patching the code; 0x76 halts.
+    #[derive(Debug, Clone)]
+    pub enum Op {
+        Nop,
+        Halt,
+        Store(u16),
+    }
+
+    impl From<Nop> for Op {
+        fn from(_: Nop) -> Self {
+            Op::Nop
+        }
+    }
+
+    impl Instruction<CPU8> for Op {
+        fn execute(&self, cpu: &mut CPU8) {
+            if let Op::Halt = self {
+                Halt.execute(cpu)
+            }
+        }
+    }
+
+    impl CPUExecute<Bus<u16, u8>> for CPU8 {
+        type Instruction = Op;
+        fn execute<O>(
+            &mut self,
+            memory: &mut Bus<u16, u8>,
+            op: &Op,
+            len: u64,
+            observer: &mut O,
+        ) -> u64
+        where
+            O: ExecutionObserver<Self>,
+        {
+            *self.program_counter() += len as u16;
+            match *op {
+                Op::Store(address) => {
+                    *self = self
+                        .load_address(address)
+                        .load_data(0x76)
+                        .store_memory_observed(memory, observer)
+                }
+                _ => op.execute(self),
+            }
+            4
+        }
+    }
+
+    #[test]
+    fn cached() {
+        use crate::instruction::typical::DecoderBuilder;
+        use crate::instruction::{IllegalOpcode, Operand};
+
+        let mut decoder = DecoderBuilder::new()
+            .map(0x00, Operand::None, |_, _| Op::Nop)
+            .map(0x76, Operand::None, |_, _| Op::Halt)
+            .map(0x32, Operand::Imm16, |_, address| Op::Store(address))
+            .illegal(IllegalOpcode::Error)
+            .build();
+        let mut bus = Bus::default();
+        bus.map_memory(
+            0x0000..=0xffff,
+            Memory8Bit64KB::new(&[0x00, 0x32, 0x00, 0x00, 0xff]),
+        );
+        let mut machine = Machine::new(CPU8::default(), bus, Clock::default());
+        let mut cache = DecodeCache::new();
+        for _ in 0..2 {
+            *machine.cpu.program_counter() = 0x0000;
+            assert_eq!(machine.step_cached(&mut cache, &mut decoder), Ok(4));
+        }
+        assert_eq!((cache.hits(), cache.misses()), (1, 1));
+        // the store patches HLT over the cached NOP
+        assert_eq!(machine.step_cached(&mut cache, &mut decoder), Ok(4));
+        assert_eq!(*machine.cpu.program_counter(), 0x0004);
+        assert_eq!(cache.len(), 1);
+        *machine.cpu.program_counter() = 0x0000;
+        machine.step_cached(&mut cache, &mut decoder).unwrap();
+        assert_eq!(machine.cpu.running_state(), CPURunningState::Halted);
+        // halted, it fetches nothing
+        assert_eq!(machine.step_cached(&mut cache, &mut decoder), Ok(4));
+        assert_eq!(cache.misses(), 3);
+        machine.cpu.set_halted(false);
+        *machine.cpu.program_counter() = 0x0004;
+        assert_eq!(machine.step_cached(&mut cache, &mut decoder), Err(0xff));
+        assert_eq!(machine.cycles(), 20);
+    }
+
+    #[test]
+    fn describe() {
+        use crate::bus::typical::StubDevice;
+
+        let mut bus = Bus::default();
+        bus.map_memory(0x0000..=0x7fff, Memory8Bit64KB::default())
+            .map_rom(0x8000..=0xffff, Memory8Bit64KB::default())
+            .map(0xe000..=0xe003, Timer::new())
+            .map(0xe010..=0xe011, StubDevice::new("OPN", 0xff));
+        let machine = Machine::new(CPU8::default(), bus, Clock::PC8801_4MHZ);
+        let description = machine.describe();
+        assert_eq!(description.cpu, "CPU8");
+        let devices: Vec<_> = description
+            .regions
+            .iter()
+            .map(|r| r.device.as_str())
+            .collect();
+        assert_eq!(devices, ["RAM", "ROM", "Timer", "OPN"]);
+        let json = description.to_json();
+        assert!(json.starts_with(r#"{"cpu":"CPU8","clock_hz":3993600,"interrupt":"INT","regions":[{"start":0,"end":32767,"device":"RAM"},"#));
+        assert!(json.ends_with(r#"{"start":57360,"end":57361,"device":"OPN"}]}"#));
+        let dot = description.to_dot();
+        assert!(dot.contains("  cpu [shape=box, label=\"CPU8\\n3993600 Hz\"];\n"));
+        assert!(dot.contains(
+            "  d2 [label=\"Timer\"];\n  bus -> d2 [dir=both, label=\"0xe000-0xe003\"];\n"
+        ));
+    }
+}
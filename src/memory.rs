@@ -1,44 +1,776 @@
+use crate::BitwiseOps;
+use alloc::{boxed::Box, vec, vec::Vec};
+use core::fmt;
+use core::marker::PhantomData;
+
 pub trait Memory {
     type Address;
     type Data;
     fn read(&self, address: Self::Address) -> Self::Data;
     fn store(&mut self, address: Self::Address, data: Self::Data);
+    /// like `read`, but fails instead of panicking or making up a value.
+    fn try_read(&self, address: Self::Address) -> Result<Self::Data, MemoryError<Self::Address>> {
+        Ok(self.read(address))
+    }
+    /// like `store`, but fails instead of panicking or dropping the data.
+    fn try_store(
+        &mut self,
+        address: Self::Address,
+        data: Self::Data,
+    ) -> Result<(), MemoryError<Self::Address>> {
+        self.store(address, data);
+        Ok(())
+    }
+}
+
+/// a borrowed memory, e.g. the bus of a [`crate::machine::Machine`] lent to a
+/// [`crate::system::System`] for a few instructions.
+impl<M: Memory + ?Sized> Memory for &mut M {
+    type Address = M::Address;
+    type Data = M::Data;
+    fn read(&self, address: Self::Address) -> Self::Data {
+        (**self).read(address)
+    }
+    fn store(&mut self, address: Self::Address, data: Self::Data) {
+        (**self).store(address, data)
+    }
+    fn try_read(&self, address: Self::Address) -> Result<Self::Data, MemoryError<Self::Address>> {
+        (**self).try_read(address)
+    }
+    fn try_store(
+        &mut self,
+        address: Self::Address,
+        data: Self::Data,
+    ) -> Result<(), MemoryError<Self::Address>> {
+        (**self).try_store(address, data)
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum MemoryError<A> {
+    /// nothing answers at the address.
+    Unmapped(A),
+    /// the address cannot be written.
+    ReadOnly(A),
+    /// the address cannot be read.
+    WriteOnly(A),
+    /// the address can be neither read nor written.
+    NoAccess(A),
+    /// the address is not aligned to the access size.
+    Misaligned(A),
+}
+
+impl<A> MemoryError<A> {
+    pub fn address(&self) -> &A {
+        match self {
+            MemoryError::Unmapped(a)
+            | MemoryError::ReadOnly(a)
+            | MemoryError::WriteOnly(a)
+            | MemoryError::NoAccess(a)
+            | MemoryError::Misaligned(a) => a,
+        }
+    }
+
+    /// the same error at another address, e.g. translating an offset into a bus address.
+    pub fn at<B>(self, address: B) -> MemoryError<B> {
+        match self {
+            MemoryError::Unmapped(_) => MemoryError::Unmapped(address),
+            MemoryError::ReadOnly(_) => MemoryError::ReadOnly(address),
+            MemoryError::WriteOnly(_) => MemoryError::WriteOnly(address),
+            MemoryError::NoAccess(_) => MemoryError::NoAccess(address),
+            MemoryError::Misaligned(_) => MemoryError::Misaligned(address),
+        }
+    }
+}
+
+impl<A: fmt::LowerHex> fmt::Display for MemoryError<A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let what = match self {
+            MemoryError::Unmapped(_) => "unmapped",
+            MemoryError::ReadOnly(_) => "read-only",
+            MemoryError::WriteOnly(_) => "write-only",
+            MemoryError::NoAccess(_) => "no-access",
+            MemoryError::Misaligned(_) => "misaligned",
+        };
+        write!(f, "{} address {:#x}", what, self.address())
+    }
+}
+
+impl<A: fmt::Debug + fmt::LowerHex> core::error::Error for MemoryError<A> {}
+
+/// Byte order of multi-byte values in memory.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Endian {
+    /// low byte first, as I8080, Z80 and 6502.
+    Little,
+    /// high byte first, as 6809 and 68000.
+    Big,
+}
+
+/// 16-bit access to byte memories. The address wraps around past 0xffff.
+pub trait MemoryWord: Memory<Address = u16, Data = u8> {
+    fn read_word(&self, address: u16, endian: Endian) -> u16 {
+        let bytes = [self.read(address), self.read(address.wrapping_add(1))];
+        match endian {
+            Endian::Little => u16::from_le_bytes(bytes),
+            Endian::Big => u16::from_be_bytes(bytes),
+        }
+    }
+    fn store_word(&mut self, address: u16, data: u16, endian: Endian) {
+        let bytes = match endian {
+            Endian::Little => data.to_le_bytes(),
+            Endian::Big => data.to_be_bytes(),
+        };
+        self.store(address, bytes[0]);
+        self.store(address.wrapping_add(1), bytes[1]);
+    }
 }
 
+impl<M: Memory<Address = u16, Data = u8> + ?Sized> MemoryWord for M {}
+
 pub mod typical {
     use super::*;
+    use alloc::collections::BTreeMap;
+    use alloc::rc::Rc;
+    use core::cell::{Ref, RefCell, RefMut};
+    use core::ops::Range;
+    #[cfg(feature = "std")]
+    use std::sync::{Arc, Mutex, MutexGuard};
 
-    #[derive(Debug)]
-    pub struct Memory8Bit64KB {
-        bytes: [u8; 65536],
+    /// 64KB of RAM, the whole address space of 8-bit CPUs.
+    /// It lives on the heap, so that systems holding it are cheap to move.
+    pub type Memory8Bit64KB = BoxedFlatMemory<0x10000, u16>;
+
+    fn index<A: Into<u64>>(address: A, size: usize) -> Option<usize> {
+        let index = address.into();
+        (index < size as u64).then_some(index as usize)
     }
 
-    impl Memory8Bit64KB {
-        fn new(bytes: &[u8]) -> Self {
+    /// `N` words of `D` from address 0, inline, e.g. `MemoryArray<0x1000>` for 4KB
+    /// or `MemoryArray<0x8000, u16, u16>` for a word-addressed machine.
+    /// Accesses beyond `N` panic, or fail with `try_*`.
+    #[derive(Debug, Clone)]
+    pub struct MemoryArray<const N: usize, A = u16, D = u8> {
+        words: [D; N],
+        address: PhantomData<A>,
+    }
+
+    /// `N` bytes from address 0, inline.
+    pub type FlatMemory<const N: usize, A = u16> = MemoryArray<N, A, u8>;
+
+    impl<const N: usize, A, D: Copy + Default> MemoryArray<N, A, D> {
+        /// a memory holding `words` from address 0, the rest cleared.
+        pub fn new(words: &[D]) -> Self {
             let mut mem = Self::default();
-            for (i, &x) in bytes.iter().enumerate() {
-                mem.store(i as u16, x);
+            mem.words[..words.len()].copy_from_slice(words);
+            mem
+        }
+
+        pub fn as_slice(&self) -> &[D] {
+            &self.words
+        }
+
+        pub fn as_mut_slice(&mut self) -> &mut [D] {
+            &mut self.words
+        }
+    }
+
+    impl<const N: usize, A> MemoryArray<N, A, u8> {
+        /// the whole memory as a view, to be split among devices.
+        pub fn view_mut(&mut self) -> MemoryView<'_, A> {
+            MemoryView::new(&mut self.words)
+        }
+    }
+
+    impl<const N: usize, A, D: Copy + Default> Default for MemoryArray<N, A, D> {
+        fn default() -> Self {
+            Self {
+                words: [D::default(); N],
+                address: PhantomData,
             }
+        }
+    }
+
+    impl<const N: usize, A: Copy + Into<u64>, D: Copy> Memory for MemoryArray<N, A, D> {
+        type Address = A;
+        type Data = D;
+        fn read(&self, address: A) -> D {
+            self.words[address.into() as usize]
+        }
+        fn store(&mut self, address: A, data: D) {
+            self.words[address.into() as usize] = data
+        }
+        fn try_read(&self, address: A) -> Result<D, MemoryError<A>> {
+            index(address, N)
+                .map(|i| self.words[i])
+                .ok_or(MemoryError::Unmapped(address))
+        }
+        fn try_store(&mut self, address: A, data: D) -> Result<(), MemoryError<A>> {
+            let i = index(address, N).ok_or(MemoryError::Unmapped(address))?;
+            self.words[i] = data;
+            Ok(())
+        }
+    }
+
+    /// Like [`FlatMemory`], but on the heap, for sizes that would overflow the stack
+    /// such as 16MB of a 24-bit address space.
+    #[derive(Debug, Clone)]
+    pub struct BoxedFlatMemory<const N: usize, A = u32> {
+        bytes: Box<[u8]>,
+        address: PhantomData<A>,
+    }
+
+    impl<const N: usize, A> BoxedFlatMemory<N, A> {
+        pub fn new(bytes: &[u8]) -> Self {
+            let mut mem = Self::default();
+            mem.bytes[..bytes.len()].copy_from_slice(bytes);
             mem
         }
+
+        pub fn as_slice(&self) -> &[u8] {
+            &self.bytes
+        }
+
+        pub fn as_mut_slice(&mut self) -> &mut [u8] {
+            &mut self.bytes
+        }
+
+        /// the whole memory as a view, to be split among devices.
+        pub fn view_mut(&mut self) -> MemoryView<'_, A> {
+            MemoryView::new(&mut self.bytes)
+        }
     }
 
-    impl Default for Memory8Bit64KB {
+    impl<const N: usize, A> Default for BoxedFlatMemory<N, A> {
         fn default() -> Self {
-            Memory8Bit64KB {
-                bytes: [0u8; 65536],
+            Self {
+                bytes: vec![0u8; N].into_boxed_slice(),
+                address: PhantomData,
+            }
+        }
+    }
+
+    impl<const N: usize, A: Copy + Into<u64>> Memory for BoxedFlatMemory<N, A> {
+        type Address = A;
+        type Data = u8;
+        fn read(&self, address: A) -> u8 {
+            self.bytes[address.into() as usize]
+        }
+        fn store(&mut self, address: A, data: u8) {
+            self.bytes[address.into() as usize] = data
+        }
+        fn try_read(&self, address: A) -> Result<u8, MemoryError<A>> {
+            index(address, N)
+                .map(|i| self.bytes[i])
+                .ok_or(MemoryError::Unmapped(address))
+        }
+        fn try_store(&mut self, address: A, data: u8) -> Result<(), MemoryError<A>> {
+            let i = index(address, N).ok_or(MemoryError::Unmapped(address))?;
+            self.bytes[i] = data;
+            Ok(())
+        }
+    }
+
+    #[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+    pub enum PageAccess {
+        #[default]
+        ReadWrite,
+        ReadOnly,
+        /// e.g. a latch that can be set but not read back.
+        WriteOnly,
+        NoAccess,
+    }
+
+    impl PageAccess {
+        pub fn readable(self) -> bool {
+            matches!(self, PageAccess::ReadWrite | PageAccess::ReadOnly)
+        }
+
+        pub fn writable(self) -> bool {
+            matches!(self, PageAccess::ReadWrite | PageAccess::WriteOnly)
+        }
+    }
+
+    /// A memory divided into pages of `1 << page_bits` with access permissions,
+    /// all [`PageAccess::ReadWrite`] at first.
+    ///
+    /// `try_read` and `try_store` report violations. `read` and `store` behave as
+    /// the hardware would: forbidden reads see the open bus and forbidden writes are lost.
+    #[derive(Debug, Clone)]
+    pub struct PagedMemory<M: Memory> {
+        memory: M,
+        page_bits: u32,
+        pages: BTreeMap<u64, PageAccess>,
+        open_bus: M::Data,
+    }
+
+    impl<M> PagedMemory<M>
+    where
+        M: Memory,
+        M::Address: Copy + Into<u64>,
+        M::Data: Copy,
+    {
+        pub fn new(memory: M, page_bits: u32, open_bus: M::Data) -> Self {
+            Self {
+                memory,
+                page_bits,
+                pages: BTreeMap::new(),
+                open_bus,
+            }
+        }
+
+        pub fn page_size(&self) -> u64 {
+            1 << self.page_bits
+        }
+
+        /// sets the permission of the pages overlapping `start..=end`.
+        pub fn protect(
+            &mut self,
+            start: M::Address,
+            end: M::Address,
+            access: PageAccess,
+        ) -> &mut Self {
+            let first = start.into() >> self.page_bits;
+            let last = end.into() >> self.page_bits;
+            for page in first..=last {
+                match access {
+                    PageAccess::ReadWrite => self.pages.remove(&page),
+                    _ => self.pages.insert(page, access),
+                };
+            }
+            self
+        }
+
+        pub fn access(&self, address: M::Address) -> PageAccess {
+            let page = address.into() >> self.page_bits;
+            self.pages.get(&page).copied().unwrap_or_default()
+        }
+
+        pub fn inner(&self) -> &M {
+            &self.memory
+        }
+
+        pub fn inner_mut(&mut self) -> &mut M {
+            &mut self.memory
+        }
+    }
+
+    impl<M> Memory for PagedMemory<M>
+    where
+        M: Memory,
+        M::Address: Copy + Into<u64>,
+        M::Data: Copy,
+    {
+        type Address = M::Address;
+        type Data = M::Data;
+        fn read(&self, address: M::Address) -> M::Data {
+            self.try_read(address).unwrap_or(self.open_bus)
+        }
+        fn store(&mut self, address: M::Address, data: M::Data) {
+            let _ = self.try_store(address, data);
+        }
+        fn try_read(&self, address: M::Address) -> Result<M::Data, MemoryError<M::Address>> {
+            match self.access(address) {
+                PageAccess::WriteOnly => Err(MemoryError::WriteOnly(address)),
+                PageAccess::NoAccess => Err(MemoryError::NoAccess(address)),
+                _ => self.memory.try_read(address),
+            }
+        }
+        fn try_store(
+            &mut self,
+            address: M::Address,
+            data: M::Data,
+        ) -> Result<(), MemoryError<M::Address>> {
+            match self.access(address) {
+                PageAccess::ReadOnly => Err(MemoryError::ReadOnly(address)),
+                PageAccess::NoAccess => Err(MemoryError::NoAccess(address)),
+                _ => self.memory.try_store(address, data),
+            }
+        }
+    }
+
+    /// A memory behind a partially decoded address bus: only the address bits in
+    /// `mask` reach it, so a 2KB RAM with mask 0x07ff repeats every 2KB.
+    ///
+    /// Addresses are taken relative to the start of the window, as [`crate::bus::Bus`] does.
+    #[derive(Debug, Clone)]
+    pub struct MirroredMemory<M: Memory> {
+        memory: M,
+        mask: M::Address,
+    }
+
+    impl<M> MirroredMemory<M>
+    where
+        M: Memory,
+        M::Address: BitwiseOps,
+    {
+        pub fn new(memory: M, mask: M::Address) -> Self {
+            Self { memory, mask }
+        }
+
+        pub fn inner(&self) -> &M {
+            &self.memory
+        }
+
+        pub fn inner_mut(&mut self) -> &mut M {
+            &mut self.memory
+        }
+    }
+
+    impl<M> Memory for MirroredMemory<M>
+    where
+        M: Memory,
+        M::Address: BitwiseOps,
+    {
+        type Address = M::Address;
+        type Data = M::Data;
+        fn read(&self, address: M::Address) -> M::Data {
+            self.memory.read(address & self.mask)
+        }
+        fn store(&mut self, address: M::Address, data: M::Data) {
+            self.memory.store(address & self.mask, data)
+        }
+        fn try_read(&self, address: M::Address) -> Result<M::Data, MemoryError<M::Address>> {
+            self.memory
+                .try_read(address & self.mask)
+                .map_err(|e| e.at(address))
+        }
+        fn try_store(
+            &mut self,
+            address: M::Address,
+            data: M::Data,
+        ) -> Result<(), MemoryError<M::Address>> {
+            self.memory
+                .try_store(address & self.mask, data)
+                .map_err(|e| e.at(address))
+        }
+    }
+
+    /// A handle to a memory held by several bus masters, e.g. the CPU and a DMA controller.
+    /// Clones share the same backing store.
+    #[derive(Debug, Default)]
+    pub struct SharedMemory<M> {
+        memory: Rc<RefCell<M>>,
+    }
+
+    impl<M> SharedMemory<M> {
+        pub fn new(memory: M) -> Self {
+            Self {
+                memory: Rc::new(RefCell::new(memory)),
+            }
+        }
+
+        pub fn borrow(&self) -> Ref<'_, M> {
+            self.memory.borrow()
+        }
+
+        pub fn borrow_mut(&self) -> RefMut<'_, M> {
+            self.memory.borrow_mut()
+        }
+    }
+
+    impl<M> Clone for SharedMemory<M> {
+        fn clone(&self) -> Self {
+            Self {
+                memory: self.memory.clone(),
+            }
+        }
+    }
+
+    impl<M: Memory> Memory for SharedMemory<M> {
+        type Address = M::Address;
+        type Data = M::Data;
+        fn read(&self, address: M::Address) -> M::Data {
+            self.memory.borrow().read(address)
+        }
+        fn store(&mut self, address: M::Address, data: M::Data) {
+            self.memory.borrow_mut().store(address, data)
+        }
+        fn try_read(&self, address: M::Address) -> Result<M::Data, MemoryError<M::Address>> {
+            self.memory.borrow().try_read(address)
+        }
+        fn try_store(
+            &mut self,
+            address: M::Address,
+            data: M::Data,
+        ) -> Result<(), MemoryError<M::Address>> {
+            self.memory.borrow_mut().try_store(address, data)
+        }
+    }
+
+    /// Like [`SharedMemory`], for bus masters running on other threads.
+    #[cfg(feature = "std")]
+    #[derive(Debug, Default)]
+    pub struct SyncSharedMemory<M> {
+        memory: Arc<Mutex<M>>,
+    }
+
+    #[cfg(feature = "std")]
+    impl<M> SyncSharedMemory<M> {
+        pub fn new(memory: M) -> Self {
+            Self {
+                memory: Arc::new(Mutex::new(memory)),
+            }
+        }
+
+        /// the memory, locked for a batch of accesses.
+        pub fn lock(&self) -> MutexGuard<'_, M> {
+            // a master panicking mid-access leaves no invariant of the memory broken.
+            self.memory.lock().unwrap_or_else(|e| e.into_inner())
+        }
+    }
+
+    #[cfg(feature = "std")]
+    impl<M> Clone for SyncSharedMemory<M> {
+        fn clone(&self) -> Self {
+            Self {
+                memory: self.memory.clone(),
             }
         }
     }
 
-    impl Memory for Memory8Bit64KB {
-        type Address = u16;
+    #[cfg(feature = "std")]
+    impl<M: Memory> Memory for SyncSharedMemory<M> {
+        type Address = M::Address;
+        type Data = M::Data;
+        fn read(&self, address: M::Address) -> M::Data {
+            self.lock().read(address)
+        }
+        fn store(&mut self, address: M::Address, data: M::Data) {
+            self.lock().store(address, data)
+        }
+        fn try_read(&self, address: M::Address) -> Result<M::Data, MemoryError<M::Address>> {
+            self.lock().try_read(address)
+        }
+        fn try_store(
+            &mut self,
+            address: M::Address,
+            data: M::Data,
+        ) -> Result<(), MemoryError<M::Address>> {
+            self.lock().try_store(address, data)
+        }
+    }
+
+    /// A borrowed region of a byte memory, addressed from 0.
+    ///
+    /// Views split from one memory never overlap, so a machine can hand each region
+    /// to a different device without sharing or copying.
+    #[derive(Debug)]
+    pub struct MemoryView<'a, A = u16> {
+        bytes: &'a mut [u8],
+        address: PhantomData<A>,
+    }
+
+    impl<'a, A> MemoryView<'a, A> {
+        pub fn new(bytes: &'a mut [u8]) -> Self {
+            Self {
+                bytes,
+                address: PhantomData,
+            }
+        }
+
+        pub fn len(&self) -> usize {
+            self.bytes.len()
+        }
+
+        pub fn is_empty(&self) -> bool {
+            self.bytes.is_empty()
+        }
+
+        /// splits into the views before and from `mid`.
+        pub fn split_at(self, mid: usize) -> (Self, Self) {
+            let (low, high) = self.bytes.split_at_mut(mid);
+            (Self::new(low), Self::new(high))
+        }
+
+        /// splits into views of the given lengths, in order, and what is left over.
+        pub fn split(self, lengths: &[usize]) -> (Vec<Self>, Self) {
+            let mut rest = self;
+            let mut views = Vec::with_capacity(lengths.len());
+            for &length in lengths {
+                let (view, tail) = rest.split_at(length);
+                views.push(view);
+                rest = tail;
+            }
+            (views, rest)
+        }
+
+        /// narrows the view to `range`.
+        pub fn region(self, range: Range<usize>) -> Self {
+            Self::new(&mut self.bytes[range])
+        }
+    }
+
+    impl<A: Copy + Into<u64>> Memory for MemoryView<'_, A> {
+        type Address = A;
         type Data = u8;
-        fn read(&self, index: u16) -> u8 {
-            self.bytes[index as usize]
+        fn read(&self, address: A) -> u8 {
+            self.bytes[address.into() as usize]
         }
-        fn store(&mut self, index: u16, data: u8) {
-            self.bytes[index as usize] = data
+        fn store(&mut self, address: A, data: u8) {
+            self.bytes[address.into() as usize] = data
         }
+        fn try_read(&self, address: A) -> Result<u8, MemoryError<A>> {
+            index(address, self.bytes.len())
+                .map(|i| self.bytes[i])
+                .ok_or(MemoryError::Unmapped(address))
+        }
+        fn try_store(&mut self, address: A, data: u8) -> Result<(), MemoryError<A>> {
+            let i = index(address, self.bytes.len()).ok_or(MemoryError::Unmapped(address))?;
+            self.bytes[i] = data;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::typical::*;
+    use super::*;
+    use alloc::string::ToString;
+
+    #[test]
+    fn fallible() {
+        let mut memory = Memory8Bit64KB::default();
+        assert_eq!(memory.try_store(0xffff, 0x12), Ok(()));
+        assert_eq!(memory.try_read(0xffff), Ok(0x12));
+        let error = MemoryError::ReadOnly(0x0003u16).at(0xe003u16);
+        assert_eq!(error, MemoryError::ReadOnly(0xe003));
+        assert_eq!(error.to_string(), "read-only address 0xe003");
+    }
+
+    #[test]
+    fn word() {
+        let mut memory = Memory8Bit64KB::new(&[0x34, 0x12]);
+        assert_eq!(memory.read_word(0x0000, Endian::Little), 0x1234);
+        assert_eq!(memory.read_word(0x0000, Endian::Big), 0x3412);
+        memory.store_word(0xffff, 0xabcd, Endian::Big);
+        assert_eq!((memory.read(0xffff), memory.read(0x0000)), (0xab, 0xcd));
+        assert_eq!(memory.read_word(0xffff, Endian::Little), 0xcdab);
+    }
+
+    #[test]
+    #[should_panic]
+    fn flat_out_of_range() {
+        FlatMemory::<0x400>::default().read(0x0400);
+    }
+
+    #[test]
+    fn flat() {
+        let mut small = FlatMemory::<0x400>::new(&[1, 2, 3]);
+        assert_eq!(small.read(0x0002), 3);
+        assert_eq!(small.try_read(0x03ff), Ok(0));
+        assert_eq!(small.try_read(0x0400), Err(MemoryError::Unmapped(0x0400)));
+        assert_eq!(
+            small.try_store(0x8000, 1),
+            Err(MemoryError::Unmapped(0x8000))
+        );
+        // only a pointer to move around
+        assert_eq!(
+            core::mem::size_of::<Memory8Bit64KB>(),
+            core::mem::size_of::<Box<[u8]>>()
+        );
+        let mut big = BoxedFlatMemory::<0x100_0000>::default();
+        big.store(0xff_ffff, 0x12);
+        assert_eq!(big.read(0xff_ffff), 0x12);
+        assert_eq!(big.as_slice().len(), 0x100_0000);
+        assert_eq!(
+            big.try_read(0x100_0000),
+            Err(MemoryError::Unmapped(0x100_0000))
+        );
+    }
+
+    #[test]
+    fn array() {
+        let mut ram = MemoryArray::<0x4000>::default();
+        ram.store(0x3fff, 0xef);
+        assert_eq!(ram.try_read(0x3fff), Ok(0xef));
+        // 16-bit words of a word-addressed machine
+        let mut rom = MemoryArray::<0x1000, u16, u16>::new(&[0x1234, 0x5678]);
+        assert_eq!(rom.read(0x0001), 0x5678);
+        assert_eq!(rom.try_store(0x1000, 0), Err(MemoryError::Unmapped(0x1000)));
+    }
+
+    #[test]
+    fn shared() {
+        let mut cpu = SharedMemory::new(Memory8Bit64KB::default());
+        let mut dma = cpu.clone();
+        dma.store(0x8000, 0x12);
+        assert_eq!(cpu.read(0x8000), 0x12);
+        cpu.store(0x8001, 0x34);
+        assert_eq!(dma.borrow().as_slice()[0x8001], 0x34);
+
+        #[cfg(feature = "std")]
+        {
+            let mut cpu = SyncSharedMemory::new(Memory8Bit64KB::default());
+            let mut video = cpu.clone();
+            std::thread::spawn(move || video.store(0xc000, 0x56))
+                .join()
+                .unwrap();
+            assert_eq!(cpu.read(0xc000), 0x56);
+            cpu.store(0xc001, 0x78);
+            assert_eq!(cpu.lock().read(0xc001), 0x78);
+        }
+    }
+
+    #[test]
+    fn paged() {
+        let mut memory = PagedMemory::new(Memory8Bit64KB::new(&[0x12]), 10, 0xff);
+        memory
+            .protect(0x0000, 0x03ff, PageAccess::ReadOnly)
+            .protect(0x0400, 0x0400, PageAccess::WriteOnly)
+            .protect(0xfc00, 0xffff, PageAccess::NoAccess);
+        assert_eq!(memory.page_size(), 0x400);
+        assert_eq!(memory.access(0x07ff), PageAccess::WriteOnly);
+        assert_eq!(
+            memory.try_store(0x0000, 0),
+            Err(MemoryError::ReadOnly(0x0000))
+        );
+        memory.store(0x0000, 0);
+        assert_eq!(memory.read(0x0000), 0x12);
+        assert_eq!(memory.try_read(0x0401), Err(MemoryError::WriteOnly(0x0401)));
+        memory.store(0x0401, 0x34);
+        assert_eq!(memory.read(0x0401), 0xff);
+        assert_eq!(memory.inner().read(0x0401), 0x34);
+        assert_eq!(memory.try_read(0xfffe), Err(MemoryError::NoAccess(0xfffe)));
+        memory.protect(0x0400, 0x07ff, PageAccess::ReadWrite);
+        assert_eq!(memory.try_read(0x0401), Ok(0x34));
+    }
+
+    #[test]
+    fn mirrored() {
+        let mut memory = MirroredMemory::new(FlatMemory::<0x800>::default(), 0x07ff);
+        memory.store(0x0801, 0x12);
+        assert_eq!(
+            [0x0001, 0x0801, 0x1001, 0x1801].map(|a| memory.read(a)),
+            [0x12; 4]
+        );
+        assert_eq!(memory.inner().read(0x0001), 0x12);
+        let mut bus = crate::bus::Bus::default();
+        bus.map_memory(0xc000..=0xdfff, memory);
+        assert_eq!(bus.read(0xd801), 0x12);
+    }
+
+    #[test]
+    fn views() {
+        let mut memory = Memory8Bit64KB::default();
+        let (mut views, mut rest) = memory.view_mut().split(&[0x8000, 0x4000]);
+        assert_eq!(rest.len(), 0x4000);
+        views[1].store(0x0000, 0x12);
+        rest.store(0x3fff, 0x34);
+        assert_eq!(
+            views[1].try_read(0x4000),
+            Err(MemoryError::Unmapped(0x4000))
+        );
+        let mut text = views.remove(0).region(0x10..0x20);
+        text.store(0x0001, 0x56);
+        assert_eq!(
+            [
+                memory.read(0x8000),
+                memory.read(0xffff),
+                memory.read(0x0011)
+            ],
+            [0x12, 0x34, 0x56]
+        );
     }
 }
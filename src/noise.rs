@@ -0,0 +1,129 @@
+use crate::bus::Device;
+use crate::memory::Memory;
+
+/// A reproducible source of randomness, for devices modelling analog uncertainty
+/// such as the power-on pattern of RAM, a floating bus or tape noise.
+pub trait Noise {
+    fn next_u64(&mut self) -> u64;
+    fn next_u8(&mut self) -> u8 {
+        (self.next_u64() >> 56) as u8
+    }
+    /// true once in `one_in` calls on average.
+    fn chance(&mut self, one_in: u64) -> bool {
+        one_in != 0 && self.next_u64() % one_in == 0
+    }
+    /// fills `range` of `memory` as it would be at power on.
+    fn fill<M>(&mut self, memory: &mut M, range: core::ops::RangeInclusive<u16>)
+    where
+        M: Memory<Address = u16, Data = u8>,
+        Self: Sized,
+    {
+        for address in range {
+            memory.store(address, self.next_u8())
+        }
+    }
+}
+
+pub mod typical {
+    use super::*;
+
+    /// SplitMix64, reproducible from its seed.
+    #[derive(Debug, Clone)]
+    pub struct SplitMix64 {
+        seed: u64,
+        state: u64,
+    }
+
+    impl SplitMix64 {
+        pub fn new(seed: u64) -> Self {
+            Self { seed, state: seed }
+        }
+
+        pub fn seed(&self) -> u64 {
+            self.seed
+        }
+    }
+
+    impl Noise for SplitMix64 {
+        fn next_u64(&mut self) -> u64 {
+            self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+            let mut z = self.state;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+            z ^ (z >> 31)
+        }
+    }
+
+    /// No noise at all, for when accuracy is not wanted.
+    #[derive(Debug, Default, Clone)]
+    pub struct Silence(pub u64);
+
+    impl Noise for Silence {
+        fn next_u64(&mut self) -> u64 {
+            self.0
+        }
+    }
+
+    /// Nothing drives the bus: reads return noise and writes are lost.
+    #[derive(Debug, Clone)]
+    pub struct FloatingBus<N> {
+        pub noise: N,
+    }
+
+    impl<N> FloatingBus<N> {
+        pub fn new(noise: N) -> Self {
+            Self { noise }
+        }
+    }
+
+    impl<N: Noise> Device for FloatingBus<N> {
+        type Address = u16;
+        type Data = u8;
+        fn read(&mut self, _offset: u16) -> u8 {
+            self.noise.next_u8()
+        }
+        fn write(&mut self, _offset: u16, _data: u8) {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::typical::*;
+    use super::*;
+    use crate::bus::Bus;
+    use crate::memory::typical::Memory8Bit64KB;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn reproducible() {
+        let sequence = |seed| {
+            let mut noise = SplitMix64::new(seed);
+            (0..8).map(|_| noise.next_u64()).collect::<Vec<_>>()
+        };
+        assert_eq!(sequence(1), sequence(1));
+        assert_ne!(sequence(1), sequence(2));
+        assert_eq!(SplitMix64::new(0).next_u64(), 0xe220_a839_7b1d_cdaf);
+        assert_eq!(Silence(0x1200_0000_0000_0000).next_u8(), 0x12);
+        assert!(!Silence(1).chance(2));
+        assert!(!Silence(0).chance(0));
+    }
+
+    #[test]
+    fn power_on_and_floating_bus() {
+        let mut a = Memory8Bit64KB::default();
+        let mut b = Memory8Bit64KB::default();
+        SplitMix64::new(88).fill(&mut a, 0x0000..=0x00ff);
+        SplitMix64::new(88).fill(&mut b, 0x0000..=0x00ff);
+        assert!((0..=0xff).all(|i| a.read(i) == b.read(i)));
+        assert!((0..=0xff).any(|i| a.read(i) != 0));
+        assert_eq!(a.read(0x0100), 0);
+
+        let mut bus = Bus::default();
+        bus.map(0x0000..=0xffff, FloatingBus::new(SplitMix64::new(88)));
+        bus.map_memory(0x0000..=0x7fff, Memory8Bit64KB::default());
+        let mut noise = SplitMix64::new(88);
+        assert_eq!(bus.read(0x8000), noise.next_u8());
+        assert_eq!(bus.read(0x0000), 0);
+        assert_eq!(bus.read(0xffff), noise.next_u8());
+    }
+}
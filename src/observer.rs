@@ -0,0 +1,198 @@
+use crate::cpu::CPU;
+
+/// Hooks called by the CPU run loop, for building tracers, profilers and debuggers.
+/// Every method does nothing by default, so an observer only overrides what it needs.
+pub trait ExecutionObserver<C: CPU> {
+    /// Called when an instruction word is fetched from `address`.
+    fn on_fetch(&mut self, _address: C::Address, _data: C::Data) {}
+    /// Called before an decoded instruction is executed on `cpu`.
+    fn on_execute(&mut self, _cpu: &C) {}
+    fn on_memory_read(&mut self, _address: C::Address, _data: C::Data) {}
+    fn on_memory_write(&mut self, _address: C::Address, _data: C::Data) {}
+}
+
+/// The no-op observer.
+impl<C: CPU> ExecutionObserver<C> for () {}
+
+/// host-side observers, measuring wall-clock time or writing to files.
+#[cfg(feature = "std")]
+pub mod typical {
+    use super::*;
+    use alloc::collections::BTreeMap;
+    use alloc::{string::String, vec::Vec};
+    use core::fmt;
+    use std::io::{self, Write};
+    use std::time::{Duration, Instant};
+
+    /// Counts executed instructions by opcode, the first word fetched for each instruction.
+    #[derive(Debug, Clone)]
+    pub struct Histogram<D> {
+        counts: BTreeMap<D, u64>,
+        instructions: u64,
+        opcode_next: bool,
+        started: Instant,
+    }
+
+    impl<D: Ord> Histogram<D> {
+        pub fn new() -> Self {
+            Self {
+                counts: BTreeMap::new(),
+                instructions: 0,
+                opcode_next: true,
+                started: Instant::now(),
+            }
+        }
+
+        pub fn instructions(&self) -> u64 {
+            self.instructions
+        }
+
+        pub fn count(&self, opcode: &D) -> u64 {
+            self.counts.get(opcode).copied().unwrap_or(0)
+        }
+
+        /// Summarizes the run so far, grouping opcodes by `name`, e.g. their mnemonics.
+        pub fn report<F>(&self, name: F) -> Report
+        where
+            D: Copy,
+            F: Fn(D) -> String,
+        {
+            let mut mix = BTreeMap::new();
+            for (&opcode, &count) in &self.counts {
+                *mix.entry(name(opcode)).or_insert(0) += count;
+            }
+            let mut mix: Vec<_> = mix.into_iter().collect();
+            mix.sort_by(|(_, a), (_, b)| b.cmp(a));
+            Report {
+                instructions: self.instructions,
+                elapsed: self.started.elapsed(),
+                mix,
+            }
+        }
+    }
+
+    impl<D: Ord> Default for Histogram<D> {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    impl<C: CPU> ExecutionObserver<C> for Histogram<C::Data>
+    where
+        C::Data: Ord,
+    {
+        fn on_fetch(&mut self, _address: C::Address, data: C::Data) {
+            if self.opcode_next {
+                *self.counts.entry(data).or_insert(0) += 1;
+                self.opcode_next = false;
+            }
+        }
+        fn on_execute(&mut self, _cpu: &C) {
+            self.instructions += 1;
+            self.opcode_next = true;
+        }
+    }
+
+    /// Writes the state of the CPU before every instruction, one `Display` line each,
+    /// to diff against traces of reference emulators.
+    #[derive(Debug)]
+    pub struct TraceLines<W> {
+        out: W,
+        error: Option<io::Error>,
+    }
+
+    impl<W: Write> TraceLines<W> {
+        pub fn new(out: W) -> Self {
+            Self { out, error: None }
+        }
+
+        /// the first write error, after which nothing more is written.
+        pub fn error(&self) -> Option<&io::Error> {
+            self.error.as_ref()
+        }
+
+        pub fn into_inner(self) -> W {
+            self.out
+        }
+    }
+
+    impl<C: CPU + fmt::Display, W: Write> ExecutionObserver<C> for TraceLines<W> {
+        fn on_execute(&mut self, cpu: &C) {
+            if self.error.is_none() {
+                self.error = writeln!(self.out, "{}", cpu).err();
+            }
+        }
+    }
+
+    /// End-of-run summary of a [`Histogram`].
+    #[derive(Debug, Clone)]
+    pub struct Report {
+        pub instructions: u64,
+        pub elapsed: Duration,
+        /// executions by name, most frequent first.
+        pub mix: Vec<(String, u64)>,
+    }
+
+    impl Report {
+        /// emulated million instructions per second of host time.
+        pub fn mips(&self) -> f64 {
+            match self.elapsed.as_secs_f64() {
+                secs if secs > 0.0 => self.instructions as f64 / secs / 1e6,
+                _ => 0.0,
+            }
+        }
+    }
+
+    impl fmt::Display for Report {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            writeln!(
+                f,
+                "{} instructions in {:?} ({:.2} MIPS)",
+                self.instructions,
+                self.elapsed,
+                self.mips()
+            )?;
+            for (name, count) in &self.mix {
+                let ratio = *count as f64 * 100.0 / self.instructions.max(1) as f64;
+                writeln!(f, "{:<8}{:>12}{:>8.2}%", name, count, ratio)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::typical::*;
+    use super::*;
+    use crate::cpu::tests::CPU8;
+    use crate::disasm::typical::I8080_OPCODES;
+
+    #[test]
+    fn histogram() {
+        let mut histogram = Histogram::new();
+        let cpu = CPU8::default();
+        // MVI A,1; MOV B,A; MOV C,A; MVI A,2
+        for words in [&[0x3e, 0x01][..], &[0x47], &[0x4f], &[0x3e, 0x02]] {
+            for (i, &word) in words.iter().enumerate() {
+                ExecutionObserver::<CPU8>::on_fetch(&mut histogram, i as u16, word);
+            }
+            histogram.on_execute(&cpu);
+        }
+        assert_eq!(histogram.instructions(), 4);
+        assert_eq!(histogram.count(&0x3e), 2);
+        assert_eq!(histogram.count(&0x01), 0);
+        let report = histogram.report(|opcode| {
+            let opcode = I8080_OPCODES[opcode as usize].unwrap();
+            opcode.mnemonic.split(' ').next().unwrap().to_string()
+        });
+        assert_eq!(report.instructions, 4);
+        assert_eq!(
+            report.mix,
+            vec![("MOV".to_string(), 2), ("MVI".to_string(), 2)]
+        );
+        let text = report.to_string();
+        let line: Vec<_> = text.lines().nth(1).unwrap().split_whitespace().collect();
+        assert_eq!(line, ["MOV", "2", "50.00%"]);
+    }
+}
@@ -0,0 +1,157 @@
+use crate::cpu::CPU;
+use crate::observer::ExecutionObserver;
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::ops::RangeInclusive;
+
+/// What a [`Profile`] counts.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum AccessKind {
+    Read,
+    Write,
+    /// instructions starting in the bucket, operands not counted.
+    Execute,
+}
+
+/// The accesses to a bucket of addresses.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub struct AccessCounts {
+    pub reads: u64,
+    pub writes: u64,
+    pub executes: u64,
+}
+
+impl AccessCounts {
+    pub fn get(&self, kind: AccessKind) -> u64 {
+        match kind {
+            AccessKind::Read => self.reads,
+            AccessKind::Write => self.writes,
+            AccessKind::Execute => self.executes,
+        }
+    }
+}
+
+/// Counts reads, writes and executed instructions per bucket of `2^shift` addresses,
+/// to find the tight loops and the data they hammer, or writes where none are expected.
+///
+/// Run the machine with it, by [`Machine::run_observed`] or [`Machine::step_observed`].
+/// A shift of 0 counts every address apart.
+///
+/// [`Machine::run_observed`]: crate::machine::Machine::run_observed
+/// [`Machine::step_observed`]: crate::machine::Machine::step_observed
+#[derive(Debug, Clone)]
+pub struct Profile {
+    buckets: BTreeMap<u64, AccessCounts>,
+    shift: u32,
+    opcode_next: bool,
+}
+
+impl Profile {
+    pub fn new(shift: u32) -> Self {
+        assert!(shift < 64);
+        Self {
+            buckets: BTreeMap::new(),
+            shift,
+            opcode_next: true,
+        }
+    }
+
+    /// the counts of the bucket holding `address`.
+    pub fn counts<A: Into<u64>>(&self, address: A) -> AccessCounts {
+        let bucket = address.into() >> self.shift;
+        self.buckets.get(&bucket).copied().unwrap_or_default()
+    }
+
+    /// the `n` buckets most accessed by `kind`, most first, as their address ranges.
+    pub fn hottest(&self, kind: AccessKind, n: usize) -> Vec<(RangeInclusive<u64>, u64)> {
+        let mut hot: Vec<_> = self
+            .buckets
+            .iter()
+            .map(|(&bucket, counts)| (bucket, counts.get(kind)))
+            .filter(|&(_, count)| count > 0)
+            .collect();
+        // stable, so ties stay in address order
+        hot.sort_by(|(_, a), (_, b)| b.cmp(a));
+        hot.into_iter()
+            .take(n)
+            .map(|(bucket, count)| (self.range(bucket), count))
+            .collect()
+    }
+
+    pub fn clear(&mut self) {
+        self.buckets.clear();
+        self.opcode_next = true;
+    }
+
+    fn range(&self, bucket: u64) -> RangeInclusive<u64> {
+        let start = bucket << self.shift;
+        start..=start + ((1 << self.shift) - 1)
+    }
+
+    fn bucket<A: Into<u64>>(&mut self, address: A) -> &mut AccessCounts {
+        self.buckets
+            .entry(address.into() >> self.shift)
+            .or_default()
+    }
+}
+
+impl<C: CPU> ExecutionObserver<C> for Profile
+where
+    C::Address: Into<u64>,
+{
+    fn on_fetch(&mut self, address: C::Address, _data: C::Data) {
+        if self.opcode_next {
+            self.bucket(address).executes += 1;
+            self.opcode_next = false;
+        }
+    }
+    fn on_execute(&mut self, _cpu: &C) {
+        self.opcode_next = true;
+    }
+    fn on_memory_read(&mut self, address: C::Address, _data: C::Data) {
+        self.bucket(address).reads += 1;
+    }
+    fn on_memory_write(&mut self, address: C::Address, _data: C::Data) {
+        self.bucket(address).writes += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::Bus;
+    use crate::clock::Clock;
+    use crate::machine::Machine;
+    use crate::memory::typical::Memory8Bit64KB;
+    use crate::typical::i8080::I8080;
+
+    #[test]
+    fn hottest() {
+        let mut memory = Memory8Bit64KB::new(&[
+            0x21, 0x00, 0x80, // LXI H,8000
+            0xc3, 0x00, 0x01, // JMP 0100
+        ]);
+        // a loop storing to 0x8000 and up
+        memory.as_mut_slice()[0x0100..0x0105].copy_from_slice(&[
+            0x77, // MOV M,A
+            0x2c, // INR L
+            0xc3, 0x00, 0x01, // JMP 0100
+        ]);
+        let mut bus = Bus::default();
+        bus.map_memory(0x0000..=0xffff, memory);
+        let mut machine = Machine::new(I8080::default(), bus, Clock::PC8801_4MHZ);
+        let mut profile = Profile::new(4);
+        machine.run_observed(10 + 10 + 20 * (7 + 5 + 10), &mut profile);
+        assert_eq!(
+            profile.hottest(AccessKind::Execute, 2),
+            [(0x0100..=0x010f, 60), (0x0000..=0x000f, 2)]
+        );
+        assert_eq!(
+            profile.hottest(AccessKind::Write, 3),
+            [(0x8000..=0x800f, 16), (0x8010..=0x801f, 4)]
+        );
+        assert_eq!(profile.counts(0x0102u16).executes, 60);
+        assert_eq!(profile.counts(0x0102u16).reads, 0);
+        assert!(profile.hottest(AccessKind::Read, 1).is_empty());
+    }
+}
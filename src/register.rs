@@ -1,13 +1,37 @@
 use crate::BitwiseOps;
+use alloc::vec::Vec;
 
 pub trait RegisterCode {
     type Register;
 }
 
+/// Register codes that can be listed, e.g. for a debugger to show any CPU's registers.
+pub trait RegisterEnumerable: RegisterCode + Copy + 'static {
+    /// every code, in the conventional display order.
+    const ALL: &'static [Self];
+    fn name(self) -> &'static str;
+}
+
 pub trait RegisterSet<C: RegisterCode<Register = Self::Register>> {
     type Register;
     fn load_of(&mut self, code: C, bits: Self::Register);
     fn read_of(&self, code: C) -> Self::Register;
+    /// name and current value of every register.
+    fn registers(&self) -> Vec<(&'static str, Self::Register)>
+    where
+        C: RegisterEnumerable,
+    {
+        C::ALL
+            .iter()
+            .map(|&c| (c.name(), self.read_of(c)))
+            .collect()
+    }
+}
+
+/// Registers with alternate banks swapped as a whole, e.g. Z80 `EX AF,AF'` and `EXX`.
+pub trait RegisterBanks<K> {
+    /// swaps the registers of `bank` with their alternates.
+    fn exchange_bank(&mut self, bank: K);
 }
 
 pub trait Register {
@@ -15,24 +39,38 @@ pub trait Register {
     fn read(&self) -> Self;
 }
 
+/// Wrapping increment, as INX does on a register pair.
 pub trait RegisterIncrementable {
-    fn increment(&mut self);
+    /// Returns the carry out, i.e. whether the register wrapped around to zero.
+    fn overflowing_increment(&mut self) -> bool;
+    fn increment(&mut self) {
+        self.overflowing_increment();
+    }
 }
 
+/// Wrapping decrement, as DCX does on a register pair.
 pub trait RegisterDecrementable {
-    fn decrement(&mut self);
+    /// Returns the borrow out, i.e. whether the register wrapped around from zero.
+    fn overflowing_decrement(&mut self) -> bool;
+    fn decrement(&mut self) {
+        self.overflowing_decrement();
+    }
 }
 
 macro_rules! register_impl {
     ($($t:ty)*) => {$(
         impl RegisterIncrementable for $t {
-            fn increment(&mut self) {
-                *self = self.wrapping_add(1)
+            fn overflowing_increment(&mut self) -> bool {
+                let (res, carry) = self.overflowing_add(1);
+                *self = res;
+                carry
             }
         }
         impl RegisterDecrementable for $t {
-            fn decrement(&mut self) {
-                *self = self.wrapping_sub(1)
+            fn overflowing_decrement(&mut self) -> bool {
+                let (res, borrow) = self.overflowing_sub(1);
+                *self = res;
+                borrow
             }
         }
         impl Register for $t {
@@ -48,11 +86,55 @@ macro_rules! register_impl {
 
 register_impl!(u8 u16 u32 u64 usize);
 
-pub(crate) trait RegisterLoader: RegisterReader {
+/// A writable view of (part of) a register.
+///
+/// Not sealed: register files of other CPUs implement it for their own views,
+/// e.g. one byte of a 32-bit register:
+///
+/// ```
+/// use n88::register::{RegisterLoader, RegisterReader};
+///
+/// struct ByteOf<'a> {
+///     register: &'a mut u32,
+///     index: u32,
+/// }
+///
+/// impl RegisterReader for ByteOf<'_> {
+///     type Size = u8;
+///     fn read(&self) -> u8 {
+///         (*self.register >> (8 * self.index)) as u8
+///     }
+/// }
+///
+/// impl RegisterLoader for ByteOf<'_> {
+///     fn load(&mut self, bits: u8) {
+///         let shift = 8 * self.index;
+///         *self.register = *self.register & !(0xff << shift) | (bits as u32) << shift;
+///     }
+/// }
+///
+/// let mut eax = 0x1234_5678;
+/// ByteOf { register: &mut eax, index: 2 }.load(0xab);
+/// assert_eq!(eax, 0x12ab_5678);
+/// ```
+pub trait RegisterLoader: RegisterReader {
     fn load(&mut self, bits: Self::Size);
 }
 
-pub(crate) trait RegisterReader {
+/// A read-only view of (part of) a register.
+///
+/// Loaders compose, e.g. masking a view so that only some flags are written:
+///
+/// ```
+/// use n88::register::typical::{MaskedRegisterLoader, Register16In8Loader};
+/// use n88::register::RegisterLoader;
+///
+/// let mut psw = 0x12ff;
+/// let flags = Register16In8Loader::low(&mut psw);
+/// MaskedRegisterLoader::new(flags, 0x01).load(0x00);
+/// assert_eq!(psw, 0x12fe);
+/// ```
+pub trait RegisterReader {
     type Size;
     fn read(&self) -> Self::Size;
 }
@@ -60,8 +142,22 @@ pub(crate) trait RegisterReader {
 pub mod typical {
     use super::*;
 
+    /// A bank of registers `R` and its alternate.
+    #[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+    pub struct Banked<R> {
+        pub active: R,
+        pub alternate: R,
+    }
+
+    impl<R> Banked<R> {
+        pub fn exchange(&mut self) {
+            core::mem::swap(&mut self.active, &mut self.alternate)
+        }
+    }
+
+    /// loads only the bits in `mask`, preserving the others.
     #[derive(Debug)]
-    pub(crate) struct MaskedRegisterLoader<B, L> {
+    pub struct MaskedRegisterLoader<B, L> {
         loader: L,
         mask: B,
     }
@@ -87,23 +183,130 @@ pub mod typical {
         }
     }
 
-    /// fixme: divide into low and high Loader
-    pub(crate) struct Register16In8Loader<'a> {
+    macro_rules! register_newtype_impl {
+        ($($name:ident($t:ty);)*) => {$(
+            #[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Hash)]
+            pub struct $name(pub $t);
+
+            /// bits are numbered from 0, modulo the width, as a shift count wraps.
+            impl $name {
+                pub fn bit(&self, n: u32) -> bool {
+                    let n = n % <$t>::BITS;
+                    self.0 >> n & 1 != 0
+                }
+                pub fn set_bit(&mut self, n: u32, set: bool) {
+                    let n = n % <$t>::BITS;
+                    self.0 = self.0 & !(1 << n) | (set as $t) << n;
+                }
+            }
+
+            impl From<$t> for $name {
+                fn from(bits: $t) -> Self {
+                    Self(bits)
+                }
+            }
+
+            impl From<$name> for $t {
+                fn from(register: $name) -> Self {
+                    register.0
+                }
+            }
+
+            impl<'a> From<&'a $name> for &'a $t {
+                fn from(register: &'a $name) -> Self {
+                    &register.0
+                }
+            }
+
+            impl<'a> From<&'a mut $name> for &'a mut $t {
+                fn from(register: &'a mut $name) -> Self {
+                    &mut register.0
+                }
+            }
+
+            impl Register for $name {
+                fn load(&mut self, bits: Self) {
+                    *self = bits
+                }
+                fn read(&self) -> Self {
+                    *self
+                }
+            }
+        )*}
+    }
+
+    register_newtype_impl! {
+        Register8(u8);
+        Register16(u16);
+        Register32(u32);
+    }
+
+    impl Register16 {
+        pub fn high(&self) -> u8 {
+            (self.0 >> 8) as u8
+        }
+        pub fn low(&self) -> u8 {
+            self.0 as u8
+        }
+        pub fn set_high(&mut self, bits: u8) {
+            self.0 = self.0 & 0x00ff | (bits as u16) << 8;
+        }
+        pub fn set_low(&mut self, bits: u8) {
+            self.0 = self.0 & 0xff00 | bits as u16;
+        }
+    }
+
+    impl Register32 {
+        pub fn high(&self) -> u16 {
+            (self.0 >> 16) as u16
+        }
+        pub fn low(&self) -> u16 {
+            self.0 as u16
+        }
+        pub fn set_high(&mut self, bits: u16) {
+            self.0 = self.0 & 0x0000_ffff | (bits as u32) << 16;
+        }
+        pub fn set_low(&mut self, bits: u16) {
+            self.0 = self.0 & 0xffff_0000 | bits as u32;
+        }
+    }
+
+    /// one byte of a 16-bit register.
+    pub struct Register16In8Loader<'a> {
         register: &'a mut u16,
         low: bool,
     }
 
     impl<'a> Register16In8Loader<'a> {
+        #[deprecated(note = "use `high` or `low`")]
         pub fn new(register: &'a mut u16, low: bool) -> Self {
             Self { register, low }
         }
+        pub fn high(register: impl Into<&'a mut u16>) -> Self {
+            let register = register.into();
+            Self {
+                register,
+                low: false,
+            }
+        }
+        pub fn low(register: impl Into<&'a mut u16>) -> Self {
+            let register = register.into();
+            Self {
+                register,
+                low: true,
+            }
+        }
     }
 
     impl<'a> RegisterReader for Register16In8Loader<'a> {
         type Size = u8;
 
         fn read(&self) -> Self::Size {
-            Register16In8Reader::new(self.register, self.low).read()
+            Register16In8Reader {
+                register: self.register,
+                low: self.low,
+            }
+            .read()
         }
     }
 
@@ -118,8 +321,9 @@ pub mod typical {
         }
     }
 
+    /// the whole of a 16-bit register.
     #[derive(Debug)]
-    pub(crate) struct Register16Loader<'a> {
+    pub struct Register16Loader<'a> {
         pub register: &'a mut u16,
     }
 
@@ -143,7 +347,8 @@ pub mod typical {
         }
     }
 
-    pub(crate) struct Register16In8Reader<'a> {
+    /// one byte of a 16-bit register.
+    pub struct Register16In8Reader<'a> {
         register: &'a u16,
         low: bool,
     }
@@ -161,13 +366,29 @@ pub mod typical {
     }
 
     impl<'a> Register16In8Reader<'a> {
+        #[deprecated(note = "use `high` or `low`")]
         pub fn new(register: &'a u16, low: bool) -> Self {
             Self { register, low }
         }
+        pub fn high(register: impl Into<&'a u16>) -> Self {
+            let register = register.into();
+            Self {
+                register,
+                low: false,
+            }
+        }
+        pub fn low(register: impl Into<&'a u16>) -> Self {
+            let register = register.into();
+            Self {
+                register,
+                low: true,
+            }
+        }
     }
 
+    /// the whole of a 16-bit register.
     #[derive(Debug)]
-    pub(crate) struct Register16Reader<'a> {
+    pub struct Register16Reader<'a> {
         register: &'a u16,
     }
 
@@ -254,7 +475,10 @@ mod tests {
                 Register8Code::A => &mut self.af,
                 Register8Code::H | Register8Code::L => &mut self.hl,
             };
-            Register16In8Loader::new(register, code.is_low()).load(bits);
+            match code.is_low() {
+                true => Register16In8Loader::low(register).load(bits),
+                false => Register16In8Loader::high(register).load(bits),
+            }
         }
 
         fn read_of(&self, code: Register8Code) -> Self::Register {
@@ -262,11 +486,15 @@ mod tests {
                 Register8Code::A => &self.af,
                 Register8Code::H | Register8Code::L => &self.hl,
             };
-            Register16In8Reader::new(register, code.is_low()).read()
+            match code.is_low() {
+                true => Register16In8Reader::low(register).read(),
+                false => Register16In8Reader::high(register).read(),
+            }
         }
     }
 
     #[test]
+    #[allow(deprecated)]
     fn register_modifier() {
         let mut reg = 0x1234;
         assert_eq!(reg.read(), 0x1234);
@@ -278,6 +506,34 @@ mod tests {
         assert_eq!(reg.read(), 0x7856);
     }
 
+    #[test]
+    fn newtypes() {
+        let mut reg = Register16::from(0x1234);
+        assert_eq!((reg.high(), reg.low()), (0x12, 0x34));
+        Register16In8Loader::low(&mut reg).load(0x56);
+        assert_eq!(u16::from(reg), 0x1256);
+        Register16In8Loader::high(&mut reg).load(0x78);
+        assert_eq!(Register16In8Reader::high(&reg).read(), 0x78);
+        reg.set_low(0x9a);
+        assert_eq!(reg, Register16(0x789a));
+        assert!(reg.bit(1));
+        assert!(!reg.bit(0));
+        reg.set_bit(0, true);
+        reg.set_bit(15, true);
+        assert_eq!(reg.0, 0xf89b);
+        let mut reg = Register32(0x1234_5678);
+        assert_eq!((reg.high(), reg.low()), (0x1234, 0x5678));
+        reg.set_high(0xabcd);
+        assert_eq!(reg.0, 0xabcd_5678);
+        let mut reg = Register8::default();
+        reg.set_bit(7, true);
+        assert_eq!(u8::from(reg), 0x80);
+        // bit 9 of 8 is bit 1
+        reg.set_bit(9, true);
+        assert!(reg.bit(1) && reg.bit(15) && !reg.bit(u32::MAX - 1));
+        assert_eq!(reg.0, 0x82);
+    }
+
     #[test]
     fn register_set() {
         use self::Register16Code::*;
@@ -299,4 +555,79 @@ mod tests {
         loader.load(0x1234);
         assert_eq!(loader.read(), 0x1030);
     }
+
+    #[test]
+    fn increment_decrement() {
+        let mut reg = 0xfffeu16;
+        assert!(!reg.overflowing_increment());
+        assert_eq!(reg, 0xffff);
+        assert!(reg.overflowing_increment());
+        assert_eq!(reg, 0x0000);
+        assert!(reg.overflowing_decrement());
+        assert_eq!(reg, 0xffff);
+        reg.decrement();
+        assert_eq!(reg, 0xfffe);
+        let mut reg = 0xffu8;
+        reg.increment();
+        assert_eq!(reg, 0);
+    }
+
+    /// Z80 main registers, with AF banked apart from BC, DE and HL.
+    #[derive(Default, Debug)]
+    struct Z80Registers {
+        af: Banked<u16>,
+        bc_de_hl: Banked<[u16; 3]>,
+    }
+
+    #[derive(Copy, Clone)]
+    enum Z80Bank {
+        AF,
+        Main,
+    }
+
+    impl RegisterSet<Register16Code> for Z80Registers {
+        type Register = u16;
+        fn load_of(&mut self, code: Register16Code, bits: u16) {
+            match code {
+                Register16Code::AF => self.af.active = bits,
+                Register16Code::HL => self.bc_de_hl.active[2] = bits,
+            }
+        }
+        fn read_of(&self, code: Register16Code) -> u16 {
+            match code {
+                Register16Code::AF => self.af.active,
+                Register16Code::HL => self.bc_de_hl.active[2],
+            }
+        }
+    }
+
+    impl RegisterBanks<Z80Bank> for Z80Registers {
+        fn exchange_bank(&mut self, bank: Z80Bank) {
+            match bank {
+                Z80Bank::AF => self.af.exchange(),
+                Z80Bank::Main => self.bc_de_hl.exchange(),
+            }
+        }
+    }
+
+    #[test]
+    fn banks() {
+        use crate::instruction::typical::ExchangeBank;
+        use crate::instruction::Instruction;
+        use Register16Code::*;
+        let mut regs = Z80Registers::default();
+        regs.load_of(AF, 0x1234);
+        regs.load_of(HL, 0x5678);
+        // EX AF,AF'
+        ExchangeBank::new(Z80Bank::AF).execute(&mut regs);
+        assert_eq!((regs.read_of(AF), regs.read_of(HL)), (0x0000, 0x5678));
+        regs.load_of(AF, 0x9abc);
+        // EXX
+        ExchangeBank::new(Z80Bank::Main).execute(&mut regs);
+        assert_eq!(regs.read_of(HL), 0x0000);
+        ExchangeBank::new(Z80Bank::AF).execute(&mut regs);
+        ExchangeBank::new(Z80Bank::Main).execute(&mut regs);
+        assert_eq!((regs.read_of(AF), regs.read_of(HL)), (0x1234, 0x5678));
+        assert_eq!(regs.af.alternate, 0x9abc);
+    }
 }
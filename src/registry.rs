@@ -0,0 +1,123 @@
+use crate::bus::Bus;
+use crate::clock::Clock;
+use crate::cpu::CPUStep;
+use crate::machine::Machine;
+use alloc::collections::BTreeMap;
+use alloc::{string::String, vec::Vec};
+
+/// What a machine is built with, as a command line or a test matrix gives it.
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    /// the clock to run at, the machine's own if None.
+    pub clock: Option<Clock>,
+    /// memory images by name, e.g. `"n88basic"` for a ROM or `"program"`.
+    pub images: BTreeMap<String, Vec<u8>>,
+    /// settings by name, e.g. `"dipsw"` for the DIP switches.
+    pub options: BTreeMap<String, String>,
+}
+
+impl Config {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn clock(mut self, clock: Clock) -> Self {
+        self.clock = Some(clock);
+        self
+    }
+
+    pub fn image(mut self, name: &str, bytes: &[u8]) -> Self {
+        self.images.insert(name.into(), bytes.into());
+        self
+    }
+
+    pub fn option(mut self, name: &str, value: &str) -> Self {
+        self.options.insert(name.into(), value.into());
+        self
+    }
+}
+
+/// Builds a machine from a [`Config`].
+pub type Constructor<C, A, D> = fn(&Config) -> Machine<C, A, D>;
+
+/// Machine constructors by name, such as `"i8080-test"` or `"pc8801mk2sr"`, so that
+/// a command line, a test matrix or a script can pick a machine by a string.
+#[derive(Debug)]
+pub struct Registry<C, A, D> {
+    constructors: BTreeMap<String, Constructor<C, A, D>>,
+}
+
+impl<C, A, D> Default for Registry<C, A, D> {
+    fn default() -> Self {
+        Self {
+            constructors: BTreeMap::new(),
+        }
+    }
+}
+
+impl<C, A, D> Registry<C, A, D>
+where
+    C: CPUStep<Bus<A, D>>,
+{
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// registers `constructor` under `name`, which must not be taken.
+    pub fn register(&mut self, name: &str, constructor: Constructor<C, A, D>) -> &mut Self {
+        let previous = self.constructors.insert(name.into(), constructor);
+        assert!(previous.is_none(), "machine {} registered twice", name);
+        self
+    }
+
+    /// the registered names, in order.
+    pub fn names(&self) -> impl Iterator<Item = &str> + '_ {
+        self.constructors.keys().map(String::as_str)
+    }
+
+    /// a machine built by the constructor registered under `name`, if any.
+    pub fn build(&self, name: &str, config: &Config) -> Option<Machine<C, A, D>> {
+        self.constructors
+            .get(name)
+            .map(|constructor| constructor(config))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::CPURunningState;
+    use crate::memory::Memory;
+    use crate::typical::i8080::{self, I8080};
+
+    #[test]
+    fn registry() {
+        let mut registry = Registry::new();
+        registry
+            .register("i8080-test", i8080::test_machine)
+            .register("i8080-fast", |config| {
+                i8080::test_machine(&config.clone().clock(Clock::PC8801_8MHZ))
+            });
+        assert_eq!(
+            registry.names().collect::<Vec<_>>(),
+            ["i8080-fast", "i8080-test"]
+        );
+        // MVI A,2; STA 0100; HLT
+        let config = Config::new().image("program", &[0x3e, 0x02, 0x32, 0x00, 0x01, 0x76]);
+        let mut machine: Machine<I8080, u16, u8> = registry.build("i8080-test", &config).unwrap();
+        assert_eq!(machine.run(1000), CPURunningState::Halted);
+        assert_eq!(machine.bus.read(0x0100), 0x02);
+        assert_eq!(machine.clock(), Clock::PC8801_4MHZ);
+        let machine = registry.build("i8080-fast", &config).unwrap();
+        assert_eq!(machine.clock(), Clock::PC8801_8MHZ);
+        assert!(registry.build("pc8801mk2sr", &config).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "machine i8080-test registered twice")]
+    fn registered_twice() {
+        Registry::new()
+            .register("i8080-test", i8080::test_machine)
+            .register("i8080-test", i8080::test_machine);
+    }
+}
@@ -0,0 +1,140 @@
+use crate::clock::Clock;
+use alloc::collections::VecDeque;
+
+/// A saved state and when it was taken.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Snapshot<S> {
+    /// cycles since power on.
+    pub cycles: u64,
+    pub state: S,
+}
+
+/// Snapshots taken every `interval` cycles, the last `capacity` of them kept,
+/// to step back in a debugger or rewind a game.
+///
+/// The state `S` is whatever the frontend saves and restores, e.g. a
+/// [`MachineState`](crate::machine::MachineState) with the memory beside it, loaded
+/// through the restore function of a [`Runner`](crate::runner::Runner). The run loop calls
+/// [`Rewind::tick`] after running, which takes a snapshot when one is due.
+#[derive(Debug, Clone)]
+pub struct Rewind<S> {
+    history: VecDeque<Snapshot<S>>,
+    capacity: usize,
+    interval: u64,
+    next: u64,
+}
+
+impl<S> Rewind<S> {
+    /// `None` for an `interval` of 0 cycles.
+    pub fn new(interval: u64, capacity: usize) -> Option<Self> {
+        (interval > 0).then(|| Self {
+            history: VecDeque::with_capacity(capacity),
+            capacity,
+            interval,
+            next: 0,
+        })
+    }
+
+    /// a snapshot every `frames` frames of `frames_per_second` on `clock`,
+    /// `None` if that is not a cycle.
+    pub fn every_frames(
+        clock: Clock,
+        frames_per_second: f64,
+        frames: u64,
+        capacity: usize,
+    ) -> Option<Self> {
+        Self::new(clock.cycles_per_frame(frames_per_second) * frames, capacity)
+    }
+
+    /// takes a snapshot with `save` if one is due at `cycles`, returning whether it did.
+    pub fn tick<F: FnOnce() -> S>(&mut self, cycles: u64, save: F) -> bool {
+        if cycles < self.next || self.capacity == 0 {
+            return false;
+        }
+        if self.history.len() == self.capacity {
+            self.history.pop_front();
+        }
+        self.history.push_back(Snapshot {
+            cycles,
+            state: save(),
+        });
+        self.next = cycles + self.interval;
+        true
+    }
+
+    /// snapshots, oldest first.
+    pub fn snapshots(&self) -> impl Iterator<Item = &Snapshot<S>> {
+        self.history.iter()
+    }
+
+    /// goes back to the latest snapshot taken at or before `cycles`, forgetting the later
+    /// ones, and returns it to restore. The next snapshot is due an interval after it.
+    pub fn rewind_to(&mut self, cycles: u64) -> Option<&Snapshot<S>> {
+        let kept = self.history.partition_point(|s| s.cycles <= cycles);
+        if kept == 0 {
+            return None;
+        }
+        self.history.truncate(kept);
+        let latest = self.history.back()?;
+        self.next = latest.cycles + self.interval;
+        Some(latest)
+    }
+
+    /// goes back to the snapshot before the latest, e.g. for each press of a rewind button.
+    pub fn step_back(&mut self) -> Option<&Snapshot<S>> {
+        let previous = self.history.len().checked_sub(2)?;
+        let cycles = self.history[previous].cycles;
+        self.rewind_to(cycles)
+    }
+
+    pub fn clear(&mut self) {
+        self.history.clear();
+        self.next = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::Bus;
+    use crate::cpu::tests::CPU8;
+    use crate::cpu::CPUProgramCounter;
+    use crate::machine::Machine;
+    use crate::memory::typical::Memory8Bit64KB;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn rewind() {
+        let mut bus = Bus::default();
+        bus.map_memory(0x0000..=0xffff, Memory8Bit64KB::default());
+        let mut machine = Machine::new(CPU8::default(), bus, Clock::default());
+        let mut rewind = Rewind::new(100, 3).unwrap();
+        for _ in 0..10 {
+            machine.run_for(50);
+            let cpu = machine.cpu;
+            rewind.tick(machine.cycles(), || cpu);
+        }
+        let taken: Vec<_> = rewind.snapshots().map(|s| s.cycles).collect();
+        // 13 NOPs of 4 cycles run for each 50, a snapshot due every 100
+        assert_eq!(taken, [260, 364, 468]);
+        let snapshot = rewind.rewind_to(450).unwrap();
+        assert_eq!(snapshot.cycles, 364);
+        machine.cpu = snapshot.state;
+        assert_eq!(*machine.cpu.program_counter(), 91);
+        assert_eq!(rewind.step_back().map(|s| s.cycles), Some(260));
+        assert!(rewind.step_back().is_none());
+        assert!(!rewind.tick(300, || machine.cpu));
+        assert!(rewind.tick(360, || machine.cpu));
+        assert!(rewind.rewind_to(259).is_none());
+        assert_eq!(rewind.snapshots().count(), 2);
+    }
+
+    #[test]
+    fn no_interval() {
+        assert!(Rewind::<()>::new(0, 3).is_none());
+        let slow = Clock::new(30).unwrap();
+        assert!(Rewind::<()>::every_frames(slow, 60.0, 1, 3).is_none());
+        let rewind = Rewind::<()>::every_frames(Clock::new(60).unwrap(), 60.0, 2, 3).unwrap();
+        assert_eq!(rewind.interval, 2);
+    }
+}
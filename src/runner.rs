@@ -0,0 +1,198 @@
+use crate::bus::Bus;
+use crate::cpu::{CPUHalt, CPUReset, CPURunningState, CPUStep};
+use crate::machine::Machine;
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread::{self, JoinHandle};
+
+/// What a frontend asks of a [`Runner`].
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Command<S> {
+    Pause,
+    Resume,
+    /// runs `n` instructions and pauses.
+    Step(u64),
+    Reset,
+    /// puts the machine back into a saved state, through the restore function of the runner.
+    LoadSnapshot(S),
+    /// ends the worker thread.
+    Stop,
+}
+
+/// What a [`Runner`] tells its frontend.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Event {
+    /// stopped running, after a command or because the CPU halted for good.
+    Paused {
+        state: CPURunningState,
+        cycles: u64,
+    },
+    Resumed,
+    /// the worker thread has ended.
+    Stopped,
+}
+
+/// Runs a [`Machine`] on a worker thread, driven by [`Command`]s.
+///
+/// Devices need not be `Send`, so the machine is built on the worker by `build`.
+/// It starts [paused](Machine::pause). While running, commands are served between
+/// slices of `slice` cycles.
+#[derive(Debug)]
+pub struct Runner<S> {
+    commands: Sender<Command<S>>,
+    events: Receiver<Event>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl<S: Send + 'static> Runner<S> {
+    pub fn spawn<C, A, D, F, R>(slice: u64, build: F, mut restore: R) -> Self
+    where
+        C: CPUStep<Bus<A, D>> + CPUHalt + CPUReset<Bus<A, D>>,
+        F: FnOnce() -> Machine<C, A, D> + Send + 'static,
+        R: FnMut(&mut Machine<C, A, D>, S) + Send + 'static,
+    {
+        let (commands, command_rx) = mpsc::channel();
+        let (event_tx, events) = mpsc::channel();
+        let worker = thread::spawn(move || {
+            let mut machine = build();
+            let paused = |machine: &Machine<C, A, D>| Event::Paused {
+                state: machine.cpu.running_state(),
+                cycles: machine.cycles(),
+            };
+            machine.pause();
+            loop {
+                let command = match !machine.paused() {
+                    true => match command_rx.try_recv() {
+                        Ok(command) => Some(command),
+                        Err(TryRecvError::Empty) => None,
+                        Err(TryRecvError::Disconnected) => break,
+                    },
+                    false => match command_rx.recv() {
+                        Ok(command) => Some(command),
+                        Err(_) => break,
+                    },
+                };
+                // the frontend may have gone away; the worker still serves the commands queued.
+                let _ = match command {
+                    Some(Command::Pause) => {
+                        machine.pause();
+                        event_tx.send(paused(&machine))
+                    }
+                    Some(Command::Resume) => {
+                        machine.resume();
+                        event_tx.send(Event::Resumed)
+                    }
+                    Some(Command::Step(n)) => {
+                        machine.pause();
+                        for _ in 0..n {
+                            if machine.cpu.halted() && !machine.cpu.interrupt_enabled() {
+                                break;
+                            }
+                            machine.step();
+                        }
+                        event_tx.send(paused(&machine))
+                    }
+                    Some(Command::Reset) => {
+                        machine.reset();
+                        match machine.paused() {
+                            false => Ok(()),
+                            true => event_tx.send(paused(&machine)),
+                        }
+                    }
+                    Some(Command::LoadSnapshot(snapshot)) => {
+                        restore(&mut machine, snapshot);
+                        match machine.paused() {
+                            false => Ok(()),
+                            true => event_tx.send(paused(&machine)),
+                        }
+                    }
+                    Some(Command::Stop) => break,
+                    None => Ok(()),
+                };
+                if !machine.paused() && machine.run(slice) != CPURunningState::Running {
+                    machine.pause();
+                    let _ = event_tx.send(paused(&machine));
+                }
+            }
+            let _ = event_tx.send(Event::Stopped);
+        });
+        Self {
+            commands,
+            events,
+            worker: Some(worker),
+        }
+    }
+
+    /// returns false if the worker has ended.
+    pub fn send(&self, command: Command<S>) -> bool {
+        self.commands.send(command).is_ok()
+    }
+
+    /// the events so far, without waiting.
+    pub fn events(&self) -> impl Iterator<Item = Event> + '_ {
+        self.events.try_iter()
+    }
+
+    /// waits for the next event, or `None` once the worker has ended.
+    pub fn wait_event(&self) -> Option<Event> {
+        self.events.recv().ok()
+    }
+}
+
+impl<S> Runner<S> {
+    /// stops the worker and waits for it to end.
+    pub fn stop(mut self) {
+        self.shutdown();
+    }
+
+    fn shutdown(&mut self) {
+        if let Some(worker) = self.worker.take() {
+            let _ = self.commands.send(Command::Stop);
+            let _ = worker.join();
+        }
+    }
+}
+
+impl<S> Drop for Runner<S> {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::Clock;
+    use crate::cpu::tests::CPU8;
+    use crate::cpu::CPUProgramCounter;
+    use crate::memory::typical::Memory8Bit64KB;
+
+    fn build() -> Machine<CPU8, u16, u8> {
+        let mut bus = Bus::default();
+        bus.map_memory(0x0000..=0xffff, Memory8Bit64KB::new(&[0x00, 0x00, 0x76]));
+        Machine::new(CPU8::default(), bus, Clock::default())
+    }
+
+    #[test]
+    fn commands() {
+        let runner = Runner::spawn(100, build, |machine, pc: u16| {
+            *machine.cpu.program_counter() = pc
+        });
+        let paused = |state, cycles| Some(Event::Paused { state, cycles });
+        runner.send(Command::Step(1));
+        assert_eq!(runner.wait_event(), paused(CPURunningState::Running, 4));
+        runner.send(Command::Resume);
+        assert_eq!(runner.wait_event(), Some(Event::Resumed));
+        // HLT with interrupts disabled ends the run.
+        assert_eq!(runner.wait_event(), paused(CPURunningState::Halted, 12));
+        runner.send(Command::Reset);
+        assert_eq!(runner.wait_event(), paused(CPURunningState::Running, 12));
+        runner.send(Command::LoadSnapshot(0x0002));
+        runner.send(Command::Step(3));
+        assert_eq!(runner.wait_event(), paused(CPURunningState::Running, 12));
+        assert_eq!(runner.wait_event(), paused(CPURunningState::Halted, 16));
+        assert_eq!(runner.events().count(), 0);
+        runner.send(Command::Stop);
+        assert_eq!(runner.wait_event(), Some(Event::Stopped));
+        assert_eq!(runner.wait_event(), None);
+    }
+}
@@ -0,0 +1,244 @@
+use alloc::rc::{Rc, Weak};
+use alloc::{boxed::Box, vec::Vec};
+use core::cell::RefCell;
+use core::fmt;
+
+/// A wire between components, e.g. the interrupt output of a timer into a [`Pic`].
+///
+/// Clones are the same wire. The driving side sets it, the others read it whenever they like
+/// or subscribe to its changes. Subscribers are not called from [`Line::set`], where the driver
+/// may be borrowed, but by [`Line::dispatch`], which a [`Machine`] does between instructions
+/// for the lines in its [`Signals`].
+///
+/// [`Pic`]: crate::typical::pic::Pic
+/// [`Machine`]: crate::machine::Machine
+pub struct Line<T>(Rc<RefCell<LineState<T>>>);
+
+/// A line of an interrupt request, raised while asserted.
+pub type IrqLine = Line<bool>;
+
+type Subscriber<T> = Box<dyn FnMut(&T)>;
+
+struct LineState<T> {
+    value: T,
+    /// the value subscribers last saw.
+    dispatched: T,
+    subscribers: Vec<Subscriber<T>>,
+}
+
+impl<T: Clone> Line<T> {
+    pub fn new(value: T) -> Self {
+        Self(Rc::new(RefCell::new(LineState {
+            value: value.clone(),
+            dispatched: value,
+            subscribers: Vec::new(),
+        })))
+    }
+
+    pub fn set(&self, value: T) {
+        self.0.borrow_mut().value = value;
+    }
+
+    pub fn get(&self) -> T {
+        self.0.borrow().value.clone()
+    }
+
+    /// calls `f` with the new value whenever a dispatch finds the line changed.
+    ///
+    /// The line owns `f`, so a subscriber holding a clone of its own line would keep the
+    /// line alive for good; give it a [`Line::downgrade`] instead.
+    pub fn subscribe<F: FnMut(&T) + 'static>(&self, f: F) {
+        self.0.borrow_mut().subscribers.push(Box::new(f));
+    }
+}
+
+impl<T> Line<T> {
+    /// a handle that does not keep the line alive, e.g. for its own subscribers.
+    pub fn downgrade(&self) -> WeakLine<T> {
+        WeakLine(Rc::downgrade(&self.0))
+    }
+}
+
+/// A [`Line`] not kept alive by this handle, from [`Line::downgrade`].
+pub struct WeakLine<T>(Weak<RefCell<LineState<T>>>);
+
+impl<T> WeakLine<T> {
+    /// the line, unless every [`Line`] handle to it has been dropped.
+    pub fn upgrade(&self) -> Option<Line<T>> {
+        self.0.upgrade().map(Line)
+    }
+}
+
+impl<T> Clone for WeakLine<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T> fmt::Debug for WeakLine<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WeakLine")
+            .field("alive", &(self.0.strong_count() > 0))
+            .finish()
+    }
+}
+
+impl<T: Clone + PartialEq> Line<T> {
+    /// tells the subscribers of a change since the last dispatch, returning whether it changed.
+    /// Subscribers may set lines, this one included, for the next dispatch.
+    pub fn dispatch(&self) -> bool {
+        let (value, mut subscribers) = {
+            let mut state = self.0.borrow_mut();
+            if state.value == state.dispatched {
+                return false;
+            }
+            state.dispatched = state.value.clone();
+            (state.value.clone(), core::mem::take(&mut state.subscribers))
+        };
+        subscribers.iter_mut().for_each(|f| f(&value));
+        let mut state = self.0.borrow_mut();
+        // keeping the ones subscribed meanwhile
+        subscribers.append(&mut state.subscribers);
+        state.subscribers = subscribers;
+        true
+    }
+}
+
+impl Line<bool> {
+    pub fn raise(&self) {
+        self.set(true)
+    }
+
+    pub fn lower(&self) {
+        self.set(false)
+    }
+
+    pub fn is_raised(&self) -> bool {
+        self.get()
+    }
+}
+
+impl<T> Clone for Line<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T: Default + Clone> Default for Line<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Line<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.0.borrow();
+        f.debug_struct("Line")
+            .field("value", &state.value)
+            .field("subscribers", &state.subscribers.len())
+            .finish()
+    }
+}
+
+/// The lines whose subscribers a [`Machine`](crate::machine::Machine) calls between instructions.
+#[derive(Default)]
+pub struct Signals {
+    lines: Vec<Box<dyn Fn() -> bool>>,
+}
+
+impl Signals {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// a new line dispatched with the others.
+    pub fn line<T: Clone + PartialEq + 'static>(&mut self, value: T) -> Line<T> {
+        let line = Line::new(value);
+        self.add(line.clone());
+        line
+    }
+
+    pub fn add<T: Clone + PartialEq + 'static>(&mut self, line: Line<T>) {
+        self.lines.push(Box::new(move || line.dispatch()));
+    }
+
+    /// dispatches every line, returning whether any changed.
+    pub fn dispatch(&self) -> bool {
+        self.lines
+            .iter()
+            .fold(false, |changed, dispatch| dispatch() | changed)
+    }
+
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+}
+
+impl fmt::Debug for Signals {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Signals")
+            .field("lines", &self.lines.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::cell::Cell;
+
+    #[test]
+    fn line() {
+        let mut signals = Signals::new();
+        let irq = signals.line(false);
+        let edges = Rc::new(Cell::new(0));
+        let counter = edges.clone();
+        let ack = irq.downgrade();
+        // a subscriber acknowledging at once, lowering the line it is called for
+        irq.subscribe(move |&raised| {
+            counter.set(counter.get() + 1);
+            if let Some(irq) = ack.upgrade().filter(|_| raised) {
+                irq.lower();
+            }
+        });
+        assert!(!signals.dispatch());
+        irq.raise();
+        assert!(irq.is_raised());
+        assert_eq!(edges.get(), 0);
+        assert!(signals.dispatch());
+        assert!(!irq.is_raised());
+        assert!(signals.dispatch());
+        assert!(!signals.dispatch());
+        assert_eq!(edges.get(), 2);
+        // a pulse between dispatches is missed
+        irq.raise();
+        irq.lower();
+        assert!(!signals.dispatch());
+
+        let data = Line::new(0u8);
+        data.set(0x41);
+        assert_eq!(data.get(), 0x41);
+        assert!(data.dispatch());
+    }
+
+    #[test]
+    fn dropped() {
+        let line = Line::new(0u8);
+        let weak = line.downgrade();
+        let own = line.downgrade();
+        line.subscribe(move |&value| {
+            if let Some(line) = own.upgrade() {
+                line.set(value + 1);
+            }
+        });
+        line.set(1);
+        line.dispatch();
+        assert_eq!(weak.upgrade().map(|line| line.get()), Some(2));
+        drop(line);
+        assert!(weak.upgrade().is_none());
+    }
+}
@@ -0,0 +1,145 @@
+use alloc::collections::BTreeMap;
+use alloc::{string::String, string::ToString};
+use core::fmt;
+
+/// `line` is 1-origin.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum SymbolError {
+    InvalidLine { line: usize, text: String },
+}
+
+impl fmt::Display for SymbolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SymbolError::InvalidLine { line, text } => {
+                write!(
+                    f,
+                    "line {}: expected `address name`, found `{}`",
+                    line, text
+                )
+            }
+        }
+    }
+}
+
+impl core::error::Error for SymbolError {}
+
+/// Labels of addresses, for disassembly and traces to show `CALL print_char`
+/// rather than `CALL 3F2AH`.
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub struct SymbolTable {
+    labels: BTreeMap<u64, String>,
+    addresses: BTreeMap<String, u64>,
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads a listing of one `address name` per line, the address in hex as `3f2a`,
+    /// `0x3f2a` or `3F2AH`. Blank lines and those starting with `;` or `#` are skipped.
+    pub fn parse(text: &str) -> Result<Self, SymbolError> {
+        let mut table = Self::new();
+        for (i, source) in text.lines().enumerate() {
+            let source = source.trim();
+            if source.is_empty() || source.starts_with([';', '#']) {
+                continue;
+            }
+            let invalid = || SymbolError::InvalidLine {
+                line: i + 1,
+                text: source.to_string(),
+            };
+            let mut fields = source.split_whitespace();
+            let (Some(address), Some(name), None) = (fields.next(), fields.next(), fields.next())
+            else {
+                return Err(invalid());
+            };
+            let digits = address
+                .strip_prefix("0x")
+                .or_else(|| address.strip_suffix(['H', 'h']))
+                .unwrap_or(address);
+            let address = u64::from_str_radix(digits, 16).map_err(|_| invalid())?;
+            table.insert(address, name);
+        }
+        Ok(table)
+    }
+
+    /// labels `address` as `name`, replacing the label of either.
+    pub fn insert<A: Into<u64>>(&mut self, address: A, name: &str) {
+        let address = address.into();
+        if let Some(old) = self.labels.insert(address, name.to_string()) {
+            self.addresses.remove(&old);
+        }
+        if let Some(old) = self.addresses.insert(name.to_string(), address) {
+            if old != address {
+                self.labels.remove(&old);
+            }
+        }
+    }
+
+    pub fn label<A: Into<u64>>(&self, address: A) -> Option<&str> {
+        self.labels.get(&address.into()).map(String::as_str)
+    }
+
+    pub fn address(&self, name: &str) -> Option<u64> {
+        self.addresses.get(name).copied()
+    }
+
+    /// the closest label at or before `address` and how far past it `address` is,
+    /// e.g. for `print_char+3` in a trace.
+    pub fn nearest<A: Into<u64>>(&self, address: A) -> Option<(&str, u64)> {
+        let address = address.into();
+        self.labels
+            .range(..=address)
+            .next_back()
+            .map(|(&start, name)| (name.as_str(), address - start))
+    }
+
+    /// labels by address.
+    pub fn iter(&self) -> impl Iterator<Item = (u64, &str)> {
+        self.labels.iter().map(|(&a, name)| (a, name.as_str()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.labels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let table = SymbolTable::parse(
+            "; BIOS entry points\n\
+             0000 boot\n\
+             0x3f2a print_char\n\
+             \n\
+             3F40H print_string\n",
+        )
+        .unwrap();
+        assert_eq!(table.len(), 3);
+        assert_eq!(table.label(0x3f2au16), Some("print_char"));
+        assert_eq!(table.address("print_string"), Some(0x3f40));
+        assert_eq!(table.nearest(0x3f2du16), Some(("print_char", 3)));
+        assert_eq!(
+            SymbolTable::parse("0000 boot\nzzzz oops\n"),
+            Err(SymbolError::InvalidLine {
+                line: 2,
+                text: "zzzz oops".to_string()
+            })
+        );
+        let mut table = table;
+        table.insert(0x3f2au16, "putc");
+        assert_eq!(
+            (table.label(0x3f2au16), table.address("print_char")),
+            (Some("putc"), None)
+        );
+    }
+}
@@ -0,0 +1,37 @@
+use crate::register::{RegisterBanks, RegisterCode, RegisterSet};
+
+/// A CPU together with the memory it is attached to.
+///
+/// CPUs keep their memory external; instructions and addressing modes that touch memory
+/// work on a `System` instead of the bare CPU. Registers are forwarded to the CPU.
+#[derive(Debug, Default, Clone)]
+pub struct System<C, M> {
+    pub cpu: C,
+    pub memory: M,
+}
+
+impl<C, M> System<C, M> {
+    pub fn new(cpu: C, memory: M) -> Self {
+        Self { cpu, memory }
+    }
+}
+
+impl<C, M, R, B> RegisterSet<R> for System<C, M>
+where
+    C: RegisterSet<R, Register = B>,
+    R: RegisterCode<Register = B>,
+{
+    type Register = B;
+    fn load_of(&mut self, code: R, bits: B) {
+        self.cpu.load_of(code, bits)
+    }
+    fn read_of(&self, code: R) -> B {
+        self.cpu.read_of(code)
+    }
+}
+
+impl<C: RegisterBanks<K>, M, K> RegisterBanks<K> for System<C, M> {
+    fn exchange_bank(&mut self, bank: K) {
+        self.cpu.exchange_bank(bank)
+    }
+}
@@ -0,0 +1,694 @@
+use crate::bus::{Device, RegionKind, StateError};
+use crate::cpu::CPU;
+use crate::memory::{Memory, MemoryError};
+use crate::observer::ExecutionObserver;
+#[cfg(feature = "std")]
+use crate::symbols::SymbolTable;
+use alloc::collections::VecDeque;
+use alloc::rc::Rc;
+use alloc::{string::String, vec::Vec};
+use core::cell::RefCell;
+use core::fmt;
+#[cfg(feature = "std")]
+use std::io::{self, Write};
+
+/// The kind of a bus cycle, as told by the control signals.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum BusCycleKind {
+    /// an instruction fetch, with M1 asserted.
+    Fetch,
+    Read,
+    Write,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct BusCycle<A, D> {
+    pub time: u64,
+    pub address: A,
+    pub data: D,
+    pub kind: BusCycleKind,
+}
+
+/// Records the signals of every bus cycle, for comparison with logic analyzer captures.
+///
+/// Each cycle is stamped with the current time, which advances by one per cycle
+/// unless the run loop drives it with [`SignalTrace::advance`].
+#[derive(Debug, Clone)]
+pub struct SignalTrace<A, D> {
+    cycles: Vec<BusCycle<A, D>>,
+    time: u64,
+}
+
+impl<A, D> Default for SignalTrace<A, D> {
+    fn default() -> Self {
+        Self {
+            cycles: Vec::new(),
+            time: 0,
+        }
+    }
+}
+
+impl<A: Copy, D: Copy> SignalTrace<A, D> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cycles(&self) -> &[BusCycle<A, D>] {
+        &self.cycles
+    }
+
+    /// advances the time by `cycles` clock cycles.
+    pub fn advance(&mut self, cycles: u64) {
+        self.time += cycles;
+    }
+
+    fn record(&mut self, address: A, data: D, kind: BusCycleKind) {
+        self.cycles.push(BusCycle {
+            time: self.time,
+            address,
+            data,
+            kind,
+        });
+        self.time += 1;
+    }
+
+    /// Writes the trace as a VCD file, with `timescale` such as `"250 ns"`.
+    #[cfg(feature = "std")]
+    pub fn write_vcd<W: Write>(&self, out: &mut W, timescale: &str) -> io::Result<()>
+    where
+        A: Into<u64>,
+        D: Into<u64>,
+    {
+        let address_width = 8 * core::mem::size_of::<A>();
+        let data_width = 8 * core::mem::size_of::<D>();
+        writeln!(out, "$timescale {} $end", timescale)?;
+        writeln!(out, "$scope module bus $end")?;
+        writeln!(out, "$var wire {} a address $end", address_width)?;
+        writeln!(out, "$var wire {} d data $end", data_width)?;
+        writeln!(out, "$var wire 1 m m1 $end")?;
+        writeln!(out, "$var wire 1 r rd $end")?;
+        writeln!(out, "$var wire 1 w wr $end")?;
+        writeln!(out, "$upscope $end")?;
+        writeln!(out, "$enddefinitions $end")?;
+        for cycle in &self.cycles {
+            let (m1, rd, wr) = match cycle.kind {
+                BusCycleKind::Fetch => (1, 1, 0),
+                BusCycleKind::Read => (0, 1, 0),
+                BusCycleKind::Write => (0, 0, 1),
+            };
+            let address: u64 = cycle.address.into();
+            let data: u64 = cycle.data.into();
+            writeln!(out, "#{}", cycle.time)?;
+            writeln!(out, "b{:0w$b} a", address, w = address_width)?;
+            writeln!(out, "b{:0w$b} d", data, w = data_width)?;
+            writeln!(out, "{}m\n{}r\n{}w", m1, rd, wr)?;
+        }
+        Ok(())
+    }
+}
+
+impl<C: CPU> ExecutionObserver<C> for SignalTrace<C::Address, C::Data> {
+    fn on_fetch(&mut self, address: C::Address, data: C::Data) {
+        self.record(address, data, BusCycleKind::Fetch)
+    }
+    fn on_memory_read(&mut self, address: C::Address, data: C::Data) {
+        self.record(address, data, BusCycleKind::Read)
+    }
+    fn on_memory_write(&mut self, address: C::Address, data: C::Data) {
+        self.record(address, data, BusCycleKind::Write)
+    }
+}
+
+/// The component driving a bus transaction.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum BusMaster {
+    CPU,
+    DMA,
+    /// the disk sub-system CPU, or another CPU sharing the bus.
+    SubCPU,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum AddressSpace {
+    Memory,
+    IO,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Transaction<A, D> {
+    pub time: u64,
+    pub master: BusMaster,
+    pub space: AddressSpace,
+    pub kind: BusCycleKind,
+    pub address: A,
+    pub data: D,
+}
+
+impl<A: fmt::LowerHex, D: fmt::LowerHex> fmt::Display for Transaction<A, D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:>10} {:?} {:?} {:?} {:04x} {:02x}",
+            self.time, self.master, self.space, self.kind, self.address, self.data
+        )
+    }
+}
+
+/// The last `capacity` bus transactions of every master, for post-mortem analysis
+/// of bugs between CPUs and DMA that a per-CPU trace cannot show.
+///
+/// Transactions are stamped with the current time, which the run loop drives with
+/// [`TransactionLog::advance`].
+#[derive(Debug, Clone)]
+pub struct TransactionLog<A, D> {
+    transactions: VecDeque<Transaction<A, D>>,
+    capacity: usize,
+    time: u64,
+}
+
+impl<A, D> TransactionLog<A, D> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            transactions: VecDeque::with_capacity(capacity),
+            capacity,
+            time: 0,
+        }
+    }
+
+    /// a log shared by the [`Logged`] wrappers of several components.
+    pub fn shared(capacity: usize) -> Rc<RefCell<Self>> {
+        Rc::new(RefCell::new(Self::new(capacity)))
+    }
+
+    pub fn advance(&mut self, cycles: u64) {
+        self.time += cycles;
+    }
+
+    pub fn record(
+        &mut self,
+        master: BusMaster,
+        space: AddressSpace,
+        kind: BusCycleKind,
+        address: A,
+        data: D,
+    ) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.transactions.len() == self.capacity {
+            self.transactions.pop_front();
+        }
+        self.transactions.push_back(Transaction {
+            time: self.time,
+            master,
+            space,
+            kind,
+            address,
+            data,
+        });
+    }
+
+    /// transactions, oldest first.
+    pub fn transactions(&self) -> impl Iterator<Item = &Transaction<A, D>> {
+        self.transactions.iter()
+    }
+
+    pub fn clear(&mut self) {
+        self.transactions.clear();
+    }
+
+    /// writes the transactions one per line, oldest first.
+    #[cfg(feature = "std")]
+    pub fn dump<W: Write>(&self, out: &mut W) -> io::Result<()>
+    where
+        A: fmt::LowerHex,
+        D: fmt::LowerHex,
+    {
+        for transaction in &self.transactions {
+            writeln!(out, "{}", transaction)?;
+        }
+        Ok(())
+    }
+}
+
+/// The CPU's own accesses, as memory transactions.
+impl<C: CPU> ExecutionObserver<C> for TransactionLog<C::Address, C::Data> {
+    fn on_fetch(&mut self, address: C::Address, data: C::Data) {
+        self.record(
+            BusMaster::CPU,
+            AddressSpace::Memory,
+            BusCycleKind::Fetch,
+            address,
+            data,
+        )
+    }
+    fn on_memory_read(&mut self, address: C::Address, data: C::Data) {
+        self.record(
+            BusMaster::CPU,
+            AddressSpace::Memory,
+            BusCycleKind::Read,
+            address,
+            data,
+        )
+    }
+    fn on_memory_write(&mut self, address: C::Address, data: C::Data) {
+        self.record(
+            BusMaster::CPU,
+            AddressSpace::Memory,
+            BusCycleKind::Write,
+            address,
+            data,
+        )
+    }
+}
+
+/// An executed instruction as a [`TraceBuffer`] keeps it.
+#[derive(Debug, Clone)]
+pub struct TracedInstruction<C: CPU> {
+    pub pc: C::Address,
+    /// the words fetched for it, opcode first.
+    pub words: Vec<C::Data>,
+    /// the CPU before executing it.
+    pub cpu: C,
+}
+
+/// The last `depth` instructions executed, to see how a crashed program got where it did.
+///
+/// Run the machine with it, by [`Machine::run_observed`] or [`Machine::step_observed`],
+/// and [`TraceBuffer::dump`] it on an error or breakpoint.
+///
+/// [`Machine::run_observed`]: crate::machine::Machine::run_observed
+/// [`Machine::step_observed`]: crate::machine::Machine::step_observed
+#[derive(Debug, Clone)]
+pub struct TraceBuffer<C: CPU> {
+    instructions: VecDeque<TracedInstruction<C>>,
+    depth: usize,
+    /// where the instruction being fetched started, and its words so far.
+    fetching: Option<(C::Address, Vec<C::Data>)>,
+}
+
+impl<C: CPU> TraceBuffer<C> {
+    pub fn new(depth: usize) -> Self {
+        Self {
+            instructions: VecDeque::with_capacity(depth),
+            depth,
+            fetching: None,
+        }
+    }
+
+    /// instructions, oldest first.
+    pub fn instructions(&self) -> impl Iterator<Item = &TracedInstruction<C>> {
+        self.instructions.iter()
+    }
+
+    pub fn clear(&mut self) {
+        self.instructions.clear();
+        self.fetching = None;
+    }
+
+    /// writes the instructions one per line, oldest first: the address, the words and the CPU.
+    #[cfg(feature = "std")]
+    pub fn dump<W: Write>(&self, out: &mut W) -> io::Result<()>
+    where
+        C: fmt::Debug,
+        C::Address: fmt::LowerHex + Into<u64>,
+        C::Data: fmt::LowerHex,
+    {
+        self.dump_with(out, &SymbolTable::new())
+    }
+
+    /// like [`TraceBuffer::dump`], adding the label each address is at or follows.
+    #[cfg(feature = "std")]
+    pub fn dump_with<W: Write>(&self, out: &mut W, symbols: &SymbolTable) -> io::Result<()>
+    where
+        C: fmt::Debug,
+        C::Address: fmt::LowerHex + Into<u64>,
+        C::Data: fmt::LowerHex,
+    {
+        for traced in &self.instructions {
+            write!(out, "{:04x}", traced.pc)?;
+            match symbols.nearest(traced.pc) {
+                Some((label, 0)) => write!(out, " <{}>:", label)?,
+                Some((label, offset)) => write!(out, " <{}+{}>:", label, offset)?,
+                None => write!(out, ":")?,
+            }
+            for word in &traced.words {
+                write!(out, " {:02x}", word)?;
+            }
+            writeln!(out, "  {:?}", traced.cpu)?;
+        }
+        Ok(())
+    }
+}
+
+impl<C: CPU + Clone> ExecutionObserver<C> for TraceBuffer<C> {
+    fn on_fetch(&mut self, address: C::Address, data: C::Data) {
+        self.fetching
+            .get_or_insert_with(|| (address, Vec::new()))
+            .1
+            .push(data);
+    }
+    fn on_execute(&mut self, cpu: &C) {
+        let Some((pc, words)) = self.fetching.take() else {
+            return;
+        };
+        if self.depth == 0 {
+            return;
+        }
+        if self.instructions.len() == self.depth {
+            self.instructions.pop_front();
+        }
+        self.instructions.push_back(TracedInstruction {
+            pc,
+            words,
+            cpu: cpu.clone(),
+        });
+    }
+}
+
+/// A memory or device as seen by one master, recording every access into a shared log.
+#[derive(Debug)]
+pub struct Logged<T, A, D> {
+    inner: T,
+    log: Rc<RefCell<TransactionLog<A, D>>>,
+    master: BusMaster,
+    space: AddressSpace,
+}
+
+impl<T, A, D> Logged<T, A, D> {
+    pub fn new(
+        inner: T,
+        log: Rc<RefCell<TransactionLog<A, D>>>,
+        master: BusMaster,
+        space: AddressSpace,
+    ) -> Self {
+        Self {
+            inner,
+            log,
+            master,
+            space,
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T, A: Copy, D: Copy> Logged<T, A, D> {
+    fn record(&self, kind: BusCycleKind, address: A, data: D) {
+        self.log
+            .borrow_mut()
+            .record(self.master, self.space, kind, address, data)
+    }
+}
+
+impl<M: Memory> Memory for Logged<M, M::Address, M::Data>
+where
+    M::Address: Copy,
+    M::Data: Copy,
+{
+    type Address = M::Address;
+    type Data = M::Data;
+    fn read(&self, address: M::Address) -> M::Data {
+        let data = self.inner.read(address);
+        self.record(BusCycleKind::Read, address, data);
+        data
+    }
+    fn store(&mut self, address: M::Address, data: M::Data) {
+        self.record(BusCycleKind::Write, address, data);
+        self.inner.store(address, data)
+    }
+    fn try_read(&self, address: M::Address) -> Result<M::Data, MemoryError<M::Address>> {
+        let data = self.inner.try_read(address)?;
+        self.record(BusCycleKind::Read, address, data);
+        Ok(data)
+    }
+    fn try_store(
+        &mut self,
+        address: M::Address,
+        data: M::Data,
+    ) -> Result<(), MemoryError<M::Address>> {
+        self.inner.try_store(address, data)?;
+        self.record(BusCycleKind::Write, address, data);
+        Ok(())
+    }
+}
+
+impl<T: Device> Device for Logged<T, T::Address, T::Data>
+where
+    T::Address: Copy,
+    T::Data: Copy,
+{
+    type Address = T::Address;
+    type Data = T::Data;
+    fn read(&mut self, offset: T::Address) -> T::Data {
+        let data = self.inner.read(offset);
+        self.record(BusCycleKind::Read, offset, data);
+        data
+    }
+    fn write(&mut self, offset: T::Address, data: T::Data) {
+        self.record(BusCycleKind::Write, offset, data);
+        self.inner.write(offset, data)
+    }
+    fn tick(&mut self, cycles: u64) {
+        self.inner.tick(cycles)
+    }
+    fn interrupt(&self) -> bool {
+        self.inner.interrupt()
+    }
+    fn try_write(
+        &mut self,
+        offset: T::Address,
+        data: T::Data,
+    ) -> Result<(), MemoryError<T::Address>> {
+        self.inner.try_write(offset, data)?;
+        self.record(BusCycleKind::Write, offset, data);
+        Ok(())
+    }
+    fn reset(&mut self) {
+        self.inner.reset()
+    }
+    fn acknowledge(&mut self) -> Option<T::Data> {
+        self.inner.acknowledge()
+    }
+    fn name(&self) -> String {
+        self.inner.name()
+    }
+    fn kind(&self) -> RegionKind {
+        self.inner.kind()
+    }
+    fn bank(&self) -> Option<usize> {
+        self.inner.bank()
+    }
+    fn writable(&self) -> bool {
+        self.inner.writable()
+    }
+    fn write_latency(&self, offset: T::Address) -> u64 {
+        self.inner.write_latency(offset)
+    }
+    fn state_version(&self) -> u16 {
+        self.inner.state_version()
+    }
+    fn save_state(&self, state: &mut Vec<u8>) {
+        self.inner.save_state(state)
+    }
+    fn load_state(&mut self, version: u16, state: &[u8]) -> Result<(), StateError> {
+        self.inner.load_state(version, state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::tests::CPU8;
+    use crate::cpu::{CPUMemory, CPUProgramCounter};
+    use crate::memory::typical::Memory8Bit64KB;
+    use alloc::vec;
+
+    #[test]
+    fn signals() {
+        let mut memory = Memory8Bit64KB::new(&[0x3a, 0x34, 0x12]);
+        let mut trace = SignalTrace::new();
+        let cpu = CPU8::default().program_fetch_observed(&memory, &mut trace);
+        trace.advance(3);
+        let cpu = cpu
+            .load_address(0x1234)
+            .fetch_memory_observed(&memory, &mut trace)
+            .load_address(0x8000)
+            .load_data(0xff)
+            .store_memory_observed(&mut memory, &mut trace);
+        assert_eq!(cpu.address(), 0x8000);
+        let kinds: Vec<_> = trace.cycles().iter().map(|c| (c.time, c.kind)).collect();
+        use BusCycleKind::*;
+        assert_eq!(kinds, [(0, Fetch), (4, Read), (5, Write)]);
+        assert_eq!(trace.cycles()[0].data, 0x3a);
+
+        #[cfg(feature = "std")]
+        {
+            let mut vcd = Vec::new();
+            trace.write_vcd(&mut vcd, "250 ns").unwrap();
+            let vcd = String::from_utf8(vcd).unwrap();
+            assert!(vcd.starts_with("$timescale 250 ns $end\n"));
+            assert!(vcd.contains("$var wire 16 a address $end"));
+            assert!(vcd.contains("#5\nb1000000000000000 a\nb11111111 d\n0m\n0r\n1w\n"));
+        }
+    }
+
+    #[test]
+    fn transaction_log() {
+        use crate::dma::Transfer;
+        use crate::typical::timer::Timer;
+
+        let log = TransactionLog::shared(3);
+        let mut memory = Memory8Bit64KB::new(&[0x3e, 0x12]);
+        let cpu = CPU8::default().program_fetch_observed(&memory, &mut *log.borrow_mut());
+        assert_eq!(cpu.data(), 0x3e);
+        log.borrow_mut().advance(4);
+        let src = Logged::new(
+            Memory8Bit64KB::new(&[1, 2]),
+            log.clone(),
+            BusMaster::DMA,
+            AddressSpace::Memory,
+        );
+        Transfer::new(0x0000, 0x8000, 1).run(&src, &mut memory, 100);
+        let mut timer = Logged::new(Timer::new(), log.clone(), BusMaster::CPU, AddressSpace::IO);
+        timer.write(0, 0x10);
+        timer.read(0);
+        let kinds: Vec<_> = log
+            .borrow()
+            .transactions()
+            .map(|t| (t.time, t.master, t.space, t.kind))
+            .collect();
+        use AddressSpace::*;
+        use BusCycleKind::*;
+        use BusMaster::*;
+        assert_eq!(
+            kinds,
+            [
+                (4, DMA, Memory, Read),
+                (4, CPU, IO, Write),
+                (4, CPU, IO, Read)
+            ]
+        );
+        #[cfg(feature = "std")]
+        {
+            let mut dump = Vec::new();
+            log.borrow().dump(&mut dump).unwrap();
+            let dump = String::from_utf8(dump).unwrap();
+            assert_eq!(
+                dump.lines().next(),
+                Some("         4 DMA Memory Read 0000 01")
+            );
+        }
+    }
+
+    #[test]
+    fn logged_fallible() {
+        use crate::bus::typical::Rom;
+        use crate::memory::typical::MemoryView;
+
+        let log = TransactionLog::shared(4);
+        let mut bytes = [0x12, 0x34];
+        let mut view = Logged::new(
+            MemoryView::<u16>::new(&mut bytes),
+            log.clone(),
+            BusMaster::CPU,
+            AddressSpace::Memory,
+        );
+        assert_eq!(view.try_read(1), Ok(0x34));
+        assert_eq!(view.try_read(2), Err(MemoryError::Unmapped(2)));
+        assert_eq!(view.try_store(2, 0x56), Err(MemoryError::Unmapped(2)));
+        let mut rom = Logged::new(
+            Rom::new(Memory8Bit64KB::new(&[0x78])),
+            log.clone(),
+            BusMaster::CPU,
+            AddressSpace::Memory,
+        );
+        assert_eq!(rom.try_write(0, 0x9a), Err(MemoryError::ReadOnly(0)));
+        // only the access that went through is on the bus
+        let kinds: Vec<_> = log
+            .borrow()
+            .transactions()
+            .map(|t| (t.kind, t.address, t.data))
+            .collect();
+        assert_eq!(kinds, [(BusCycleKind::Read, 1, 0x34)]);
+    }
+
+    #[test]
+    fn logged_map() {
+        use crate::bus::typical::Rom;
+        use crate::bus::{Bus, MapEntry};
+
+        let log = TransactionLog::shared(1);
+        let mut bus = Bus::new(0xff);
+        bus.map(
+            0x0000..=0xffff,
+            Logged::new(
+                Rom::new(Memory8Bit64KB::default()),
+                log,
+                BusMaster::CPU,
+                AddressSpace::Memory,
+            ),
+        );
+        assert_eq!(
+            bus.memory_map(),
+            [MapEntry {
+                start: 0x0000,
+                end: 0xffff,
+                device: "ROM".into(),
+                kind: RegionKind::Rom,
+                bank: None,
+                writable: false,
+            }]
+        );
+    }
+
+    #[test]
+    fn trace_buffer() {
+        use crate::bus::Bus;
+        use crate::clock::Clock;
+        use crate::cpu::CPURunningState;
+        use crate::machine::Machine;
+        use crate::typical::i8080::I8080;
+
+        // MVI A,1; MOV B,A; MVI C,2; HLT
+        let memory = Memory8Bit64KB::new(&[0x3e, 0x01, 0x47, 0x0e, 0x02, 0x76]);
+        let mut bus = Bus::default();
+        bus.map_memory(0x0000..=0xffff, memory);
+        let mut machine = Machine::new(I8080::default(), bus, Clock::PC8801_4MHZ);
+        let mut trace = TraceBuffer::new(2);
+        assert_eq!(
+            machine.run_observed(1000, &mut trace),
+            CPURunningState::Halted
+        );
+        let traced: Vec<_> = trace
+            .instructions()
+            .map(|t| (t.pc, t.words.clone(), t.cpu.address()))
+            .collect();
+        assert_eq!(
+            traced,
+            [
+                (0x0003, vec![0x0e, 0x02], 0x0004),
+                (0x0005, vec![0x76], 0x0005)
+            ]
+        );
+        #[cfg(feature = "std")]
+        {
+            let mut dump = Vec::new();
+            trace.dump(&mut dump).unwrap();
+            let dump = String::from_utf8(dump).unwrap();
+            assert!(dump.starts_with("0003: 0e 02  I8080 {"));
+            assert_eq!(dump.lines().count(), 2);
+            let symbols = SymbolTable::parse("0003 load\n").unwrap();
+            let mut dump = Vec::new();
+            trace.dump_with(&mut dump, &symbols).unwrap();
+            let dump = String::from_utf8(dump).unwrap();
+            assert!(dump
+                .lines()
+                .nth(1)
+                .unwrap()
+                .starts_with("0005 <load+2>: 76  I8080 {"));
+        }
+    }
+}
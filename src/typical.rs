@@ -1 +1,9 @@
-// pub mod i8080;
+pub mod cpm;
+pub mod i8080;
+pub mod keyboard;
+pub mod mos6502;
+pub mod pic;
+pub mod speed_switch;
+pub mod timer;
+pub mod uart;
+pub mod vram;
@@ -0,0 +1,228 @@
+use crate::cpu::{CPUProgramCounter, CPUStackPointer};
+use crate::instruction::typical::Return;
+use crate::instruction::Instruction;
+use crate::machine::Machine;
+use crate::memory::{Endian, Memory, MemoryWord};
+use crate::register::RegisterSet;
+use crate::system::System;
+use crate::typical::i8080::I8080RegisterCode16Bit::DE;
+use crate::typical::i8080::I8080RegisterCode8Bit::{C, E};
+use crate::typical::i8080::{I8080System, I8080};
+use alloc::rc::Rc;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+
+/// The console calls of the CP/M 2.2 BDOS, enough to run test programs such as
+/// TST8080, 8080PRE and CPUTEST.
+///
+/// [`Bdos::attach`] has a [`Machine`] call [`Bdos::trap`] before each instruction; it serves
+/// calls to [`Bdos::ENTRY`] itself and notices the warm boot jump to 0 that ends a program.
+#[derive(Debug, Default, Clone)]
+pub struct Bdos {
+    output: Vec<u8>,
+    finished: bool,
+}
+
+impl Bdos {
+    pub const ENTRY: u16 = 0x0005;
+    /// where COM files are loaded and started.
+    pub const TPA: u16 = 0x0100;
+    /// the top of the program area, as read by programs from 0x0006.
+    pub const TOP: u16 = 0xfe00;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// sets up the zero page and the registers to start the program at [`Bdos::TPA`].
+    pub fn install<M: Memory<Address = u16, Data = u8>>(&self, system: &mut I8080System<M>) {
+        // HLT at the warm boot entry, RET at the BDOS entry
+        system.memory.store(0x0000, 0x76);
+        system.memory.store(Self::ENTRY, 0xc9);
+        system
+            .memory
+            .store_word(Self::ENTRY + 1, Self::TOP, Endian::Little);
+        *system.cpu.program_counter() = Self::TPA;
+        *system.cpu.stack_pointer() = Self::TOP;
+    }
+
+    /// installs itself in `machine` and serves its calls from the machine's trap,
+    /// returning a handle to read the output by.
+    pub fn attach(self, machine: &mut Machine<I8080, u16, u8>) -> Rc<RefCell<Self>> {
+        let mut system = System::new(machine.cpu, &mut machine.bus);
+        self.install(&mut system);
+        machine.cpu = system.cpu;
+        let bdos = Rc::new(RefCell::new(self));
+        let trapped = bdos.clone();
+        machine.set_trap(move |cpu, bus| {
+            let mut system = System::new(*cpu, bus);
+            trapped.borrow_mut().trap(&mut system);
+            *cpu = system.cpu;
+        });
+        bdos
+    }
+
+    /// serves a BDOS call or the end of the program at the current PC, returning
+    /// whether it did.
+    pub fn trap<M: Memory<Address = u16, Data = u8>>(
+        &mut self,
+        system: &mut I8080System<M>,
+    ) -> bool {
+        match *system.cpu.program_counter() {
+            0x0000 => {
+                self.finished = true;
+                true
+            }
+            Self::ENTRY => {
+                match system.read_of(C) {
+                    0 => self.finished = true,
+                    2 => self.output.push(system.read_of(E)),
+                    // up to the `$`, or once around memory if there is none
+                    9 => {
+                        let start = system.read_of(DE);
+                        for i in 0..=u16::MAX {
+                            match system.memory.read(start.wrapping_add(i)) {
+                                b'$' => break,
+                                c => self.output.push(c),
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+                Return::new(Endian::Little).execute(system);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// console output so far.
+    pub fn output(&self) -> &[u8] {
+        &self.output
+    }
+
+    /// whether the program has returned to CP/M.
+    pub fn finished(&self) -> bool {
+        self.finished
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instruction::typical::Call;
+    use crate::loader::load_bin;
+    use crate::memory::typical::Memory8Bit64KB;
+
+    #[test]
+    fn console() {
+        let mut system = I8080System::new(I8080::default(), Memory8Bit64KB::default());
+        let mut bdos = Bdos::new();
+        bdos.install(&mut system);
+        assert_eq!(system.memory.read_word(0x0006, Endian::Little), Bdos::TOP);
+        assert!(!bdos.trap(&mut system));
+        load_bin(&mut system.memory, 0x0200, b"CPU IS OPERATIONAL$").unwrap();
+        // the program at 0x0100 calls 5 with C = 9
+        *system.cpu.program_counter() = 0x0103;
+        system.load_of(C, 9);
+        system.load_of(DE, 0x0200);
+        Call::new(Bdos::ENTRY, Endian::Little).execute(&mut system);
+        assert!(bdos.trap(&mut system));
+        assert_eq!(*system.cpu.program_counter(), 0x0103);
+        assert_eq!(*system.cpu.stack_pointer(), Bdos::TOP);
+        system.load_of(C, 2);
+        system.load_of(E, b'!');
+        Call::new(Bdos::ENTRY, Endian::Little).execute(&mut system);
+        assert!(bdos.trap(&mut system));
+        assert_eq!(bdos.output(), b"CPU IS OPERATIONAL!");
+        assert!(!bdos.finished());
+        *system.cpu.program_counter() = 0x0000;
+        assert!(bdos.trap(&mut system));
+        assert!(bdos.finished());
+    }
+
+    #[test]
+    fn unterminated() {
+        let mut system = I8080System::new(I8080::default(), Memory8Bit64KB::default());
+        let mut bdos = Bdos::new();
+        *system.cpu.stack_pointer() = 0x8000;
+        system.load_of(C, 9);
+        Call::new(Bdos::ENTRY, Endian::Little).execute(&mut system);
+        assert!(bdos.trap(&mut system));
+        assert_eq!(bdos.output().len(), 0x10000);
+    }
+
+    #[test]
+    fn machine() {
+        use crate::bus::Bus;
+        use crate::clock::Clock;
+        use crate::cpu::CPURunningState;
+
+        let program = [
+            0x0e, 0x09, // MVI C,9
+            0x11, 0x0b, 0x01, // LXI D,010B
+            0xcd, 0x05, 0x00, // CALL 0005
+            0xc3, 0x00, 0x00, // JMP 0000
+            b'H', b'I', b'$',
+        ];
+        let mut bus = Bus::default();
+        bus.map_memory(0x0000..=0xffff, Memory8Bit64KB::default());
+        let mut machine = Machine::new(I8080::default(), bus, Clock::PC8801_4MHZ);
+        load_bin(&mut machine.bus, Bdos::TPA, &program).unwrap();
+        let bdos = Bdos::new().attach(&mut machine);
+        assert_eq!(machine.run(1000), CPURunningState::Halted);
+        assert_eq!(bdos.borrow().output(), b"HI");
+        assert!(bdos.borrow().finished());
+    }
+
+    /// runs the COM files listed in `N88_EXERCISER`, as `PATH` lists them, to their end,
+    /// checking that they report success.
+    #[cfg(feature = "exerciser")]
+    #[test]
+    fn exerciser() {
+        use crate::bus::Bus;
+        use crate::clock::Clock;
+        use crate::cpu::CPURunningState;
+        use alloc::string::String;
+
+        // TST8080, 8080PRE, CPUTEST and 8080EXM
+        const PASSED: [&str; 4] = [
+            "CPU IS OPERATIONAL",
+            "Preliminary tests complete",
+            "CPU TESTS OK",
+            "Tests complete",
+        ];
+        let Some(paths) = std::env::var_os("N88_EXERCISER") else {
+            std::eprintln!("N88_EXERCISER is not set, no exerciser run");
+            return;
+        };
+        for path in std::env::split_paths(&paths) {
+            let image = std::fs::read(&path)
+                .unwrap_or_else(|error| panic!("{}: {}", path.display(), error));
+            let mut bus = Bus::default();
+            bus.map_memory(0x0000..=0xffff, Memory8Bit64KB::default());
+            let mut machine = Machine::new(I8080::default(), bus, Clock::PC8801_4MHZ);
+            load_bin(&mut machine.bus, Bdos::TPA, &image).unwrap();
+            let bdos = Bdos::new().attach(&mut machine);
+            let state = loop {
+                match machine.run(1 << 24) {
+                    CPURunningState::Running => continue,
+                    state => break state,
+                }
+            };
+            let output = String::from_utf8_lossy(bdos.borrow().output()).into_owned();
+            let passed = PASSED.iter().any(|message| output.contains(message));
+            assert!(
+                state == CPURunningState::Halted
+                    && bdos.borrow().finished()
+                    && passed
+                    && !output.contains("ERROR"),
+                "{}: {:?} after {} cycles, output:\n{}",
+                path.display(),
+                state,
+                machine.cycles(),
+                output
+            );
+        }
+    }
+}
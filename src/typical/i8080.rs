@@ -1,10 +1,29 @@
-use crate::addressing::Addressing;
-use crate::alu::typical::*;
+use crate::addressing::{Addressing, AddressingMut};
+use crate::alu::helpers::half_carry;
+use crate::alu::typical::FlagSetBits;
+use crate::alu::{ALUBits, ALUCarry, ALUCounter, FlagRegister, FlagSet, ALU};
+use crate::bus::Bus;
+use crate::clock::Clock;
 use crate::cpu::*;
+use crate::instruction::typical::{
+    Arithmetic, ArithmeticWithCarry, Call, Compare, DecoderBuilder, Decrement, Exchange,
+    ExchangeStack, Increment, Jump, Load, PopPair, PushPair, Restart, Return, TableDecoder,
+};
+use crate::instruction::{self, IllegalOpcode, Instruction, Operand};
+use crate::machine::Machine;
 use crate::memory::typical::*;
-use crate::memory::Memory;
+use crate::memory::{Endian, Memory, MemoryError};
+use crate::observer::ExecutionObserver;
 use crate::register::typical::*;
-use crate::register::{RegisterLoader, RegisterReader, RegisterSet};
+use crate::register::{
+    RegisterCode, RegisterEnumerable, RegisterLoader, RegisterReader, RegisterSet,
+};
+use crate::registry::Config;
+use crate::system::System;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::cell::{Cell, RefCell};
+use core::fmt;
 
 #[derive(Debug, Default, Copy, Clone)]
 pub struct I8080 {
@@ -16,6 +35,32 @@ pub struct I8080 {
     h: u16,
     sp: u16,
     pc: u16,
+    halted: bool,
+    interrupt_enabled: bool,
+    fault: Option<I8080Fault>,
+}
+
+/// Why a [`Machine`](crate::machine::Machine) stopped the CPU, halted with interrupts
+/// disabled, as [`CPURunningState::Error`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum I8080Fault {
+    /// an opcode [`decode`] does not know, at `pc`.
+    IllegalOpcode { pc: u16, opcode: u8 },
+    /// the first access the bus failed, by the instruction at `pc` or while fetching it.
+    Memory { pc: u16, error: MemoryError<u16> },
+}
+
+impl fmt::Display for I8080Fault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            I8080Fault::IllegalOpcode { pc, opcode } => {
+                write!(f, "illegal opcode {:02X} at {:04X}", opcode, pc)
+            }
+            I8080Fault::Memory { pc, error } => {
+                write!(f, "{}, by the instruction at {:04X}", error, pc)
+            }
+        }
+    }
 }
 
 impl CPU for I8080 {
@@ -40,16 +85,526 @@ impl CPU for I8080 {
         self
     }
 
+    /// instructions execute whole on a [`System`], leaving no state between their cycles.
     fn cycle(self) -> Self {
-        todo!()
+        self
     }
 
+    /// stops once HLT has halted the CPU.
     fn run(self) -> Option<Self> {
-        todo!()
+        match self.halted {
+            true => None,
+            false => Some(self),
+        }
+    }
+}
+
+impl<M: Memory<Address = u16, Data = u8>> CPUMemory<M> for I8080 {}
+
+impl CPUProgramCounter for I8080 {
+    fn program_counter(&mut self) -> &mut Self::Address {
+        &mut self.pc
+    }
+}
+
+impl CPUStackPointer for I8080 {
+    fn stack_pointer(&mut self) -> &mut Self::Address {
+        &mut self.sp
+    }
+}
+
+impl CPUJump for I8080 {}
+
+/// clears the program counter, the interrupt enable flip-flop and any fault.
+impl<M> CPUReset<M> for I8080 {
+    fn reset(&mut self, _memory: &mut M) {
+        self.pc = 0;
+        self.halted = false;
+        self.interrupt_enabled = false;
+        self.fault = None;
+    }
+}
+
+impl CPUHalt for I8080 {
+    fn halted(&self) -> bool {
+        self.halted
+    }
+    fn set_halted(&mut self, halted: bool) {
+        self.halted = halted
+    }
+    fn interrupt_enabled(&self) -> bool {
+        self.interrupt_enabled
+    }
+    fn set_interrupt_enabled(&mut self, enabled: bool) {
+        self.interrupt_enabled = enabled
+    }
+    fn running_state(&self) -> CPURunningState {
+        match (self.fault, self.halted) {
+            (Some(fault), _) => CPURunningState::Error(fault.to_string()),
+            (None, true) => CPURunningState::Halted,
+            (None, false) => CPURunningState::Running,
+        }
+    }
+}
+
+/// Fetches the instruction at the program counter with its operand, decodes it with
+/// [`decode`] and executes it, taking the cycles of [`I8080::CYCLES`].
+///
+/// An interrupt is accepted while enabled, disabling further ones and leaving a halt:
+/// the instruction the acknowledging device puts on the data bus is executed without
+/// advancing the program counter, so that an RST or a CALL returns to the interrupted one.
+/// Operand bytes are acknowledged in turn. A bus nothing drives reads 0xFF, RST 7.
+///
+/// An access the bus fails, such as a fetch from an unmapped address or a store into ROM,
+/// stops the CPU with an [`I8080Fault::Memory`]; the instruction still completes.
+impl CPUStep<Bus<u16, u8>> for I8080 {
+    fn step_observed<O>(&mut self, bus: &mut Bus<u16, u8>, observer: &mut O) -> u64
+    where
+        O: ExecutionObserver<Self>,
+    {
+        // a halted 8080 idles, as NOPs would
+        if self.halted {
+            return 4;
+        }
+        let pc = self.pc;
+        match self.fetch_instruction(bus, observer) {
+            Ok((opcode, operand)) => self.execute_on(bus, pc, opcode, operand, observer),
+            Err(error) => {
+                self.stop(I8080Fault::Memory { pc, error });
+                4
+            }
+        }
+    }
+
+    fn interrupt_observed<O>(&mut self, bus: &mut Bus<u16, u8>, observer: &mut O) -> u64
+    where
+        O: ExecutionObserver<Self>,
+    {
+        if !self.interrupt_enabled {
+            return 0;
+        }
+        let mut data_bus = || bus.acknowledge().unwrap_or(0xff);
+        let opcode = data_bus();
+        let operand =
+            (0..operand(opcode).bytes()).fold(0, |value, i| value | (data_bus() as u16) << (8 * i));
+        self.interrupt_enabled = false;
+        self.halted = false;
+        self.execute_on(bus, self.pc, opcode, operand, observer)
+    }
+}
+
+/// Runs an instruction from [`decoder`] the run loop fetched, reading its opcode again
+/// for the cycles of [`I8080::CYCLES`].
+impl CPUExecute<Bus<u16, u8>> for I8080 {
+    type Instruction = I8080Instruction;
+    fn execute<O>(
+        &mut self,
+        bus: &mut Bus<u16, u8>,
+        instruction: &I8080Instruction,
+        len: u64,
+        observer: &mut O,
+    ) -> u64
+    where
+        O: ExecutionObserver<Self>,
+    {
+        let pc = self.pc;
+        let opcode = bus.read(pc);
+        self.pc = pc.wrapping_add(len as u16);
+        if let Err(error) = self.execute_observed(bus, instruction, observer) {
+            self.stop(I8080Fault::Memory { pc, error });
+        }
+        Self::CYCLES[opcode as usize] as u64
+    }
+}
+
+/// the bus as an instruction sees it, its accesses reported to an observer and the first
+/// one failed kept. A failed read gives the open bus value.
+struct Observed<'a, O> {
+    bus: &'a mut Bus<u16, u8>,
+    observer: RefCell<&'a mut O>,
+    error: Cell<Option<MemoryError<u16>>>,
+}
+
+impl<O> Observed<'_, O> {
+    fn fail(&self, error: MemoryError<u16>) {
+        self.error.set(self.error.get().or(Some(error)));
+    }
+}
+
+impl<O: ExecutionObserver<I8080>> Memory for Observed<'_, O> {
+    type Address = u16;
+    type Data = u8;
+    fn read(&self, address: u16) -> u8 {
+        let data = self.bus.try_read(address).unwrap_or_else(|error| {
+            self.fail(error);
+            self.bus.read(address)
+        });
+        self.observer.borrow_mut().on_memory_read(address, data);
+        data
+    }
+    fn store(&mut self, address: u16, data: u8) {
+        if let Err(error) = self.bus.try_store(address, data) {
+            self.fail(error);
+        }
+        self.observer.get_mut().on_memory_write(address, data);
+    }
+}
+
+impl CPUAlu for I8080 {
+    type ALU = I8080ALU;
+    fn alu(&self) -> &I8080ALU {
+        &I8080ALU
+    }
+}
+
+impl CPUAccumulator for I8080 {
+    fn acc(&self) -> u8 {
+        self.read_of(I8080RegisterCode8Bit::A)
+    }
+    fn acc_write(&mut self, data: u8) {
+        self.load_of(I8080RegisterCode8Bit::A, data)
+    }
+}
+
+impl I8080 {
+    /// flag bit 1, always read as 1.
+    pub const FLAGS_FIXED_ONE: u8 = <I8080ALU as FlagRegister>::FIXED_ONE;
+    /// flag bits 3 and 5, always read as 0.
+    pub const FLAGS_FIXED_ZERO: u8 = <I8080ALU as FlagRegister>::FIXED_ZERO;
+
+    /// the clock cycles of each opcode, those of conditional instructions not taken.
+    #[rustfmt::skip]
+    pub const CYCLES: [u8; 256] = [
+        4, 10, 7, 5, 5, 5, 7, 4, 4, 10, 7, 5, 5, 5, 7, 4,
+        4, 10, 7, 5, 5, 5, 7, 4, 4, 10, 7, 5, 5, 5, 7, 4,
+        4, 10, 16, 5, 5, 5, 7, 4, 4, 10, 16, 5, 5, 5, 7, 4,
+        4, 10, 13, 5, 10, 10, 10, 4, 4, 10, 13, 5, 5, 5, 7, 4,
+        5, 5, 5, 5, 5, 5, 7, 5, 5, 5, 5, 5, 5, 5, 7, 5,
+        5, 5, 5, 5, 5, 5, 7, 5, 5, 5, 5, 5, 5, 5, 7, 5,
+        5, 5, 5, 5, 5, 5, 7, 5, 5, 5, 5, 5, 5, 5, 7, 5,
+        7, 7, 7, 7, 7, 7, 7, 7, 5, 5, 5, 5, 5, 5, 7, 5,
+        4, 4, 4, 4, 4, 4, 7, 4, 4, 4, 4, 4, 4, 4, 7, 4,
+        4, 4, 4, 4, 4, 4, 7, 4, 4, 4, 4, 4, 4, 4, 7, 4,
+        4, 4, 4, 4, 4, 4, 7, 4, 4, 4, 4, 4, 4, 4, 7, 4,
+        4, 4, 4, 4, 4, 4, 7, 4, 4, 4, 4, 4, 4, 4, 7, 4,
+        5, 10, 10, 10, 11, 11, 7, 11, 5, 10, 10, 10, 11, 17, 7, 11,
+        5, 10, 10, 10, 11, 11, 7, 11, 5, 10, 10, 10, 11, 17, 7, 11,
+        5, 10, 10, 18, 11, 11, 7, 11, 5, 5, 10, 4, 11, 17, 7, 11,
+        5, 10, 10, 4, 11, 11, 7, 11, 5, 5, 10, 4, 11, 17, 7, 11,
+    ];
+
+    fn fixed_flags(flags: u8) -> u8 {
+        I8080ALU::fixed(flags)
+    }
+
+    /// what stopped the CPU, if anything did; cleared by a reset.
+    pub fn fault(&self) -> Option<I8080Fault> {
+        self.fault
+    }
+
+    /// executes the instruction `opcode` at `pc`, or stops if it does not decode.
+    fn execute_on<O>(
+        &mut self,
+        bus: &mut Bus<u16, u8>,
+        pc: u16,
+        opcode: u8,
+        operand: u16,
+        observer: &mut O,
+    ) -> u64
+    where
+        O: ExecutionObserver<Self>,
+    {
+        let Some(instruction) = decode(opcode, operand) else {
+            self.stop(I8080Fault::IllegalOpcode { pc, opcode });
+            return 4;
+        };
+        if let Err(error) = self.execute_observed(bus, &instruction, observer) {
+            self.stop(I8080Fault::Memory { pc, error });
+        }
+        Self::CYCLES[opcode as usize] as u64
+    }
+
+    /// fetches the opcode at the program counter and its operand, zero-extended,
+    /// reporting each byte to `observer`.
+    fn fetch_instruction<O>(
+        &mut self,
+        bus: &mut Bus<u16, u8>,
+        observer: &mut O,
+    ) -> Result<(u8, u16), MemoryError<u16>>
+    where
+        O: ExecutionObserver<Self>,
+    {
+        let mut fetch = |cpu: &mut Self| {
+            let data = bus.try_read(cpu.pc)?;
+            *cpu = cpu.load_address(cpu.pc).load_data(data);
+            cpu.pc = cpu.pc.wrapping_add(1);
+            observer.on_fetch(cpu.address, data);
+            Ok(data)
+        };
+        let opcode = fetch(self)?;
+        let mut value = 0;
+        for i in 0..operand(opcode).bytes() {
+            value |= (fetch(self)? as u16) << (8 * i);
+        }
+        Ok((opcode, value))
+    }
+
+    /// executes `instruction` on `bus`, its accesses reported to `observer`, failing with
+    /// the first the bus failed.
+    fn execute_observed<O>(
+        &mut self,
+        bus: &mut Bus<u16, u8>,
+        instruction: &I8080Instruction,
+        observer: &mut O,
+    ) -> Result<(), MemoryError<u16>>
+    where
+        O: ExecutionObserver<Self>,
+    {
+        observer.on_execute(self);
+        let memory = Observed {
+            bus,
+            observer: RefCell::new(observer),
+            error: Cell::new(None),
+        };
+        let mut system = System::new(*self, memory);
+        instruction.execute(&mut system);
+        *self = system.cpu;
+        match system.memory.error.get() {
+            Some(error) => Err(error),
+            None => Ok(()),
+        }
+    }
+
+    /// stops the CPU for good: halted, as nothing can wake it with interrupts disabled.
+    fn stop(&mut self, fault: I8080Fault) {
+        self.fault = Some(fault);
+        self.halted = true;
+        self.interrupt_enabled = false;
     }
 }
 
-impl<M> CPUMemory<M> for I8080 {}
+/// the conventional trace line, e.g.
+/// `A=12 BC=3456 DE=0000 HL=8000 SP=F000 PC=0100 FLAGS=SZ-P-`, flags ordered S, Z, A, P, C.
+impl fmt::Display for I8080 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use I8080ALUFlag::*;
+        let flags = self.flag_read();
+        let flags: String = [
+            (Sign, 'S'),
+            (Zero, 'Z'),
+            (AuxiliaryCarry, 'A'),
+            (Parity, 'P'),
+            (Carry, 'C'),
+        ]
+        .iter()
+        .map(|&(flag, c)| match flags & u8::from(flag) {
+            0 => '-',
+            _ => c,
+        })
+        .collect();
+        write!(
+            f,
+            "A={:02X} BC={:04X} DE={:04X} HL={:04X} SP={:04X} PC={:04X} FLAGS={}",
+            self.psw >> 8,
+            self.b,
+            self.d,
+            self.h,
+            self.sp,
+            self.pc,
+            flags
+        )
+    }
+}
+
+/// the flags are the low byte of PSW.
+impl CPUFlagRegister for I8080 {
+    type FlagRegisterSize = u8;
+
+    fn flag_load(&mut self, bits: u8) {
+        Register16In8Loader::low(&mut self.psw).load(bits)
+    }
+
+    fn flag_read(&self) -> u8 {
+        Self::fixed_flags(Register16In8Reader::low(&self.psw).read())
+    }
+}
+
+/// The documented opcode that an undocumented alias behaves as: the alternate NOPs,
+/// JMP at 0xcb, RET at 0xd9 and CALL at 0xdd, 0xed and 0xfd.
+pub const fn canonical_opcode(opcode: u8) -> u8 {
+    match opcode {
+        0x08 | 0x10 | 0x18 | 0x20 | 0x28 | 0x30 | 0x38 => 0x00,
+        0xcb => 0xc3,
+        0xd9 => 0xc9,
+        0xdd | 0xed | 0xfd => 0xcd,
+        opcode => opcode,
+    }
+}
+
+/// I8080 owning its memory.
+pub type I8080System<M = Memory8Bit64KB> = System<I8080, M>;
+
+/// flags committed by an ALU instruction.
+pub type I8080Flags = &'static [I8080ALUFlag];
+
+/// The instructions of I8080 built from the typical ones, dispatched by `match`,
+/// so that a decoder producing them allocates nothing per instruction.
+#[derive(Clone)]
+pub enum I8080Instruction {
+    Nop,
+    Halt,
+    InterruptEnable(bool),
+    Load(Load<I8080Addressing8Bit, I8080Addressing8Bit>),
+    LoadPair(Load<I8080Addressing16Bit, I8080Addressing16Bit>),
+    Arithmetic(Arithmetic<I8080ALUControl, I8080Flags, I8080Addressing8Bit, I8080Addressing8Bit>),
+    ArithmeticWithCarry(
+        ArithmeticWithCarry<I8080ALUControl, I8080Flags, I8080Addressing8Bit, I8080Addressing8Bit>,
+    ),
+    Compare(Compare<I8080ALUControl, I8080Flags, I8080Addressing8Bit, I8080Addressing8Bit>),
+    Increment(Increment<I8080Flags, I8080Addressing8Bit>),
+    Decrement(Decrement<I8080Flags, I8080Addressing8Bit>),
+    Jump(Jump<u16>),
+    Call(Call),
+    Return(Return),
+    Restart(Restart),
+    Push(PushPair<I8080RegisterCode16Bit>),
+    Pop(PopPair<I8080RegisterCode16Bit>),
+    Exchange(Exchange<I8080RegisterCode16Bit, I8080RegisterCode16Bit>),
+    ExchangeStack(ExchangeStack<I8080RegisterCode16Bit>),
+}
+
+impl<M: Memory<Address = u16, Data = u8>> Instruction<I8080System<M>> for I8080Instruction {
+    fn execute(&self, system: &mut I8080System<M>) {
+        use I8080Instruction::*;
+        match self {
+            Nop => {}
+            Halt => instruction::typical::Halt.execute(system),
+            InterruptEnable(enable) => {
+                instruction::typical::InterruptEnable(*enable).execute(system)
+            }
+            Load(i) => i.execute(system),
+            LoadPair(i) => i.execute(system),
+            Arithmetic(i) => i.execute(system),
+            ArithmeticWithCarry(i) => i.execute(system),
+            Compare(i) => i.execute(system),
+            Increment(i) => i.execute(system),
+            Decrement(i) => i.execute(system),
+            Jump(i) => i.execute(&mut system.cpu),
+            Call(i) => i.execute(system),
+            Return(i) => i.execute(system),
+            Restart(i) => i.execute(system),
+            Push(i) => i.execute(system),
+            Pop(i) => i.execute(system),
+            Exchange(i) => i.execute(system),
+            ExchangeStack(i) => i.execute(system),
+        }
+    }
+}
+
+/// The instruction of `opcode` with its `operand`, zero-extended, for the instructions
+/// [`I8080Instruction`] can express; None for those it cannot yet, such as conditional
+/// jumps, DAD and DAA. Undocumented aliases decode as their [`canonical_opcode`].
+pub fn decode(opcode: u8, operand: u16) -> Option<I8080Instruction> {
+    use I8080ALUControl::*;
+    use I8080ALUFlag::*;
+    use I8080Addressing8Bit::{DirectRegister, DirectValue, ImmediateValue};
+    use I8080Instruction as I;
+    use I8080RegisterCode16Bit::{BC, DE, HL, PSW};
+
+    const ALL: I8080Flags = &[Sign, Zero, AuxiliaryCarry, Parity, Carry];
+    const INR: I8080Flags = &[Sign, Zero, AuxiliaryCarry, Parity];
+    let field = I8080Addressing8Bit::from_register_field;
+    let a = I8080Addressing8Bit::ImmediateRegister(I8080RegisterCode8Bit::A);
+    // bits 4 and 5, PSW where SP would be for LXI
+    let pair = [BC, DE, HL, PSW][(opcode >> 4 & 3) as usize];
+    // ADD, ADC, SUB, SBB, ANA, XRA, ORA and CMP by bits 3 to 5
+    let accumulator = |rhs| {
+        let control = [
+            Add, Add, Subtract, Subtract, BitAnd, BitXor, BitOr, Subtract,
+        ];
+        let control = control[(opcode >> 3 & 7) as usize];
+        match opcode >> 3 & 7 {
+            1 | 3 => I::ArithmeticWithCarry(ArithmeticWithCarry::new(control, ALL, a, rhs)),
+            7 => I::Compare(Compare::new(control, ALL, a, rhs)),
+            _ => I::Arithmetic(Arithmetic::new(control, ALL, a, rhs)),
+        }
+    };
+    let instruction = match canonical_opcode(opcode) {
+        0x00 => I::Nop,
+        0x01 | 0x11 | 0x21 => I::LoadPair(Load::new(
+            I8080Addressing16Bit::ImmediateRegister(pair),
+            I8080Addressing16Bit::ImmediateValue(operand),
+        )),
+        0x02 | 0x12 => I::Load(Load::new(DirectRegister(pair), a)),
+        0x0a | 0x1a => I::Load(Load::new(a, DirectRegister(pair))),
+        0x32 => I::Load(Load::new(DirectValue(operand), a)),
+        0x3a => I::Load(Load::new(a, DirectValue(operand))),
+        op if op & 0xc7 == 0x04 => I::Increment(Increment::new(INR, field(op >> 3))),
+        op if op & 0xc7 == 0x05 => I::Decrement(Decrement::new(INR, field(op >> 3))),
+        op if op & 0xc7 == 0x06 => {
+            I::Load(Load::new(field(op >> 3), ImmediateValue(operand as u8)))
+        }
+        0x0f => I::Arithmetic(Arithmetic::new(Right, &[Carry], a, ImmediateValue(0))),
+        0x76 => I::Halt,
+        op @ 0x40..=0x7f => I::Load(Load::new(field(op >> 3), field(op))),
+        op @ 0x80..=0xbf => accumulator(field(op)),
+        op if op & 0xc7 == 0xc6 => accumulator(ImmediateValue(operand as u8)),
+        0xc1 | 0xd1 | 0xe1 | 0xf1 => I::Pop(PopPair::new(pair)),
+        0xc5 | 0xd5 | 0xe5 | 0xf5 => I::Push(PushPair::new(pair)),
+        0xc3 => I::Jump(Jump::new(operand)),
+        0xc9 => I::Return(Return::new(Endian::Little)),
+        0xcd => I::Call(Call::new(operand, Endian::Little)),
+        op if op & 0xc7 == 0xc7 => I::Restart(Restart::rst(op >> 3)),
+        0xe3 => I::ExchangeStack(ExchangeStack::new(HL, Endian::Little)),
+        0xeb => I::Exchange(Exchange::new(DE, HL)),
+        0xf3 => I::InterruptEnable(false),
+        0xfb => I::InterruptEnable(true),
+        _ => return None,
+    };
+    Some(instruction)
+}
+
+/// The operand following `opcode`, for every opcode of the 8080.
+pub const fn operand(opcode: u8) -> Operand {
+    match canonical_opcode(opcode) {
+        0x01 | 0x11 | 0x21 | 0x31 | 0x22 | 0x2a | 0x32 | 0x3a | 0xc3 | 0xcd => Operand::Imm16,
+        // conditional jumps and calls
+        op if op & 0xc7 == 0xc2 || op & 0xc7 == 0xc4 => Operand::Imm16,
+        // MVI, the immediate accumulator operations, OUT and IN
+        op if op & 0xc7 == 0x06 || op & 0xc7 == 0xc6 || op == 0xd3 || op == 0xdb => Operand::Imm8,
+        _ => Operand::None,
+    }
+}
+
+/// An I8080 with 64KB of RAM, the `"program"` image of `config` loaded at 0 where it
+/// starts, clocked as `config` says or at [`Clock::PC8801_4MHZ`]. For a
+/// [`Registry`](crate::registry::Registry), e.g. as `"i8080-test"`.
+pub fn test_machine(config: &Config) -> Machine<I8080, u16, u8> {
+    let program = config.images.get("program").map_or(&[][..], Vec::as_slice);
+    let mut bus = Bus::default();
+    bus.map_memory(0x0000..=0xffff, Memory8Bit64KB::new(program));
+    let clock = config.clock.unwrap_or(Clock::PC8801_4MHZ);
+    Machine::new(I8080::default(), bus, clock)
+}
+
+/// A decoder of the opcodes [`decode`] knows, handing the others to `illegal`.
+pub fn decoder(illegal: IllegalOpcode<I8080Instruction, u8>) -> TableDecoder<I8080Instruction> {
+    (0..=0xff)
+        .filter(|&opcode| decode(opcode, 0).is_some())
+        .fold(DecoderBuilder::new(), |builder, opcode| {
+            builder.map(opcode, operand(opcode), |opcode, value| {
+                decode(opcode, value).expect("mapped opcodes decode")
+            })
+        })
+        .illegal(illegal)
+        .build()
+}
+
+impl From<instruction::typical::Nop> for I8080Instruction {
+    fn from(_: instruction::typical::Nop) -> Self {
+        I8080Instruction::Nop
+    }
+}
 
 impl RegisterSet<I8080RegisterCode8Bit> for I8080 {
     type Register = u8;
@@ -64,7 +619,10 @@ impl RegisterSet<I8080RegisterCode8Bit> for I8080 {
             I8080RegisterCode8Bit::H => &mut self.h,
             I8080RegisterCode8Bit::L => &mut self.h,
         };
-        Register16In8Loader::new(register, low).load(bits)
+        match low {
+            true => Register16In8Loader::low(register).load(bits),
+            false => Register16In8Loader::high(register).load(bits),
+        }
     }
 
     fn read_of(&self, code: I8080RegisterCode8Bit) -> Self::Register {
@@ -78,7 +636,10 @@ impl RegisterSet<I8080RegisterCode8Bit> for I8080 {
             I8080RegisterCode8Bit::H => &self.h,
             I8080RegisterCode8Bit::L => &self.h,
         };
-        Register16In8Reader::new(register, low).read()
+        match low {
+            true => Register16In8Reader::low(register).read(),
+            false => Register16In8Reader::high(register).read(),
+        }
     }
 }
 
@@ -96,49 +657,92 @@ impl RegisterSet<I8080RegisterCode16Bit> for I8080 {
     }
 
     fn read_of(&self, code: I8080RegisterCode16Bit) -> Self::Register {
-        Register16Reader::new(match code {
-            I8080RegisterCode16Bit::PSW => &self.psw,
-            I8080RegisterCode16Bit::BC => &self.b,
-            I8080RegisterCode16Bit::DE => &self.d,
-            I8080RegisterCode16Bit::HL => &self.h,
-        })
-        .read()
+        match code {
+            // the flag bits with a fixed value read as such
+            I8080RegisterCode16Bit::PSW => {
+                (self.psw & 0xff00) | Self::fixed_flags(self.psw as u8) as u16
+            }
+            I8080RegisterCode16Bit::BC => Register16Reader::new(&self.b).read(),
+            I8080RegisterCode16Bit::DE => Register16Reader::new(&self.d).read(),
+            I8080RegisterCode16Bit::HL => Register16Reader::new(&self.h).read(),
+        }
     }
 }
 
 #[derive(Debug, Copy, Clone)]
-enum I8080Addressing8Bit {
+pub enum I8080Addressing8Bit {
     ImmediateValue(u8),
     ImmediateRegister(I8080RegisterCode8Bit),
     DirectValue(u16),
     DirectRegister(I8080RegisterCode16Bit),
 }
 
-impl Addressing<I8080> for I8080Addressing8Bit {
+impl<M: Memory<Address = u16, Data = u8>> Addressing<I8080System<M>> for I8080Addressing8Bit {
     type Size = u8;
 
-    fn value(&self, cpu: &I8080) -> Self::Size {
+    fn value(&self, system: &I8080System<M>) -> Self::Size {
         match *self {
             I8080Addressing8Bit::ImmediateValue(v) => v,
-            I8080Addressing8Bit::ImmediateRegister(reg) => cpu.read_of(reg),
-            I8080Addressing8Bit::DirectValue(addr) => cpu.fetch_memory(addr),
+            I8080Addressing8Bit::ImmediateRegister(reg) => system.read_of(reg),
+            I8080Addressing8Bit::DirectValue(addr) => system.memory.read(addr),
             I8080Addressing8Bit::DirectRegister(reg) => {
-                I8080Addressing8Bit::DirectValue(cpu.read_of(reg)).value(cpu)
+                I8080Addressing8Bit::DirectValue(system.read_of(reg)).value(system)
+            }
+        }
+    }
+}
+
+impl I8080Addressing8Bit {
+    /// the pseudo-register M, the memory addressed by HL.
+    pub const M: Self = I8080Addressing8Bit::DirectRegister(I8080RegisterCode16Bit::HL);
+
+    /// operand of the 3-bit register field of opcodes such as MOV or ADD,
+    /// ordered B, C, D, E, H, L, M, A. Only the low 3 bits of `bits` are used.
+    pub fn from_register_field(bits: u8) -> Self {
+        use I8080RegisterCode8Bit::*;
+        let reg = match bits & 7 {
+            0 => B,
+            1 => C,
+            2 => D,
+            3 => E,
+            4 => H,
+            5 => L,
+            6 => return Self::M,
+            _ => A,
+        };
+        I8080Addressing8Bit::ImmediateRegister(reg)
+    }
+}
+
+/// An immediate value is a source only; the decoder never makes it a destination,
+/// so writing one panics rather than hiding the bug.
+impl<M: Memory<Address = u16, Data = u8>> AddressingMut<I8080System<M>> for I8080Addressing8Bit {
+    fn write(&self, system: &mut I8080System<M>, value: u8) {
+        match *self {
+            I8080Addressing8Bit::ImmediateValue(_) => unreachable!("write to an immediate operand"),
+            I8080Addressing8Bit::ImmediateRegister(reg) => system.load_of(reg, value),
+            I8080Addressing8Bit::DirectValue(addr) => system.memory.store(addr, value),
+            I8080Addressing8Bit::DirectRegister(reg) => {
+                let addr = system.read_of(reg);
+                system.memory.store(addr, value)
             }
         }
     }
 }
 
 #[derive(Debug, Copy, Clone)]
-enum I8080Addressing16Bit {
+pub enum I8080Addressing16Bit {
     ImmediateValue(u16),
     ImmediateRegister(I8080RegisterCode16Bit),
 }
 
-impl Addressing<I8080> for I8080Addressing16Bit {
+impl<C> Addressing<C> for I8080Addressing16Bit
+where
+    C: RegisterSet<I8080RegisterCode16Bit, Register = u16>,
+{
     type Size = u16;
 
-    fn value(&self, cpu: &I8080) -> Self::Size {
+    fn value(&self, cpu: &C) -> Self::Size {
         match *self {
             I8080Addressing16Bit::ImmediateValue(v) => v,
             I8080Addressing16Bit::ImmediateRegister(reg) => cpu.read_of(reg),
@@ -146,8 +750,42 @@ impl Addressing<I8080> for I8080Addressing16Bit {
     }
 }
 
+/// Panics on an immediate value, as [`I8080Addressing8Bit`] does.
+impl<C> AddressingMut<C> for I8080Addressing16Bit
+where
+    C: RegisterSet<I8080RegisterCode16Bit, Register = u16>,
+{
+    fn write(&self, cpu: &mut C, value: u16) {
+        match *self {
+            I8080Addressing16Bit::ImmediateValue(_) => {
+                unreachable!("write to an immediate operand")
+            }
+            I8080Addressing16Bit::ImmediateRegister(reg) => cpu.load_of(reg, value),
+        }
+    }
+}
+
+/// register codes address the register itself.
+macro_rules! register_code_addressing_impl {
+    ($($t:ty => $b:ty),*) => {$(
+        impl<C: RegisterSet<$t, Register = $b>> Addressing<C> for $t {
+            type Size = $b;
+            fn value(&self, cpu: &C) -> $b {
+                cpu.read_of(*self)
+            }
+        }
+        impl<C: RegisterSet<$t, Register = $b>> AddressingMut<C> for $t {
+            fn write(&self, cpu: &mut C, value: $b) {
+                cpu.load_of(*self, value)
+            }
+        }
+    )*}
+}
+
+register_code_addressing_impl!(I8080RegisterCode8Bit => u8, I8080RegisterCode16Bit => u16);
+
 #[derive(Debug, Copy, Clone)]
-enum I8080RegisterCode8Bit {
+pub enum I8080RegisterCode8Bit {
     A,
     B,
     C,
@@ -171,29 +809,190 @@ impl I8080RegisterCode8Bit {
     }
 }
 
+impl RegisterCode for I8080RegisterCode8Bit {
+    type Register = u8;
+}
+
+impl RegisterEnumerable for I8080RegisterCode8Bit {
+    const ALL: &'static [Self] = {
+        use I8080RegisterCode8Bit::*;
+        &[A, B, C, D, E, H, L]
+    };
+    fn name(self) -> &'static str {
+        match self {
+            I8080RegisterCode8Bit::A => "A",
+            I8080RegisterCode8Bit::B => "B",
+            I8080RegisterCode8Bit::C => "C",
+            I8080RegisterCode8Bit::D => "D",
+            I8080RegisterCode8Bit::E => "E",
+            I8080RegisterCode8Bit::H => "H",
+            I8080RegisterCode8Bit::L => "L",
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone)]
-enum I8080RegisterCode16Bit {
+pub enum I8080RegisterCode16Bit {
     PSW,
     BC,
     DE,
     HL,
 }
 
-#[derive(Debug)]
-pub struct I8080ALU {
-    stats: FlagSetBits<u8>,
+impl RegisterCode for I8080RegisterCode16Bit {
+    type Register = u16;
+}
+
+impl RegisterEnumerable for I8080RegisterCode16Bit {
+    const ALL: &'static [Self] = {
+        use I8080RegisterCode16Bit::*;
+        &[PSW, BC, DE, HL]
+    };
+    fn name(self) -> &'static str {
+        match self {
+            I8080RegisterCode16Bit::PSW => "PSW",
+            I8080RegisterCode16Bit::BC => "BC",
+            I8080RegisterCode16Bit::DE => "DE",
+            I8080RegisterCode16Bit::HL => "HL",
+        }
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
+pub struct I8080ALU;
+
+impl ALU for I8080ALU {
+    type Data = u8;
+    type Control = I8080ALUControl;
+    type Flag = I8080ALUFlag;
+    type FlagSet = FlagSetBits<u8>;
+
+    /// `Increase`, `Decrease` and `Right` ignore `b`.
+    fn op(&self, code: Self::Control, a: u8, b: u8) -> (u8, Self::FlagSet) {
+        self.op_carry(code, a, b, false)
+    }
+}
+
+/// `Add` adds the carry in, as ADC, and `Subtract` takes it as a borrow, as SBB.
+/// The others ignore it.
+impl ALUCarry for I8080ALU {
+    const CARRY: I8080ALUFlag = I8080ALUFlag::Carry;
+
+    fn op_carry(&self, code: Self::Control, a: u8, b: u8, carry: bool) -> (u8, Self::FlagSet) {
+        use I8080ALUFlag::*;
+        let mut flags = FlagSetBits::from(0);
+        let result = match code {
+            I8080ALUControl::Add => {
+                let sum = a as u16 + b as u16 + carry as u16;
+                flags.change(Carry, sum > 0xff);
+                flags.change(AuxiliaryCarry, half_carry(a, b, carry));
+                sum as u8
+            }
+            I8080ALUControl::Subtract => {
+                let subtrahend = b as u16 + carry as u16;
+                flags.change(Carry, subtrahend > a as u16);
+                flags.change(AuxiliaryCarry, half_carry(a, !b, !carry));
+                (a as u16).wrapping_sub(subtrahend) as u8
+            }
+            I8080ALUControl::BitAnd => {
+                flags.change(AuxiliaryCarry, (a | b) & 0x08 != 0);
+                a & b
+            }
+            I8080ALUControl::BitOr => a | b,
+            I8080ALUControl::BitXor => a ^ b,
+            I8080ALUControl::Increase => {
+                flags.change(AuxiliaryCarry, half_carry(a, 1, false));
+                a.wrapping_add(1)
+            }
+            I8080ALUControl::Decrease => {
+                flags.change(AuxiliaryCarry, half_carry(a, !1, true));
+                a.wrapping_sub(1)
+            }
+            I8080ALUControl::Right => {
+                flags.change(Carry, a & 0x01 != 0);
+                return (a.rotate_right(1), flags);
+            }
+        };
+        let szp = FlagSetBits::from_slice(&[Sign, Zero, Parity]);
+        (result, flags & !szp | FlagSetBits::from(Self::szp(result)))
+    }
+}
+
+impl I8080ALU {
+    /// S, Z and P of every result, as flag bits, in the order reference emulators tabulate them.
+    pub const SZP: [u8; 256] = {
+        let mut table = [0; 256];
+        let mut i = 0;
+        while i < 256 {
+            table[i] = Self::szp_computed(i as u8);
+            i += 1;
+        }
+        table
+    };
+
+    const fn szp_computed(result: u8) -> u8 {
+        let mut bits = result & 0x80;
+        if result == 0 {
+            bits |= 0x40;
+        }
+        if result.count_ones() & 1 == 0 {
+            bits |= 0x04;
+        }
+        bits
+    }
+
+    /// looked up in [`I8080ALU::SZP`], or computed with the `computed-flags` feature
+    /// to check the table against.
+    fn szp(result: u8) -> u8 {
+        if cfg!(feature = "computed-flags") {
+            Self::szp_computed(result)
+        } else {
+            Self::SZP[result as usize]
+        }
+    }
+}
+
+/// the flag byte of PSW, `S Z 0 A 0 P 1 C` from bit 7.
+impl FlagRegister for I8080ALU {
+    type Bits = u8;
+    const FIXED_ONE: u8 = 0x02;
+    const FIXED_ZERO: u8 = 0x28;
+}
+
+impl ALUCounter for I8080ALU {
+    fn increment(&self, a: u8) -> (u8, Self::FlagSet) {
+        self.op(I8080ALUControl::Increase, a, 0)
+    }
+    fn decrement(&self, a: u8) -> (u8, Self::FlagSet) {
+        self.op(I8080ALUControl::Decrease, a, 0)
+    }
+}
+
+/// the flags as Z80 `BIT` sets them: zero and parity if the bit is clear, sign for a set bit 7.
+impl ALUBits for I8080ALU {
+    fn bit_test(&self, a: u8, bit: u8) -> Self::FlagSet {
+        let masked = a & 1 << (bit & 7);
+        let mut flags = FlagSetBits::from(Self::szp(masked));
+        flags.set(I8080ALUFlag::AuxiliaryCarry);
+        flags
+    }
+    fn bit_change(&self, a: u8, bit: u8, set: bool) -> u8 {
+        let mask = 1 << (bit & 7);
+        if set {
+            a | mask
+        } else {
+            a & !mask
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
 pub enum I8080ALUFlag {
     Sign,
     Zero,
     AuxiliaryCarry,
     Parity,
     Carry,
-    // flags.change(Sign, acc >= 0x80);
-    // flags.change(Zero, acc == 0x00);
-    // flags.change(Parity, acc.count_ones() % 2 == 0);
 }
 
 impl From<I8080ALUFlag> for u8 {
@@ -208,7 +1007,7 @@ impl From<I8080ALUFlag> for u8 {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone)]
 pub enum I8080ALUControl {
     Add,
     Subtract,
@@ -220,36 +1019,697 @@ pub enum I8080ALUControl {
     Right,
 }
 
+pub mod fuzz;
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::instruction::typical::*;
     use crate::instruction::Instruction;
+    use alloc::vec;
     use I8080Addressing8Bit::*;
     use I8080RegisterCode16Bit::*;
     use I8080RegisterCode8Bit::*;
 
     #[test]
-    fn load() {
-        use crate::instruction::typical::Load;
+    fn cycle_run() {
+        let cpu = I8080::default().load_data(0x12).cycle();
+        assert_eq!(cpu.data(), 0x12);
+        assert!(cpu.run().is_some());
+        let mut system: I8080System = I8080System::default();
+        Halt.execute(&mut system);
+        assert!(system.cpu.run().is_none());
+    }
+
+    #[test]
+    fn push_pop_pair() {
+        let mut system: I8080System = I8080System::default();
+        *system.cpu.stack_pointer() = 0x0000;
+        system.load_of(BC, 0x1234);
+        PushPair::new(BC).execute(&mut system);
+        assert_eq!(*system.cpu.stack_pointer(), 0xfffe);
+        assert_eq!(
+            (system.memory.read(0xffff), system.memory.read(0xfffe)),
+            (0x12, 0x34)
+        );
+        PopPair::new(HL).execute(&mut system);
+        assert_eq!(system.read_of(HL), 0x1234);
+        assert_eq!(*system.cpu.stack_pointer(), 0x0000);
+    }
+
+    #[test]
+    fn increment_decrement() {
+        use I8080ALUFlag::*;
+        let mut system: I8080System = I8080System::default();
+        let inr = [Sign, Zero, AuxiliaryCarry, Parity];
+        system.cpu.flag_load(u8::from(Carry));
+        system.load_of(B, 0x0f);
+        Increment::new(inr, B).execute(&mut system.cpu);
+        assert_eq!(system.read_of(B), 0x10);
+        assert!(system.cpu.flag_on(AuxiliaryCarry) && system.cpu.flag_on(Carry));
+        system.load_of(HL, 0x2000);
+        system.memory.store(0x2000, 0x01);
+        Decrement::new(inr, I8080Addressing8Bit::M).execute(&mut system);
+        assert_eq!(system.memory.read(0x2000), 0x00);
+        assert!(system.cpu.flag_on(Zero) && system.cpu.flag_on(Carry));
+    }
+
+    #[test]
+    fn bit_ops() {
+        use I8080ALUFlag::*;
+        let mut system: I8080System = I8080System::default();
+        let bit = [Sign, Zero, AuxiliaryCarry, Parity];
+        system.cpu.flag_load(u8::from(Carry));
+        system.load_of(H, 0x80);
+        BitTest::new(7, bit, H).execute(&mut system.cpu);
+        assert!(system.cpu.flag_on(Sign) && !system.cpu.flag_on(Zero));
+        BitTest::new(6, bit, H).execute(&mut system.cpu);
+        assert!(system.cpu.flag_on(Zero) && system.cpu.flag_on(Parity));
+        assert!(system.cpu.flag_on(AuxiliaryCarry) && system.cpu.flag_on(Carry));
+        BitSet::new(0, A).execute(&mut system.cpu);
+        BitReset::new(7, H).execute(&mut system.cpu);
+        assert_eq!((system.read_of(A), system.read_of(H)), (0x01, 0x00));
+        system.load_of(HL, 0x2000);
+        BitSet::new(3, I8080Addressing8Bit::M).execute(&mut system);
+        assert_eq!(system.memory.read(0x2000), 0x08);
+        BitTest::new(3, bit, I8080Addressing8Bit::M).execute(&mut system);
+        assert!(!system.cpu.flag_on(Zero));
+    }
+
+    #[test]
+    fn exchange() {
+        use crate::memory::Endian;
+        let mut system: I8080System = I8080System::default();
+        system.load_of(DE, 0x1234);
+        system.load_of(HL, 0x5678);
+        Exchange::new(DE, HL).execute(&mut system);
+        assert_eq!((system.read_of(DE), system.read_of(HL)), (0x5678, 0x1234));
+        Exchange::new(B, C).execute(&mut system.cpu);
+        *system.cpu.stack_pointer() = 0xf000;
+        system.memory.store(0xf000, 0xcd);
+        system.memory.store(0xf001, 0xab);
+        ExchangeStack::new(HL, Endian::Little).execute(&mut system);
+        assert_eq!(system.read_of(HL), 0xabcd);
+        assert_eq!(
+            (system.memory.read(0xf000), system.memory.read(0xf001)),
+            (0x34, 0x12)
+        );
+        assert_eq!(*system.cpu.stack_pointer(), 0xf000);
+    }
+
+    #[test]
+    fn psw_round_trip() {
+        let mut system: I8080System = I8080System::default();
+        *system.cpu.stack_pointer() = 0x0000;
+        use I8080ALUFlag::*;
+        let flags = FlagSetBits::from_slice(&[Sign, Carry]);
+        system.cpu.load_of(A, 0x9a);
+        system.cpu.flag_load(I8080ALU::pack(flags));
+        PushPair::new(PSW).execute(&mut system);
+        assert_eq!(system.memory.read(0xfffe), 0x83);
+        // whatever is popped, the fixed bits read back as the hardware has them.
+        system.memory.store(0xfffe, 0xff);
+        PopPair::new(PSW).execute(&mut system);
+        assert_eq!(system.cpu.read_of(PSW), 0x9ad7);
+        let flags = I8080ALU::unpack(system.cpu.flag_read());
+        assert!(flags.is_set(AuxiliaryCarry) && flags.is_set(Zero));
+        assert_eq!(u8::from(flags), 0xd7);
+    }
+
+    #[test]
+    fn enum_dispatch() {
+        use crate::instruction::typical::DecoderBuilder;
+        use crate::instruction::{InstructionDecoder, Operand};
+        use I8080ALUFlag::*;
+
+        const INR: I8080Flags = &[Sign, Zero, AuxiliaryCarry, Parity];
+        let register = |op: u8| I8080Addressing8Bit::from_register_field(op >> 3);
+        let mut decoder = DecoderBuilder::new()
+            .map_all([0x06, 0x0e, 0x36], Operand::Imm8, move |op, value| {
+                I8080Instruction::Load(Load::new(register(op), ImmediateValue(value as u8)))
+            })
+            .map_all([0x04, 0x0c, 0x34], Operand::None, move |op, _| {
+                I8080Instruction::Increment(Increment::new(INR, register(op)))
+            })
+            .map(0xc5, Operand::None, |_, _| {
+                I8080Instruction::Push(PushPair::new(BC))
+            })
+            .map(0x76, Operand::None, |_, _| I8080Instruction::Halt)
+            .build();
+        let mut system: I8080System = I8080System::default();
+        *system.cpu.stack_pointer() = 0x0000;
+        // MVI B,0FH; MVI C,0FFH; INR B; INR C; PUSH B; HLT
+        for &byte in &[0x06, 0x0f, 0x0e, 0xff, 0x04, 0x0c, 0xc5, 0x76] {
+            let decoded = InstructionDecoder::<I8080System>::decode(&mut decoder, byte);
+            if let Some(instruction) = decoded.complete() {
+                instruction.execute(&mut system);
+            }
+        }
+        assert_eq!(system.read_of(BC), 0x1000);
+        assert!(system.cpu.flag_on(Zero) && system.cpu.halted());
+        assert_eq!(system.memory.read(0xffff), 0x10);
+    }
+
+    #[test]
+    fn crate_decoder() {
+        use crate::instruction::{DecodeResult, InstructionDecoder};
+
+        let mut decoder = decoder(IllegalOpcode::Error);
+        let mut system: I8080System = I8080System::default();
+        // LXI H,2000H; MVI M,41H; MVI A,40H; CMP M; ACI 0; STA 3000H; HLT
+        let program = [
+            0x21, 0x00, 0x20, 0x36, 0x41, 0x3e, 0x40, 0xbe, 0xce, 0x00, 0x32, 0x00, 0x30, 0x76,
+        ];
+        for byte in program {
+            let decoded = InstructionDecoder::<I8080System>::decode(&mut decoder, byte);
+            if let Some(instruction) = decoded.complete() {
+                instruction.execute(&mut system);
+            }
+        }
+        assert_eq!(system.memory.read(0x3000), 0x41);
+        assert!(system.cpu.halted());
+        // JNZ is not expressed yet, so the policy decides
+        let decode = |decoder: &mut TableDecoder<I8080Instruction>| {
+            InstructionDecoder::<I8080System>::decode(decoder, 0xc2)
+        };
+        assert!(matches!(decode(&mut decoder), DecodeResult::Invalid(0xc2)));
+        let mut lenient = super::decoder(IllegalOpcode::Nop);
+        assert!(decode(&mut lenient).complete().is_some());
+    }
+
+    #[test]
+    fn decoded_aliases() {
+        use crate::instruction::InstructionDecoder;
+
+        let mut decoder = decoder(IllegalOpcode::Error);
+        let mut system: I8080System = I8080System::default();
+        *system.cpu.stack_pointer() = 0xf000;
+        *system.cpu.program_counter() = 0x1234;
+        // the alternate NOP, CALL, RET and JMP: 08H; 0DDH 0100H; 0D9H; 0CBH 0200H
+        for byte in [0x08, 0xdd, 0x00, 0x01, 0xd9, 0xcb, 0x00, 0x02] {
+            let decoded = InstructionDecoder::<I8080System>::decode(&mut decoder, byte);
+            if let Some(instruction) = decoded.complete() {
+                instruction.execute(&mut system);
+            }
+        }
+        assert_eq!(*system.cpu.program_counter(), 0x0200);
+        assert_eq!(*system.cpu.stack_pointer(), 0xf000);
+        // the CALL pushed the program counter, and the RET popped it
+        assert_eq!(system.memory.read(0xeffe), 0x34);
+    }
+
+    #[test]
+    fn szp_table() {
+        use I8080ALUFlag::*;
+        for result in 0..=0xffu8 {
+            let mut flags = FlagSetBits::<u8>::from(0);
+            flags.change(Sign, result >= 0x80);
+            flags.change(Zero, result == 0x00);
+            flags.change(Parity, result.count_ones() % 2 == 0);
+            assert_eq!(I8080ALU::SZP[result as usize], u8::from(flags));
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn trace_line() {
+        use crate::observer::typical::TraceLines;
+        use crate::observer::ExecutionObserver;
+
+        let mut cpu = I8080::default();
+        cpu.load_of(A, 0x12);
+        cpu.load_of(BC, 0x3456);
+        cpu.load_of(HL, 0x8000);
+        *cpu.stack_pointer() = 0xf000;
+        *cpu.program_counter() = 0x0100;
+        cpu.flag_load(0xc4);
+        let mut trace = TraceLines::new(Vec::new());
+        trace.on_execute(&cpu);
+        *cpu.program_counter() = 0x0101;
+        trace.on_execute(&cpu);
+        assert!(trace.error().is_none());
+        assert_eq!(
+            String::from_utf8(trace.into_inner()).unwrap(),
+            "A=12 BC=3456 DE=0000 HL=8000 SP=F000 PC=0100 FLAGS=SZ-P-\n\
+             A=12 BC=3456 DE=0000 HL=8000 SP=F000 PC=0101 FLAGS=SZ-P-\n"
+        );
+    }
+
+    #[test]
+    fn introspection() {
         let mut cpu = I8080::default();
-        let mut memory = Memory8Bit64KB::default();
-        Load::new(A, ImmediateValue(36)).execute(&mut cpu);
+        cpu.load_of(BC, 0x1234);
+        cpu.load_of(A, 0x56);
+        assert_eq!(
+            RegisterSet::<I8080RegisterCode16Bit>::registers(&cpu),
+            [("PSW", 0x5602), ("BC", 0x1234), ("DE", 0), ("HL", 0)]
+        );
+        let registers = RegisterSet::<I8080RegisterCode8Bit>::registers(&cpu);
+        assert_eq!(registers[..3], [("A", 0x56), ("B", 0x12), ("C", 0x34)]);
+    }
+
+    #[test]
+    fn reset() {
+        let mut cpu = I8080::default();
+        cpu.load_of(HL, 0x1234);
+        *cpu.program_counter() = 0x8000;
+        cpu.set_interrupt_enabled(true);
+        cpu.set_halted(true);
+        cpu.reset(&mut ());
+        assert_eq!(*cpu.program_counter(), 0);
+        assert!(!cpu.interrupt_enabled() && !cpu.halted());
+        assert_eq!(cpu.read_of(HL), 0x1234);
+    }
+
+    #[test]
+    fn undocumented() {
+        let mut cpu = I8080::default();
+        cpu.load_of(PSW, 0x12ff);
+        assert_eq!(cpu.read_of(PSW), 0x12d7);
+        cpu.flag_load(0x00);
+        assert_eq!(cpu.flag_read(), 0x02);
+        assert_eq!(cpu.read_of(A), 0x12);
+        assert_eq!(
+            [0x08, 0x38, 0xcb, 0xd9, 0xfd, 0x76].map(canonical_opcode),
+            [0x00, 0x00, 0xc3, 0xc9, 0xcd, 0x76]
+        );
+    }
+
+    #[test]
+    fn registers() {
+        let mut cpu = I8080::default();
+        cpu.load_of(A, 36);
+        cpu.load_of(B, 36);
+        cpu.load_of(C, 36);
+        assert_eq!(cpu.read_of(A), 36);
+        assert_eq!(cpu.read_of(BC), 36 * 256 + 36);
+        cpu.load_of(HL, 0x1234);
+        assert_eq!(cpu.read_of(H), 0x12);
+        assert_eq!(cpu.read_of(L), 0x34);
+        assert_eq!(
+            I8080Addressing16Bit::ImmediateRegister(BC).value(&cpu),
+            0x2424
+        );
+    }
+
+    #[test]
+    fn addressing() {
+        let mut system = I8080System::new(I8080::default(), Memory8Bit64KB::new(&[0, 1, 2, 3]));
+        system.load_of(A, 36);
+        system.load_of(HL, 0x0002);
+        assert_eq!(ImmediateValue(7).value(&system), 7);
+        assert_eq!(ImmediateRegister(A).value(&system), 36);
+        assert_eq!(DirectValue(0x0003).value(&system), 3);
+        assert_eq!(DirectRegister(HL).value(&system), 2);
+        assert_eq!(
+            I8080Addressing16Bit::ImmediateValue(0x1234).value(&system),
+            0x1234
+        );
+        assert_eq!(
+            I8080Addressing16Bit::ImmediateRegister(HL).value(&system),
+            2
+        );
+        *system.cpu.program_counter() = 2;
+        system.cpu = system.cpu.program_fetch(&system.memory);
+        assert_eq!(system.cpu.data(), 2);
+        assert_eq!(*system.cpu.program_counter(), 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "write to an immediate operand")]
+    fn immediate_destination() {
+        let mut system: I8080System = I8080System::default();
+        Load::new(ImmediateValue(7), ImmediateRegister(A)).execute(&mut system);
+    }
+
+    #[test]
+    fn load() {
+        let mut system: I8080System = I8080System::default();
+        Load::new(A, ImmediateValue(36)).execute(&mut system);
         Store::new(
             I8080Addressing16Bit::ImmediateValue(0x1234),
             ImmediateRegister(A),
         )
-        .execute(&mut cpu);
-        assert_eq!(cpu.read_of(A), 36);
-        cpu = cpu.load_address(0x1234);
-        assert_eq!(cpu.fetch_memory(&mut memory), 36);
-        Load::new(B, ImmediateRegister(A)).execute(&mut cpu);
-        Load::new(C, ImmediateRegister(A)).execute(&mut cpu);
-        assert_eq!(cpu.read_of(B), 36);
-        assert_eq!(cpu.read_of(C), 36);
-        assert_eq!(cpu.read_of(BC), 36 * 256 + 36);
-        Load::new(HL, I8080Addressing16Bit::ImmediateRegister(BC)).execute(&mut cpu);
-        assert_eq!(cpu.read_of(HL), 36 * 256 + 36);
-        println!("{:?}", cpu);
+        .execute(&mut system);
+        assert_eq!(system.read_of(A), 36);
+        let cpu = system.cpu.load_address(0x1234);
+        assert_eq!(cpu.fetch_memory(&system.memory).data(), 36);
+        Load::new(B, ImmediateRegister(A)).execute(&mut system);
+        Load::new(C, ImmediateRegister(A)).execute(&mut system);
+        assert_eq!(system.read_of(B), 36);
+        assert_eq!(system.read_of(C), 36);
+        assert_eq!(system.read_of(BC), 36 * 256 + 36);
+        Load::new(HL, I8080Addressing16Bit::ImmediateRegister(BC)).execute(&mut system);
+        assert_eq!(system.read_of(HL), 36 * 256 + 36);
+    }
+
+    #[test]
+    fn load_memory() {
+        let mut system: I8080System = I8080System::default();
+        system.memory.store(0x1234, 36);
+        Load::new(C, DirectValue(0x1234)).execute(&mut system);
+        Load::new(B, ImmediateRegister(C)).execute(&mut system);
+        assert_eq!(system.read_of(BC), 36 * 256 + 36);
+        Load::new(HL, I8080Addressing16Bit::ImmediateRegister(BC)).execute(&mut system);
+        Load::new(DirectRegister(HL), ImmediateValue(7)).execute(&mut system);
+        assert_eq!(system.memory.read(36 * 256 + 36), 7);
+        Load::new(DirectValue(0x1234), ImmediateRegister(B)).execute(&mut system);
+        assert_eq!(system.memory.read(0x1234), 36);
+        Load::new(DE, HL).execute(&mut system);
+        assert_eq!(system.read_of(DE), 36 * 256 + 36);
+    }
+
+    #[test]
+    fn stack_and_jump() {
+        let mut system: I8080System = I8080System::default();
+        *system.cpu.stack_pointer() = 0x100;
+        Push::new(0x12).execute(&mut system);
+        Push::new(0x34).execute(&mut system);
+        assert_eq!(system.memory.read(0xff), 0x12);
+        assert_eq!(system.memory.read(0xfe), 0x34);
+        Pop::new(L).execute(&mut system);
+        Pop::new(H).execute(&mut system);
+        assert_eq!(system.read_of(HL), 0x1234);
+        assert_eq!(*system.cpu.stack_pointer(), 0x100);
+
+        let mut cpu = I8080::default();
+        Jump::new(0x4000).execute(&mut cpu);
+        assert_eq!(*cpu.program_counter(), 0x4000);
+        let is_zero = |cpu: &I8080| cpu.read_of(A) == 0;
+        Condition::new(is_zero, Jump::new(0x5000)).execute(&mut cpu);
+        assert_eq!(*cpu.program_counter(), 0x5000);
+        cpu.load_of(A, 1);
+        Condition::new(is_zero, Jump::new(0x6000)).execute(&mut cpu);
+        assert_eq!(*cpu.program_counter(), 0x5000);
+    }
+
+    #[test]
+    fn mov_matrix() {
+        let fill = |system: &mut I8080System| {
+            for (i, reg) in [B, C, D, E, H, L, A].into_iter().enumerate() {
+                system.load_of(reg, 0x10 + i as u8);
+            }
+            // HL = 0x1415
+            system.memory.store(0x1415, 0xee);
+        };
+        for opcode in 0x40..=0x7fu8 {
+            if opcode == 0x76 {
+                continue;
+            }
+            let dst = I8080Addressing8Bit::from_register_field(opcode >> 3);
+            let src = I8080Addressing8Bit::from_register_field(opcode);
+            let mut system = I8080System::default();
+            fill(&mut system);
+            let expected = src.value(&system);
+            Load::new(dst, src).execute(&mut system);
+            assert_eq!(dst.value(&system), expected, "opcode {:02X}", opcode);
+        }
+        let mut system = I8080System::default();
+        fill(&mut system);
+        // MOV M,B then MOV A,M
+        Load::new(I8080Addressing8Bit::M, ImmediateRegister(B)).execute(&mut system);
+        assert_eq!(system.memory.read(0x1415), 0x10);
+        Load::new(A, I8080Addressing8Bit::M).execute(&mut system);
+        assert_eq!(system.read_of(A), 0x10);
+    }
+
+    #[test]
+    fn masked_flags() {
+        use I8080ALUFlag::*;
+        let mut cpu = I8080::default();
+        cpu.load_of(A, 0x12);
+        cpu.flag_load(u8::from(Carry) | u8::from(Zero));
+        // INR B: 0x7f + 1 affects S, Z, AC and P but leaves the carry alone
+        cpu.load_of(B, 0x7f);
+        let (result, flags) = I8080ALU.op(I8080ALUControl::Increase, cpu.read_of(B), 0);
+        cpu.flag_load_mask_slice(&[Sign, Zero, AuxiliaryCarry, Parity], flags.into());
+        cpu.load_of(B, result);
+        assert_eq!(cpu.read_of(B), 0x80);
+        assert!(cpu.flag_on(Sign));
+        assert!(!cpu.flag_on(Zero));
+        assert!(cpu.flag_on(AuxiliaryCarry));
+        assert!(!cpu.flag_on(Parity));
+        assert!(cpu.flag_on(Carry));
+        assert_eq!(cpu.read_of(A), 0x12);
+        // ADD clears the carry it does not produce
+        let (_, flags) = I8080ALU.op(I8080ALUControl::Add, 0x01, 0x02);
+        cpu.flag_load_masked(FlagSetBits::from(0xd5), flags.into());
+        assert_eq!(cpu.flag_read(), u8::from(Parity) | I8080::FLAGS_FIXED_ONE);
+    }
+
+    #[test]
+    fn alu_ops() {
+        use I8080ALUFlag::*;
+        let mut cpu = I8080::default();
+        cpu.load_of(A, 0x0f);
+        // ADI 01H
+        let flags = cpu.alu_acc_op(I8080ALUControl::Add, 0x01);
+        assert_eq!(cpu.read_of(A), 0x10);
+        assert!(flags.is_set(AuxiliaryCarry));
+        // SUB B through addressing modes
+        cpu.load_of(B, 0x10);
+        let flags = cpu.alu_op(I8080ALUControl::Subtract, &A, &B);
+        assert_eq!(cpu.read_of(A), 0x00);
+        assert!(flags.is_set(Zero));
+        assert!(!flags.is_set(Carry));
+        // the flag register itself is left to the caller
+        assert_eq!(cpu.flag_read(), I8080::FLAGS_FIXED_ONE);
+        cpu = cpu.load_data(0x42).acc_load();
+        assert_eq!(cpu.acc(), 0x42);
+    }
+
+    #[test]
+    fn arithmetic() {
+        use I8080ALUControl::*;
+        use I8080ALUFlag::*;
+        let mut system: I8080System = I8080System::default();
+        system.cpu.flag_load(u8::from(Sign));
+        system.load_of(A, 0xfe);
+        Arithmetic::new(Add, vec![Carry, Zero], A, ImmediateValue(5)).execute(&mut system);
+        assert_eq!(system.read_of(A), 0x03);
+        assert!(system.cpu.flag_on(Carry));
+        assert!(!system.cpu.flag_on(Zero));
+        // not in the mask, so left as it was
+        assert!(system.cpu.flag_on(Sign));
+        assert!(!system.cpu.flag_on(Parity));
+
+        // INR M keeps the carry
+        let inr = vec![Sign, Zero, AuxiliaryCarry, Parity];
+        system.load_of(HL, 0x2000);
+        system.memory.store(0x2000, 0xff);
+        Arithmetic::new(Increase, inr, I8080Addressing8Bit::M, ImmediateValue(0))
+            .execute(&mut system);
+        assert_eq!(system.memory.read(0x2000), 0x00);
+        assert_eq!(
+            system.cpu.flag_read(),
+            [Zero, AuxiliaryCarry, Parity, Carry]
+                .map(u8::from)
+                .iter()
+                .sum::<u8>()
+                | I8080::FLAGS_FIXED_ONE
+        );
+
+        // register-only operands on the bare cpu
+        let mut cpu = I8080::default();
+        cpu.load_of(A, 0x0f);
+        cpu.load_of(B, 0x0f);
+        let all = vec![Sign, Zero, AuxiliaryCarry, Parity, Carry];
+        Arithmetic::new(BitXor, all, A, B).execute(&mut cpu);
+        assert_eq!(cpu.read_of(A), 0x00);
+        assert_eq!(
+            cpu.flag_read(),
+            u8::from(Zero) | u8::from(Parity) | I8080::FLAGS_FIXED_ONE
+        );
+    }
+
+    #[test]
+    fn carry_in() {
+        use I8080ALUControl::*;
+        use I8080ALUFlag::*;
+        let alu = I8080ALU;
+        for a in 0..=0xffu8 {
+            for b in 0..=0xffu8 {
+                for c in [false, true] {
+                    let (sum, flags) = alu.op_carry(Add, a, b, c);
+                    let wide = a as u16 + b as u16 + c as u16;
+                    assert_eq!((sum, flags.is_set(Carry)), (wide as u8, wide > 0xff));
+                    let (difference, flags) = alu.op_carry(Subtract, a, b, c);
+                    let wide = (a as u16).wrapping_sub(b as u16 + c as u16);
+                    assert_eq!((difference, flags.is_set(Carry)), (wide as u8, wide > 0xff));
+                }
+            }
+        }
+
+        // ADC A, SBB B, ACI 0 and SBI 0 with the carry set
+        let all: I8080Flags = &[Sign, Zero, AuxiliaryCarry, Parity, Carry];
+        let mut system: I8080System = I8080System::default();
+        system.cpu.flag_load(u8::from(Carry));
+        system.load_of(A, 0x80);
+        ArithmeticWithCarry::new(Add, all, A, A).execute(&mut system);
+        assert_eq!(system.read_of(A), 0x01);
+        assert!(system.cpu.flag_on(Carry));
+        ArithmeticWithCarry::new(Subtract, all, A, B).execute(&mut system);
+        assert_eq!(system.read_of(A), 0x00);
+        assert!(system.cpu.flag_on(Zero) && !system.cpu.flag_on(Carry));
+        system.load_of(A, 0xff);
+        system.cpu.flag_load(u8::from(Carry));
+        ArithmeticWithCarry::new(Add, all, A, ImmediateValue(0)).execute(&mut system);
+        assert_eq!(system.read_of(A), 0x00);
+        assert!(system.cpu.flag_on(Carry) && system.cpu.flag_on(AuxiliaryCarry));
+        ArithmeticWithCarry::new(Subtract, all, A, ImmediateValue(0)).execute(&mut system);
+        assert_eq!(system.read_of(A), 0xff);
+        assert!(system.cpu.flag_on(Carry) && !system.cpu.flag_on(AuxiliaryCarry));
+    }
+
+    #[test]
+    fn machine() {
+        use crate::clock::Clock;
+        use crate::machine::Machine;
+        #[rustfmt::skip]
+        let program = [
+            0x3e, 0x05,       // MVI A,5
+            0x06, 0x03,       // MVI B,3
+            0x80,             // ADD B
+            0x32, 0x00, 0x01, // STA 0100
+            0xcd, 0x0c, 0x00, // CALL 000C
+            0x76,             // HLT
+            0x3c,             // INR A
+            0xc9,             // RET
+        ];
+        let mut bus = Bus::default();
+        bus.map_memory(0x0000..=0xffff, Memory8Bit64KB::new(&program));
+        let mut machine = Machine::new(I8080::default(), bus, Clock::PC8801_4MHZ);
+        assert_eq!(machine.run(1000), CPURunningState::Halted);
+        assert_eq!(machine.bus.read(0x0100), 8);
+        assert_eq!(machine.cpu.acc(), 9);
+        assert_eq!(machine.cycles(), 7 + 7 + 4 + 13 + 17 + 5 + 10 + 7);
+
+        // DAA is not decoded yet
+        let mut bus = Bus::default();
+        bus.map_memory(0x0000..=0xffff, Memory8Bit64KB::new(&[0x00, 0x27]));
+        let mut machine = Machine::new(I8080::default(), bus, Clock::PC8801_4MHZ);
+        machine.cpu.set_interrupt_enabled(true);
+        let error = CPURunningState::Error("illegal opcode 27 at 0001".into());
+        assert_eq!(machine.run(1000), error);
+        assert_eq!(
+            machine.cpu.fault(),
+            Some(I8080Fault::IllegalOpcode {
+                pc: 0x0001,
+                opcode: 0x27
+            })
+        );
+        machine.reset();
+        assert_eq!(machine.cpu.running_state(), CPURunningState::Running);
+    }
+
+    #[test]
+    fn interrupt() {
+        use crate::clock::Clock;
+        use crate::machine::Machine;
+        use crate::typical::pic::Pic;
+        use crate::typical::timer::Timer;
+        use alloc::rc::Rc;
+        use core::cell::RefCell;
+        let mut memory = Memory8Bit64KB::new(&[
+            0xfb, // EI
+            0x76, // HLT
+            0x3c, // INR A
+            0x76, // HLT
+        ]);
+        #[rustfmt::skip]
+        memory.as_mut_slice()[0x0008..0x0010].copy_from_slice(&[
+            0x3a, 0x03, 0xe0, // LDA E003, acknowledging the timer
+            0x32, 0x21, 0xe0, // STA E021, ending the interrupt
+            0xfb,             // EI
+            0xc9,             // RET
+        ]);
+        let timer = Rc::new(RefCell::new(Timer::new()));
+        let mut pic = Pic::new();
+        let wired = pic.wire(1, timer.clone());
+        let mut bus = Bus::default();
+        bus.map_memory(0x0000..=0xffff, memory)
+            .map(0xe000..=0xe003, wired)
+            .map(0xe020..=0xe022, pic);
+        bus.store(0xe000, 100);
+        bus.store(
+            0xe002,
+            Timer::ENABLE | Timer::INTERRUPT_ENABLE | Timer::ONE_SHOT,
+        );
+        let mut machine = Machine::new(I8080::default(), bus, Clock::PC8801_4MHZ);
+        assert_eq!(machine.run(1000), CPURunningState::Halted);
+        // RST 1 left the first HLT, the handler returned to INR A
+        assert_eq!(*machine.cpu.program_counter(), 0x0004);
+        assert_eq!(machine.cpu.acc(), 0x02);
+        assert_eq!(machine.bus.read(0xe022), 0);
+        assert!(machine.cpu.interrupt_enabled());
+
+        // disabled, then RST 7 off a bus nothing drives
+        let mut cpu = I8080::default();
+        assert_eq!(cpu.interrupt(&mut machine.bus), 0);
+        cpu.set_interrupt_enabled(true);
+        assert_eq!(cpu.interrupt(&mut machine.bus), 11);
+        assert_eq!(*cpu.program_counter(), 0x0038);
+        assert!(!cpu.interrupt_enabled());
+    }
+
+    #[test]
+    fn self_modifying() {
+        use crate::cache::DecodeCache;
+        use crate::clock::Clock;
+        use crate::machine::Machine;
+        let mut memory = Memory8Bit64KB::new(&[
+            0xcd, 0x10, 0x00, // CALL 0010
+            0x3e, 0x3d, // MVI A,3D
+            0x32, 0x10, 0x00, // STA 0010, patching INR A into DCR A
+            0xcd, 0x10, 0x00, // CALL 0010
+            0x76, // HLT
+        ]);
+        memory.as_mut_slice()[0x0010..0x0012].copy_from_slice(&[
+            0x3c, // INR A
+            0xc9, // RET
+        ]);
+        let mut bus = Bus::default();
+        bus.map_memory(0x0000..=0xffff, memory);
+        let mut machine = Machine::new(I8080::default(), bus, Clock::PC8801_4MHZ);
+        let mut decoder = decoder(IllegalOpcode::Error);
+        let mut cache = DecodeCache::new();
+        while !machine.cpu.halted() {
+            machine
+                .step_cached::<I8080System, _>(&mut cache, &mut decoder)
+                .unwrap();
+        }
+        assert_eq!(machine.cpu.acc(), 0x3c);
+        // RET decoded once, INR A and DCR A once each
+        assert_eq!((cache.hits(), cache.misses()), (1, 8));
+        assert_eq!(machine.cycles(), 17 + 5 + 10 + 7 + 13 + 17 + 5 + 10 + 7);
+    }
+
+    #[test]
+    fn memory_fault() {
+        use crate::clock::Clock;
+        use crate::machine::Machine;
+        let program = [
+            0x32, 0x00, 0x80, // STA 8000
+            0xc3, 0x00, 0x50, // JMP 5000
+        ];
+        // RAM at 0000, nothing at 4000, ROM at 8000
+        let mut bus = Bus::default();
+        bus.map_memory(0x0000..=0x3fff, Memory8Bit64KB::new(&program))
+            .map_rom(0x8000..=0xffff, Memory8Bit64KB::default());
+        let mut machine = Machine::new(I8080::default(), bus, Clock::PC8801_4MHZ);
+        let error = "read-only address 0x8000, by the instruction at 0000";
+        assert_eq!(machine.run(1000), CPURunningState::Error(error.into()));
+        assert_eq!(*machine.cpu.program_counter(), 0x0003);
+        machine.reset();
+        *machine.cpu.program_counter() = 0x0003;
+        let error = "unmapped address 0x5000, by the instruction at 5000";
+        assert_eq!(machine.run(1000), CPURunningState::Error(error.into()));
+        assert_eq!(
+            machine.cpu.fault(),
+            Some(I8080Fault::Memory {
+                pc: 0x5000,
+                error: MemoryError::Unmapped(0x5000)
+            })
+        );
     }
 }
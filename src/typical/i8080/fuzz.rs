@@ -0,0 +1,231 @@
+//! Differential fuzzing of the I8080: random instructions on random states, run through
+//! the crate's [`decoder`](super::decoder), ALU and addressing modes, and through
+//! [`reference`], an 8080 written apart from them.
+
+use super::*;
+use crate::instruction::{DecodeResult, InstructionDecoder};
+use alloc::vec::Vec;
+use I8080RegisterCode8Bit::*;
+
+/// xorshift64, so that a divergence is reproduced from its seed.
+#[derive(Debug, Copy, Clone)]
+pub struct Random(pub u64);
+
+impl Random {
+    pub fn word(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+    pub fn byte(&mut self) -> u8 {
+        self.word() as u8
+    }
+}
+
+/// what an instruction may touch: B, C, D, E, H, L, M and A in register field order,
+/// M being the memory at HL before the instruction, and the flag byte.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct State {
+    pub registers: [u8; 8],
+    pub flags: u8,
+}
+
+/// by register field; 6 is M, not a register.
+const FIELD: [I8080RegisterCode8Bit; 8] = [B, C, D, E, H, L, A, A];
+
+impl State {
+    pub fn random(random: &mut Random) -> Self {
+        let mut registers = [0; 8];
+        registers.fill_with(|| random.byte());
+        Self {
+            registers,
+            // as the flag register holds them
+            flags: random.byte() & !0x28 | 0x02,
+        }
+    }
+    pub fn hl(&self) -> u16 {
+        u16::from_be_bytes([self.registers[4], self.registers[5]])
+    }
+    pub fn load(&self) -> I8080System {
+        let mut system: I8080System = I8080System::default();
+        for (i, &value) in self.registers.iter().enumerate().filter(|&(i, _)| i != 6) {
+            system.load_of(FIELD[i], value);
+        }
+        system.memory.store(self.hl(), self.registers[6]);
+        system.cpu.flag_load(self.flags);
+        system
+    }
+    /// read back from `system`, M at `hl`.
+    pub fn save(system: &I8080System, hl: u16) -> Self {
+        let mut registers = [0; 8];
+        for (i, register) in registers.iter_mut().enumerate() {
+            *register = match i {
+                6 => system.memory.read(hl),
+                _ => system.read_of(FIELD[i]),
+            };
+        }
+        Self {
+            registers,
+            flags: system.cpu.flag_read(),
+        }
+    }
+}
+
+/// An 8080 written apart from the ALU, flag by flag as the data sheet puts it, for
+/// MOV, MVI, INR, DCR, RRC and the accumulator operations with registers and immediates.
+/// None for any other instruction.
+pub fn reference(state: State, opcode: u8, immediate: u8) -> Option<State> {
+    let mut next = state;
+    let carry_in = state.flags & 0x01;
+    let r = &mut next.registers;
+    let (sign, zero, half, parity, carry) = (0x80, 0x40, 0x10, 0x04, 0x01);
+    let szp = |value: u8| {
+        let mut bits = value & sign;
+        if value == 0 {
+            bits |= zero;
+        }
+        if value.count_ones() & 1 == 0 {
+            bits |= parity;
+        }
+        bits
+    };
+    let source = match opcode {
+        0xc0..=0xff => immediate,
+        _ => r[opcode as usize & 7],
+    };
+    let destination = (opcode >> 3) as usize & 7;
+    let with = |condition: bool, bit: u8| if condition { bit } else { 0 };
+    match opcode {
+        0x76 => return None,
+        0x40..=0x7f => r[destination] = source,
+        _ if opcode & 0xc7 == 0x06 => r[destination] = immediate,
+        _ if opcode & 0xc7 == 0x04 => {
+            let d = &mut r[destination];
+            *d = d.wrapping_add(1);
+            next.flags = next.flags & carry | 0x02 | szp(*d) | with(*d & 0x0f == 0, half);
+        }
+        _ if opcode & 0xc7 == 0x05 => {
+            let d = &mut r[destination];
+            *d = d.wrapping_sub(1);
+            next.flags = next.flags & carry | 0x02 | szp(*d) | with(*d & 0x0f != 0x0f, half);
+        }
+        0x0f => {
+            let a = r[7];
+            r[7] = a >> 1 | (a & 1) << 7;
+            next.flags = next.flags & !carry | a & carry;
+        }
+        _ if opcode & 0xc0 == 0x80 || opcode & 0xc7 == 0xc6 => {
+            let a = r[7];
+            // ADC and SBB take the carry in, ADD, SUB and CMP do not
+            let c = match opcode & 0x38 {
+                0x08 | 0x18 => carry_in,
+                _ => 0,
+            };
+            let (result, flags) = match opcode & 0x38 {
+                0x00 | 0x08 => {
+                    let sum = a as u16 + source as u16 + c as u16;
+                    (
+                        sum as u8,
+                        with(sum > 0xff, carry)
+                            | with((a & 0x0f) + (source & 0x0f) + c > 0x0f, half),
+                    )
+                }
+                0x10 | 0x18 | 0x38 => (
+                    a.wrapping_sub(source).wrapping_sub(c),
+                    with(source as u16 + c as u16 > a as u16, carry)
+                        | with(a & 0x0f >= (source & 0x0f) + c, half),
+                ),
+                0x20 => (a & source, with((a | source) & 0x08 != 0, half)),
+                0x28 => (a ^ source, 0),
+                _ => (a | source, 0),
+            };
+            // CMP only compares
+            if opcode & 0x38 != 0x38 {
+                r[7] = result;
+            }
+            next.flags = 0x02 | szp(result) | flags;
+        }
+        _ => return None,
+    }
+    Some(next)
+}
+
+/// An instruction whose result differs from the reference;
+/// `actual` is None when the crate did not decode it.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Divergence {
+    pub instruction: [u8; 2],
+    pub before: State,
+    pub expected: State,
+    pub actual: Option<State>,
+}
+
+/// runs `cases` random instructions on random states through the crate and the reference,
+/// skipping those the reference does not cover.
+pub fn run(seed: u64, cases: usize) -> Vec<Divergence> {
+    let mut random = Random(seed);
+    let mut decoder = decoder(IllegalOpcode::Error);
+    let mut divergences = Vec::new();
+    for _ in 0..cases {
+        let before = State::random(&mut random);
+        let instruction = [random.byte(), random.byte()];
+        let Some(expected) = reference(before, instruction[0], instruction[1]) else {
+            continue;
+        };
+        let mut decode = |word| InstructionDecoder::<I8080System>::decode(&mut decoder, word);
+        let decoded = match decode(instruction[0]) {
+            DecodeResult::Incomplete => decode(instruction[1]).complete(),
+            result => result.complete(),
+        };
+        let actual = decoded.map(|decoded| {
+            let mut system = before.load();
+            decoded.execute(&mut system);
+            State::save(&system, before.hl())
+        });
+        if actual != Some(expected) {
+            divergences.push(Divergence {
+                instruction,
+                before,
+                expected,
+                actual,
+            });
+        }
+    }
+    divergences
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn against_reference() {
+        let divergences = run(0x8080_2341, 100_000);
+        if let Some(first) = divergences.first() {
+            panic!(
+                "{} divergences, the first {:02x?} from {:02x?}: expected {:02x?}, got {:02x?}",
+                divergences.len(),
+                first.instruction,
+                first.before,
+                first.expected,
+                first.actual
+            );
+        }
+    }
+
+    #[test]
+    fn covers_carry_and_compare() {
+        let before = State {
+            registers: [0x01, 0, 0, 0, 0, 0, 0, 0x10],
+            flags: 0x03,
+        };
+        // ADC B, SBB B, CMP B
+        let a = |opcode| reference(before, opcode, 0).map(|s| s.registers[7]);
+        assert_eq!(
+            (a(0x88), a(0x98), a(0xb8)),
+            (Some(0x12), Some(0x0e), Some(0x10))
+        );
+        assert_eq!(reference(before, 0xc3, 0), None);
+    }
+}
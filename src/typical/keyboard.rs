@@ -0,0 +1,150 @@
+use crate::bus::Device;
+use alloc::collections::{BTreeMap, BTreeSet};
+
+/// How the CPU scans a [`Keyboard`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Scan {
+    /// writing offset 0 selects a row, reading offset 1 returns its columns.
+    Selected,
+    /// reading offset `n` returns the columns of row `n`, as the PC-88 ports 00h-0Eh.
+    Direct,
+}
+
+/// A matrix of up to 8 columns per row, read active low: a pressed key reads as 0.
+///
+/// Host keys of type `K` are placed on the matrix with [`Keyboard::map`],
+/// and frontends forward their key events to [`Keyboard::press`] and [`Keyboard::release`].
+#[derive(Debug, Clone)]
+pub struct Keyboard<K> {
+    layout: BTreeMap<K, (u8, u8)>,
+    /// pressed keys rather than positions, as keys sharing a position are released apart.
+    pressed: BTreeSet<K>,
+    rows: usize,
+    scan: Scan,
+    selected: u8,
+}
+
+impl<K: Ord> Keyboard<K> {
+    pub fn new(rows: usize, scan: Scan) -> Self {
+        Self {
+            layout: BTreeMap::new(),
+            pressed: BTreeSet::new(),
+            rows,
+            scan,
+            selected: 0,
+        }
+    }
+
+    /// places `key` at `row` and `column`. Several keys may share a position.
+    pub fn map(mut self, key: K, row: u8, column: u8) -> Self {
+        assert!((row as usize) < self.rows && column < 8);
+        self.layout.insert(key, (row, column));
+        self
+    }
+
+    /// returns false if `key` is not on the matrix.
+    pub fn press(&mut self, key: K) -> bool {
+        if !self.layout.contains_key(&key) {
+            return false;
+        }
+        self.pressed.insert(key);
+        true
+    }
+
+    /// returns false if `key` is not on the matrix.
+    pub fn release(&mut self, key: K) -> bool {
+        self.pressed.remove(&key);
+        self.layout.contains_key(&key)
+    }
+
+    pub fn release_all(&mut self) {
+        self.pressed.clear();
+    }
+
+    /// whether a key at `row` and `column` is down, never for a position off the matrix.
+    pub fn pressed_at(&self, row: u8, column: u8) -> bool {
+        column < 8 && self.pressed_bits(row) & 1 << column != 0
+    }
+
+    /// the pressed keys of `row`, a set bit per column.
+    fn pressed_bits(&self, row: u8) -> u8 {
+        self.pressed
+            .iter()
+            .filter_map(|key| self.layout.get(key))
+            .filter(|&&(r, _)| r == row)
+            .fold(0, |bits, &(_, column)| bits | 1 << column)
+    }
+
+    fn columns(&self, row: u8) -> u8 {
+        !self.pressed_bits(row)
+    }
+}
+
+impl<K: Ord> Device for Keyboard<K> {
+    type Address = u16;
+    type Data = u8;
+
+    fn read(&mut self, offset: u16) -> u8 {
+        match (self.scan, offset) {
+            (Scan::Selected, 1) => self.columns(self.selected),
+            (Scan::Selected, _) => 0xff,
+            (Scan::Direct, row) => self.columns(row.try_into().unwrap_or(u8::MAX)),
+        }
+    }
+
+    fn write(&mut self, offset: u16, data: u8) {
+        if let (Scan::Selected, 0) = (self.scan, offset) {
+            self.selected = data;
+        }
+    }
+
+    /// keys held on the host stay down.
+    fn reset(&mut self) {
+        self.selected = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matrix() {
+        let mut keyboard = Keyboard::new(15, Scan::Direct)
+            .map('0', 0, 0)
+            .map('a', 2, 1)
+            .map('\r', 1, 7);
+        assert!(keyboard.press('a') && keyboard.press('\r'));
+        assert!(!keyboard.press('?'));
+        assert_eq!(keyboard.read(0), 0xff);
+        assert_eq!((keyboard.read(1), keyboard.read(2)), (0x7f, 0xfd));
+        keyboard.release('a');
+        assert_eq!(keyboard.read(2), 0xff);
+        assert!(keyboard.pressed_at(1, 7));
+        // rows beyond the matrix read as released
+        assert_eq!(keyboard.read(0x20), 0xff);
+
+        let mut keyboard = Keyboard::new(2, Scan::Selected).map("shift", 1, 0);
+        keyboard.press("shift");
+        keyboard.write(0, 0);
+        assert_eq!(keyboard.read(1), 0xff);
+        keyboard.write(0, 1);
+        assert_eq!(keyboard.read(1), 0xfe);
+        keyboard.release_all();
+        assert_eq!(keyboard.read(1), 0xff);
+    }
+
+    #[test]
+    fn shared_position() {
+        let mut keyboard = Keyboard::new(1, Scan::Direct)
+            .map("return", 0, 7)
+            .map("enter", 0, 7);
+        keyboard.press("return");
+        keyboard.press("enter");
+        keyboard.release("return");
+        assert!(keyboard.pressed_at(0, 7));
+        keyboard.release("enter");
+        assert!(!keyboard.pressed_at(0, 7));
+        assert!(!keyboard.pressed_at(0, 8));
+    }
+}
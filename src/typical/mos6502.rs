@@ -0,0 +1,662 @@
+use crate::addressing::{Addressing, AddressingMut};
+use crate::alu::helpers::overflow_add;
+use crate::alu::typical::FlagSetBits;
+use crate::alu::{ALUCounter, FlagSet, ALU};
+use crate::cpu::*;
+use crate::memory::typical::*;
+use crate::memory::{Endian, Memory, MemoryWord};
+use crate::register::{
+    RegisterCode, RegisterDecrementable, RegisterEnumerable, RegisterIncrementable, RegisterSet,
+};
+use crate::system::System;
+
+/// The stack lives in page 1. `sp` is kept as the address of the top of the stack,
+/// i.e. `0x0100 | (S + 1)`, so that the common push/pop of [`CPUStackPointer`] applies.
+#[derive(Debug, Copy, Clone)]
+pub struct MOS6502 {
+    data_bus: u8,
+    address: u16,
+    a: u8,
+    x: u8,
+    y: u8,
+    p: u8,
+    sp: u16,
+    pc: u16,
+}
+
+impl Default for MOS6502 {
+    /// state after reset, except the program counter which is read from the reset vector.
+    fn default() -> Self {
+        Self {
+            data_bus: 0,
+            address: 0,
+            a: 0,
+            x: 0,
+            y: 0,
+            p: MOS6502ALUFlag::Unused as u8 | MOS6502ALUFlag::Interrupt as u8,
+            sp: 0x01fe,
+            pc: 0,
+        }
+    }
+}
+
+impl CPU for MOS6502 {
+    type Data = u8;
+    type Address = u16;
+
+    fn data(&self) -> Self::Data {
+        self.data_bus
+    }
+
+    fn address(&self) -> Self::Address {
+        self.address
+    }
+
+    fn load_data(mut self, data: Self::Data) -> Self {
+        self.data_bus = data;
+        self
+    }
+
+    fn load_address(mut self, address: Self::Address) -> Self {
+        self.address = address;
+        self
+    }
+
+    /// instructions execute whole on a [`System`], leaving no state between their cycles.
+    fn cycle(self) -> Self {
+        self
+    }
+
+    /// the 6502 has no halt, so it always runs on.
+    fn run(self) -> Option<Self> {
+        Some(self)
+    }
+}
+
+impl<M: Memory<Address = u16, Data = u8>> CPUMemory<M> for MOS6502 {}
+
+impl CPUProgramCounter for MOS6502 {
+    fn program_counter(&mut self) -> &mut Self::Address {
+        &mut self.pc
+    }
+}
+
+impl CPUStackPointer for MOS6502 {
+    fn stack_pointer(&mut self) -> &mut Self::Address {
+        &mut self.sp
+    }
+    fn stack_pointer_decrement(&mut self)
+    where
+        Self::Address: RegisterDecrementable,
+    {
+        self.sp = 0x0100 | (self.sp as u8).wrapping_sub(1) as u16;
+    }
+    fn stack_pointer_increment(&mut self)
+    where
+        Self::Address: RegisterIncrementable,
+    {
+        self.sp = 0x0100 | (self.sp as u8).wrapping_add(1) as u16;
+    }
+}
+
+impl CPUAlu for MOS6502 {
+    type ALU = MOS6502ALU;
+    fn alu(&self) -> &MOS6502ALU {
+        &MOS6502ALU
+    }
+}
+
+impl CPUAccumulator for MOS6502 {
+    fn acc(&self) -> u8 {
+        self.a
+    }
+    fn acc_write(&mut self, data: u8) {
+        self.a = data
+    }
+}
+
+impl CPUFlagRegister for MOS6502 {
+    type FlagRegisterSize = u8;
+
+    fn flag_load(&mut self, bits: u8) {
+        self.p = bits;
+    }
+
+    fn flag_read(&self) -> u8 {
+        self.p
+    }
+}
+
+impl CPUJump for MOS6502 {}
+
+impl MOS6502 {
+    pub const RESET_VECTOR: u16 = 0xfffc;
+}
+
+/// moves S down by 3 as the aborted pushes do, disables interrupts and jumps
+/// through the reset vector.
+impl<M: Memory<Address = u16, Data = u8>> CPUReset<M> for MOS6502 {
+    fn reset(&mut self, memory: &mut M) {
+        for _ in 0..3 {
+            self.stack_pointer_decrement();
+        }
+        self.p |= MOS6502ALUFlag::Interrupt as u8;
+        self.pc = memory.read_word(Self::RESET_VECTOR, Endian::Little);
+    }
+}
+
+/// MOS6502 owning its memory.
+pub type MOS6502System<M = Memory8Bit64KB> = System<MOS6502, M>;
+
+#[derive(Debug, Copy, Clone)]
+pub enum MOS6502RegisterCode {
+    A,
+    X,
+    Y,
+    /// processor status.
+    P,
+    /// stack pointer, the low byte of the next free slot in page 1.
+    S,
+}
+
+impl RegisterCode for MOS6502RegisterCode {
+    type Register = u8;
+}
+
+impl RegisterEnumerable for MOS6502RegisterCode {
+    const ALL: &'static [Self] = {
+        use MOS6502RegisterCode::*;
+        &[A, X, Y, P, S]
+    };
+    fn name(self) -> &'static str {
+        match self {
+            MOS6502RegisterCode::A => "A",
+            MOS6502RegisterCode::X => "X",
+            MOS6502RegisterCode::Y => "Y",
+            MOS6502RegisterCode::P => "P",
+            MOS6502RegisterCode::S => "S",
+        }
+    }
+}
+
+impl RegisterSet<MOS6502RegisterCode> for MOS6502 {
+    type Register = u8;
+
+    fn load_of(&mut self, code: MOS6502RegisterCode, bits: u8) {
+        match code {
+            MOS6502RegisterCode::A => self.a = bits,
+            MOS6502RegisterCode::X => self.x = bits,
+            MOS6502RegisterCode::Y => self.y = bits,
+            MOS6502RegisterCode::P => self.p = bits,
+            MOS6502RegisterCode::S => self.sp = 0x0100 | bits.wrapping_add(1) as u16,
+        }
+    }
+
+    fn read_of(&self, code: MOS6502RegisterCode) -> u8 {
+        match code {
+            MOS6502RegisterCode::A => self.a,
+            MOS6502RegisterCode::X => self.x,
+            MOS6502RegisterCode::Y => self.y,
+            MOS6502RegisterCode::P => self.p,
+            MOS6502RegisterCode::S => (self.sp as u8).wrapping_sub(1),
+        }
+    }
+}
+
+impl<C: RegisterSet<MOS6502RegisterCode, Register = u8>> Addressing<C> for MOS6502RegisterCode {
+    type Size = u8;
+    fn value(&self, cpu: &C) -> u8 {
+        cpu.read_of(*self)
+    }
+}
+
+impl<C: RegisterSet<MOS6502RegisterCode, Register = u8>> AddressingMut<C> for MOS6502RegisterCode {
+    fn write(&self, cpu: &mut C, value: u8) {
+        cpu.load_of(*self, value)
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub enum MOS6502Addressing {
+    /// `#nn`
+    Immediate(u8),
+    /// the accumulator or an index register, e.g. `ASL A`.
+    Register(MOS6502RegisterCode),
+    /// `nn`
+    ZeroPage(u8),
+    /// `nn,X`, wrapping within the zero page.
+    ZeroPageX(u8),
+    /// `nn,Y`, wrapping within the zero page.
+    ZeroPageY(u8),
+    /// `nnnn`
+    Absolute(u16),
+    /// `nnnn,X`
+    AbsoluteX(u16),
+    /// `nnnn,Y`
+    AbsoluteY(u16),
+    /// `(nnnn)`, only used by `JMP`. The pointer does not carry into the high byte,
+    /// so `($10FF)` reads its high byte from `$1000`.
+    Indirect(u16),
+    /// `(nn,X)`
+    IndexedIndirect(u8),
+    /// `(nn),Y`
+    IndirectIndexed(u8),
+}
+
+impl MOS6502Addressing {
+    /// the effective address, or `None` if the operand is not in memory.
+    pub fn address<M: Memory<Address = u16, Data = u8>>(
+        &self,
+        system: &MOS6502System<M>,
+    ) -> Option<u16> {
+        use MOS6502RegisterCode::*;
+        let word = |low: u16, high: u16| {
+            u16::from_le_bytes([system.memory.read(low), system.memory.read(high)])
+        };
+        let zero_page_word = |zp: u8| word(zp as u16, zp.wrapping_add(1) as u16);
+        Some(match *self {
+            MOS6502Addressing::Immediate(_) | MOS6502Addressing::Register(_) => return None,
+            MOS6502Addressing::ZeroPage(zp) => zp as u16,
+            MOS6502Addressing::ZeroPageX(zp) => zp.wrapping_add(system.read_of(X)) as u16,
+            MOS6502Addressing::ZeroPageY(zp) => zp.wrapping_add(system.read_of(Y)) as u16,
+            MOS6502Addressing::Absolute(addr) => addr,
+            MOS6502Addressing::AbsoluteX(addr) => addr.wrapping_add(system.read_of(X) as u16),
+            MOS6502Addressing::AbsoluteY(addr) => addr.wrapping_add(system.read_of(Y) as u16),
+            MOS6502Addressing::Indirect(ptr) => {
+                word(ptr, (ptr & 0xff00) | (ptr as u8).wrapping_add(1) as u16)
+            }
+            MOS6502Addressing::IndexedIndirect(zp) => {
+                zero_page_word(zp.wrapping_add(system.read_of(X)))
+            }
+            MOS6502Addressing::IndirectIndexed(zp) => {
+                zero_page_word(zp).wrapping_add(system.read_of(Y) as u16)
+            }
+        })
+    }
+}
+
+impl<M: Memory<Address = u16, Data = u8>> Addressing<MOS6502System<M>> for MOS6502Addressing {
+    type Size = u8;
+
+    fn value(&self, system: &MOS6502System<M>) -> u8 {
+        match (*self, self.address(system)) {
+            (MOS6502Addressing::Immediate(v), _) => v,
+            (MOS6502Addressing::Register(reg), _) => system.read_of(reg),
+            (_, Some(addr)) => system.memory.read(addr),
+            (_, None) => unreachable!(),
+        }
+    }
+}
+
+/// No 6502 instruction stores to `#imm`, so a write to it is a bug and panics.
+impl<M: Memory<Address = u16, Data = u8>> AddressingMut<MOS6502System<M>> for MOS6502Addressing {
+    fn write(&self, system: &mut MOS6502System<M>, value: u8) {
+        match (*self, self.address(system)) {
+            (MOS6502Addressing::Immediate(_), _) => unreachable!("write to an immediate operand"),
+            (MOS6502Addressing::Register(reg), _) => system.load_of(reg, value),
+            (_, Some(addr)) => system.memory.store(addr, value),
+            (_, None) => unreachable!(),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub enum MOS6502ALUFlag {
+    Negative = 0x80,
+    Overflow = 0x40,
+    /// always reads as set.
+    Unused = 0x20,
+    Break = 0x10,
+    Decimal = 0x08,
+    Interrupt = 0x04,
+    Zero = 0x02,
+    Carry = 0x01,
+}
+
+impl From<MOS6502ALUFlag> for u8 {
+    fn from(flag: MOS6502ALUFlag) -> Self {
+        flag as u8
+    }
+}
+
+/// Carry in and decimal mode are passed through the control,
+/// since the ALU itself holds no state.
+#[derive(Debug, Copy, Clone)]
+pub enum MOS6502ALUControl {
+    /// `ADC`
+    Add {
+        carry: bool,
+        decimal: bool,
+    },
+    /// `SBC`, where the carry is the inverted borrow.
+    Subtract {
+        carry: bool,
+        decimal: bool,
+    },
+    /// `CMP`, `CPX` and `CPY`.
+    Compare,
+    BitAnd,
+    BitOr,
+    BitXor,
+    /// `BIT`: N and V are taken from the operand.
+    BitTest,
+    /// `ASL` of `a`.
+    ShiftLeft,
+    /// `LSR` of `a`.
+    ShiftRight,
+    /// `ROL` of `a`.
+    RotateLeft {
+        carry: bool,
+    },
+    /// `ROR` of `a`.
+    RotateRight {
+        carry: bool,
+    },
+    /// `INC`, `INX` and `INY` of `a`.
+    Increase,
+    /// `DEC`, `DEX` and `DEY` of `a`.
+    Decrease,
+}
+
+impl MOS6502ALUControl {
+    /// the flags that the operation changes.
+    pub fn affected(&self) -> FlagSetBits<u8> {
+        use MOS6502ALUFlag::*;
+        let flags: &[MOS6502ALUFlag] = match self {
+            MOS6502ALUControl::Add { .. } | MOS6502ALUControl::Subtract { .. } => {
+                &[Negative, Overflow, Zero, Carry]
+            }
+            MOS6502ALUControl::BitTest => &[Negative, Overflow, Zero],
+            MOS6502ALUControl::Compare
+            | MOS6502ALUControl::ShiftLeft
+            | MOS6502ALUControl::ShiftRight
+            | MOS6502ALUControl::RotateLeft { .. }
+            | MOS6502ALUControl::RotateRight { .. } => &[Negative, Zero, Carry],
+            MOS6502ALUControl::BitAnd
+            | MOS6502ALUControl::BitOr
+            | MOS6502ALUControl::BitXor
+            | MOS6502ALUControl::Increase
+            | MOS6502ALUControl::Decrease => &[Negative, Zero],
+        };
+        FlagSetBits::from_slice(flags)
+    }
+}
+
+/// NMOS 6502 ALU. In decimal mode N, V and Z are not meaningful, and are given as the NMOS does.
+#[derive(Debug, Default)]
+pub struct MOS6502ALU;
+
+impl MOS6502ALU {
+    /// result and the C, V, N and Z flags.
+    fn add(a: u8, b: u8, carry: bool, decimal: bool) -> (u8, [bool; 4]) {
+        let binary = a as u16 + b as u16 + carry as u16;
+        let overflow = |result: u8| (a ^ result) & (b ^ result) & 0x80 != 0;
+        // Z follows the binary result even in decimal mode
+        let zero = binary as u8 == 0;
+        if !decimal {
+            let result = binary as u8;
+            let overflowed = overflow_add(a, b, carry);
+            let flags = [binary > 0xff, overflowed, result & 0x80 != 0, zero];
+            return (result, flags);
+        }
+        let mut low = (a & 0x0f) as u16 + (b & 0x0f) as u16 + carry as u16;
+        if low > 9 {
+            low += 6;
+        }
+        let mut high = (a >> 4) as u16 + (b >> 4) as u16 + (low > 0x0f) as u16;
+        let unadjusted = (high << 4) as u8 | (low & 0x0f) as u8;
+        let negative = unadjusted & 0x80 != 0;
+        let overflowed = overflow(unadjusted);
+        if high > 9 {
+            high += 6;
+        }
+        let result = (high << 4) as u8 | (low & 0x0f) as u8;
+        (result, [high > 0x0f, overflowed, negative, zero])
+    }
+
+    /// flags are those of the binary subtraction in both modes.
+    fn subtract(a: u8, b: u8, carry: bool, decimal: bool) -> (u8, [bool; 4]) {
+        let (binary, flags) = Self::add(a, !b, carry, false);
+        if !decimal {
+            return (binary, flags);
+        }
+        let mut low = (a & 0x0f) as i16 - (b & 0x0f) as i16 - !carry as i16;
+        let mut high = (a >> 4) as i16 - (b >> 4) as i16;
+        if low < 0 {
+            low -= 6;
+            high -= 1;
+        }
+        if high < 0 {
+            high -= 6;
+        }
+        let result = ((high << 4) as u8) | (low & 0x0f) as u8;
+        (result, flags)
+    }
+}
+
+impl ALU for MOS6502ALU {
+    type Data = u8;
+    type Control = MOS6502ALUControl;
+    type Flag = MOS6502ALUFlag;
+    type FlagSet = FlagSetBits<u8>;
+
+    fn op(&self, code: Self::Control, a: u8, b: u8) -> (u8, Self::FlagSet) {
+        use MOS6502ALUFlag::*;
+        let mut flags = FlagSetBits::from(0);
+        let result = match code {
+            MOS6502ALUControl::Add { carry, decimal } => {
+                let (result, changes) = Self::add(a, b, carry, decimal);
+                for (flag, set) in [Carry, Overflow, Negative, Zero].into_iter().zip(changes) {
+                    flags.change(flag, set);
+                }
+                return (result, flags);
+            }
+            MOS6502ALUControl::Subtract { carry, decimal } => {
+                let (result, changes) = Self::subtract(a, b, carry, decimal);
+                for (flag, set) in [Carry, Overflow, Negative, Zero].into_iter().zip(changes) {
+                    flags.change(flag, set);
+                }
+                return (result, flags);
+            }
+            MOS6502ALUControl::Compare => {
+                flags.change(Carry, a >= b);
+                let result = a.wrapping_sub(b);
+                flags.change(Negative, result & 0x80 != 0);
+                flags.change(Zero, result == 0);
+                return (a, flags);
+            }
+            MOS6502ALUControl::BitTest => {
+                flags.change(Negative, b & 0x80 != 0);
+                flags.change(Overflow, b & 0x40 != 0);
+                flags.change(Zero, a & b == 0);
+                return (a, flags);
+            }
+            MOS6502ALUControl::BitAnd => a & b,
+            MOS6502ALUControl::BitOr => a | b,
+            MOS6502ALUControl::BitXor => a ^ b,
+            MOS6502ALUControl::ShiftLeft => {
+                flags.change(Carry, a & 0x80 != 0);
+                a << 1
+            }
+            MOS6502ALUControl::ShiftRight => {
+                flags.change(Carry, a & 0x01 != 0);
+                a >> 1
+            }
+            MOS6502ALUControl::RotateLeft { carry } => {
+                flags.change(Carry, a & 0x80 != 0);
+                a << 1 | carry as u8
+            }
+            MOS6502ALUControl::RotateRight { carry } => {
+                flags.change(Carry, a & 0x01 != 0);
+                a >> 1 | (carry as u8) << 7
+            }
+            MOS6502ALUControl::Increase => a.wrapping_add(1),
+            MOS6502ALUControl::Decrease => a.wrapping_sub(1),
+        };
+        flags.change(Negative, result & 0x80 != 0);
+        flags.change(Zero, result == 0);
+        (result, flags)
+    }
+}
+
+/// `INC` and `DEC`, setting N and Z.
+impl ALUCounter for MOS6502ALU {
+    fn increment(&self, a: u8) -> (u8, Self::FlagSet) {
+        self.op(MOS6502ALUControl::Increase, a, 0)
+    }
+    fn decrement(&self, a: u8) -> (u8, Self::FlagSet) {
+        self.op(MOS6502ALUControl::Decrease, a, 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::addressing::{ImmediateRegister, Indexed};
+    use crate::instruction::typical::*;
+    use crate::instruction::Instruction;
+    use alloc::vec::Vec;
+    use MOS6502ALUFlag::*;
+    use MOS6502Addressing::*;
+    use MOS6502RegisterCode::*;
+
+    fn system() -> MOS6502System {
+        let bytes: Vec<u8> = (0..=255).collect();
+        MOS6502System::new(MOS6502::default(), Memory8Bit64KB::new(&bytes))
+    }
+
+    #[test]
+    fn addressing() {
+        let mut system = system();
+        system.load_of(X, 0x10);
+        system.load_of(Y, 0x20);
+        assert_eq!(Immediate(7).value(&system), 7);
+        assert_eq!(ZeroPage(0x30).value(&system), 0x30);
+        assert_eq!(ZeroPageX(0xf8).address(&system), Some(0x0008));
+        assert_eq!(ZeroPageY(0xf8).address(&system), Some(0x0018));
+        assert_eq!(AbsoluteX(0x01f8).address(&system), Some(0x0208));
+        assert_eq!(AbsoluteY(0xfff0).address(&system), Some(0x0010));
+        // ($20,X) reads the pointer at $30, $31
+        assert_eq!(IndexedIndirect(0x20).address(&system), Some(0x3130));
+        // ($FF),Y reads the pointer at $FF, $00
+        assert_eq!(IndirectIndexed(0xff).address(&system), Some(0x011f));
+        system.memory.store(0x10ff, 0x34);
+        system.memory.store(0x1000, 0x12);
+        assert_eq!(Indirect(0x10ff).address(&system), Some(0x1234));
+        assert_eq!(Register(A).address(&system), None);
+        // the common combinators express the same modes
+        let abs_x = Indexed::new(
+            crate::addressing::Immediate::new(0x0040u16),
+            ImmediateRegister::new(X),
+        );
+        assert_eq!(
+            crate::addressing::Indirect::new(abs_x).value(&system),
+            AbsoluteX(0x0040).value(&system)
+        );
+        Load::new(ZeroPageX(0x00), Immediate(0x99)).execute(&mut system);
+        assert_eq!(system.memory.read(0x0010), 0x99);
+        system.memory.store(0x0040, 0x10);
+        system.memory.store(0x0041, 0x00);
+        Load::new(A, IndirectIndexed(0x40)).execute(&mut system);
+        assert_eq!(system.read_of(A), 0x30);
+        Load::new(Register(X), Register(A)).execute(&mut system);
+        assert_eq!(system.read_of(X), 0x30);
+    }
+
+    #[test]
+    fn stack() {
+        let mut system = system();
+        system.load_of(S, 0x01);
+        Push::new(0x12).execute(&mut system);
+        Push::new(0x34).execute(&mut system);
+        Push::new(0x56).execute(&mut system);
+        assert_eq!(system.memory.read(0x0101), 0x12);
+        assert_eq!(system.memory.read(0x0100), 0x34);
+        assert_eq!(system.memory.read(0x01ff), 0x56);
+        assert_eq!(system.read_of(S), 0xfe);
+        Pop::new(A).execute(&mut system);
+        Pop::new(X).execute(&mut system);
+        Pop::new(Y).execute(&mut system);
+        assert_eq!(
+            (system.read_of(A), system.read_of(X), system.read_of(Y)),
+            (0x56, 0x34, 0x12)
+        );
+        assert_eq!(system.read_of(S), 0x01);
+    }
+
+    #[test]
+    fn relative() {
+        let mut system = system();
+        // BNE -3; BEQ +4
+        system.memory.store(0x0201, 0xfd);
+        system.memory.store(0x0203, 0x04);
+        *system.cpu.program_counter() = 0x0201;
+        system.cpu.flag_load_mask_slice(&[Zero], 0xff);
+        JumpRelativeIf::new(Zero, false).execute(&mut system);
+        assert_eq!(*system.cpu.program_counter(), 0x0202);
+        *system.cpu.program_counter() = 0x0201;
+        JumpRelative.execute(&mut system);
+        assert_eq!(*system.cpu.program_counter(), 0x01ff);
+        *system.cpu.program_counter() = 0x0203;
+        JumpRelativeIf::new(Zero, true).execute(&mut system);
+        assert_eq!(*system.cpu.program_counter(), 0x0208);
+    }
+
+    #[test]
+    fn reset() {
+        let mut system = system();
+        system
+            .memory
+            .store_word(MOS6502::RESET_VECTOR, 0xe000, Endian::Little);
+        system.load_of(S, 0x01);
+        system.cpu.flag_load(0x00);
+        system.cpu.reset(&mut system.memory);
+        assert_eq!(*system.cpu.program_counter(), 0xe000);
+        assert_eq!(system.read_of(S), 0xfe);
+        assert!(system.cpu.flag_on(Interrupt));
+    }
+
+    #[test]
+    fn flags_and_branch() {
+        let mut cpu = MOS6502::default();
+        assert!(cpu.flag_on(Interrupt));
+        cpu.flag_load_mask_slice(&[Zero, Carry], 0xff);
+        assert_eq!(cpu.flag_read(), 0x27);
+        cpu.jump_on(0x1234, Zero);
+        assert_eq!(*cpu.program_counter(), 0x1234);
+        cpu.jump_on(0x5678, Negative);
+        assert_eq!(*cpu.program_counter(), 0x1234);
+    }
+
+    #[test]
+    fn alu() {
+        use MOS6502ALUControl::*;
+        let alu = MOS6502ALU;
+        let adc = |a, b, carry, decimal| {
+            let (r, f) = alu.op(Add { carry, decimal }, a, b);
+            (r, u8::from(f))
+        };
+        let sbc = |a, b, carry, decimal| {
+            let (r, f) = alu.op(Subtract { carry, decimal }, a, b);
+            (r, u8::from(f))
+        };
+        // binary
+        assert_eq!(adc(0x50, 0x50, false, false), (0xa0, 0xc0));
+        assert_eq!(adc(0xff, 0x00, true, false), (0x00, 0x03));
+        assert_eq!(sbc(0x50, 0xb0, true, false), (0xa0, 0xc0));
+        assert_eq!(sbc(0x00, 0x01, true, false), (0xff, 0x80));
+        // decimal
+        assert_eq!(adc(0x15, 0x27, false, true), (0x42, 0x00));
+        assert_eq!(adc(0x58, 0x46, true, true), (0x05, 0xc1));
+        assert_eq!(adc(0x99, 0x00, true, true), (0x00, 0x81));
+        assert_eq!(sbc(0x46, 0x12, true, true), (0x34, 0x01));
+        assert_eq!(sbc(0x40, 0x13, true, true), (0x27, 0x01));
+        assert_eq!(sbc(0x12, 0x21, true, true), (0x91, 0x80));
+        // others
+        assert_eq!(u8::from(alu.op(Compare, 0x10, 0x10).1), 0x03);
+        assert_eq!(u8::from(alu.op(BitTest, 0x01, 0xc0).1), 0xc2);
+        assert_eq!(alu.op(RotateRight { carry: true }, 0x01, 0).0, 0x80);
+        assert_eq!(u8::from(alu.op(Decrease, 0x01, 0).1), 0x02);
+        assert_eq!(u8::from(ShiftLeft.affected()), 0x83);
+    }
+}
@@ -0,0 +1,248 @@
+use crate::bus::{Device, RegionKind, StateError, StateReader};
+use crate::memory::MemoryError;
+use alloc::rc::Rc;
+use alloc::{boxed::Box, string::String, vec::Vec};
+use core::cell::RefCell;
+use core::fmt;
+
+/// An interrupt controller of 8 levels, level 0 the most urgent, presenting the lines
+/// wired to it as the one request of the CPU.
+///
+/// Acknowledging puts the vector of the most urgent request on the data bus and marks its
+/// level in service, which holds off that level and the less urgent ones until an end of
+/// interrupt is written.
+///
+/// Registers, by offset:
+/// 0: mask, bit `n` masking level `n`.
+/// 1: the levels requesting when read. Writing any value ends the most urgent level in service.
+/// 2: the levels in service, read only.
+pub struct Pic {
+    lines: Vec<(u8, Box<dyn Fn() -> bool>)>,
+    mask: u8,
+    in_service: u8,
+    vector: fn(u8) -> u8,
+}
+
+impl Default for Pic {
+    fn default() -> Self {
+        Self {
+            lines: Vec::new(),
+            mask: 0,
+            in_service: 0,
+            vector: Self::rst,
+        }
+    }
+}
+
+impl Pic {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// the I8080 `RST level` opcode, the default vector.
+    pub fn rst(level: u8) -> u8 {
+        0xc7 | level << 3
+    }
+
+    /// what is put on the data bus for each level, e.g. `|level| level * 2` for Z80 mode 2.
+    pub fn vectors(mut self, vector: fn(u8) -> u8) -> Self {
+        self.vector = vector;
+        self
+    }
+
+    /// wires `line` to `level`. Lines sharing a level are ORed.
+    pub fn connect<F: Fn() -> bool + 'static>(&mut self, level: u8, line: F) -> &mut Self {
+        assert!(level < 8);
+        self.lines.push((level, Box::new(line)));
+        self
+    }
+
+    /// wires the interrupt of `device` to `level`, returning the device to map in its place,
+    /// so that the bus sees its line only through the controller.
+    pub fn wire<T: Device + 'static>(&mut self, level: u8, device: Rc<RefCell<T>>) -> Wired<T> {
+        let line = device.clone();
+        self.connect(level, move || line.borrow().interrupt());
+        Wired(device)
+    }
+
+    /// the levels whose lines are asserted, masked or not.
+    pub fn requests(&self) -> u8 {
+        self.lines
+            .iter()
+            .filter(|(_, line)| line())
+            .fold(0, |bits, (level, _)| bits | 1 << level)
+    }
+
+    /// the most urgent level let through by the mask and the levels in service.
+    fn pending(&self) -> Option<u8> {
+        let level = (self.requests() & !self.mask).trailing_zeros() as u8;
+        let blocked = self.in_service.trailing_zeros() as u8;
+        (level < 8 && level < blocked).then_some(level)
+    }
+}
+
+impl Device for Pic {
+    type Address = u16;
+    type Data = u8;
+
+    fn read(&mut self, offset: u16) -> u8 {
+        match offset {
+            0 => self.mask,
+            1 => self.requests(),
+            2 => self.in_service,
+            _ => 0xff,
+        }
+    }
+
+    fn write(&mut self, offset: u16, data: u8) {
+        match offset {
+            0 => self.mask = data,
+            // clears the lowest set bit, the most urgent level
+            1 => self.in_service &= self.in_service.wrapping_sub(1),
+            _ => {}
+        }
+    }
+
+    fn interrupt(&self) -> bool {
+        self.pending().is_some()
+    }
+
+    fn acknowledge(&mut self) -> Option<u8> {
+        let level = self.pending()?;
+        self.in_service |= 1 << level;
+        Some((self.vector)(level))
+    }
+
+    fn reset(&mut self) {
+        self.mask = 0;
+        self.in_service = 0;
+    }
+
+    fn save_state(&self, state: &mut Vec<u8>) {
+        state.extend([self.mask, self.in_service]);
+    }
+
+    fn load_state(&mut self, _version: u16, state: &[u8]) -> Result<(), StateError> {
+        let mut state = StateReader::new(state);
+        self.mask = state.u8()?;
+        self.in_service = state.u8()?;
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Pic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Pic")
+            .field("lines", &self.lines.len())
+            .field("mask", &self.mask)
+            .field("in_service", &self.in_service)
+            .finish()
+    }
+}
+
+/// A device whose interrupt goes through a [`Pic`], hiding its line and vector from the bus.
+#[derive(Debug)]
+pub struct Wired<T>(Rc<RefCell<T>>);
+
+impl<T: Device> Device for Wired<T> {
+    type Address = T::Address;
+    type Data = T::Data;
+    fn read(&mut self, offset: T::Address) -> T::Data {
+        self.0.read(offset)
+    }
+    fn write(&mut self, offset: T::Address, data: T::Data) {
+        self.0.write(offset, data)
+    }
+    fn tick(&mut self, cycles: u64) {
+        self.0.tick(cycles)
+    }
+    fn try_write(
+        &mut self,
+        offset: T::Address,
+        data: T::Data,
+    ) -> Result<(), MemoryError<T::Address>> {
+        self.0.try_write(offset, data)
+    }
+    fn reset(&mut self) {
+        self.0.reset()
+    }
+    fn name(&self) -> String {
+        self.0.name()
+    }
+    fn kind(&self) -> RegionKind {
+        self.0.kind()
+    }
+    fn bank(&self) -> Option<usize> {
+        self.0.bank()
+    }
+    fn writable(&self) -> bool {
+        self.0.writable()
+    }
+    fn write_latency(&self, offset: T::Address) -> u64 {
+        self.0.write_latency(offset)
+    }
+    fn state_version(&self) -> u16 {
+        self.0.state_version()
+    }
+    fn save_state(&self, state: &mut Vec<u8>) {
+        self.0.save_state(state)
+    }
+    fn load_state(&mut self, version: u16, state: &[u8]) -> Result<(), StateError> {
+        self.0.load_state(version, state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::Bus;
+    use crate::memory::typical::Memory8Bit64KB;
+    use crate::memory::Memory;
+    use crate::typical::timer::Timer;
+    use crate::typical::uart::Uart;
+
+    #[test]
+    fn priority() {
+        let timer = Rc::new(RefCell::new(Timer::new()));
+        let uart = Rc::new(RefCell::new(Uart::new().interrupt_on_rx(true)));
+        let mut pic = Pic::new();
+        let wired_timer = pic.wire(1, timer.clone());
+        let wired_uart = pic.wire(2, uart.clone());
+        let mut bus = Bus::default();
+        bus.map_memory(0x0000..=0xffff, Memory8Bit64KB::default())
+            .map(0xe000..=0xe003, wired_timer)
+            .map(0xe010..=0xe011, wired_uart)
+            .map(0xe020..=0xe022, pic);
+        bus.store(0xe011, 0x4e);
+        bus.store(0xe011, Uart::RX_ENABLE);
+        uart.borrow_mut().feed(b"a");
+        bus.store(0xe000, 10);
+        bus.store(0xe002, Timer::ENABLE | Timer::INTERRUPT_ENABLE);
+        bus.tick(10);
+        assert_eq!(bus.read(0xe021), 0b110);
+        // the timer first, holding off the UART until its end of interrupt
+        assert_eq!(bus.acknowledge(), Some(Pic::rst(1)));
+        assert_eq!(bus.read(0xe022), 0b010);
+        bus.read(0xe003);
+        assert!(!bus.interrupt());
+        bus.store(0xe021, 0);
+        assert_eq!(bus.acknowledge(), Some(0xd7));
+        bus.store(0xe021, 0);
+        // masked, the line stays asserted but is not let through
+        bus.store(0xe020, 0b100);
+        assert!(!bus.interrupt());
+        bus.store(0xe020, 0);
+        assert!(bus.interrupt());
+        bus.read(0xe010);
+        assert!(!bus.interrupt());
+    }
+
+    #[test]
+    fn wired_rom() {
+        use crate::bus::typical::Rom;
+
+        let rom = Rc::new(RefCell::new(Rom::new(Memory8Bit64KB::default())));
+        let mut wired = Pic::new().wire(0, rom);
+        assert_eq!(wired.try_write(0, 0x12), Err(MemoryError::ReadOnly(0)));
+    }
+}
@@ -0,0 +1,114 @@
+use crate::bus::{Device, StateError, StateReader};
+use crate::clock::Clock;
+use crate::signal::Line;
+use alloc::vec::Vec;
+
+/// The CPU speed switch the program sees on the PC-8801 mkII SR and later: a port whose
+/// [`SpeedSwitch::FAST`] bit selects [`Clock::PC8801_8MHZ`] over [`Clock::PC8801_4MHZ`].
+///
+/// The selected clock drives [`SpeedSwitch::line`], which a machine follows by
+/// [`Machine::follow_clock`], so that its frames and devices run at the new rate.
+/// Reset goes back to the clock the switch was built with, as set by the DIP switch.
+///
+/// [`Machine::follow_clock`]: crate::machine::Machine::follow_clock
+#[derive(Debug, Clone)]
+pub struct SpeedSwitch {
+    line: Line<Clock>,
+    power_on: Clock,
+}
+
+impl SpeedSwitch {
+    pub const FAST: u8 = 0x80;
+
+    pub fn new(clock: Clock) -> Self {
+        Self {
+            line: Line::new(clock),
+            power_on: clock,
+        }
+    }
+
+    /// the line carrying the selected clock.
+    pub fn line(&self) -> Line<Clock> {
+        self.line.clone()
+    }
+
+    fn select(&self, fast: bool) {
+        self.line.set(match fast {
+            true => Clock::PC8801_8MHZ,
+            false => Clock::PC8801_4MHZ,
+        })
+    }
+
+    fn fast(&self) -> bool {
+        self.line.get() == Clock::PC8801_8MHZ
+    }
+}
+
+impl Device for SpeedSwitch {
+    type Address = u16;
+    type Data = u8;
+
+    fn read(&mut self, _offset: u16) -> u8 {
+        match self.fast() {
+            true => Self::FAST,
+            false => 0,
+        }
+    }
+
+    fn write(&mut self, _offset: u16, data: u8) {
+        self.select(data & Self::FAST != 0)
+    }
+
+    fn reset(&mut self) {
+        self.line.set(self.power_on)
+    }
+
+    fn save_state(&self, state: &mut Vec<u8>) {
+        state.push(self.fast() as u8);
+    }
+
+    fn load_state(&mut self, _version: u16, state: &[u8]) -> Result<(), StateError> {
+        let fast = StateReader::new(state).bool()?;
+        self.select(fast);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::Bus;
+    use crate::cpu::CPURunningState;
+    use crate::machine::Machine;
+    use crate::memory::Memory;
+    use crate::registry::Config;
+    use crate::typical::i8080::{self, I8080};
+
+    #[test]
+    fn switches_the_clock() {
+        // NOP; NOP; HLT
+        let config = Config::new().image("program", &[0x00, 0x00, 0x76]);
+        let mut machine: Machine<I8080, u16, u8> = i8080::test_machine(&config);
+        let mut ports = Bus::new(0xff);
+        let switch = SpeedSwitch::new(Clock::PC8801_4MHZ);
+        machine.follow_clock(switch.line());
+        ports.map(0x6e..=0x6e, switch);
+        assert_eq!(ports.read(0x6e), 0);
+        ports.store(0x6e, SpeedSwitch::FAST);
+        assert_eq!(ports.read(0x6e), SpeedSwitch::FAST);
+        assert_eq!(machine.clock(), Clock::PC8801_4MHZ);
+        machine.step();
+        assert_eq!(machine.clock(), Clock::PC8801_8MHZ);
+        // the NOP during which it switched ran at 4 MHz, the rest at 8 MHz
+        let elapsed = Clock::PC8801_4MHZ.duration_of(4);
+        assert_eq!(machine.elapsed(), elapsed);
+        assert_eq!(machine.run(1000), CPURunningState::Halted);
+        assert_eq!(
+            machine.elapsed(),
+            elapsed + Clock::PC8801_8MHZ.duration_of(machine.cycles() - 4)
+        );
+        ports.reset();
+        machine.step();
+        assert_eq!(machine.clock(), Clock::PC8801_4MHZ);
+    }
+}
@@ -0,0 +1,175 @@
+use crate::bus::{Device, StateError, StateReader};
+use alloc::vec::Vec;
+
+/// A 16-bit down counter of CPU cycles that raises its interrupt line on expiry.
+///
+/// Registers, by offset:
+/// 0, 1: reload value, low and high. 0 counts 65536 cycles.
+/// 2: control, see [`Timer::ENABLE`] and others. Enabling loads the counter.
+/// 3: status, bit 0 set on expiry. Reading it acknowledges the interrupt.
+#[derive(Debug, Default, Clone)]
+pub struct Timer {
+    reload: u16,
+    counter: u32,
+    control: u8,
+    expired: bool,
+}
+
+impl Timer {
+    pub const ENABLE: u8 = 0x01;
+    pub const INTERRUPT_ENABLE: u8 = 0x02;
+    /// stop after the first expiry instead of reloading.
+    pub const ONE_SHOT: u8 = 0x04;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// cycles left until the next expiry, if running.
+    pub fn remaining(&self) -> Option<u32> {
+        self.running().then_some(self.counter)
+    }
+
+    fn running(&self) -> bool {
+        self.control & Self::ENABLE != 0
+    }
+
+    fn period(&self) -> u32 {
+        match self.reload {
+            0 => 0x10000,
+            reload => reload as u32,
+        }
+    }
+}
+
+impl Device for Timer {
+    type Address = u16;
+    type Data = u8;
+
+    fn read(&mut self, offset: u16) -> u8 {
+        match offset {
+            0 => self.reload as u8,
+            1 => (self.reload >> 8) as u8,
+            2 => self.control,
+            3 => core::mem::take(&mut self.expired) as u8,
+            _ => 0xff,
+        }
+    }
+
+    fn write(&mut self, offset: u16, data: u8) {
+        match offset {
+            0 => self.reload = (self.reload & 0xff00) | data as u16,
+            1 => self.reload = (self.reload & 0x00ff) | (data as u16) << 8,
+            2 => {
+                if !self.running() && data & Self::ENABLE != 0 {
+                    self.counter = self.period();
+                }
+                self.control = data;
+            }
+            _ => {}
+        }
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        if !self.running() {
+            return;
+        }
+        let Some(past) = cycles.checked_sub(self.counter as u64) else {
+            self.counter -= cycles as u32;
+            return;
+        };
+        self.expired = true;
+        if self.control & Self::ONE_SHOT != 0 {
+            self.control &= !Self::ENABLE;
+            self.counter = self.period();
+            return;
+        }
+        // reloaded on every expiry, so only the part of the last period run counts
+        let period = self.period();
+        self.counter = period - (past % period as u64) as u32;
+    }
+
+    fn interrupt(&self) -> bool {
+        self.expired && self.control & Self::INTERRUPT_ENABLE != 0
+    }
+
+    fn reset(&mut self) {
+        *self = Self::default()
+    }
+
+    fn save_state(&self, state: &mut Vec<u8>) {
+        state.extend(self.reload.to_le_bytes());
+        state.extend(self.counter.to_le_bytes());
+        state.extend([self.control, self.expired as u8]);
+    }
+
+    fn load_state(&mut self, _version: u16, state: &[u8]) -> Result<(), StateError> {
+        let mut state = StateReader::new(state);
+        self.reload = state.u16()?;
+        self.counter = state.u32()?;
+        self.control = state.u8()?;
+        self.expired = state.bool()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::Bus;
+    use crate::memory::typical::Memory8Bit64KB;
+    use crate::memory::Memory;
+
+    #[test]
+    fn periodic() {
+        let mut bus = Bus::default();
+        bus.map_memory(0x0000..=0xffff, Memory8Bit64KB::default())
+            .map(0xe000..=0xe003, Timer::new());
+        bus.store(0xe000, 100);
+        bus.store(0xe002, Timer::ENABLE | Timer::INTERRUPT_ENABLE);
+        bus.tick(99);
+        assert!(!bus.interrupt());
+        bus.tick(1);
+        assert!(bus.interrupt());
+        assert_eq!(bus.read(0xe003), 1);
+        assert!(!bus.interrupt());
+        assert_eq!(bus.read(0xe003), 0);
+        // several periods at once
+        bus.tick(250);
+        assert!(bus.interrupt());
+        bus.read(0xe003);
+        bus.tick(49);
+        assert!(!bus.interrupt());
+        bus.tick(1);
+        assert!(bus.interrupt());
+    }
+
+    #[test]
+    fn one_shot() {
+        let mut timer = Timer::new();
+        timer.write(1, 0x01);
+        timer.write(2, Timer::ENABLE | Timer::ONE_SHOT);
+        assert_eq!(timer.remaining(), Some(0x100));
+        timer.tick(0x1000);
+        assert_eq!(timer.remaining(), None);
+        assert!(!timer.interrupt());
+        assert_eq!(timer.read(3), 1);
+        timer.write(0, 0x00);
+        timer.write(1, 0x00);
+        timer.write(2, Timer::ENABLE);
+        assert_eq!(timer.remaining(), Some(0x10000));
+    }
+
+    #[test]
+    fn long_tick() {
+        let mut timer = Timer::new();
+        timer.write(0, 3);
+        timer.write(2, Timer::ENABLE);
+        timer.tick(u64::MAX);
+        assert_eq!(timer.read(3), 1);
+        // u64::MAX is 0 modulo 3, so it ends on an expiry and a full period is left
+        assert_eq!(timer.remaining(), Some(3));
+        timer.tick(4);
+        assert_eq!(timer.remaining(), Some(2));
+    }
+}
@@ -0,0 +1,142 @@
+use crate::bus::Device;
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+/// A serial port modelled on the 8251, moving whole bytes without baud timing.
+///
+/// Registers, by offset:
+/// 0: data. Reading takes the received byte, writing sends one.
+/// 1: status when read, see [`Uart::TX_READY`] and others. Written, the first byte after
+/// reset is the mode instruction and the rest are commands, see [`Uart::TX_ENABLE`] and others.
+///
+/// The host side feeds bytes with [`Uart::feed`] and drains what was sent with [`Uart::drain`].
+#[derive(Debug, Default, Clone)]
+pub struct Uart {
+    received: VecDeque<u8>,
+    sent: Vec<u8>,
+    mode: Option<u8>,
+    command: u8,
+    interrupt_on_rx: bool,
+}
+
+impl Uart {
+    pub const TX_READY: u8 = 0x01;
+    pub const RX_READY: u8 = 0x02;
+    pub const TX_EMPTY: u8 = 0x04;
+
+    pub const TX_ENABLE: u8 = 0x01;
+    pub const RX_ENABLE: u8 = 0x04;
+    /// back to expecting a mode instruction.
+    pub const INTERNAL_RESET: u8 = 0x40;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// raises the interrupt line while a received byte is waiting, as RxRDY wired to it.
+    pub fn interrupt_on_rx(mut self, enable: bool) -> Self {
+        self.interrupt_on_rx = enable;
+        self
+    }
+
+    /// queues bytes arriving on the line.
+    pub fn feed(&mut self, bytes: &[u8]) {
+        self.received.extend(bytes);
+    }
+
+    /// the bytes sent so far, leaving none.
+    pub fn drain(&mut self) -> Vec<u8> {
+        core::mem::take(&mut self.sent)
+    }
+
+    /// the mode instruction written after reset, if any.
+    pub fn mode(&self) -> Option<u8> {
+        self.mode
+    }
+
+    fn rx_ready(&self) -> bool {
+        self.command & Self::RX_ENABLE != 0 && !self.received.is_empty()
+    }
+
+    fn status(&self) -> u8 {
+        let rx = match self.rx_ready() {
+            true => Self::RX_READY,
+            false => 0,
+        };
+        Self::TX_READY | Self::TX_EMPTY | rx
+    }
+}
+
+impl Device for Uart {
+    type Address = u16;
+    type Data = u8;
+
+    fn read(&mut self, offset: u16) -> u8 {
+        match offset {
+            0 if self.rx_ready() => self.received.pop_front().unwrap_or(0xff),
+            0 => 0xff,
+            1 => self.status(),
+            _ => 0xff,
+        }
+    }
+
+    fn write(&mut self, offset: u16, data: u8) {
+        match offset {
+            0 if self.command & Self::TX_ENABLE != 0 => self.sent.push(data),
+            1 if self.mode.is_none() => self.mode = Some(data),
+            1 if data & Self::INTERNAL_RESET != 0 => {
+                self.mode = None;
+                self.command = 0;
+            }
+            1 => self.command = data,
+            _ => {}
+        }
+    }
+
+    fn interrupt(&self) -> bool {
+        self.interrupt_on_rx && self.rx_ready()
+    }
+
+    /// keeps the host side's queues, as the line is outside the chip.
+    fn reset(&mut self) {
+        self.mode = None;
+        self.command = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::Bus;
+    use crate::memory::typical::Memory8Bit64KB;
+    use crate::memory::Memory;
+    use alloc::rc::Rc;
+    use core::cell::RefCell;
+
+    #[test]
+    fn console() {
+        let uart = Rc::new(RefCell::new(Uart::new().interrupt_on_rx(true)));
+        let mut bus = Bus::default();
+        bus.map_memory(0x0000..=0xffff, Memory8Bit64KB::default())
+            .map(0xe010..=0xe011, uart.clone());
+        // the mode instruction, then enabling both directions
+        bus.store(0xe011, 0x4e);
+        bus.store(0xe011, Uart::TX_ENABLE | Uart::RX_ENABLE);
+        assert_eq!(uart.borrow().mode(), Some(0x4e));
+        for &byte in b"ok" {
+            assert_ne!(bus.read(0xe011) & Uart::TX_READY, 0);
+            bus.store(0xe010, byte);
+        }
+        assert_eq!(uart.borrow_mut().drain(), b"ok");
+        assert_eq!(bus.read(0xe011) & Uart::RX_READY, 0);
+        uart.borrow_mut().feed(b"hi");
+        assert!(bus.interrupt());
+        assert_eq!((bus.read(0xe010), bus.read(0xe010)), (b'h', b'i'));
+        assert!(!bus.interrupt());
+        // an internal reset waits for the mode again and stops sending
+        bus.store(0xe011, Uart::INTERNAL_RESET);
+        bus.store(0xe010, b'x');
+        assert_eq!(uart.borrow().mode(), None);
+        assert!(uart.borrow_mut().drain().is_empty());
+    }
+}
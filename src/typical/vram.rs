@@ -0,0 +1,147 @@
+use crate::memory::Memory;
+use alloc::{vec, vec::Vec};
+use core::ops::RangeInclusive;
+
+/// A character cell of a [`TextVram`] by its position on screen.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Cell {
+    pub row: usize,
+    pub column: usize,
+    pub value: u8,
+}
+
+/// Text-mode video memory, a byte per character cell in row-major order,
+/// remembering the cells changed since the last [`TextVram::end_frame`].
+///
+/// Share it with the frontend through [`SharedMemory`](crate::memory::typical::SharedMemory)
+/// to map it on a bus and render it too. Storing the value a cell already holds does not dirty it.
+#[derive(Debug, Clone)]
+pub struct TextVram {
+    cells: Vec<u8>,
+    dirty: Vec<bool>,
+    columns: usize,
+}
+
+impl TextVram {
+    /// a screen of `columns` by `rows` cells, `None` without columns to lay the rows out by.
+    pub fn new(columns: usize, rows: usize) -> Option<Self> {
+        if columns == 0 {
+            return None;
+        }
+        let cells = columns.checked_mul(rows)?;
+        Some(Self {
+            cells: vec![0; cells],
+            dirty: vec![false; cells],
+            columns,
+        })
+    }
+
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+
+    pub fn rows(&self) -> usize {
+        self.cells.len() / self.columns
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        &self.cells
+    }
+
+    /// the cells changed this frame.
+    pub fn changed(&self) -> impl Iterator<Item = Cell> + '_ {
+        self.dirty
+            .iter()
+            .enumerate()
+            .filter(|(_, &dirty)| dirty)
+            .map(|(i, _)| Cell {
+                row: i / self.columns,
+                column: i % self.columns,
+                value: self.cells[i],
+            })
+    }
+
+    /// runs of consecutive offsets changed this frame.
+    pub fn dirty_ranges(&self) -> impl Iterator<Item = RangeInclusive<usize>> + '_ {
+        let mut i = 0;
+        core::iter::from_fn(move || {
+            let start = i + self.dirty[i..].iter().position(|&dirty| dirty)?;
+            let len = self.dirty[start..]
+                .iter()
+                .take_while(|&&dirty| dirty)
+                .count();
+            i = start + len;
+            Some(start..=i - 1)
+        })
+    }
+
+    /// forgets the changes, once the frame is rendered.
+    pub fn end_frame(&mut self) {
+        self.dirty.iter_mut().for_each(|dirty| *dirty = false);
+    }
+
+    /// marks every cell changed, e.g. to draw the first frame.
+    pub fn invalidate(&mut self) {
+        self.dirty.iter_mut().for_each(|dirty| *dirty = true);
+    }
+}
+
+/// addresses beyond the screen read as 0xff and ignore writes.
+impl Memory for TextVram {
+    type Address = u16;
+    type Data = u8;
+
+    fn read(&self, address: u16) -> u8 {
+        self.cells.get(address as usize).copied().unwrap_or(0xff)
+    }
+
+    fn store(&mut self, address: u16, data: u8) {
+        if let Some(cell) = self.cells.get_mut(address as usize) {
+            if *cell != data {
+                *cell = data;
+                self.dirty[address as usize] = true;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::Bus;
+    use crate::memory::typical::{Memory8Bit64KB, SharedMemory};
+
+    #[test]
+    fn dirty() {
+        let vram = SharedMemory::new(TextVram::new(80, 25).unwrap());
+        let mut bus = Bus::default();
+        bus.map_memory(0x0000..=0xffff, Memory8Bit64KB::default())
+            .map_memory(0xf300..=0xffff, vram.clone());
+        for (i, &byte) in b"HI".iter().enumerate() {
+            bus.store(0xf300 + 80 + i as u16, byte);
+        }
+        bus.store(0xf300 + 85, 0x00);
+        bus.store(0xf300 + 1999, b'!');
+        let changed: Vec<_> = vram
+            .borrow()
+            .changed()
+            .map(|cell| (cell.row, cell.column, cell.value))
+            .collect();
+        assert_eq!(changed, [(1, 0, b'H'), (1, 1, b'I'), (24, 79, b'!')]);
+        let ranges: Vec<_> = vram.borrow().dirty_ranges().collect();
+        assert_eq!(ranges, [80..=81, 1999..=1999]);
+        vram.borrow_mut().end_frame();
+        assert_eq!(vram.borrow().changed().count(), 0);
+        assert_eq!(bus.read(0xf300 + 81), b'I');
+        assert_eq!(bus.read(0xfff0), 0xff);
+    }
+
+    #[test]
+    fn shape() {
+        assert!(TextVram::new(0, 25).is_none());
+        let mut vram = TextVram::new(1, 0x10001).unwrap();
+        vram.invalidate();
+        // cells past the 16-bit offsets still show up whole
+        assert_eq!(vram.dirty_ranges().collect::<Vec<_>>(), [0..=0x10000]);
+    }
+}